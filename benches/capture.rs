@@ -119,7 +119,9 @@ fn bench_zero_copy_operations(c: &mut Criterion) {
 
         b.iter(|| {
             match zero_copy.capture_zero_copy(&*capturer, 0) {
-                Ok(result) => criterion::black_box(result),
+                Ok(result) => {
+                    criterion::black_box(result);
+                }
                 Err(_) => {
                     // Handle capture failure
                 }
@@ -130,6 +132,51 @@ fn bench_zero_copy_operations(c: &mut Criterion) {
     group.finish();
 }
 
+/// Compare the MIT-SHM and GetImage paths, e.g. under `Xvfb :99 -screen 0 3840x2160x24`
+#[cfg(target_os = "linux")]
+fn bench_x11_shm_capture(c: &mut Criterion) {
+    use capture::linux::X11Capture;
+
+    let mut group = c.benchmark_group("x11_shm_capture");
+
+    let regions = [
+        ("region_640x480", CaptureRegion::new(0, 0, 640, 480)),
+        ("region_1920x1080", CaptureRegion::new(0, 0, 1920, 1080)),
+        ("region_offset_800x600", CaptureRegion::new(123, 77, 800, 600)),
+    ];
+
+    for (use_shm, path) in [(true, "shm"), (false, "get_image")] {
+        let capture = match X11Capture::new() {
+            Ok(capture) => capture.with_shm(use_shm),
+            Err(_) => return, // Skip if no X server is available
+        };
+        if use_shm && !capture.uses_shm() {
+            continue; // Skip if the server lacks MIT-SHM
+        }
+
+        for (name, region) in regions {
+            group.throughput(Throughput::Elements(region.width as u64 * region.height as u64));
+            group.bench_with_input(BenchmarkId::new(path, name), &region, |b, &region| {
+                b.iter(|| {
+                    match capture.capture_region(region) {
+                        Ok(image) => {
+                            criterion::black_box(image);
+                        }
+                        Err(_) => {
+                            // Region larger than the screen
+                        }
+                    }
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bench_x11_shm_capture(_c: &mut Criterion) {}
+
 fn bench_multi_display_capture(c: &mut Criterion) {
     let mut group = c.benchmark_group("multi_display_capture");
 
@@ -184,7 +231,9 @@ fn bench_capture_with_config(c: &mut Criterion) {
 
             b.iter(|| {
                 match screenshot.capture_with_config(0, config.clone()) {
-                    Ok(result) => criterion::black_box(result),
+                    Ok(result) => {
+                        criterion::black_box(result);
+                    }
                     Err(_) => {
                         // Handle capture failure
                     }
@@ -211,10 +260,10 @@ fn bench_memory_pool_operations(c: &mut Criterion) {
 
         group.bench_with_input(BenchmarkId::new("allocate_buffer", format!("{}kb", size / 1024)), &size, |b, &buffer_size| {
             b.iter(|| {
-                match memory_pool::global_pool().allocate(buffer_size) {
+                match memory_pool::global_pool().acquire(buffer_size) {
                     Ok(buffer) => {
+                        // Dropping the buffer returns it to the pool
                         criterion::black_box(&buffer);
-                        memory_pool::global_pool().return_buffer(buffer);
                     }
                     Err(_) => {
                         // Handle allocation failure
@@ -227,22 +276,20 @@ fn bench_memory_pool_operations(c: &mut Criterion) {
             // Pre-allocate buffers to test reuse efficiency
             let mut buffers = Vec::new();
             for _ in 0..10 {
-                if let Ok(buffer) = memory_pool::global_pool().allocate(buffer_size) {
+                if let Ok(buffer) = memory_pool::global_pool().acquire(buffer_size) {
                     buffers.push(buffer);
                 }
             }
 
             // Return all buffers to pool
-            for buffer in buffers {
-                memory_pool::global_pool().return_buffer(buffer);
-            }
+            drop(buffers);
 
             b.iter(|| {
                 // This should reuse existing buffers
-                match memory_pool::global_pool().allocate(buffer_size) {
+                match memory_pool::global_pool().acquire(buffer_size) {
                     Ok(buffer) => {
+                        // Dropping the buffer returns it to the pool
                         criterion::black_box(&buffer);
-                        memory_pool::global_pool().return_buffer(buffer);
                     }
                     Err(_) => {
                         // Handle allocation failure
@@ -314,7 +361,9 @@ fn bench_convenience_functions(c: &mut Criterion) {
     group.bench_function("capture_primary_display", |b| {
         b.iter(|| {
             match capture_primary_display() {
-                Ok(result) => criterion::black_box(result),
+                Ok(result) => {
+                    criterion::black_box(result);
+                }
                 Err(_) => {
                     // Handle capture failure
                 }
@@ -327,7 +376,9 @@ fn bench_convenience_functions(c: &mut Criterion) {
         group.bench_with_input(BenchmarkId::new("capture_with_quality", quality), &quality, |b, &q| {
             b.iter(|| {
                 match capture_with_quality(0, q) {
-                    Ok(result) => criterion::black_box(result),
+                    Ok(result) => {
+                        criterion::black_box(result);
+                    }
                     Err(_) => {
                         // Handle capture failure
                     }
@@ -399,6 +450,7 @@ criterion_group!(
     bench_display_enumeration,
     bench_capturer_creation,
     bench_zero_copy_operations,
+    bench_x11_shm_capture,
     bench_multi_display_capture,
     bench_capture_with_config,
     bench_memory_pool_operations,
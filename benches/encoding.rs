@@ -4,6 +4,7 @@
 
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use std::time::Duration;
+use webp_screenshot_rust::pipeline::streaming::StreamingConfig;
 use webp_screenshot_rust::*;
 
 fn bench_webp_encoding_quality_levels(c: &mut Criterion) {
@@ -321,9 +322,10 @@ fn bench_encoding_streaming(c: &mut Criterion) {
             },
             use_zero_copy: false,
             use_gpu: false,
+            ..Default::default()
         };
 
-        let pipeline = StreamingPipeline::new(Box::new(capturer), config);
+        let pipeline = StreamingPipeline::new(capturer, config);
 
         b.iter(|| {
             // Simulate streaming encoding by starting and immediately stopping
@@ -553,12 +555,7 @@ fn create_test_image(width: u32, height: u32, pattern: ImagePattern) -> RawImage
         }
     }
 
-    RawImage {
-        data,
-        width,
-        height,
-        format: PixelFormat::RGBA,
-    }
+    RawImage::new(data, width, height, PixelFormat::RGBA8)
 }
 
 fn create_test_image_bgra(width: u32, height: u32) -> RawImage {
@@ -574,12 +571,7 @@ fn create_test_image_bgra(width: u32, height: u32) -> RawImage {
         }
     }
 
-    RawImage {
-        data,
-        width,
-        height,
-        format: PixelFormat::BGRA,
-    }
+    RawImage::new(data, width, height, PixelFormat::BGRA8)
 }
 
 fn create_test_image_with_alpha(width: u32, height: u32) -> RawImage {
@@ -597,12 +589,7 @@ fn create_test_image_with_alpha(width: u32, height: u32) -> RawImage {
         }
     }
 
-    RawImage {
-        data,
-        width,
-        height,
-        format: PixelFormat::RGBA,
-    }
+    RawImage::new(data, width, height, PixelFormat::RGBA8)
 }
 
 criterion_group!(
@@ -0,0 +1,183 @@
+//! Backend-independent cursor compositing
+//!
+//! Backends report the cursor shape and pointer position through
+//! [`ScreenCapture::capture_cursor`](super::ScreenCapture::capture_cursor); this
+//! module blends it into a captured image. Cursor pixels are premultiplied, so
+//! blending is a plain Porter-Duff "over" without dividing by the cursor alpha.
+
+use crate::types::{CursorImage, PixelFormat, RawImage, Rectangle};
+
+/// Blend a cursor into an image captured from `region` (global coordinates)
+///
+/// The cursor is placed so its hotspot sits on the pointer position and is
+/// clipped against the image edges. When the image has a higher resolution than
+/// `region` (HiDPI), the pointer position is scaled accordingly. Returns whether
+/// the cursor overlaps the image.
+pub fn composite_cursor(image: &mut RawImage, cursor: &CursorImage, region: Rectangle) -> bool {
+    let cursor_size = cursor.width as usize * cursor.height as usize * 4;
+    if region.width == 0 || region.height == 0 || cursor.data.len() < cursor_size {
+        return false;
+    }
+
+    let (r, g, b, a, bpp) = match image.format {
+        PixelFormat::RGBA8 => (0, 1, 2, Some(3), 4),
+        PixelFormat::BGRA8 => (2, 1, 0, Some(3), 4),
+        PixelFormat::RGB8 => (0, 1, 2, None, 3),
+        PixelFormat::BGR8 => (2, 1, 0, None, 3),
+        PixelFormat::Gray8 | PixelFormat::GrayA8 => return false,
+    };
+
+    // Cursor top-left in image pixels
+    let scale_x = image.width as f64 / region.width as f64;
+    let scale_y = image.height as f64 / region.height as f64;
+    let left = ((cursor.x - region.x) as f64 * scale_x).round() as i64 - cursor.hotspot_x as i64;
+    let top = ((cursor.y - region.y) as f64 * scale_y).round() as i64 - cursor.hotspot_y as i64;
+
+    // Clip the cursor rectangle to the image
+    let x_start = left.max(0);
+    let y_start = top.max(0);
+    let x_end = (left + cursor.width as i64).min(image.width as i64);
+    let y_end = (top + cursor.height as i64).min(image.height as i64);
+    if x_start >= x_end || y_start >= y_end {
+        return false;
+    }
+
+    for y in y_start..y_end {
+        let cursor_row = ((y - top) as usize * cursor.width as usize) * 4;
+        let image_row = y as usize * image.stride;
+        for x in x_start..x_end {
+            let src = &cursor.data[cursor_row + (x - left) as usize * 4..][..4];
+            if src[3] == 0 {
+                continue;
+            }
+
+            let offset = image_row + x as usize * bpp;
+            let Some(dst) = image.data.get_mut(offset..offset + bpp) else {
+                return true;
+            };
+            blend_over(src, dst, [r, g, b], a);
+        }
+    }
+
+    true
+}
+
+/// Blend a premultiplied RGBA source over a straight-alpha destination pixel
+fn blend_over(src: &[u8], dst: &mut [u8], channels: [usize; 3], alpha: Option<usize>) {
+    let src_alpha = src[3] as u32;
+    let dst_alpha = alpha.map_or(255, |a| dst[a] as u32);
+
+    // Destination coverage left after the cursor, then the combined coverage
+    let dst_weight = div255(dst_alpha * (255 - src_alpha));
+    let out_alpha = src_alpha + dst_weight;
+
+    for (channel, &index) in channels.iter().enumerate() {
+        let numerator = src[channel] as u32 * 255 + dst[index] as u32 * dst_weight;
+        dst[index] = ((numerator + out_alpha / 2) / out_alpha).min(255) as u8;
+    }
+    if let Some(a) = alpha {
+        dst[a] = out_alpha as u8;
+    }
+}
+
+/// Rounded division by 255
+fn div255(value: u32) -> u32 {
+    (value + 127) / 255
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_image(width: u32, height: u32, pixel: [u8; 4]) -> RawImage {
+        let data = pixel.repeat((width * height) as usize);
+        RawImage::new(data, width, height, PixelFormat::RGBA8)
+    }
+
+    /// 2x2 cursor: opaque red, half-transparent white (premultiplied), clear, opaque blue
+    fn test_cursor(x: i32, y: i32, hotspot: (u32, u32)) -> CursorImage {
+        CursorImage {
+            x,
+            y,
+            hotspot_x: hotspot.0,
+            hotspot_y: hotspot.1,
+            width: 2,
+            height: 2,
+            data: vec![255, 0, 0, 255, 128, 128, 128, 128, 0, 0, 0, 0, 0, 0, 255, 255],
+        }
+    }
+
+    #[test]
+    fn test_hotspot_offset() {
+        let mut image = solid_image(4, 4, [0, 0, 0, 255]);
+        let region = Rectangle::new(100, 100, 4, 4);
+
+        // Pointer at (102, 102) with hotspot (1, 1) puts the image origin at (1, 1)
+        assert!(composite_cursor(&mut image, &test_cursor(102, 102, (1, 1)), region));
+        assert_eq!(image.get_pixel(1, 1).unwrap(), &[255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(2, 2).unwrap(), &[0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(1, 2).unwrap(), &[0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 0).unwrap(), &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_premultiplied_blend() {
+        let mut image = solid_image(2, 1, [0, 0, 200, 255]);
+        assert!(composite_cursor(&mut image, &test_cursor(0, 0, (0, 0)), Rectangle::new(0, 0, 2, 1)));
+
+        // 50% white over blue: 128 + 200 * 127 / 255 = 228 for blue, 128 elsewhere
+        assert_eq!(image.get_pixel(1, 0).unwrap(), &[128, 128, 228, 255]);
+    }
+
+    #[test]
+    fn test_partial_overlap_is_clipped() {
+        let mut image = solid_image(3, 3, [0, 0, 0, 255]);
+
+        // Top-left of the cursor lies above and left of the region
+        assert!(composite_cursor(&mut image, &test_cursor(10, 10, (1, 1)), Rectangle::new(10, 10, 3, 3)));
+        assert_eq!(image.get_pixel(0, 0).unwrap(), &[0, 0, 255, 255]);
+
+        // Entirely outside
+        let mut untouched = solid_image(3, 3, [0, 0, 0, 255]);
+        assert!(!composite_cursor(&mut untouched, &test_cursor(20, 20, (0, 0)), Rectangle::new(10, 10, 3, 3)));
+        assert!(untouched.data.chunks(4).all(|pixel| pixel == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_padded_bgr_and_scaled_region() {
+        // 4x4 BGR8 image with 2 bytes of row padding, captured at 2x from a 2x2 region
+        let mut image = RawImage::with_stride(vec![0; 14 * 4], 4, 4, PixelFormat::BGR8, 14);
+        let cursor = CursorImage {
+            width: 1,
+            height: 1,
+            data: vec![255, 0, 0, 255],
+            ..test_cursor(11, 11, (0, 0))
+        };
+
+        assert!(composite_cursor(&mut image, &cursor, Rectangle::new(10, 10, 2, 2)));
+        assert_eq!(image.get_pixel(2, 2).unwrap(), &[0, 0, 255]);
+        assert_eq!(image.data.iter().filter(|&&byte| byte != 0).count(), 1);
+    }
+
+    #[test]
+    fn test_transparent_destination() {
+        let mut image = solid_image(1, 1, [0, 0, 0, 0]);
+        let cursor = CursorImage {
+            width: 1,
+            height: 1,
+            data: vec![64, 0, 0, 128],
+            ..test_cursor(0, 0, (0, 0))
+        };
+
+        composite_cursor(&mut image, &cursor, Rectangle::new(0, 0, 1, 1));
+        // Un-premultiplied onto a clear pixel: red 64 / 0.5 alpha = 128
+        assert_eq!(image.get_pixel(0, 0).unwrap(), &[128, 0, 0, 128]);
+    }
+
+    #[test]
+    fn test_from_argb32() {
+        let cursor = CursorImage::from_argb32(5, 6, 1, 2, 1, 1, &[0x80402010]);
+        assert_eq!(cursor.data, [0x40, 0x20, 0x10, 0x80]);
+        assert_eq!(cursor.bounds(), Rectangle::new(4, 4, 1, 1));
+    }
+}
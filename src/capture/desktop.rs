@@ -0,0 +1,220 @@
+//! Stitching per-display captures into one virtual desktop image
+//!
+//! Display bounds are logical pixels while captures may be denser on HiDPI
+//! displays, so every capture is resampled to a single reference scale, chosen
+//! from the measured capture densities, before being copied to its offset within
+//! the union of all bounds. Edges are rounded from the global coordinates, so
+//! adjacent displays tile without gaps. Areas no display covers keep the fill
+//! color.
+
+use crate::{
+    encoder::transform,
+    error::{EncodingError, EncodingResult},
+    types::{
+        DesktopOptions, DesktopScale, DisplayInfo, DisplayPlacement, ImageTransform, PixelFormat, RawImage, Rectangle,
+    },
+};
+
+/// Union of all display bounds
+pub fn desktop_bounds(displays: &[DisplayInfo]) -> Option<Rectangle> {
+    displays.iter().map(DisplayInfo::bounds).reduce(|union, bounds| union.union(&bounds))
+}
+
+/// Output pixels per logical pixel for `scale`
+pub fn reference_scale(displays: &[DisplayInfo], scale: DesktopScale) -> f32 {
+    let factors = displays.iter().map(|display| display.scale_factor).filter(|f| f.is_finite() && *f > 0.0);
+    match scale {
+        DesktopScale::Highest => factors.fold(None, |max: Option<f32>, f| Some(max.map_or(f, |max| max.max(f)))),
+        DesktopScale::Lowest => factors.fold(None, |min: Option<f32>, f| Some(min.map_or(f, |min| min.min(f)))),
+        DesktopScale::Primary => displays
+            .iter()
+            .find(|display| display.is_primary)
+            .or(displays.first())
+            .map(|display| display.scale_factor),
+        DesktopScale::Fixed(scale) => Some(scale),
+    }
+    .filter(|f| f.is_finite() && *f > 0.0)
+    .unwrap_or(1.0)
+}
+
+/// Composite display captures at their desktop positions into an RGBA image
+///
+/// Returns the image and where each display was placed in it. Fails before
+/// allocating if the canvas would exceed [`ImageTransform::MAX_OUTPUT_SIDE`] on
+/// either side.
+pub fn stitch(
+    captures: &[(DisplayInfo, RawImage)],
+    options: &DesktopOptions,
+) -> EncodingResult<(RawImage, Vec<DisplayPlacement>)> {
+    options.validate().map_err(EncodingError::InvalidConfiguration)?;
+    // Scale factors are only hints on some backends (X11 derives them from the
+    // DPI), so the density is measured from the captures themselves
+    let displays: Vec<DisplayInfo> = captures
+        .iter()
+        .map(|(display, image)| DisplayInfo {
+            scale_factor: match display.width {
+                0 => display.scale_factor,
+                width => image.width as f32 / width as f32,
+            },
+            ..display.clone()
+        })
+        .collect();
+    let union = desktop_bounds(&displays).ok_or(EncodingError::InvalidDimensions { width: 0, height: 0 })?;
+    let scale = reference_scale(&displays, options.scale) as f64;
+
+    let to_pixels = |offset: i64| (offset as f64 * scale).round() as i64;
+    let (width, height) = (to_pixels(union.width as i64), to_pixels(union.height as i64));
+    let too_large = || EncodingError::InvalidDimensions {
+        width: width.clamp(0, u32::MAX as i64) as u32,
+        height: height.clamp(0, u32::MAX as i64) as u32,
+    };
+    let width = u32::try_from(width).ok().filter(|&w| w > 0).ok_or_else(too_large)?;
+    let height = u32::try_from(height).ok().filter(|&h| h > 0).ok_or_else(too_large)?;
+    if width > ImageTransform::MAX_OUTPUT_SIDE || height > ImageTransform::MAX_OUTPUT_SIDE {
+        return Err(EncodingError::InvalidConfiguration(format!(
+            "Stitched desktop size {}x{} exceeds {} pixels per side",
+            width,
+            height,
+            ImageTransform::MAX_OUTPUT_SIDE
+        )));
+    }
+    let row_size = (width as usize).checked_mul(4).ok_or_else(too_large)?;
+    let len = row_size.checked_mul(height as usize).ok_or_else(too_large)?;
+
+    let mut canvas = options.fill.repeat(len / 4);
+    let mut placements = Vec::with_capacity(captures.len());
+
+    for (display, image) in captures {
+        let bounds = display.bounds();
+        let left = to_pixels(bounds.x as i64 - union.x as i64);
+        let top = to_pixels(bounds.y as i64 - union.y as i64);
+        let right = to_pixels(bounds.x as i64 + bounds.width as i64 - union.x as i64);
+        let bottom = to_pixels(bounds.y as i64 + bounds.height as i64 - union.y as i64);
+        let area = Rectangle::new(left as i32, top as i32, (right - left) as u32, (bottom - top) as u32);
+        if area.width == 0 || area.height == 0 || image.width == 0 || image.height == 0 {
+            continue;
+        }
+
+        let source_scale = image.width as f32 / bounds.width as f32;
+        let resized;
+        let image = if (image.width, image.height) == (area.width, area.height) {
+            image
+        } else {
+            resized = transform::resize(image, area.width, area.height, options.filter)?;
+            &resized
+        };
+        let rgba = image.to_packed_rgba()?;
+
+        let area_row = area.width as usize * 4;
+        for (y, row) in rgba.chunks_exact(area_row).enumerate() {
+            let start = (area.y as usize + y) * row_size + area.x as usize * 4;
+            canvas[start..start + area_row].copy_from_slice(row);
+        }
+
+        placements.push(DisplayPlacement {
+            display_index: display.index,
+            display_id: display.id.clone(),
+            bounds,
+            area,
+            source_scale,
+        });
+    }
+
+    Ok((RawImage::new(canvas, width, height, PixelFormat::RGBA8), placements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(index: usize, bounds: Rectangle, scale_factor: f32) -> DisplayInfo {
+        DisplayInfo {
+            index,
+            x: bounds.x,
+            y: bounds.y,
+            width: bounds.width,
+            height: bounds.height,
+            scale_factor,
+            is_primary: index == 0,
+            ..Default::default()
+        }
+    }
+
+    fn solid(width: u32, height: u32, bgra: [u8; 4]) -> RawImage {
+        RawImage::new(bgra.repeat((width * height) as usize), width, height, PixelFormat::BGRA8)
+    }
+
+    fn pixel(image: &RawImage, x: u32, y: u32) -> [u8; 4] {
+        image.get_pixel(x, y).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_reference_scale() {
+        let displays = [
+            display(0, Rectangle::new(0, 0, 100, 100), 1.0),
+            display(1, Rectangle::new(100, 0, 100, 100), 2.0),
+        ];
+        assert_eq!(reference_scale(&displays, DesktopScale::Highest), 2.0);
+        assert_eq!(reference_scale(&displays, DesktopScale::Lowest), 1.0);
+        assert_eq!(reference_scale(&displays, DesktopScale::Primary), 1.0);
+        assert_eq!(reference_scale(&displays, DesktopScale::Fixed(0.5)), 0.5);
+        assert_eq!(reference_scale(&[], DesktopScale::Highest), 1.0);
+    }
+
+    #[test]
+    fn test_stitch_places_displays_and_fills_gaps() {
+        // A 1x display left of a 2x display that sits lower, leaving a gap above and below
+        let captures = vec![
+            (display(0, Rectangle::new(-40, 0, 40, 30), 1.0), solid(40, 30, [0, 0, 255, 255])),
+            (display(1, Rectangle::new(0, 10, 20, 10), 2.0), solid(40, 20, [0, 255, 0, 255])),
+        ];
+        let options = DesktopOptions { fill: [1, 2, 3, 4], ..Default::default() };
+
+        let (image, layout) = stitch(&captures, &options).unwrap();
+        assert_eq!((image.width, image.height, image.format), (120, 60, PixelFormat::RGBA8));
+        assert_eq!(layout[0].area, Rectangle::new(0, 0, 80, 60));
+        assert_eq!(layout[0].source_scale, 1.0);
+        assert_eq!(layout[1].area, Rectangle::new(80, 20, 40, 20));
+        assert_eq!(layout[1].source_scale, 2.0);
+
+        assert_eq!(pixel(&image, 79, 59), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 80, 20), [0, 255, 0, 255]);
+        assert_eq!(pixel(&image, 119, 39), [0, 255, 0, 255]);
+        assert_eq!(pixel(&image, 100, 0), [1, 2, 3, 4]);
+        assert_eq!(pixel(&image, 100, 59), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_stitch_measures_density() {
+        // A DPI-derived hint, as X11 reports, with the capture at one pixel per unit
+        let captures = vec![(display(0, Rectangle::new(0, 0, 30, 20), 2.0), solid(30, 20, [0; 4]))];
+        let (image, layout) = stitch(&captures, &DesktopOptions::default()).unwrap();
+        assert_eq!((image.width, image.height), (30, 20));
+        assert_eq!(layout[0].source_scale, 1.0);
+    }
+
+    #[test]
+    fn test_stitch_rejects_bad_input() {
+        assert!(stitch(&[], &DesktopOptions::default()).is_err());
+
+        let captures = vec![(display(0, Rectangle::new(0, 0, 10, 10), 1.0), solid(10, 10, [0; 4]))];
+        let options = DesktopOptions { scale: DesktopScale::Fixed(0.0), ..Default::default() };
+        assert!(stitch(&captures, &options).is_err());
+    }
+
+    #[test]
+    fn test_stitch_rejects_oversized_layout() {
+        // Two small displays far apart span more than WebP can hold
+        let captures = vec![
+            (display(0, Rectangle::new(0, 0, 10, 10), 1.0), solid(10, 10, [0; 4])),
+            (display(1, Rectangle::new(20000, 0, 10, 10), 1.0), solid(10, 10, [0; 4])),
+        ];
+        let result = stitch(&captures, &DesktopOptions::default());
+        assert!(matches!(result, Err(EncodingError::InvalidConfiguration(_))));
+
+        // A layout that fits at 1x is too large when stitched at a higher scale
+        let captures = vec![(display(0, Rectangle::new(0, 0, 10000, 10), 1.0), solid(10, 10, [0; 4]))];
+        let options = DesktopOptions { scale: DesktopScale::Fixed(2.0), ..Default::default() };
+        assert!(matches!(stitch(&captures, &options), Err(EncodingError::InvalidConfiguration(_))));
+    }
+}
@@ -0,0 +1,161 @@
+//! Monitor identification from EDID blocks
+//!
+//! Only the 128-byte base block is read: the vendor and product IDs, the serial
+//! number, the physical size and the name and serial string descriptors. These
+//! identify a monitor regardless of the connector it is plugged into.
+
+/// Length of the EDID base block
+pub const BLOCK_SIZE: usize = 128;
+
+const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+/// Display descriptor tags
+const TAG_SERIAL: u8 = 0xFF;
+const TAG_NAME: u8 = 0xFC;
+
+/// Monitor identity read from an EDID base block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+    /// Three-letter PNP manufacturer ID
+    pub manufacturer: String,
+    /// Manufacturer's product code
+    pub product_code: u16,
+    /// Numeric serial number, 0 when unset
+    pub serial_number: u32,
+    /// Monitor name descriptor
+    pub name: Option<String>,
+    /// Serial number descriptor
+    pub serial: Option<String>,
+    /// Physical width and height in centimeters, 0 when unknown
+    pub size_cm: (u8, u8),
+}
+
+impl Edid {
+    /// Parse the base block, returning `None` if it is truncated or corrupt
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let block = data.get(..BLOCK_SIZE)?;
+        if block[..8] != HEADER || block.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+            return None;
+        }
+
+        let vendor = u16::from_be_bytes([block[8], block[9]]);
+        let manufacturer = [10, 5, 0]
+            .iter()
+            .map(|shift| match (vendor >> shift) & 0x1F {
+                letter @ 1..=26 => Some((b'A' + letter as u8 - 1) as char),
+                _ => None,
+            })
+            .collect::<Option<String>>()?;
+
+        let mut edid = Edid {
+            manufacturer,
+            product_code: u16::from_le_bytes([block[10], block[11]]),
+            serial_number: u32::from_le_bytes([block[12], block[13], block[14], block[15]]),
+            name: None,
+            serial: None,
+            size_cm: (block[21], block[22]),
+        };
+        for descriptor in block[54..126].chunks_exact(18) {
+            // Detailed timings start with a non-zero pixel clock
+            if descriptor[..2] != [0, 0] {
+                continue;
+            }
+            match descriptor[3] {
+                TAG_NAME => edid.name = descriptor_text(&descriptor[5..]),
+                TAG_SERIAL => edid.serial = descriptor_text(&descriptor[5..]),
+                _ => {}
+            }
+        }
+        Some(edid)
+    }
+
+    /// Model name: the name descriptor, or the product code in hex
+    pub fn model(&self) -> String {
+        self.name.clone().unwrap_or_else(|| format!("{:04X}", self.product_code))
+    }
+
+    /// Serial number: the serial descriptor, or the numeric serial if set
+    pub fn serial_number(&self) -> Option<String> {
+        self.serial
+            .clone()
+            .or_else(|| (self.serial_number != 0).then(|| self.serial_number.to_string()))
+    }
+
+    /// Manufacturer, product and serial, e.g. `DEL-A0B1-7X4K2`; `None` without a serial
+    pub fn identity(&self) -> Option<String> {
+        self.serial_number()
+            .map(|serial| format!("{}-{:04X}-{}", self.manufacturer, self.product_code, serial))
+    }
+}
+
+/// Text of a string descriptor, which ends at a newline and is padded with spaces
+fn descriptor_text(data: &[u8]) -> Option<String> {
+    let end = data.iter().position(|&b| b == b'\n').unwrap_or(data.len());
+    let text: String = data[..end]
+        .iter()
+        .filter(|b| b.is_ascii_graphic() || **b == b' ')
+        .map(|&b| b as char)
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(serial_number: u32, descriptors: &[(u8, &str)]) -> Vec<u8> {
+        let mut data = vec![0u8; BLOCK_SIZE];
+        data[..8].copy_from_slice(&HEADER);
+        // "DEL": D=4, E=5, L=12
+        data[8..10].copy_from_slice(&((4u16 << 10) | (5 << 5) | 12).to_be_bytes());
+        data[10..12].copy_from_slice(&0xA0B1u16.to_le_bytes());
+        data[12..16].copy_from_slice(&serial_number.to_le_bytes());
+        data[21] = 60;
+        data[22] = 34;
+        // A detailed timing descriptor first, as real monitors have
+        data[54] = 0x02;
+        data[55] = 0x3A;
+        for (i, (tag, text)) in descriptors.iter().enumerate() {
+            let start = 72 + i * 18;
+            data[start + 3] = *tag;
+            let mut field = [b' '; 13];
+            field[..text.len()].copy_from_slice(text.as_bytes());
+            if text.len() < 13 {
+                field[text.len()] = b'\n';
+            }
+            data[start + 5..start + 18].copy_from_slice(&field);
+        }
+        let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        data[127] = 0u8.wrapping_sub(sum);
+        data
+    }
+
+    #[test]
+    fn test_parse_identity() {
+        let edid = Edid::parse(&block(0x1234, &[(TAG_NAME, "DELL U2720Q"), (TAG_SERIAL, "7X4K2")])).unwrap();
+        assert_eq!(edid.manufacturer, "DEL");
+        assert_eq!(edid.product_code, 0xA0B1);
+        assert_eq!(edid.model(), "DELL U2720Q");
+        assert_eq!(edid.serial_number().as_deref(), Some("7X4K2"));
+        assert_eq!(edid.size_cm, (60, 34));
+        assert_eq!(edid.identity().as_deref(), Some("DEL-A0B1-7X4K2"));
+
+        let bare = Edid::parse(&block(0, &[])).unwrap();
+        assert_eq!(bare.model(), "A0B1");
+        assert_eq!(bare.serial_number(), None);
+        assert_eq!(bare.identity(), None);
+        assert_eq!(Edid::parse(&block(42, &[])).unwrap().identity().as_deref(), Some("DEL-A0B1-42"));
+    }
+
+    #[test]
+    fn test_parse_rejects_corrupt_blocks() {
+        let mut data = block(1, &[]);
+        assert!(Edid::parse(&data[..100]).is_none());
+        data[20] ^= 1;
+        assert!(Edid::parse(&data).is_none());
+        data[20] ^= 1;
+        data[0] = 1;
+        assert!(Edid::parse(&data).is_none());
+    }
+}
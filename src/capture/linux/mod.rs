@@ -6,13 +6,26 @@ mod x11_capture;
 #[cfg(all(target_os = "linux", feature = "wayland"))]
 mod wayland_capture;
 
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+mod portal_capture;
+
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+pub use portal_capture::PortalCapture;
+
+#[cfg(target_os = "linux")]
+pub use x11_capture::{X11Capture, X11ChangeNotifier};
+
 #[cfg(target_os = "linux")]
 use crate::{
-    capture::traits::{CaptureCapabilities, ScreenCapture},
+    capture::traits::{CaptureCapabilities, ChangeNotifier, ScreenCapture},
     error::{CaptureError, CaptureResult},
-    types::{CaptureRegion, DisplayInfo, RawImage},
+    memory_pool::MemoryPool,
+    types::{CaptureRegion, CursorImage, DisplayInfo, RawImage, WindowId, WindowInfo, WindowRedaction},
 };
 
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
+
 /// Linux screen capture implementation
 #[cfg(target_os = "linux")]
 pub struct LinuxCapture {
@@ -24,6 +37,8 @@ enum LinuxBackend {
     X11(x11_capture::X11Capture),
     #[cfg(feature = "wayland")]
     Wayland(wayland_capture::WaylandCapture),
+    #[cfg(feature = "dbus")]
+    Portal(portal_capture::PortalCapture),
 }
 
 #[cfg(target_os = "linux")]
@@ -48,6 +63,18 @@ impl LinuxCapture {
             }
         }
 
+        // Compositors without wlr-screencopy (GNOME, KDE) go through the portal
+        #[cfg(feature = "dbus")]
+        {
+            if std::env::var("WAYLAND_DISPLAY").is_ok() {
+                if let Ok(portal) = portal_capture::PortalCapture::new() {
+                    if portal.is_available() {
+                        return Ok(LinuxBackend::Portal(portal));
+                    }
+                }
+            }
+        }
+
         // Check for X11 session
         if std::env::var("DISPLAY").is_ok() {
             let x11 = x11_capture::X11Capture::new()?;
@@ -58,6 +85,15 @@ impl LinuxCapture {
             "No X11 or Wayland display found".to_string(),
         ))
     }
+
+    /// Wayland clients cannot read other clients' windows
+    #[cfg(any(feature = "wayland", feature = "dbus"))]
+    fn window_capture_unsupported(&self) -> CaptureError {
+        CaptureError::PlatformError(format!(
+            "{} does not support window capture",
+            self.implementation_name()
+        ))
+    }
 }
 
 #[cfg(target_os = "linux")]
@@ -67,6 +103,8 @@ impl ScreenCapture for LinuxCapture {
             LinuxBackend::X11(x11) => x11.get_displays(),
             #[cfg(feature = "wayland")]
             LinuxBackend::Wayland(wayland) => wayland.get_displays(),
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(portal) => portal.get_displays(),
         }
     }
 
@@ -75,6 +113,8 @@ impl ScreenCapture for LinuxCapture {
             LinuxBackend::X11(x11) => x11.capture_display(display_index),
             #[cfg(feature = "wayland")]
             LinuxBackend::Wayland(wayland) => wayland.capture_display(display_index),
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(portal) => portal.capture_display(display_index),
         }
     }
 
@@ -83,6 +123,88 @@ impl ScreenCapture for LinuxCapture {
             LinuxBackend::X11(x11) => x11.capture_region(region),
             #[cfg(feature = "wayland")]
             LinuxBackend::Wayland(wayland) => wayland.capture_region(region),
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(portal) => portal.capture_region(region),
+        }
+    }
+
+    fn capture_cursor(&self) -> CaptureResult<Option<CursorImage>> {
+        match &self.backend {
+            LinuxBackend::X11(x11) => x11.capture_cursor(),
+            // Wayland does not expose the pointer position to other clients
+            #[cfg(feature = "wayland")]
+            LinuxBackend::Wayland(_) => Ok(None),
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(_) => Ok(None),
+        }
+    }
+
+    fn list_windows(&self) -> CaptureResult<Vec<WindowInfo>> {
+        match &self.backend {
+            LinuxBackend::X11(x11) => x11.list_windows(),
+            #[cfg(feature = "wayland")]
+            LinuxBackend::Wayland(_) => Err(self.window_capture_unsupported()),
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(_) => Err(self.window_capture_unsupported()),
+        }
+    }
+
+    fn capture_window(&self, window: WindowId) -> CaptureResult<RawImage> {
+        match &self.backend {
+            LinuxBackend::X11(x11) => x11.capture_window(window),
+            #[cfg(feature = "wayland")]
+            LinuxBackend::Wayland(_) => Err(self.window_capture_unsupported()),
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(_) => Err(self.window_capture_unsupported()),
+        }
+    }
+
+    fn set_window_redactions(&self, rules: Vec<WindowRedaction>) -> CaptureResult<()> {
+        match &self.backend {
+            LinuxBackend::X11(x11) => {
+                x11.set_window_redactions(rules);
+                Ok(())
+            }
+            #[cfg(feature = "wayland")]
+            LinuxBackend::Wayland(_) if !rules.is_empty() => Err(self.window_capture_unsupported()),
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(_) if !rules.is_empty() => Err(self.window_capture_unsupported()),
+            #[cfg(any(feature = "wayland", feature = "dbus"))]
+            _ => Ok(()),
+        }
+    }
+
+    fn set_cursor_overlay(&self, enabled: bool) {
+        match (&self.backend, enabled) {
+            // X11 reports the cursor for the caller to composite
+            (LinuxBackend::X11(_), _) => {}
+            // The compositor draws the cursor it won't report
+            #[cfg(feature = "wayland")]
+            (LinuxBackend::Wayland(wayland), enabled) => wayland.set_overlay_cursor(enabled),
+            #[cfg(feature = "dbus")]
+            (LinuxBackend::Portal(_), _) => {}
+        }
+    }
+
+    fn set_memory_pool(&self, pool: Arc<MemoryPool>) {
+        match &self.backend {
+            LinuxBackend::X11(x11) => x11.set_memory_pool(pool),
+            #[cfg(feature = "wayland")]
+            LinuxBackend::Wayland(wayland) => wayland.set_memory_pool(pool),
+            // Screenshots arrive as PNG files, decoded by the image crate
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(_) => {}
+        }
+    }
+
+    fn change_notifier(&self) -> CaptureResult<Option<Box<dyn ChangeNotifier>>> {
+        match &self.backend {
+            LinuxBackend::X11(_) => Ok(Some(Box::new(x11_capture::X11ChangeNotifier::new()?))),
+            #[cfg(feature = "wayland")]
+            LinuxBackend::Wayland(_) => Ok(Some(Box::new(wayland_capture::WaylandChangeNotifier::new()?))),
+            // The portal only hands out screenshots of the whole desktop
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(_) => Ok(None),
         }
     }
 
@@ -91,18 +213,39 @@ impl ScreenCapture for LinuxCapture {
             LinuxBackend::X11(_) => "Linux X11".to_string(),
             #[cfg(feature = "wayland")]
             LinuxBackend::Wayland(_) => "Linux Wayland".to_string(),
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(_) => "Linux xdg-desktop-portal".to_string(),
         }
     }
 
     fn capabilities(&self) -> CaptureCapabilities {
-        CaptureCapabilities {
-            supports_cursor: true,
-            supports_window_capture: true,
-            supports_hdr: false,
-            max_resolution: (0, 0), // No limit
-            supports_multi_display: true,
-            supports_gpu_acceleration: false,
-            estimated_latency_ms: 20,
+        match &self.backend {
+            LinuxBackend::X11(x11) => CaptureCapabilities {
+                supports_cursor: true,
+                supports_window_capture: true,
+                supports_hdr: false,
+                max_resolution: (0, 0), // No limit
+                supports_multi_display: true,
+                supports_gpu_acceleration: false,
+                estimated_latency_ms: 20,
+                // Zero-copy reads the screen directly, skipping window redaction
+                supports_zero_copy: !x11.redacts_windows(),
+            },
+            #[cfg(feature = "wayland")]
+            LinuxBackend::Wayland(_) => CaptureCapabilities {
+                // Composited by the compositor; the pointer position stays private
+                supports_cursor: true,
+                supports_window_capture: false,
+                supports_hdr: false,
+                max_resolution: (0, 0), // No limit
+                supports_multi_display: true,
+                supports_gpu_acceleration: false,
+                estimated_latency_ms: 30,
+                // The zero-copy optimizer uses X11 MIT-SHM
+                supports_zero_copy: false,
+            },
+            #[cfg(feature = "dbus")]
+            LinuxBackend::Portal(portal) => portal.capabilities(),
         }
     }
 }
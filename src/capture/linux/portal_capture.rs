@@ -0,0 +1,579 @@
+//! xdg-desktop-portal Screenshot backend
+//!
+//! Used on Wayland compositors without wlr-screencopy (GNOME, KDE). The request
+//! is issued on `org.freedesktop.portal.Screenshot`, the portal answers with a
+//! `Response` signal on the request object carrying the URI of the saved
+//! screenshot, which is loaded and then removed if the portal saved it in a
+//! temporary or runtime directory. Some portal backends save into the user's own
+//! directories, where the file is left alone.
+
+use crate::{
+    capture::traits::{CaptureCapabilities, ScreenCapture},
+    error::{CaptureError, CaptureResult},
+    types::{CaptureRegion, DisplayId, DisplayInfo, PixelFormat, RawImage, Rectangle},
+};
+
+use dbus::{
+    arg::{PropMap, RefArg, Variant},
+    blocking::Connection,
+    channel::Channel,
+    message::MatchRule,
+    strings::Path,
+};
+use parking_lot::Mutex;
+use std::{
+    path::{Path as FsPath, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+const PORTAL_BUS_NAME: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+const SCREENSHOT_INTERFACE: &str = "org.freedesktop.portal.Screenshot";
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+
+/// Portal response codes
+const RESPONSE_SUCCESS: u32 = 0;
+const RESPONSE_CANCELLED: u32 = 1;
+
+/// Screenshot capture through xdg-desktop-portal
+pub struct PortalCapture {
+    connection: Mutex<Connection>,
+    interactive: bool,
+    timeout: Duration,
+    /// Size of the most recent screenshot, reported as the single portal display
+    last_size: Mutex<Option<(u32, u32)>>,
+    request_counter: AtomicU64,
+}
+
+/// Outcome delivered by the `Response` signal
+struct PortalResponse {
+    code: u32,
+    uri: Option<String>,
+}
+
+impl PortalCapture {
+    /// Connect to the portal on the session bus
+    pub fn new() -> CaptureResult<Self> {
+        let connection = Connection::new_session().map_err(|e| {
+            CaptureError::PlatformError(format!("Failed to connect to D-Bus: {}", e))
+        })?;
+        Ok(Self::from_connection(connection))
+    }
+
+    /// Connect to the portal on the bus at `address`, e.g. a private test bus
+    pub fn with_address(address: &str) -> CaptureResult<Self> {
+        let mut channel = Channel::open_private(address).map_err(|e| {
+            CaptureError::PlatformError(format!("Failed to open D-Bus at {}: {}", address, e))
+        })?;
+        channel.register().map_err(|e| {
+            CaptureError::PlatformError(format!("Failed to register on D-Bus: {}", e))
+        })?;
+        Ok(Self::from_connection(Connection::from(channel)))
+    }
+
+    fn from_connection(connection: Connection) -> Self {
+        Self {
+            connection: Mutex::new(connection),
+            interactive: false,
+            timeout: Duration::from_secs(60),
+            last_size: Mutex::new(None),
+            request_counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Let the user pick the area to capture in the portal dialog
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Set how long to wait for the portal to answer, including user interaction
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Request a screenshot of the whole desktop
+    pub fn screenshot(&self) -> CaptureResult<RawImage> {
+        let connection = self.connection.lock();
+
+        let token = format!(
+            "webp_screenshot_{}_{}",
+            std::process::id(),
+            self.request_counter.fetch_add(1, Ordering::Relaxed)
+        );
+        let handle = request_path(&connection.unique_name(), &token)?;
+
+        // Subscribe before calling so a fast portal cannot answer before we listen
+        let response = Arc::new(Mutex::new(None));
+        let mut subscription = Self::subscribe(&connection, handle.clone(), &response)?;
+
+        let mut options = PropMap::new();
+        options.insert("handle_token".to_string(), Variant(Box::new(token)));
+        options.insert("interactive".to_string(), Variant(Box::new(self.interactive)));
+        options.insert("modal".to_string(), Variant(Box::new(self.interactive)));
+
+        let proxy = connection.with_proxy(PORTAL_BUS_NAME, PORTAL_OBJECT_PATH, self.timeout);
+        let call: Result<(Path<'static>,), _> =
+            proxy.method_call(SCREENSHOT_INTERFACE, "Screenshot", ("", options));
+        let request = match call {
+            Ok((request,)) => request,
+            Err(e) => {
+                let _ = connection.remove_match(subscription);
+                return Err(map_dbus_error(e));
+            }
+        };
+
+        // Portals older than 0.9 ignore handle_token and pick their own path
+        if request != handle {
+            let _ = connection.remove_match(subscription);
+            subscription = Self::subscribe(&connection, request, &response)?;
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        let response = loop {
+            if let Some(response) = response.lock().take() {
+                break response;
+            }
+            if Instant::now() >= deadline {
+                let _ = connection.remove_match(subscription);
+                return Err(CaptureError::CaptureTimeout {
+                    timeout_ms: self.timeout.as_millis() as u64,
+                });
+            }
+            connection
+                .process(Duration::from_millis(100))
+                .map_err(map_dbus_error)?;
+        };
+
+        let image = match response.code {
+            RESPONSE_SUCCESS => {
+                let uri = response.uri.ok_or_else(|| {
+                    CaptureError::CaptureFailed("Portal response did not include a URI".to_string())
+                })?;
+                load_screenshot(&uri)?
+            }
+            RESPONSE_CANCELLED => {
+                return Err(CaptureError::PermissionDenied(
+                    "Screenshot request was cancelled".to_string(),
+                ))
+            }
+            code => {
+                return Err(CaptureError::CaptureFailed(format!(
+                    "Portal screenshot request failed with response {}",
+                    code
+                )))
+            }
+        };
+
+        *self.last_size.lock() = Some((image.width, image.height));
+        Ok(image)
+    }
+
+    /// Listen for the `Response` signal on a request object
+    fn subscribe(
+        connection: &Connection,
+        request: Path<'static>,
+        slot: &Arc<Mutex<Option<PortalResponse>>>,
+    ) -> CaptureResult<dbus::channel::Token> {
+        let slot = Arc::clone(slot);
+        let rule = MatchRule::new_signal(REQUEST_INTERFACE, "Response").with_path(request);
+
+        connection
+            .add_match(rule, move |(code, results): (u32, PropMap), _, _| {
+                let uri = results
+                    .get("uri")
+                    .and_then(|value| value.0.as_str())
+                    .map(str::to_string);
+                *slot.lock() = Some(PortalResponse { code, uri });
+                false
+            })
+            .map_err(map_dbus_error)
+    }
+
+    /// Check whether the portal is running or can be activated on this bus
+    fn portal_present(&self) -> bool {
+        let connection = self.connection.lock();
+        let proxy = connection.with_proxy(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            Duration::from_millis(500),
+        );
+
+        let running: Result<(bool,), _> =
+            proxy.method_call("org.freedesktop.DBus", "NameHasOwner", (PORTAL_BUS_NAME,));
+        if matches!(running, Ok((true,))) {
+            return true;
+        }
+
+        let activatable: Result<(Vec<String>,), _> =
+            proxy.method_call("org.freedesktop.DBus", "ListActivatableNames", ());
+        activatable
+            .map(|(names,)| names.iter().any(|name| name == PORTAL_BUS_NAME))
+            .unwrap_or(false)
+    }
+}
+
+impl ScreenCapture for PortalCapture {
+    /// The portal only exposes the desktop as a whole, and its size is only
+    /// learned from a screenshot
+    ///
+    /// Enumerating never asks the portal for one, as that may prompt the user, so
+    /// the desktop is reported as 0x0 until the first capture. Regions can't be
+    /// resolved against it until then.
+    fn get_displays(&self) -> CaptureResult<Vec<DisplayInfo>> {
+        let (width, height) = self.last_size.lock().unwrap_or((0, 0));
+
+        Ok(vec![DisplayInfo {
+            index: 0,
+            name: "Portal Desktop".to_string(),
+            width,
+            height,
+            x: 0,
+            y: 0,
+            scale_factor: 1.0,
+            is_primary: true,
+            refresh_rate: 60,
+            color_depth: 32,
+            id: DisplayId("portal".to_string()),
+            ..Default::default()
+        }])
+    }
+
+    fn capture_display(&self, display_index: usize) -> CaptureResult<RawImage> {
+        if display_index != 0 {
+            return Err(CaptureError::DisplayNotFound(display_index));
+        }
+        self.screenshot()
+    }
+
+    fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
+        let image = self.screenshot()?;
+        image
+            .crop(Rectangle::new(region.x, region.y, region.width, region.height))
+            .ok_or_else(|| {
+                CaptureError::CaptureFailed(format!("Region {:?} is outside the desktop", region))
+            })
+    }
+
+    fn implementation_name(&self) -> String {
+        "xdg-desktop-portal".to_string()
+    }
+
+    fn is_available(&self) -> bool {
+        self.portal_present()
+    }
+
+    fn capabilities(&self) -> CaptureCapabilities {
+        CaptureCapabilities {
+            supports_cursor: false,
+            supports_window_capture: false,
+            supports_hdr: false,
+            max_resolution: (0, 0),
+            supports_multi_display: false,
+            supports_gpu_acceleration: false,
+            estimated_latency_ms: 200,
+            supports_zero_copy: false,
+        }
+    }
+}
+
+/// Object path the portal uses for a request made with `handle_token`
+fn request_path(unique_name: &str, token: &str) -> CaptureResult<Path<'static>> {
+    let sender = unique_name.trim_start_matches(':').replace('.', "_");
+    Path::new(format!("{}/request/{}/{}", PORTAL_OBJECT_PATH, sender, token))
+        .map_err(|e| CaptureError::CaptureFailed(format!("Invalid request path: {}", e)))
+}
+
+/// Load the screenshot the portal saved, removing the file if it is temporary
+fn load_screenshot(uri: &str) -> CaptureResult<RawImage> {
+    let path = uri
+        .strip_prefix("file://")
+        .map(percent_decode)
+        .ok_or_else(|| {
+            CaptureError::CaptureFailed(format!("Unsupported screenshot URI: {}", uri))
+        })?;
+
+    let decoded = image::open(&path)
+        .map_err(|e| CaptureError::CaptureFailed(format!("Failed to load {}: {}", path, e)))?
+        .into_rgba8();
+    if is_temporary(FsPath::new(&path), &temporary_dirs()) {
+        if let Err(e) = std::fs::remove_file(&path) {
+            log::debug!("Failed to remove portal screenshot {}: {}", path, e);
+        }
+    }
+
+    let (width, height) = decoded.dimensions();
+    Ok(RawImage::new(decoded.into_raw(), width, height, PixelFormat::RGBA8))
+}
+
+/// Directories whose files are ours to clean up: the temp and runtime directories
+fn temporary_dirs() -> Vec<PathBuf> {
+    let runtime = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    std::iter::once(std::env::temp_dir()).chain(runtime).collect()
+}
+
+/// Whether `path` is inside one of `dirs`, after resolving symlinks and `..`
+fn is_temporary(path: &FsPath, dirs: &[PathBuf]) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    dirs.iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| path.starts_with(dir))
+}
+
+/// Decode `%XX` escapes in a file URI path
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Map D-Bus errors onto capture errors
+fn map_dbus_error(error: dbus::Error) -> CaptureError {
+    match error.name() {
+        Some("org.freedesktop.DBus.Error.AccessDenied") => {
+            CaptureError::PermissionDenied(error.message().unwrap_or_default().to_string())
+        }
+        Some("org.freedesktop.DBus.Error.ServiceUnknown") => {
+            CaptureError::PlatformError("xdg-desktop-portal is not running".to_string())
+        }
+        _ => CaptureError::CaptureFailed(format!("Portal D-Bus error: {}", error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dbus::{channel::MatchingReceiver, message::Message};
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::atomic::AtomicBool,
+        thread,
+    };
+
+    /// A private dbus-daemon that is killed when dropped
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+            Some(Self {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    /// Minimal portal that answers every Screenshot call with `code`, saving a
+    /// 4x2 test image for successful responses
+    struct MockPortal {
+        running: Arc<AtomicBool>,
+        thread: Option<thread::JoinHandle<()>>,
+        _dir: tempfile::TempDir,
+    }
+
+    impl MockPortal {
+        fn start(address: &str, code: u32) -> Self {
+            let mut channel = Channel::open_private(address).unwrap();
+            channel.register().unwrap();
+            let connection = Connection::from(channel);
+            connection
+                .request_name(PORTAL_BUS_NAME, false, true, true)
+                .unwrap();
+
+            let dir = tempfile::tempdir().unwrap();
+            let file = dir.path().join("screen shot.png");
+            let uri = format!("file://{}", file.display()).replace(' ', "%20");
+
+            connection.start_receive(
+                MatchRule::new_method_call(),
+                Box::new(move |message: Message, connection: &Connection| {
+                    if message.member().as_deref() != Some("Screenshot") {
+                        return true;
+                    }
+
+                    let (_parent, options): (String, PropMap) = message.read2().unwrap();
+                    let token = options["handle_token"].0.as_str().unwrap().to_string();
+                    let sender = message.sender().unwrap().to_string();
+                    let path = request_path(&sender, &token).unwrap();
+                    connection
+                        .channel()
+                        .send(message.method_return().append1(path.clone()))
+                        .unwrap();
+
+                    let mut results = PropMap::new();
+                    if code == RESPONSE_SUCCESS {
+                        // Saved in the temp directory, so the client removes it after loading
+                        let pixels = (0..8u8).flat_map(|i| [i * 30, 255 - i * 30, 7, 255]).collect();
+                        image::RgbaImage::from_raw(4, 2, pixels)
+                            .unwrap()
+                            .save(&file)
+                            .unwrap();
+                        results.insert("uri".to_string(), Variant(Box::new(uri.clone())));
+                    }
+                    let signal = Message::new_signal(path.to_string(), REQUEST_INTERFACE, "Response")
+                        .unwrap()
+                        .append2(code, results);
+                    connection.channel().send(signal).unwrap();
+                    true
+                }),
+            );
+
+            let running = Arc::new(AtomicBool::new(true));
+            let flag = Arc::clone(&running);
+            let thread = thread::spawn(move || {
+                while flag.load(Ordering::Relaxed) {
+                    connection.process(Duration::from_millis(50)).unwrap();
+                }
+            });
+
+            Self {
+                running,
+                thread: Some(thread),
+                _dir: dir,
+            }
+        }
+    }
+
+    impl Drop for MockPortal {
+        fn drop(&mut self) {
+            self.running.store(false, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("/tmp/a%20b%2Fc.png"), "/tmp/a b/c.png");
+        assert_eq!(percent_decode("/tmp/100%"), "/tmp/100%");
+    }
+
+    #[test]
+    fn test_only_temporary_files_removable() {
+        let temp = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let file = temp.path().join("shot.png");
+        std::fs::write(&file, b"png").unwrap();
+
+        assert!(is_temporary(&file, &[temp.path().to_path_buf()]));
+        assert!(!is_temporary(&file, &[other.path().to_path_buf()]));
+        // Escaping the directory with `..` doesn't count as inside it
+        let escaped = other.path().join("..").join(temp.path().file_name().unwrap()).join("shot.png");
+        assert!(!is_temporary(&escaped, &[other.path().to_path_buf()]));
+        assert!(!is_temporary(&temp.path().join("missing.png"), &[temp.path().to_path_buf()]));
+    }
+
+    #[test]
+    fn test_request_path_from_unique_name() {
+        let path = request_path(":1.42", "token").unwrap();
+        assert_eq!(&*path, "/org/freedesktop/portal/desktop/request/1_42/token");
+    }
+
+    #[test]
+    fn test_screenshot_through_mock_portal() {
+        let Some(bus) = PrivateBus::start() else {
+            return;
+        };
+        let _portal = MockPortal::start(&bus.address, RESPONSE_SUCCESS);
+
+        let capture = PortalCapture::with_address(&bus.address)
+            .unwrap()
+            .with_timeout(Duration::from_secs(5));
+        assert!(capture.is_available());
+
+        // Enumerating doesn't take a screenshot to learn the size
+        let displays = capture.get_displays().unwrap();
+        assert_eq!((displays[0].width, displays[0].height), (0, 0));
+
+        let image = capture.capture_display(0).unwrap();
+        assert_eq!((image.width, image.height), (4, 2));
+        assert_eq!(image.format, PixelFormat::RGBA8);
+        assert_eq!(image.get_pixel(1, 0).unwrap(), &[30, 225, 7, 255]);
+
+        let displays = capture.get_displays().unwrap();
+        assert_eq!((displays[0].width, displays[0].height), (4, 2));
+
+        let region = capture
+            .capture_region(CaptureRegion::new(2, 1, 2, 1))
+            .unwrap();
+        assert_eq!(region.get_pixel(0, 0).unwrap(), &[180, 75, 7, 255]);
+    }
+
+    #[test]
+    fn test_cancellation_maps_to_permission_denied() {
+        let Some(bus) = PrivateBus::start() else {
+            return;
+        };
+        let _portal = MockPortal::start(&bus.address, RESPONSE_CANCELLED);
+
+        let capture = PortalCapture::with_address(&bus.address)
+            .unwrap()
+            .with_timeout(Duration::from_secs(5));
+        assert!(matches!(
+            capture.screenshot(),
+            Err(CaptureError::PermissionDenied(_))
+        ));
+    }
+
+    #[test]
+    fn test_missing_portal_is_unavailable() {
+        let Some(bus) = PrivateBus::start() else {
+            return;
+        };
+
+        let capture = PortalCapture::with_address(&bus.address).unwrap();
+        assert!(!capture.is_available());
+        assert!(matches!(
+            capture.screenshot(),
+            Err(CaptureError::PlatformError(_))
+        ));
+    }
+}
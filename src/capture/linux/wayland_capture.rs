@@ -1,39 +1,140 @@
 //! Wayland-based screen capture for Linux
 //!
-//! Note: Wayland screen capture is more restricted than X11 due to security model.
-//! Requires compositor support and user permission via xdg-desktop-portal.
+//! Outputs are enumerated through `wl_output` and `zxdg_output_manager_v1`, and
+//! frames are copied into shared-memory buffers with the wlroots
+//! `zwlr_screencopy_manager_v1` protocol (sway, cage, river, Hyprland, ...).
+//! Compositors without screencopy fail to initialize so that the caller can
+//! fall back to another backend.
+//!
+//! Display geometry follows the compositor's logical coordinate space: `x`, `y`,
+//! `width` and `height` are logical pixels, `scale_factor` is the ratio between
+//! the output's buffer and logical size, and captured images are returned at
+//! buffer resolution. The backend can be exercised headlessly with
+//! `WLR_BACKENDS=headless sway` and `cargo test --features wayland wayland`.
 
 #[cfg(feature = "wayland")]
 use crate::{
+    encoder::simd::global_simd_converter,
+    capture::traits::ChangeNotifier,
     error::{CaptureError, CaptureResult},
-    types::{CaptureRegion, DisplayInfo, PixelFormat, RawImage},
+    memory_pool::{global_pool, MemoryPool},
+    types::{CaptureRegion, DisplayId, DisplayInfo, DisplayRotation, PixelFormat, RawImage, Rectangle},
+};
+
+#[cfg(feature = "wayland")]
+use parking_lot::Mutex;
+#[cfg(feature = "wayland")]
+use std::{
+    fs::{File, OpenOptions},
+    os::{
+        fd::{AsFd, AsRawFd},
+        unix::fs::FileExt,
+    },
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 #[cfg(feature = "wayland")]
 use wayland_client::{
-    protocol::{wl_output, wl_registry},
-    Connection, Dispatch, QueueHandle,
+    delegate_noop,
+    protocol::{wl_buffer, wl_output, wl_registry, wl_shm, wl_shm_pool},
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
+#[cfg(feature = "wayland")]
+use wayland_protocols::xdg::xdg_output::zv1::client::{zxdg_output_manager_v1, zxdg_output_v1};
+#[cfg(feature = "wayland")]
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1, zwlr_screencopy_manager_v1,
 };
 
 /// Wayland capture implementation
 #[cfg(feature = "wayland")]
 pub struct WaylandCapture {
-    connection: Connection,
+    session: Mutex<Session>,
+    /// Have the compositor draw the cursor into display and region captures
+    overlay_cursor: AtomicBool,
+}
+
+/// Event queue and the protocol state it dispatches into
+#[cfg(feature = "wayland")]
+struct Session {
+    queue: EventQueue<WaylandState>,
+    state: WaylandState,
+    /// Pool the shm contents are read into before conversion
+    memory_pool: Arc<MemoryPool>,
+}
+
+#[cfg(feature = "wayland")]
+#[derive(Default)]
+struct WaylandState {
+    shm: Option<wl_shm::WlShm>,
+    screencopy: Option<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1>,
+    xdg_output_manager: Option<zxdg_output_manager_v1::ZxdgOutputManagerV1>,
     outputs: Vec<WaylandOutput>,
+    frame: FrameState,
 }
 
 #[cfg(feature = "wayland")]
 struct WaylandOutput {
+    /// Registry name, used as user data on the output proxies
+    global_name: u32,
     output: wl_output::WlOutput,
+    xdg_output: Option<zxdg_output_v1::ZxdgOutputV1>,
     name: String,
-    width: u32,
-    height: u32,
+    description: String,
+    make: String,
+    model: String,
+    /// Panel size in millimeters, before the transform
+    physical_size: (i32, i32),
+    mode_width: i32,
+    mode_height: i32,
+    refresh_mhz: i32,
     x: i32,
     y: i32,
+    transform: wl_output::Transform,
     scale: i32,
-    refresh_rate: u32,
+    logical_position: Option<(i32, i32)>,
+    logical_size: Option<(i32, i32)>,
+}
+
+/// Buffer parameters offered by the compositor for a screencopy frame
+#[cfg(feature = "wayland")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BufferSpec {
+    format: wl_shm::Format,
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+/// Progress of the screencopy frame currently in flight
+#[cfg(feature = "wayland")]
+#[derive(Default)]
+struct FrameState {
+    buffers: Vec<BufferSpec>,
+    buffer_done: bool,
+    y_invert: bool,
+    ready: bool,
+    failed: bool,
 }
 
+/// How long a screencopy frame may take before the capture is abandoned
+#[cfg(feature = "wayland")]
+const COPY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// shm formats we can convert, in order of preference
+#[cfg(feature = "wayland")]
+const SUPPORTED_FORMATS: [wl_shm::Format; 4] = [
+    wl_shm::Format::Xrgb8888,
+    wl_shm::Format::Argb8888,
+    wl_shm::Format::Xbgr8888,
+    wl_shm::Format::Abgr8888,
+];
+
 #[cfg(feature = "wayland")]
 impl WaylandCapture {
     /// Create a new Wayland capturer
@@ -43,180 +144,885 @@ impl WaylandCapture {
             CaptureError::PlatformError(format!("Failed to connect to Wayland: {}", e))
         })?;
 
-        // Get the display outputs
-        let outputs = Self::enumerate_outputs(&connection)?;
+        let mut queue = connection.new_event_queue();
+        let qh = queue.handle();
+        connection.display().get_registry(&qh, ());
+
+        // The first roundtrip announces the globals, the second delivers the
+        // wl_output and xdg-output properties requested while binding them
+        let mut state = WaylandState::default();
+        for _ in 0..2 {
+            queue.roundtrip(&mut state).map_err(|e| {
+                CaptureError::DisplayEnumerationFailed(format!("Wayland roundtrip failed: {}", e))
+            })?;
+        }
+
+        if state.screencopy.is_none() {
+            return Err(CaptureError::PlatformError(
+                "Compositor does not support zwlr_screencopy_manager_v1".to_string(),
+            ));
+        }
+        if state.shm.is_none() {
+            return Err(CaptureError::PlatformError(
+                "Compositor does not expose wl_shm".to_string(),
+            ));
+        }
 
         Ok(Self {
-            connection,
-            outputs,
+            session: Mutex::new(Session {
+                queue,
+                state,
+                memory_pool: global_pool(),
+            }),
+            overlay_cursor: AtomicBool::new(false),
         })
     }
 
-    /// Enumerate Wayland outputs
-    fn enumerate_outputs(connection: &Connection) -> CaptureResult<Vec<WaylandOutput>> {
-        // This is a simplified implementation
-        // Full implementation would use wayland-client properly with event queue
+    /// Get display information
+    pub fn get_displays(&self) -> CaptureResult<Vec<DisplayInfo>> {
+        let mut session = self.session.lock();
+        session.refresh()?;
 
-        // For now, return a placeholder
-        // In production, this would:
-        // 1. Create event queue
-        // 2. Get registry
-        // 3. Bind to wl_output interface
-        // 4. Listen for output events
-        // 5. Collect output information
+        Ok(session
+            .state
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(index, output)| {
+                let bounds = output.logical_rect();
+                let (rotation, reflect_x) = output.orientation();
+                DisplayInfo {
+                    index,
+                    id: output.display_id(),
+                    name: output.display_name(),
+                    width: bounds.width,
+                    height: bounds.height,
+                    x: bounds.x,
+                    y: bounds.y,
+                    scale_factor: output.scale_factor(),
+                    // Wayland has no notion of a primary output; treat the first one as such
+                    is_primary: index == 0,
+                    refresh_rate: ((output.refresh_mhz + 500) / 1000) as u32,
+                    color_depth: 32,
+                    manufacturer: Some(output.make.clone()).filter(|make| !make.is_empty()),
+                    model: Some(output.model.clone()).filter(|model| !model.is_empty()),
+                    physical_size_mm: output.physical_size_mm(rotation),
+                    rotation,
+                    reflect_x,
+                    ..Default::default()
+                }
+            })
+            .collect())
+    }
 
-        Ok(Vec::new())
+    /// Read frames through buffers from `pool`
+    pub fn set_memory_pool(&self, pool: Arc<MemoryPool>) {
+        self.session.lock().memory_pool = pool;
     }
 
-    /// Get display information
-    pub fn get_displays(&self) -> CaptureResult<Vec<DisplayInfo>> {
-        if self.outputs.is_empty() {
-            // Fallback for when we can't enumerate Wayland outputs
-            // Try to use xdg-desktop-portal or return error
-            return self.get_displays_via_portal();
+    /// Composite the cursor into later display and region captures
+    ///
+    /// The pointer is private to the compositor, so it can only be drawn in by it.
+    pub fn set_overlay_cursor(&self, enabled: bool) {
+        self.overlay_cursor.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Capture a display
+    pub fn capture_display(&self, display_index: usize) -> CaptureResult<RawImage> {
+        let overlay_cursor = self.overlay_cursor.load(Ordering::Relaxed);
+        let mut session = self.session.lock();
+        session.refresh()?;
+        session.capture_output(display_index, None, overlay_cursor)
+    }
+
+    /// Capture a region given in the global logical coordinate space
+    pub fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
+        self.capture_logical_region(region, self.overlay_cursor.load(Ordering::Relaxed))
+    }
+
+    /// Capture a region, stitching together the parts of every output it covers
+    fn capture_logical_region(
+        &self,
+        region: CaptureRegion,
+        overlay_cursor: bool,
+    ) -> CaptureResult<RawImage> {
+        if region.width == 0 || region.height == 0 {
+            return Err(CaptureError::InvalidConfiguration(format!(
+                "Empty capture region: {}x{}",
+                region.width, region.height
+            )));
         }
 
-        Ok(self
+        let mut session = self.session.lock();
+        session.refresh()?;
+
+        let requested = Rectangle::new(region.x, region.y, region.width, region.height);
+        let parts: Vec<(usize, Rectangle, Rectangle, f32)> = session
+            .state
             .outputs
             .iter()
             .enumerate()
-            .map(|(index, output)| DisplayInfo {
-                index,
-                name: output.name.clone(),
-                width: output.width,
-                height: output.height,
-                x: output.x,
-                y: output.y,
-                scale_factor: output.scale as f32,
-                is_primary: index == 0,
-                refresh_rate: output.refresh_rate,
-                color_depth: 32,
+            .filter_map(|(index, output)| {
+                let bounds = output.logical_rect();
+                requested
+                    .intersection(&bounds)
+                    .map(|part| (index, part, bounds, output.scale_factor()))
             })
-            .collect())
+            .collect();
+
+        if parts.is_empty() {
+            return Err(CaptureError::CaptureFailed(format!(
+                "Region {:?} does not intersect any Wayland output",
+                region
+            )));
+        }
+
+        // A region inside a single output is copied directly at its native resolution
+        if let [(index, part, bounds, _)] = parts[..] {
+            if part == requested {
+                return session.capture_output(index, Some(to_output_local(part, bounds)), overlay_cursor);
+            }
+        }
+
+        // Otherwise compose at the highest scale among the covered outputs; areas
+        // not covered by any output stay transparent
+        let scale = parts.iter().map(|part| part.3).fold(1.0f32, f32::max);
+        let width = (region.width as f32 * scale).round() as u32;
+        let height = (region.height as f32 * scale).round() as u32;
+        let mut data = vec![0u8; width as usize * height as usize * 4];
+
+        for (index, part, bounds, _) in parts {
+            let image =
+                session.capture_output(index, Some(to_output_local(part, bounds)), overlay_cursor)?;
+            let target = Rectangle::new(
+                ((part.x - region.x) as f32 * scale).round() as i32,
+                ((part.y - region.y) as f32 * scale).round() as i32,
+                (part.width as f32 * scale).round() as u32,
+                (part.height as f32 * scale).round() as u32,
+            );
+            blit_nearest(&mut data, width, height, &image, target);
+        }
+
+        Ok(RawImage::new(data, width, height, PixelFormat::RGBA8))
     }
+}
 
-    /// Get displays via xdg-desktop-portal
-    fn get_displays_via_portal(&self) -> CaptureResult<Vec<DisplayInfo>> {
-        // Use D-Bus to communicate with xdg-desktop-portal
-        // This requires the dbus crate and portal implementation
+#[cfg(feature = "wayland")]
+impl Session {
+    /// Process pending output hotplug and mode change events
+    fn refresh(&mut self) -> CaptureResult<()> {
+        self.queue
+            .roundtrip(&mut self.state)
+            .map(|_| ())
+            .map_err(|e| CaptureError::DisplayEnumerationFailed(format!("Wayland roundtrip failed: {}", e)))
+    }
 
-        #[cfg(feature = "dbus")]
-        {
-            use dbus::blocking::Connection;
-            use std::time::Duration;
+    /// Copy an output, or a region of it in output-local logical coordinates, into an shm buffer
+    fn capture_output(
+        &mut self,
+        index: usize,
+        region: Option<Rectangle>,
+        overlay_cursor: bool,
+    ) -> CaptureResult<RawImage> {
+        let qh = self.queue.handle();
+        let output = self
+            .state
+            .outputs
+            .get(index)
+            .ok_or(CaptureError::DisplayNotFound(index))?
+            .output
+            .clone();
+        let (manager, shm) = match (&self.state.screencopy, &self.state.shm) {
+            (Some(manager), Some(shm)) => (manager.clone(), shm.clone()),
+            _ => {
+                return Err(CaptureError::PlatformError(
+                    "Wayland screencopy is not available".to_string(),
+                ))
+            }
+        };
 
-            let conn = Connection::new_session().map_err(|e| {
-                CaptureError::PlatformError(format!("Failed to connect to D-Bus: {}", e))
-            })?;
+        self.state.frame = FrameState::default();
+        let overlay_cursor = overlay_cursor as i32;
+        let frame = match region {
+            Some(rect) => manager.capture_output_region(
+                overlay_cursor,
+                &output,
+                rect.x,
+                rect.y,
+                rect.width as i32,
+                rect.height as i32,
+                &qh,
+                (),
+            ),
+            None => manager.capture_output(overlay_cursor, &output, &qh, ()),
+        };
+
+        let result = self.copy_frame(&frame, &shm, manager.version());
+        frame.destroy();
+        result
+    }
 
-            // Call org.freedesktop.portal.ScreenCast
-            // This is simplified - actual implementation would be more complex
-
-            // For now, return a default display
-            Ok(vec![DisplayInfo {
-                index: 0,
-                name: "Wayland Display".to_string(),
-                width: 1920,
-                height: 1080,
-                x: 0,
-                y: 0,
-                scale_factor: 1.0,
-                is_primary: true,
-                refresh_rate: 60,
-                color_depth: 32,
-            }])
-        }
-
-        #[cfg(not(feature = "dbus"))]
+    /// Negotiate a buffer for `frame`, wait for the copy and convert the pixels
+    fn copy_frame(
+        &mut self,
+        frame: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        shm: &wl_shm::WlShm,
+        version: u32,
+    ) -> CaptureResult<RawImage> {
+        let qh = self.queue.handle();
+        // A stalled compositor or an output removed mid-copy never answers
+        let deadline = Instant::now() + COPY_TIMEOUT;
+
+        // Version 3 lists every buffer type and ends with buffer_done, older
+        // versions send a single shm buffer event
+        while !(self.state.frame.failed
+            || self.state.frame.buffer_done
+            || (version < 3 && !self.state.frame.buffers.is_empty()))
         {
-            Err(CaptureError::PlatformError(
-                "Wayland capture requires D-Bus support (enable 'dbus' feature)".to_string(),
-            ))
+            self.dispatch(deadline)?;
+        }
+        if self.state.frame.failed {
+            return Err(CaptureError::CaptureFailed(
+                "Compositor refused the screencopy request".to_string(),
+            ));
         }
+
+        let spec = choose_buffer(&self.state.frame.buffers).ok_or_else(|| {
+            CaptureError::CaptureFailed("Compositor offered no supported shm buffer format".to_string())
+        })?;
+        let size = spec.stride as usize * spec.height as usize;
+        let file = create_shm_file(size as u64)?;
+
+        let pool = shm.create_pool(file.as_fd(), size as i32, &qh, ());
+        let buffer = pool.create_buffer(
+            0,
+            spec.width as i32,
+            spec.height as i32,
+            spec.stride as i32,
+            spec.format,
+            &qh,
+            (),
+        );
+        frame.copy(&buffer);
+
+        let copied = loop {
+            if self.state.frame.ready {
+                break Ok(());
+            }
+            if self.state.frame.failed {
+                break Err(CaptureError::CaptureFailed(
+                    "Compositor failed to copy the frame".to_string(),
+                ));
+            }
+            if let Err(e) = self.dispatch(deadline) {
+                break Err(e);
+            }
+        };
+        buffer.destroy();
+        pool.destroy();
+        copied?;
+
+        let mut buffer = self
+            .memory_pool
+            .acquire(size)
+            .map_err(|_| CaptureError::MemoryAllocationFailed { size })?;
+        let data = &mut buffer.data_mut()[..size];
+        file.read_exact_at(data, 0)?;
+        let rgba = shm_to_rgba(data, &spec, self.state.frame.y_invert)?;
+
+        Ok(RawImage::new(rgba, spec.width, spec.height, PixelFormat::RGBA8))
     }
 
-    /// Capture a display
-    pub fn capture_display(&self, display_index: usize) -> CaptureResult<RawImage> {
-        // Wayland screen capture typically requires:
-        // 1. Permission from user via xdg-desktop-portal
-        // 2. PipeWire stream setup
-        // 3. Frame buffer sharing
+    /// Wait for and dispatch the next events, failing once `deadline` has passed
+    fn dispatch(&mut self, deadline: Instant) -> CaptureResult<()> {
+        let dispatch_error = |e| CaptureError::CaptureFailed(format!("Wayland dispatch failed: {}", e));
+        if self.queue.dispatch_pending(&mut self.state).map_err(dispatch_error)? > 0 {
+            return Ok(());
+        }
+        self.queue
+            .flush()
+            .map_err(|e| CaptureError::CaptureFailed(format!("Wayland flush failed: {}", e)))?;
 
-        // This is a complex process that varies by compositor
-        // For now, we'll use the portal method
+        // No guard means events are already queued and only need dispatching
+        if let Some(guard) = self.queue.prepare_read() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut fd = libc::pollfd {
+                fd: guard.connection_fd().as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout_ms = remaining.as_millis().min(i32::MAX as u128) as i32;
+            match unsafe { libc::poll(&mut fd, 1, timeout_ms) } {
+                0 => {
+                    return Err(CaptureError::CaptureTimeout {
+                        timeout_ms: COPY_TIMEOUT.as_millis() as u64,
+                    })
+                }
+                n if n < 0 => {
+                    let error = std::io::Error::last_os_error();
+                    if error.kind() != std::io::ErrorKind::Interrupted {
+                        return Err(CaptureError::IoError(error));
+                    }
+                }
+                _ => {
+                    guard
+                        .read()
+                        .map_err(|e| CaptureError::CaptureFailed(format!("Wayland read failed: {}", e)))?;
+                }
+            }
+        }
+        self.queue.dispatch_pending(&mut self.state).map_err(dispatch_error)?;
+        Ok(())
+    }
+}
 
-        self.capture_via_portal(display_index)
+#[cfg(feature = "wayland")]
+impl WaylandOutput {
+    fn new(
+        global_name: u32,
+        output: wl_output::WlOutput,
+        xdg_output: Option<zxdg_output_v1::ZxdgOutputV1>,
+    ) -> Self {
+        Self {
+            global_name,
+            output,
+            xdg_output,
+            name: String::new(),
+            description: String::new(),
+            make: String::new(),
+            model: String::new(),
+            physical_size: (0, 0),
+            mode_width: 0,
+            mode_height: 0,
+            refresh_mhz: 60_000,
+            x: 0,
+            y: 0,
+            transform: wl_output::Transform::Normal,
+            scale: 1,
+            logical_position: None,
+            logical_size: None,
+        }
     }
 
-    /// Capture a region
-    pub fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
-        // Similar to capture_display but with region selection
-        // Most Wayland compositors don't support arbitrary region capture
-        // without going through the portal
+    fn display_name(&self) -> String {
+        if !self.name.is_empty() {
+            self.name.clone()
+        } else if !self.description.is_empty() {
+            self.description.clone()
+        } else {
+            format!("Wayland Output {}", self.global_name)
+        }
+    }
 
-        Err(CaptureError::CaptureFailed(
-            "Wayland region capture requires portal implementation".to_string(),
-        ))
+    /// Connector name, qualified by make and model when the compositor reports them
+    fn display_id(&self) -> DisplayId {
+        let name = self.display_name();
+        DisplayId(if self.make.is_empty() || self.model.is_empty() {
+            name
+        } else {
+            format!("{} {}@{}", self.make, self.model, name)
+        })
     }
 
-    /// Capture via xdg-desktop-portal
-    fn capture_via_portal(&self, _display_index: usize) -> CaptureResult<RawImage> {
-        #[cfg(feature = "dbus")]
-        {
-            // This would:
-            // 1. Request screenshot permission via portal
-            // 2. Get PipeWire stream handle
-            // 3. Connect to PipeWire
-            // 4. Receive frame buffer
-            // 5. Convert to our RawImage format
+    /// Rotation and horizontal reflection from the output transform
+    fn orientation(&self) -> (DisplayRotation, bool) {
+        use wl_output::Transform;
+        let rotation = match self.transform {
+            Transform::_90 | Transform::Flipped90 => DisplayRotation::Left,
+            Transform::_180 | Transform::Flipped180 => DisplayRotation::Inverted,
+            Transform::_270 | Transform::Flipped270 => DisplayRotation::Right,
+            _ => DisplayRotation::Normal,
+        };
+        let flipped = matches!(
+            self.transform,
+            Transform::Flipped | Transform::Flipped90 | Transform::Flipped180 | Transform::Flipped270
+        );
+        (rotation, flipped)
+    }
 
-            // Simplified error for now
-            Err(CaptureError::CaptureFailed(
-                "Portal-based capture not yet fully implemented".to_string(),
-            ))
+    /// Physical size oriented like the logical size, if the compositor knows it
+    fn physical_size_mm(&self, rotation: DisplayRotation) -> Option<(u32, u32)> {
+        let (width, height) = self.physical_size;
+        if width <= 0 || height <= 0 {
+            return None;
         }
+        let (width, height) = (width as u32, height as u32);
+        Some(if rotation.is_portrait() { (height, width) } else { (width, height) })
+    }
 
-        #[cfg(not(feature = "dbus"))]
-        {
-            Err(CaptureError::PlatformError(
-                "Wayland capture requires D-Bus support".to_string(),
-            ))
+    /// Size of the output's buffer after applying its transform
+    fn buffer_size(&self) -> (u32, u32) {
+        let (width, height) = (self.mode_width.max(0) as u32, self.mode_height.max(0) as u32);
+        match self.transform {
+            wl_output::Transform::_90
+            | wl_output::Transform::_270
+            | wl_output::Transform::Flipped90
+            | wl_output::Transform::Flipped270 => (height, width),
+            _ => (width, height),
         }
     }
 
-    /// Check if screencasting is available
-    pub fn is_screencast_available(&self) -> bool {
-        // Check if xdg-desktop-portal and PipeWire are available
-        #[cfg(feature = "dbus")]
-        {
-            if let Ok(conn) = dbus::blocking::Connection::new_session() {
-                // Check if portal service exists
-                let proxy = conn.with_proxy(
-                    "org.freedesktop.portal.Desktop",
-                    "/org/freedesktop/portal/desktop",
-                    std::time::Duration::from_millis(100),
+    /// Position and size in the compositor's logical coordinate space
+    fn logical_rect(&self) -> Rectangle {
+        let (x, y) = self.logical_position.unwrap_or((self.x, self.y));
+        let (width, height) = match self.logical_size {
+            Some((width, height)) => (width.max(0) as u32, height.max(0) as u32),
+            None => {
+                let (width, height) = self.buffer_size();
+                let scale = self.scale.max(1) as u32;
+                (width / scale, height / scale)
+            }
+        };
+        Rectangle::new(x, y, width, height)
+    }
+
+    /// Buffer pixels per logical pixel, which may be fractional
+    fn scale_factor(&self) -> f32 {
+        let logical_width = self.logical_rect().width;
+        if logical_width == 0 {
+            return self.scale.max(1) as f32;
+        }
+        self.buffer_size().0 as f32 / logical_width as f32
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl WaylandState {
+    fn output_mut(&mut self, global_name: u32) -> Option<&mut WaylandOutput> {
+        self.outputs
+            .iter_mut()
+            .find(|output| output.global_name == global_name)
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } => match interface.as_str() {
+                "wl_output" => {
+                    let output =
+                        registry.bind::<wl_output::WlOutput, u32, Self>(name, version.min(4), qh, name);
+                    let xdg_output = state
+                        .xdg_output_manager
+                        .as_ref()
+                        .map(|manager| manager.get_xdg_output(&output, qh, name));
+                    state.outputs.push(WaylandOutput::new(name, output, xdg_output));
+                }
+                "wl_shm" => {
+                    state.shm = Some(registry.bind(name, 1, qh, ()));
+                }
+                "zwlr_screencopy_manager_v1" => {
+                    state.screencopy = Some(registry.bind(name, version.min(3), qh, ()));
+                }
+                "zxdg_output_manager_v1" => {
+                    let manager: zxdg_output_manager_v1::ZxdgOutputManagerV1 =
+                        registry.bind(name, version.min(3), qh, ());
+                    // Outputs announced before the manager still need their xdg_output
+                    for output in state.outputs.iter_mut().filter(|o| o.xdg_output.is_none()) {
+                        output.xdg_output =
+                            Some(manager.get_xdg_output(&output.output, qh, output.global_name));
+                    }
+                    state.xdg_output_manager = Some(manager);
+                }
+                _ => {}
+            },
+            wl_registry::Event::GlobalRemove { name } => {
+                state.outputs.retain(|output| {
+                    if output.global_name != name {
+                        return true;
+                    }
+                    if let Some(xdg_output) = &output.xdg_output {
+                        xdg_output.destroy();
+                    }
+                    if output.output.version() >= 3 {
+                        output.output.release();
+                    }
+                    false
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl Dispatch<wl_output::WlOutput, u32> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &wl_output::WlOutput,
+        event: wl_output::Event,
+        global_name: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.output_mut(*global_name) else {
+            return;
+        };
+
+        match event {
+            wl_output::Event::Geometry {
+                x,
+                y,
+                physical_width,
+                physical_height,
+                make,
+                model,
+                transform,
+                ..
+            } => {
+                output.x = x;
+                output.y = y;
+                output.physical_size = (physical_width, physical_height);
+                output.make = make;
+                output.model = model;
+                if let WEnum::Value(transform) = transform {
+                    output.transform = transform;
+                }
+            }
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                refresh,
+            } if flags.contains(wl_output::Mode::Current) => {
+                output.mode_width = width;
+                output.mode_height = height;
+                output.refresh_mhz = refresh;
+            }
+            wl_output::Event::Scale { factor } => output.scale = factor,
+            wl_output::Event::Name { name } => output.name = name,
+            wl_output::Event::Description { description } => output.description = description,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl Dispatch<zxdg_output_v1::ZxdgOutputV1, u32> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &zxdg_output_v1::ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        global_name: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.output_mut(*global_name) else {
+            return;
+        };
+
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                output.logical_position = Some((x, y));
+            }
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                output.logical_size = Some((width, height));
+            }
+            // wl_output v4 names take precedence over the xdg-output ones
+            zxdg_output_v1::Event::Name { name } if output.name.is_empty() => output.name = name,
+            zxdg_output_v1::Event::Description { description } if output.description.is_empty() => {
+                output.description = description;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format: WEnum::Value(format),
+                width,
+                height,
+                stride,
+            } => state.frame.buffers.push(BufferSpec {
+                format,
+                width,
+                height,
+                stride,
+            }),
+            zwlr_screencopy_frame_v1::Event::Flags { flags } => {
+                state.frame.y_invert = matches!(
+                    flags,
+                    WEnum::Value(flags) if flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert)
                 );
+            }
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => state.frame.ready = true,
+            zwlr_screencopy_frame_v1::Event::Failed => state.frame.failed = true,
+            zwlr_screencopy_frame_v1::Event::BufferDone => state.frame.buffer_done = true,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "wayland")]
+delegate_noop!(WaylandState: ignore wl_shm::WlShm);
+#[cfg(feature = "wayland")]
+delegate_noop!(WaylandState: wl_shm_pool::WlShmPool);
+#[cfg(feature = "wayland")]
+delegate_noop!(WaylandState: ignore wl_buffer::WlBuffer);
+#[cfg(feature = "wayland")]
+delegate_noop!(WaylandState: zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1);
+#[cfg(feature = "wayland")]
+delegate_noop!(WaylandState: zxdg_output_manager_v1::ZxdgOutputManagerV1);
+
+/// `wl_output` hotplug and configuration notifications on a dedicated connection
+#[cfg(feature = "wayland")]
+pub struct WaylandChangeNotifier {
+    queue: EventQueue<NotifierState>,
+    state: NotifierState,
+}
+
+/// Outputs bound by a [`WaylandChangeNotifier`] and whether any of them changed
+#[cfg(feature = "wayland")]
+#[derive(Default)]
+struct NotifierState {
+    outputs: Vec<(u32, wl_output::WlOutput)>,
+    changed: bool,
+}
+
+#[cfg(feature = "wayland")]
+impl WaylandChangeNotifier {
+    /// Connect and bind every output so that its property updates are delivered
+    pub fn new() -> CaptureResult<Self> {
+        let connection = Connection::connect_to_env().map_err(|e| {
+            CaptureError::PlatformError(format!("Failed to connect to Wayland: {}", e))
+        })?;
 
-                // Try to introspect
-                use dbus::blocking::stdintf::org_freedesktop_dbus::Introspectable;
-                proxy.introspect().is_ok()
-            } else {
-                false
+        let mut queue = connection.new_event_queue();
+        connection.display().get_registry(&queue.handle(), ());
+
+        // Same two roundtrips as the capturer; the initial properties are not changes
+        let mut state = NotifierState::default();
+        for _ in 0..2 {
+            queue.roundtrip(&mut state).map_err(|e| {
+                CaptureError::PlatformError(format!("Wayland roundtrip failed: {}", e))
+            })?;
+        }
+        state.changed = false;
+
+        Ok(Self { queue, state })
+    }
+
+    fn dispatch_pending(&mut self) -> CaptureResult<()> {
+        self.queue
+            .dispatch_pending(&mut self.state)
+            .map(|_| ())
+            .map_err(|e| CaptureError::PlatformError(format!("Wayland dispatch failed: {}", e)))
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl ChangeNotifier for WaylandChangeNotifier {
+    fn wait(&mut self, timeout: Duration) -> CaptureResult<bool> {
+        self.dispatch_pending()?;
+        if !self.state.changed {
+            self.queue
+                .flush()
+                .map_err(|e| CaptureError::PlatformError(format!("Wayland flush failed: {}", e)))?;
+
+            // No guard means events are already queued and only need dispatching
+            if let Some(guard) = self.queue.prepare_read() {
+                let mut fd = libc::pollfd {
+                    fd: guard.connection_fd().as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+                if unsafe { libc::poll(&mut fd, 1, timeout_ms) } < 0 {
+                    let error = std::io::Error::last_os_error();
+                    if error.kind() != std::io::ErrorKind::Interrupted {
+                        return Err(CaptureError::IoError(error));
+                    }
+                } else if fd.revents != 0 {
+                    guard
+                        .read()
+                        .map_err(|e| CaptureError::PlatformError(format!("Wayland read failed: {}", e)))?;
+                }
             }
+            self.dispatch_pending()?;
         }
+        Ok(std::mem::take(&mut self.state.changed))
+    }
+}
 
-        #[cfg(not(feature = "dbus"))]
-        false
+#[cfg(feature = "wayland")]
+impl Dispatch<wl_registry::WlRegistry, ()> for NotifierState {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global { name, interface, version } if interface == "wl_output" => {
+                let output = registry.bind::<wl_output::WlOutput, (), Self>(name, version.min(4), qh, ());
+                state.outputs.push((name, output));
+                state.changed = true;
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(position) = state.outputs.iter().position(|(global_name, _)| *global_name == name) {
+                    let (_, output) = state.outputs.remove(position);
+                    if output.version() >= 3 {
+                        output.release();
+                    }
+                    state.changed = true;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl Dispatch<wl_output::WlOutput, ()> for NotifierState {
+    fn event(
+        state: &mut Self,
+        output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // Version 2 outputs group each update with `done`; older ones send no `done`
+        state.changed |= match event {
+            wl_output::Event::Done => true,
+            wl_output::Event::Geometry { .. } | wl_output::Event::Mode { .. } | wl_output::Event::Scale { .. } => {
+                output.version() < 2
+            }
+            _ => false,
+        };
     }
 }
 
-// Note: Full Wayland implementation would require:
-// 1. Proper Wayland protocol implementation
-// 2. wlr-screencopy protocol for wlroots-based compositors
-// 3. PipeWire integration for GNOME/KDE
-// 4. xdg-desktop-portal integration
-// 5. Permission handling
-// 6. Format conversion from compositor-specific formats
+/// Translate a global logical rectangle into coordinates relative to an output
+#[cfg(feature = "wayland")]
+fn to_output_local(rect: Rectangle, output_bounds: Rectangle) -> Rectangle {
+    Rectangle::new(
+        rect.x - output_bounds.x,
+        rect.y - output_bounds.y,
+        rect.width,
+        rect.height,
+    )
+}
+
+/// Pick the preferred shm buffer among the ones offered
+#[cfg(feature = "wayland")]
+fn choose_buffer(buffers: &[BufferSpec]) -> Option<BufferSpec> {
+    SUPPORTED_FORMATS
+        .iter()
+        .find_map(|format| buffers.iter().find(|spec| spec.format == *format))
+        .copied()
+}
+
+/// Create an unlinked file to back a `wl_shm_pool`
+#[cfg(feature = "wayland")]
+fn create_shm_file(size: u64) -> CaptureResult<File> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!(
+        "webp-screenshot-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    // Only the file descriptor is shared with the compositor
+    std::fs::remove_file(&path)?;
+    file.set_len(size)?;
+    Ok(file)
+}
+
+/// Convert a little-endian 32-bit shm buffer to packed RGBA
+#[cfg(feature = "wayland")]
+fn shm_to_rgba(data: &[u8], spec: &BufferSpec, y_invert: bool) -> CaptureResult<Vec<u8>> {
+    let row_size = spec.width as usize * 4;
+    let stride = spec.stride as usize;
+    let height = spec.height as usize;
+    if stride < row_size || data.len() < stride * height {
+        return Err(CaptureError::CaptureFailed(format!(
+            "Invalid shm buffer layout: stride {} for width {}",
+            spec.stride, spec.width
+        )));
+    }
+
+    let mut rgba = Vec::with_capacity(row_size * height);
+    for y in 0..height {
+        let src_y = if y_invert { height - 1 - y } else { y };
+        rgba.extend_from_slice(&data[src_y * stride..src_y * stride + row_size]);
+    }
+
+    match spec.format {
+        // Little-endian ARGB is stored as B, G, R, A
+        wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888 => {
+            global_simd_converter().convert_bgra_to_rgba(&mut rgba);
+        }
+        wl_shm::Format::Abgr8888 | wl_shm::Format::Xbgr8888 => {}
+        format => {
+            return Err(CaptureError::CaptureFailed(format!(
+                "Unsupported shm format: {:?}",
+                format
+            )))
+        }
+    }
+
+    if matches!(spec.format, wl_shm::Format::Xrgb8888 | wl_shm::Format::Xbgr8888) {
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+    }
+
+    Ok(rgba)
+}
+
+/// Copy `src` into `target` of an RGBA canvas, resampling with nearest neighbour
+#[cfg(feature = "wayland")]
+fn blit_nearest(dst: &mut [u8], dst_width: u32, dst_height: u32, src: &RawImage, target: Rectangle) {
+    let canvas = Rectangle::new(0, 0, dst_width, dst_height);
+    let Some(visible) = target.intersection(&canvas) else {
+        return;
+    };
+
+    for y in visible.y..visible.y + visible.height as i32 {
+        let src_y = ((y - target.y) as u64 * src.height as u64 / target.height as u64) as usize;
+        for x in visible.x..visible.x + visible.width as i32 {
+            let src_x = ((x - target.x) as u64 * src.width as u64 / target.width as u64) as usize;
+            let src_offset = src_y * src.stride + src_x * 4;
+            let dst_offset = (y as usize * dst_width as usize + x as usize) * 4;
+            dst[dst_offset..dst_offset + 4].copy_from_slice(&src.data[src_offset..src_offset + 4]);
+        }
+    }
+}
 
 // Stub for when Wayland feature is not enabled
 #[cfg(not(feature = "wayland"))]
@@ -241,4 +1047,91 @@ impl WaylandCapture {
     pub fn capture_region(&self, _region: crate::types::CaptureRegion) -> CaptureResult<crate::types::RawImage> {
         unreachable!()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, feature = "wayland"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shm_conversion_handles_stride_and_y_invert() {
+        let spec = BufferSpec {
+            format: wl_shm::Format::Xrgb8888,
+            width: 2,
+            height: 2,
+            stride: 12,
+        };
+        // Two rows of B, G, R, X pixels followed by four bytes of padding
+        let data = [
+            1, 2, 3, 0, 4, 5, 6, 0, 99, 99, 99, 99, //
+            7, 8, 9, 0, 10, 11, 12, 0, 99, 99, 99, 99,
+        ];
+
+        let rgba = shm_to_rgba(&data, &spec, false).unwrap();
+        assert_eq!(rgba, [3, 2, 1, 255, 6, 5, 4, 255, 9, 8, 7, 255, 12, 11, 10, 255]);
+
+        let flipped = shm_to_rgba(&data, &spec, true).unwrap();
+        assert_eq!(&flipped[..8], &[9, 8, 7, 255, 12, 11, 10, 255]);
+
+        let abgr = BufferSpec {
+            format: wl_shm::Format::Abgr8888,
+            ..spec
+        };
+        assert_eq!(&shm_to_rgba(&data, &abgr, false).unwrap()[..4], &[1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_choose_buffer_prefers_supported_formats() {
+        let spec = |format| BufferSpec {
+            format,
+            width: 4,
+            height: 4,
+            stride: 16,
+        };
+        let offered = [spec(wl_shm::Format::Rgb565), spec(wl_shm::Format::Abgr8888)];
+
+        assert_eq!(choose_buffer(&offered), Some(offered[1]));
+        assert_eq!(choose_buffer(&offered[..1]), None);
+    }
+
+    #[test]
+    fn test_blit_nearest_scales_and_clips() {
+        let src = RawImage::new(
+            vec![1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4],
+            2,
+            2,
+            PixelFormat::RGBA8,
+        );
+        let mut dst = vec![0u8; 3 * 3 * 4];
+        blit_nearest(&mut dst, 3, 3, &src, Rectangle::new(-1, -1, 4, 4));
+
+        let pixel = |x: usize, y: usize| dst[(y * 3 + x) * 4];
+        assert_eq!(pixel(0, 0), 1);
+        assert_eq!(pixel(1, 0), 2);
+        assert_eq!(pixel(0, 1), 3);
+        assert_eq!(pixel(2, 2), 4);
+    }
+
+    #[test]
+    fn test_headless_compositor_capture() {
+        // Needs a wlroots compositor, e.g. `WLR_BACKENDS=headless sway`
+        let Ok(capture) = WaylandCapture::new() else {
+            return;
+        };
+
+        let displays = capture.get_displays().unwrap();
+        assert!(!displays.is_empty());
+
+        let display = &displays[0];
+        let image = capture.capture_display(0).unwrap();
+        assert_eq!(
+            image.width,
+            (display.width as f32 * display.scale_factor).round() as u32
+        );
+
+        let region = CaptureRegion::new(display.x + 10, display.y + 10, 64, 32);
+        capture.set_overlay_cursor(true);
+        let image = capture.capture_region(region).unwrap();
+        assert_eq!(image.height, (32.0 * display.scale_factor).round() as u32);
+    }
+}
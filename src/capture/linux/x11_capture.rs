@@ -1,29 +1,120 @@
 //! X11-based screen capture for Linux
+//!
+//! Captures go through a MIT-SHM segment that is attached once and reused, so
+//! pixels are not copied through the X socket. When the extension is missing or
+//! the segment cannot be attached (e.g. SSH X forwarding), plain `GetImage` is
+//! used instead.
+//!
+//! Single windows are captured from XComposite named pixmaps, which hold a
+//! window's contents even while other windows cover it.
+//!
+//! Window redaction rules mask matching windows in display and region captures.
+//! Only the parts not covered by windows higher in `_NET_CLIENT_LIST_STACKING`
+//! are masked; popups and other override-redirect windows are not tracked.
 
 use crate::{
-    capture::traits::{DefaultPixelConverter, PixelFormatConverter},
+    capture::{
+        cursor::composite_cursor,
+        edid::{self, Edid},
+        redaction::redact,
+        traits::ChangeNotifier,
+    },
     encoder::simd::global_simd_converter,
     error::{CaptureError, CaptureResult},
-    memory_pool::global_pool,
-    types::{CaptureRegion, DisplayInfo, PixelFormat, RawImage},
+    memory_pool::{global_pool, MemoryPool, PooledBuffer},
+    types::{
+        CaptureRegion, CursorImage, DisplayId, DisplayInfo, DisplayRotation, PixelFormat, RawImage, Rectangle, Redaction, WindowId,
+        WindowInfo, WindowRedaction,
+    },
 };
 
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use x11rb::{
-    connection::Connection,
+    connection::{Connection, RequestConnection},
+    errors::ReplyError,
     protocol::{
+        composite,
+        Event,
         randr::{self, ConnectionExt as RandrConnectionExt},
+        shm,
         xfixes::{self, ConnectionExt as XfixesConnectionExt},
-        xproto::{self, ConnectionExt as XprotoConnectionExt, ImageFormat},
+        ErrorKind,
+        xproto::{self, ConnectionExt as XprotoConnectionExt, ImageFormat, ImageOrder},
     },
     rust_connection::RustConnection,
 };
 
+/// ICCCM WM_STATE value for iconified windows
+const ICONIC_STATE: u32 = 3;
+
+/// Time allowed for a client to repaint after its window is first redirected
+const REDIRECT_SETTLE_TIME: Duration = Duration::from_millis(50);
+
+x11rb::atom_manager! {
+    /// Atoms needed to describe client windows
+    WindowAtoms: WindowAtomsCookie {
+        _NET_CLIENT_LIST,
+        _NET_CLIENT_LIST_STACKING,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_WM_STATE,
+        _NET_WM_STATE_HIDDEN,
+        UTF8_STRING,
+        WM_STATE,
+    }
+}
+
 /// X11 capture implementation
 pub struct X11Capture {
     connection: RustConnection,
     screen_num: usize,
     root_window: xproto::Window,
-    pixel_converter: DefaultPixelConverter,
+    /// Shared-memory segment reused across captures, grown on demand
+    shm_segment: Mutex<Option<ShmSegment>>,
+    /// Cleared when MIT-SHM is unavailable or attaching a segment fails
+    shm_enabled: AtomicBool,
+    /// Atoms for window enumeration
+    atoms: OnceCell<WindowAtoms>,
+    /// Windows this connection has redirected with XComposite, released on drop
+    redirected_windows: Mutex<HashSet<xproto::Window>>,
+    /// XFixes cursor support, negotiated on first use
+    xfixes_supported: OnceCell<bool>,
+    /// Windows masked in display and region captures
+    window_redactions: Mutex<Vec<WindowRedaction>>,
+    /// Last display enumeration, dropped when RandR reports a change
+    display_cache: Mutex<Option<Vec<DisplayInfo>>>,
+    /// This connection receives RandR change notifications, so the cache can be trusted
+    layout_notifications: bool,
+    /// Pool the pixels copied out of the shared segment are allocated from
+    memory_pool: Mutex<Arc<MemoryPool>>,
+}
+
+/// A SysV shared-memory segment attached both locally and on the X server
+struct ShmSegment {
+    seg: shm::Seg,
+    addr: *mut libc::c_void,
+    size: usize,
+}
+
+// The mapping is only accessed while holding the X11Capture mutex
+unsafe impl Send for ShmSegment {}
+
+/// Memory layout of ZPixmap images for the root window
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PixmapLayout {
+    depth: u8,
+    bits_per_pixel: u8,
+    scanline_pad: u8,
+    /// Red occupies the high bits of each pixel value
+    bgr: bool,
+    /// Pixel values are stored most significant byte first
+    msb_first: bool,
 }
 
 impl X11Capture {
@@ -39,17 +130,100 @@ impl X11Capture {
         let screen = &setup.roots[screen_num];
         let root_window = screen.root;
 
+        let shm_enabled = Self::shm_supported(&connection);
+        let layout_notifications = select_layout_notifications(&connection, root_window).is_ok();
+
         Ok(Self {
             connection,
             screen_num,
             root_window,
-            pixel_converter: DefaultPixelConverter,
+            shm_segment: Mutex::new(None),
+            shm_enabled: AtomicBool::new(shm_enabled),
+            atoms: OnceCell::new(),
+            redirected_windows: Mutex::new(HashSet::new()),
+            xfixes_supported: OnceCell::new(),
+            window_redactions: Mutex::new(Vec::new()),
+            display_cache: Mutex::new(None),
+            layout_notifications,
+            memory_pool: Mutex::new(global_pool()),
         })
     }
 
+    /// Allocate captured images from `pool`
+    pub fn set_memory_pool(&self, pool: Arc<MemoryPool>) {
+        *self.memory_pool.lock() = pool;
+    }
+
+    /// Mask windows matching these rules in every display and region capture
+    pub fn with_window_redactions(self, rules: Vec<WindowRedaction>) -> Self {
+        self.set_window_redactions(rules);
+        self
+    }
+
+    /// Replace the window redaction rules; an empty list turns masking off
+    pub fn set_window_redactions(&self, rules: Vec<WindowRedaction>) {
+        *self.window_redactions.lock() = rules;
+    }
+
+    /// Whether captures currently mask windows
+    pub fn redacts_windows(&self) -> bool {
+        !self.window_redactions.lock().is_empty()
+    }
+
+    /// Enable or disable the MIT-SHM path (it stays off if the server lacks it)
+    pub fn with_shm(self, enabled: bool) -> Self {
+        let supported = self.shm_enabled.load(Ordering::Relaxed);
+        self.shm_enabled.store(enabled && supported, Ordering::Relaxed);
+        self
+    }
+
+    /// Whether captures currently go through shared memory
+    pub fn uses_shm(&self) -> bool {
+        self.shm_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Check for the MIT-SHM extension
+    fn shm_supported(connection: &RustConnection) -> bool {
+        let present = connection
+            .extension_information(shm::X11_EXTENSION_NAME)
+            .ok()
+            .flatten()
+            .is_some();
+
+        present
+            && shm::query_version(connection)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .is_some()
+    }
+
     /// Enumerate displays using XRandR
+    ///
+    /// Each enabled CRTC is one display, named after its output (the primary one
+    /// for cloned outputs) and described from that output's EDID. The result is
+    /// reused until RandR reports a screen, CRTC or output change.
     pub fn get_displays(&self) -> CaptureResult<Vec<DisplayInfo>> {
+        let mut cache = self.display_cache.lock();
+        // A broken connection counts as a change so the error surfaces below
+        if !self.layout_notifications || drain_randr_events(&self.connection).unwrap_or(true) {
+            *cache = None;
+        }
+        if let Some(displays) = cache.as_ref() {
+            return Ok(displays.clone());
+        }
+
+        let displays = self.enumerate_displays()?;
+        if self.layout_notifications {
+            *cache = Some(displays.clone());
+        }
+        Ok(displays)
+    }
+
+    /// Walk the RandR CRTCs and outputs
+    fn enumerate_displays(&self) -> CaptureResult<Vec<DisplayInfo>> {
         let mut displays = Vec::new();
+        let setup = self.connection.setup();
+        let screen = &setup.roots[self.screen_num];
 
         // Get screen resources using XRandR
         let resources = randr::get_screen_resources(&self.connection, self.root_window)
@@ -57,8 +231,19 @@ impl X11Capture {
             .reply()
             .map_err(|e| CaptureError::DisplayEnumerationFailed(format!("XRandR reply error: {}", e)))?;
 
+        let primary = randr::get_output_primary(&self.connection, self.root_window)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map_or(x11rb::NONE, |reply| reply.output);
+        // The atom only exists once a driver has published an EDID
+        let edid_atom = xproto::intern_atom(&self.connection, true, b"EDID")
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map(|reply| reply.atom)
+            .filter(|&atom| atom != x11rb::NONE);
+
         // Get information about each CRTC (display controller)
-        for (index, &crtc) in resources.crtcs.iter().enumerate() {
+        for &crtc in &resources.crtcs {
             let crtc_info = randr::get_crtc_info(
                 &self.connection,
                 crtc,
@@ -69,7 +254,7 @@ impl X11Capture {
             .map_err(|e| CaptureError::DisplayEnumerationFailed(format!("CRTC reply error: {}", e)))?;
 
             // Skip disabled CRTCs
-            if crtc_info.mode == 0 || crtc_info.num_outputs == 0 {
+            if crtc_info.mode == 0 || crtc_info.num_outputs() == 0 {
                 continue;
             }
 
@@ -89,25 +274,55 @@ impl X11Capture {
                 60 // Default
             };
 
-            displays.push(DisplayInfo {
-                index,
-                name: format!("Display {}", index + 1),
+            let is_primary = crtc_info.outputs.contains(&primary);
+            let output = if is_primary { primary } else { crtc_info.outputs[0] };
+            let output_info = randr::get_output_info(&self.connection, output, resources.config_timestamp)
+                .map_err(|e| CaptureError::DisplayEnumerationFailed(format!("Output info error: {}", e)))?
+                .reply()
+                .map_err(|e| CaptureError::DisplayEnumerationFailed(format!("Output reply error: {}", e)))?;
+
+            let name = String::from_utf8_lossy(&output_info.name).into_owned();
+            let edid = edid_atom.and_then(|atom| self.output_edid(output, atom));
+            let (rotation, reflect_x, reflect_y) = rotation_of(crtc_info.rotation.into());
+
+            let mut display = DisplayInfo {
+                index: displays.len(),
+                id: display_id(&name, edid.as_ref()),
+                name,
                 width: crtc_info.width as u32,
                 height: crtc_info.height as u32,
                 x: crtc_info.x as i32,
                 y: crtc_info.y as i32,
-                scale_factor: 1.0, // X11 doesn't directly expose scale factor
-                is_primary: index == 0, // First CRTC is typically primary
+                scale_factor: 1.0,
+                is_primary,
                 refresh_rate,
-                color_depth: 24, // Common default, could query actual depth
-            });
+                color_depth: screen.root_depth,
+                manufacturer: edid.as_ref().map(|edid| edid.manufacturer.clone()),
+                model: edid.as_ref().map(Edid::model),
+                serial: edid.as_ref().and_then(Edid::serial_number),
+                physical_size_mm: physical_size(
+                    (output_info.mm_width, output_info.mm_height),
+                    edid.as_ref(),
+                    rotation,
+                ),
+                rotation,
+                reflect_x,
+                reflect_y,
+            };
+            // X11 has no scaling of its own; suggest one from the pixel density
+            display.scale_factor = display.dpi().map_or(1.0, scale_for_dpi);
+            displays.push(display);
+        }
+
+        // Without a primary output set, the first display stands in
+        if !displays.iter().any(|display| display.is_primary) {
+            if let Some(first) = displays.first_mut() {
+                first.is_primary = true;
+            }
         }
 
         if displays.is_empty() {
             // Fallback to root window dimensions if XRandR fails
-            let setup = self.connection.setup();
-            let screen = &setup.roots[self.screen_num];
-
             displays.push(DisplayInfo {
                 index: 0,
                 name: "Primary Display".to_string(),
@@ -119,12 +334,34 @@ impl X11Capture {
                 is_primary: true,
                 refresh_rate: 60,
                 color_depth: screen.root_depth,
+                id: DisplayId(format!("screen-{}", self.screen_num)),
+                physical_size_mm: Some((screen.width_in_millimeters as u32, screen.height_in_millimeters as u32))
+                    .filter(|&(width, height)| width > 0 && height > 0),
+                ..Default::default()
             });
         }
 
         Ok(displays)
     }
 
+    /// EDID base block published for `output`, if it parses
+    fn output_edid(&self, output: randr::Output, atom: xproto::Atom) -> Option<Edid> {
+        let reply = randr::get_output_property(
+            &self.connection,
+            output,
+            atom,
+            xproto::AtomEnum::ANY,
+            0,
+            (edid::BLOCK_SIZE / 4) as u32,
+            false,
+            false,
+        )
+        .ok()?
+        .reply()
+        .ok()?;
+        Edid::parse(&reply.data)
+    }
+
     /// Capture a display
     pub fn capture_display(&self, display_index: usize) -> CaptureResult<RawImage> {
         let displays = self.get_displays()?;
@@ -142,7 +379,158 @@ impl X11Capture {
 
     /// Capture a specific region
     pub fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
-        // Get image from X server
+        if region.width == 0 || region.height == 0 {
+            return Err(CaptureError::InvalidConfiguration(format!(
+                "Empty capture region: {}x{}",
+                region.width, region.height
+            )));
+        }
+
+        // GetImage takes 16-bit coordinates and fails outside the root window
+        let screen = &self.connection.setup().roots[self.screen_num];
+        let (root_width, root_height) = (screen.width_in_pixels, screen.height_in_pixels);
+        let right = region.x as i64 + region.width as i64;
+        let bottom = region.y as i64 + region.height as i64;
+        if region.x < 0 || region.y < 0 || right > root_width as i64 || bottom > root_height as i64 {
+            return Err(CaptureError::InvalidConfiguration(format!(
+                "Capture region {:?} is outside the {}x{} X screen",
+                region, root_width, root_height
+            )));
+        }
+
+        let mut image = self.capture_region_pixels(region)?;
+        self.redact_windows(&mut image, region)?;
+        Ok(image)
+    }
+
+    /// Read the screen contents of a region
+    fn capture_region_pixels(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
+        let layout = self.pixmap_layout()?;
+
+        if self.shm_enabled.load(Ordering::Relaxed) {
+            let size = layout.stride(region.width) * region.height as usize;
+            let mut segment = self.shm_segment.lock();
+            match self.ensure_segment(&mut segment, size) {
+                Ok(segment) => return self.capture_region_shm(segment, region, layout),
+                Err(e) => {
+                    log::debug!("MIT-SHM unavailable, falling back to GetImage: {}", e);
+                    self.shm_enabled.store(false, Ordering::Relaxed);
+                }
+            }
+        }
+
+        self.capture_region_get_image(region, layout)
+    }
+
+    /// Apply the window redaction rules to an image captured from `region`
+    fn redact_windows(&self, image: &mut RawImage, region: CaptureRegion) -> CaptureResult<()> {
+        let rules = self.window_redactions.lock().clone();
+        if rules.is_empty() {
+            return Ok(());
+        }
+
+        let redactions = self.visible_window_areas(&rules)?;
+        let bounds = Rectangle::new(region.x, region.y, region.width, region.height);
+        let applied = redact(image, &redactions, bounds, (0, 0));
+        log::debug!("Masked {} window area(s) in capture", applied.len());
+        Ok(())
+    }
+
+    /// Unoccluded parts of windows matching `rules`, as global redactions
+    fn visible_window_areas(&self, rules: &[WindowRedaction]) -> CaptureResult<Vec<Redaction>> {
+        let atoms = self.atoms()?;
+        let windows: Vec<WindowInfo> = self
+            .stacked_windows(atoms)?
+            .into_iter()
+            // Windows can disappear between listing and querying them; any other
+            // failure fails the capture, as the window might need masking
+            .filter_map(|window| match self.window_info(window, atoms) {
+                Ok(info) => Some(Ok(info)),
+                Err(_) if self.window_destroyed(window) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<CaptureResult<Vec<_>>>()?
+            .into_iter()
+            .filter(|window| window.mapped && !window.minimized)
+            .collect();
+
+        let mut redactions = Vec::new();
+        for (index, window) in windows.iter().enumerate() {
+            let Some(rule) = rules.iter().find(|rule| window.matches(&rule.filter)) else {
+                continue;
+            };
+
+            let mut visible = vec![window.geometry];
+            for above in &windows[index + 1..] {
+                visible = visible.iter().flat_map(|area| area.subtract(&above.geometry)).collect();
+            }
+            redactions.extend(visible.into_iter().map(|area| Redaction::new(area, rule.mode).global()));
+        }
+
+        Ok(redactions)
+    }
+
+    /// Make sure the shared segment can hold `size` bytes, attaching a bigger one if needed
+    fn ensure_segment<'a>(
+        &self,
+        segment: &'a mut Option<ShmSegment>,
+        size: usize,
+    ) -> CaptureResult<&'a ShmSegment> {
+        if segment.as_ref().is_some_and(|segment| segment.size < size) {
+            if let Some(old) = segment.take() {
+                old.detach(&self.connection);
+            }
+        }
+
+        if segment.is_none() {
+            *segment = Some(ShmSegment::attach(&self.connection, size)?);
+        }
+
+        Ok(segment.as_ref().expect("segment attached above"))
+    }
+
+    /// Capture through the shared segment
+    fn capture_region_shm(
+        &self,
+        segment: &ShmSegment,
+        region: CaptureRegion,
+        layout: PixmapLayout,
+    ) -> CaptureResult<RawImage> {
+        shm::get_image(
+            &self.connection,
+            self.root_window,
+            region.x as i16,
+            region.y as i16,
+            region.width as u16,
+            region.height as u16,
+            !0, // All planes
+            ImageFormat::Z_PIXMAP.into(),
+            segment.seg,
+            0,
+        )
+        .map_err(|e| CaptureError::CaptureFailed(format!("X11 ShmGetImage error: {}", e)))?
+        .reply()
+        .map_err(|e| CaptureError::CaptureFailed(format!("X11 ShmGetImage reply error: {}", e)))?;
+
+        let size = layout.stride(region.width) * region.height as usize;
+        let mut data = self
+            .memory_pool
+            .lock()
+            .acquire(size)
+            .map(PooledBuffer::into_vec)
+            .map_err(|_| CaptureError::MemoryAllocationFailed { size })?;
+        // Reused buffers may be larger than requested
+        data.truncate(size);
+        data.copy_from_slice(&segment.as_slice()[..size]);
+        convert_zpixmap(data, region.width, region.height, layout)
+    }
+
+    /// Capture by copying the pixels through the X socket
+    fn capture_region_get_image(
+        &self,
+        region: CaptureRegion,
+        layout: PixmapLayout,
+    ) -> CaptureResult<RawImage> {
         let image_reply = xproto::get_image(
             &self.connection,
             ImageFormat::Z_PIXMAP,
@@ -157,70 +545,334 @@ impl X11Capture {
         .reply()
         .map_err(|e| CaptureError::CaptureFailed(format!("X11 GetImage reply error: {}", e)))?;
 
-        // Get visual info for pixel format detection
-        let setup = self.connection.setup();
-        let screen = &setup.roots[self.screen_num];
-        let visual = setup
-            .roots
-            .iter()
-            .flat_map(|screen| &screen.allowed_depths)
-            .flat_map(|depth| &depth.visuals)
-            .find(|v| v.visual_id == screen.root_visual)
-            .ok_or_else(|| CaptureError::CaptureFailed("Visual not found".to_string()))?;
+        convert_zpixmap(image_reply.data, region.width, region.height, layout)
+    }
 
-        // Determine pixel format based on depth and visual
-        let (pixel_format, bytes_per_pixel) = match image_reply.depth {
-            24 | 32 => {
-                // Check if it's BGR or RGB based on visual masks
-                let is_bgr = self.is_bgr_format(visual);
-                if image_reply.depth == 32 {
-                    (if is_bgr { PixelFormat::BGRA8 } else { PixelFormat::RGBA8 }, 4)
-                } else {
-                    (if is_bgr { PixelFormat::BGR8 } else { PixelFormat::RGB8 }, 3)
+    /// List top-level client windows
+    ///
+    /// Uses the EWMH `_NET_CLIENT_LIST` when a window manager provides it and
+    /// falls back to walking the window tree otherwise.
+    pub fn list_windows(&self) -> CaptureResult<Vec<WindowInfo>> {
+        let atoms = self.atoms()?;
+
+        let windows = self
+            .client_windows(atoms)?
+            .into_iter()
+            // Windows can disappear between listing and querying them
+            .filter_map(|window| match self.window_info(window, atoms) {
+                Ok(info) => Some(info),
+                Err(e) => {
+                    log::debug!("Skipping window {:#x}: {}", window, e);
+                    None
                 }
-            }
-            depth => {
-                return Err(CaptureError::CaptureFailed(
-                    format!("Unsupported bit depth: {}", depth),
-                ))
-            }
-        };
+            })
+            .collect();
 
-        // Get buffer from pool
-        let pool = global_pool();
-        let buffer_size = (region.width * region.height * bytes_per_pixel as u32) as usize;
-        let mut pooled_buffer = pool
-            .acquire(buffer_size)
-            .map_err(|_| CaptureError::MemoryAllocationFailed { size: buffer_size })?;
-
-        // Process and copy image data
-        match pixel_format {
-            PixelFormat::BGRA8 => {
-                // Copy data first, then convert BGRA to RGBA in place using SIMD
-                pooled_buffer.data_mut()[..image_reply.data.len()].copy_from_slice(&image_reply.data);
-                global_simd_converter().convert_bgra_to_rgba(pooled_buffer.data_mut());
-            }
-            PixelFormat::BGR8 => {
-                // Copy data first, then convert BGR to RGB in place using SIMD
-                pooled_buffer.data_mut()[..image_reply.data.len()].copy_from_slice(&image_reply.data);
-                global_simd_converter().convert_bgr_to_rgb(pooled_buffer.data_mut());
-            }
-            PixelFormat::RGBA8 | PixelFormat::RGB8 => {
-                // Direct copy
-                pooled_buffer.data_mut()[..image_reply.data.len()].copy_from_slice(&image_reply.data);
+        Ok(windows)
+    }
+
+    /// Capture a window's own contents through an XComposite named pixmap
+    ///
+    /// The window is redirected off-screen on first use so occluded parts are
+    /// captured too; that first capture waits briefly for the client to repaint,
+    /// later ones reuse the redirection until the capturer is dropped.
+    /// Minimized and unmapped windows have no contents to capture.
+    pub fn capture_window(&self, window_id: WindowId) -> CaptureResult<RawImage> {
+        let window = u32::try_from(window_id.0).map_err(|_| {
+            CaptureError::InvalidConfiguration(format!("Invalid X11 window id {}", window_id))
+        })?;
+
+        if !self.composite_supported() {
+            return Err(CaptureError::HardwareAccelerationUnavailable(
+                "XComposite 0.2 or newer is required for window capture".to_string(),
+            ));
+        }
+
+        let attributes = xproto::get_window_attributes(&self.connection, window)
+            .map_err(|e| CaptureError::CaptureFailed(format!("X11 GetWindowAttributes error: {}", e)))?
+            .reply()
+            .map_err(|_| {
+                // The server drops the redirection of destroyed windows with them
+                self.redirected_windows.lock().remove(&window);
+                CaptureError::CaptureFailed(format!("No such window {}", window_id))
+            })?;
+        if attributes.map_state != xproto::MapState::VIEWABLE {
+            return Err(CaptureError::CaptureFailed(format!(
+                "Window {} is not viewable",
+                window_id
+            )));
+        }
+
+        let geometry = xproto::get_geometry(&self.connection, window)
+            .map_err(|e| CaptureError::CaptureFailed(format!("X11 GetGeometry error: {}", e)))?
+            .reply()
+            .map_err(|e| CaptureError::CaptureFailed(format!("X11 GetGeometry reply error: {}", e)))?;
+        if geometry.width == 0 || geometry.height == 0 {
+            return Err(CaptureError::CaptureFailed(format!(
+                "Window {} has no area",
+                window_id
+            )));
+        }
+
+        self.redirect_window(window)?;
+
+        let pixmap = self
+            .connection
+            .generate_id()
+            .map_err(|e| CaptureError::CaptureFailed(format!("X11 id allocation error: {}", e)))?;
+        composite::name_window_pixmap(&self.connection, window, pixmap)
+            .map_err(|e| CaptureError::CaptureFailed(format!("XComposite NameWindowPixmap error: {}", e)))?
+            .check()
+            .map_err(|e| CaptureError::CaptureFailed(format!("XComposite NameWindowPixmap failed: {}", e)))?;
+
+        // The named pixmap includes the border; copy only the interior
+        let image = xproto::get_image(
+            &self.connection,
+            ImageFormat::Z_PIXMAP,
+            pixmap,
+            geometry.border_width as i16,
+            geometry.border_width as i16,
+            geometry.width,
+            geometry.height,
+            !0, // All planes
+        )
+        .map_err(|e| CaptureError::CaptureFailed(format!("X11 GetImage error: {}", e)))
+        .and_then(|cookie| {
+            cookie
+                .reply()
+                .map_err(|e| CaptureError::CaptureFailed(format!("X11 GetImage reply error: {}", e)))
+        });
+
+        if let Ok(cookie) = xproto::free_pixmap(&self.connection, pixmap) {
+            cookie.ignore_error();
+        }
+
+        let layout = self.layout_for(geometry.depth, attributes.visual)?;
+        convert_zpixmap(image?.data, geometry.width as u32, geometry.height as u32, layout)
+    }
+
+    /// Atoms used for window enumeration, interned on first use
+    fn atoms(&self) -> CaptureResult<&WindowAtoms> {
+        self.atoms.get_or_try_init(|| {
+            WindowAtoms::new(&self.connection)
+                .map_err(|e| CaptureError::PlatformError(format!("X11 InternAtom error: {}", e)))?
+                .reply()
+                .map_err(|e| CaptureError::PlatformError(format!("X11 InternAtom reply error: {}", e)))
+        })
+    }
+
+    /// Client windows in stacking order, bottom to top where known
+    fn client_windows(&self, atoms: &WindowAtoms) -> CaptureResult<Vec<xproto::Window>> {
+        match self.window_list(atoms._NET_CLIENT_LIST)? {
+            Some(windows) => Ok(windows),
+            None => self.tree_clients(atoms),
+        }
+    }
+
+    /// Client windows bottom to top, from `_NET_CLIENT_LIST_STACKING` or the window tree
+    fn stacked_windows(&self, atoms: &WindowAtoms) -> CaptureResult<Vec<xproto::Window>> {
+        match self.window_list(atoms._NET_CLIENT_LIST_STACKING)? {
+            Some(windows) => Ok(windows),
+            None => self.tree_clients(atoms),
+        }
+    }
+
+    /// A window list property on the root window, if the window manager sets it
+    fn window_list(&self, property: xproto::Atom) -> CaptureResult<Option<Vec<xproto::Window>>> {
+        Ok(self
+            .get_property(self.root_window, property, xproto::AtomEnum::WINDOW.into())?
+            .and_then(|reply| reply.value32().map(|values| values.collect())))
+    }
+
+    /// Client windows found by walking the tree, in stacking order
+    fn tree_clients(&self, atoms: &WindowAtoms) -> CaptureResult<Vec<xproto::Window>> {
+        // No EWMH window manager: top-level windows are root children, or one
+        // level down when a non-EWMH window manager reparents them into frames
+        let mut windows = Vec::new();
+        for child in self.query_children(self.root_window)? {
+            if self.is_client(child, atoms)? {
+                windows.push(child);
+            } else {
+                for grandchild in self.query_children(child)? {
+                    if self.is_client(grandchild, atoms)? {
+                        windows.push(grandchild);
+                    }
+                }
             }
-            _ => unreachable!(),
         }
 
-        // Convert pixel format for consistency
-        let final_format = if bytes_per_pixel == 4 {
-            PixelFormat::RGBA8
-        } else {
-            PixelFormat::RGB8
-        };
+        Ok(windows)
+    }
+
+    /// Children of a window, or none if it vanished
+    fn query_children(&self, window: xproto::Window) -> CaptureResult<Vec<xproto::Window>> {
+        let cookie = xproto::query_tree(&self.connection, window)
+            .map_err(|e| CaptureError::PlatformError(format!("X11 QueryTree error: {}", e)))?;
+        Ok(cookie.reply().map(|tree| tree.children).unwrap_or_default())
+    }
+
+    /// ICCCM client windows carry WM_STATE; bare clients at least set WM_CLASS
+    fn is_client(&self, window: xproto::Window, atoms: &WindowAtoms) -> CaptureResult<bool> {
+        Ok(self.get_property(window, atoms.WM_STATE, xproto::AtomEnum::ANY.into())?.is_some()
+            || self
+                .get_property(window, xproto::AtomEnum::WM_CLASS.into(), xproto::AtomEnum::STRING.into())?
+                .is_some())
+    }
+
+    /// Fetch a whole property, returning `None` if it is unset or the window is gone
+    fn get_property(
+        &self,
+        window: xproto::Window,
+        property: xproto::Atom,
+        type_: xproto::Atom,
+    ) -> CaptureResult<Option<xproto::GetPropertyReply>> {
+        let cookie = xproto::get_property(&self.connection, false, window, property, type_, 0, u32::MAX)
+            .map_err(|e| CaptureError::PlatformError(format!("X11 GetProperty error: {}", e)))?;
+
+        Ok(cookie
+            .reply()
+            .ok()
+            .filter(|reply| reply.type_ != u32::from(xproto::AtomEnum::NONE)))
+    }
+
+    /// Gather the properties describing one client window
+    fn window_info(&self, window: xproto::Window, atoms: &WindowAtoms) -> CaptureResult<WindowInfo> {
+        let attributes = xproto::get_window_attributes(&self.connection, window)
+            .map_err(|e| CaptureError::PlatformError(format!("X11 GetWindowAttributes error: {}", e)))?
+            .reply()
+            .map_err(|e| CaptureError::PlatformError(format!("X11 GetWindowAttributes reply error: {}", e)))?;
+        let geometry = xproto::get_geometry(&self.connection, window)
+            .map_err(|e| CaptureError::PlatformError(format!("X11 GetGeometry error: {}", e)))?
+            .reply()
+            .map_err(|e| CaptureError::PlatformError(format!("X11 GetGeometry reply error: {}", e)))?;
+        let origin = xproto::translate_coordinates(&self.connection, window, self.root_window, 0, 0)
+            .map_err(|e| CaptureError::PlatformError(format!("X11 TranslateCoordinates error: {}", e)))?
+            .reply()
+            .map_err(|e| CaptureError::PlatformError(format!("X11 TranslateCoordinates reply error: {}", e)))?;
+
+        let title = self
+            .get_property(window, atoms._NET_WM_NAME, atoms.UTF8_STRING)?
+            .or(self.get_property(window, xproto::AtomEnum::WM_NAME.into(), xproto::AtomEnum::ANY.into())?)
+            .map(|reply| String::from_utf8_lossy(&reply.value).into_owned())
+            .unwrap_or_default();
+
+        let (instance, class) = self
+            .get_property(window, xproto::AtomEnum::WM_CLASS.into(), xproto::AtomEnum::STRING.into())?
+            .map(|reply| parse_wm_class(&reply.value))
+            .unwrap_or_default();
+
+        let pid = self
+            .get_property(window, atoms._NET_WM_PID, xproto::AtomEnum::CARDINAL.into())?
+            .and_then(|reply| reply.value32().and_then(|mut values| values.next()));
+
+        let hidden = self
+            .get_property(window, atoms._NET_WM_STATE, xproto::AtomEnum::ATOM.into())?
+            .and_then(|reply| {
+                reply
+                    .value32()
+                    .map(|mut states| states.any(|state| state == atoms._NET_WM_STATE_HIDDEN))
+            })
+            .unwrap_or(false);
+        let iconic = self
+            .get_property(window, atoms.WM_STATE, atoms.WM_STATE)?
+            .and_then(|reply| reply.value32().and_then(|mut values| values.next()))
+            == Some(ICONIC_STATE);
+
+        Ok(WindowInfo {
+            id: WindowId(window as u64),
+            title,
+            instance,
+            class,
+            pid,
+            geometry: Rectangle::new(
+                origin.dst_x as i32,
+                origin.dst_y as i32,
+                geometry.width as u32,
+                geometry.height as u32,
+            ),
+            mapped: attributes.map_state == xproto::MapState::VIEWABLE,
+            minimized: hidden || iconic,
+        })
+    }
+
+    /// Whether `window` no longer exists
+    fn window_destroyed(&self, window: xproto::Window) -> bool {
+        match xproto::get_geometry(&self.connection, window).map(|cookie| cookie.reply()) {
+            Ok(Err(ReplyError::X11Error(error))) => matches!(error.error_kind, ErrorKind::Window | ErrorKind::Drawable),
+            _ => false,
+        }
+    }
+
+    /// Check for XComposite 0.2, which added NameWindowPixmap
+    fn composite_supported(&self) -> bool {
+        let present = self
+            .connection
+            .extension_information(composite::X11_EXTENSION_NAME)
+            .ok()
+            .flatten()
+            .is_some();
+
+        present
+            && composite::query_version(&self.connection, 0, 2)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .is_some_and(|version| (version.major_version, version.minor_version) >= (0, 2))
+    }
+
+    /// Redirect a window to off-screen storage the first time it is captured
+    fn redirect_window(&self, window: xproto::Window) -> CaptureResult<()> {
+        if !self.redirected_windows.lock().insert(window) {
+            return Ok(());
+        }
+
+        // Automatic redirection keeps the window on screen and may be combined
+        // with a compositing manager's own redirection
+        composite::redirect_window(&self.connection, window, composite::Redirect::AUTOMATIC)
+            .map_err(|e| CaptureError::CaptureFailed(format!("XComposite RedirectWindow error: {}", e)))?
+            .check()
+            .map_err(|e| {
+                self.redirected_windows.lock().remove(&window);
+                CaptureError::CaptureFailed(format!("XComposite RedirectWindow failed: {}", e))
+            })?;
+
+        // Newly redirected windows are exposed; give the client a moment to
+        // repaint the parts that were occluded
+        std::thread::sleep(REDIRECT_SETTLE_TIME);
+        Ok(())
+    }
 
-        let data = pooled_buffer.into_vec();
-        Ok(RawImage::new(data, region.width, region.height, final_format))
+    /// Determine the ZPixmap layout of the root window
+    fn pixmap_layout(&self) -> CaptureResult<PixmapLayout> {
+        let screen = &self.connection.setup().roots[self.screen_num];
+        self.layout_for(screen.root_depth, screen.root_visual)
+    }
+
+    /// Determine the ZPixmap layout of drawables with the given depth and visual
+    fn layout_for(&self, depth: u8, visual_id: xproto::Visualid) -> CaptureResult<PixmapLayout> {
+        let setup = self.connection.setup();
+        let screen = &setup.roots[self.screen_num];
+
+        let format = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == depth)
+            .ok_or_else(|| CaptureError::CaptureFailed(format!("No pixmap format for depth {}", depth)))?;
+
+        // Get visual info for channel order detection
+        let visual = screen
+            .allowed_depths
+            .iter()
+            .flat_map(|depth| &depth.visuals)
+            .find(|v| v.visual_id == visual_id)
+            .ok_or_else(|| CaptureError::CaptureFailed("Visual not found".to_string()))?;
+
+        Ok(PixmapLayout {
+            depth,
+            bits_per_pixel: format.bits_per_pixel,
+            scanline_pad: format.scanline_pad,
+            bgr: self.is_bgr_format(visual),
+            msb_first: setup.image_byte_order == ImageOrder::MSB_FIRST,
+        })
     }
 
     /// Check if visual uses BGR format
@@ -230,115 +882,677 @@ impl X11Capture {
         visual.blue_mask < visual.red_mask
     }
 
-    /// Convert BGRA to RGBA
-    fn convert_bgra_to_rgba(&self, src: &[u8], dst: &mut [u8], width: u32, height: u32) {
-        let pixel_count = (width * height) as usize;
-        for i in 0..pixel_count {
-            let offset = i * 4;
-            dst[offset] = src[offset + 2];     // R
-            dst[offset + 1] = src[offset + 1]; // G
-            dst[offset + 2] = src[offset];     // B
-            dst[offset + 3] = src[offset + 3]; // A
+    /// Capture a region with the cursor composited in
+    pub fn capture_with_cursor(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
+        let mut image = self.capture_region(region)?;
+
+        match self.capture_cursor() {
+            Ok(Some(cursor)) => {
+                let bounds = Rectangle::new(region.x, region.y, region.width, region.height);
+                composite_cursor(&mut image, &cursor, bounds);
+            }
+            Ok(None) => {}
+            Err(e) => log::debug!("Failed to get cursor image: {}", e),
         }
+
+        Ok(image)
     }
 
-    /// Convert BGR to RGB
-    fn convert_bgr_to_rgb(&self, src: &[u8], dst: &mut [u8], width: u32, height: u32) {
-        let pixel_count = (width * height) as usize;
-        for i in 0..pixel_count {
-            let offset = i * 3;
-            dst[offset] = src[offset + 2];     // R
-            dst[offset + 1] = src[offset + 1]; // G
-            dst[offset + 2] = src[offset];     // B
+    /// Current cursor image and pointer position from XFixes
+    pub fn capture_cursor(&self) -> CaptureResult<Option<CursorImage>> {
+        if !*self.xfixes_supported.get_or_init(|| Self::xfixes_supported(&self.connection)) {
+            return Ok(None);
+        }
+
+        let cursor = xfixes::get_cursor_image(&self.connection)
+            .map_err(|e| CaptureError::CaptureFailed(format!("XFixes GetCursorImage error: {}", e)))?
+            .reply()
+            .map_err(|e| CaptureError::CaptureFailed(format!("XFixes GetCursorImage reply error: {}", e)))?;
+
+        // Hidden cursors are reported as an empty or fully transparent image
+        if cursor.width == 0 || cursor.height == 0 || cursor.cursor_image.iter().all(|&pixel| pixel >> 24 == 0) {
+            return Ok(None);
         }
+
+        Ok(Some(CursorImage::from_argb32(
+            cursor.x as i32,
+            cursor.y as i32,
+            cursor.xhot as u32,
+            cursor.yhot as u32,
+            cursor.width as u32,
+            cursor.height as u32,
+            &cursor.cursor_image,
+        )))
     }
 
-    /// Capture with cursor using XFixes
-    pub fn capture_with_cursor(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
-        // First capture without cursor
-        let mut image = self.capture_region(region)?;
+    /// Check for XFixes 2.0 or newer; the version must be negotiated before
+    /// any other XFixes request
+    fn xfixes_supported(connection: &RustConnection) -> bool {
+        let present = connection
+            .extension_information(xfixes::X11_EXTENSION_NAME)
+            .ok()
+            .flatten()
+            .is_some();
 
-        // Try to get cursor image using XFixes
-        match xfixes::get_cursor_image(&self.connection) {
-            Ok(cursor_request) => {
-                match cursor_request.reply() {
-                    Ok(cursor) => {
-                        // Calculate cursor position relative to capture region
-                        let cursor_x = cursor.x as i32 - region.x;
-                        let cursor_y = cursor.y as i32 - region.y;
-
-                        if cursor_x >= 0
-                            && cursor_y >= 0
-                            && cursor_x < region.width as i32
-                            && cursor_y < region.height as i32
-                        {
-                            // Blend cursor with captured image
-                            self.blend_cursor(
-                                &mut image.data,
-                                &cursor.cursor_image,
-                                cursor.width as u32,
-                                cursor.height as u32,
-                                cursor_x as u32,
-                                cursor_y as u32,
-                                region.width,
-                            );
-                        }
-                    }
-                    Err(e) => {
-                        log::debug!("Failed to get cursor image: {}", e);
-                    }
-                }
+        present
+            && xfixes::query_version(connection, 5, 0)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .is_some_and(|version| version.major_version >= 2)
+    }
+}
+
+impl Drop for X11Capture {
+    fn drop(&mut self) {
+        if let Some(segment) = self.shm_segment.get_mut().take() {
+            segment.detach(&self.connection);
+        }
+
+        // Windows destroyed since their capture fail with BadWindow, which is ignored
+        for window in self.redirected_windows.get_mut().drain() {
+            if let Ok(cookie) = composite::unredirect_window(&self.connection, window, composite::Redirect::AUTOMATIC) {
+                cookie.ignore_error();
             }
+        }
+        let _ = self.connection.flush();
+    }
+}
+
+/// RandR screen, CRTC and output change notifications on a dedicated connection
+pub struct X11ChangeNotifier {
+    connection: RustConnection,
+}
+
+impl X11ChangeNotifier {
+    /// Connect and subscribe to RandR notifications for the default screen
+    pub fn new() -> CaptureResult<Self> {
+        let (connection, screen_num) = RustConnection::connect(None).map_err(|e| {
+            CaptureError::PlatformError(format!("Failed to connect to X11: {}", e))
+        })?;
+        let root = connection.setup().roots[screen_num].root;
+        select_layout_notifications(&connection, root)?;
+
+        Ok(Self { connection })
+    }
+}
+
+/// Ask for RandR screen, CRTC and output change notifications on `root`
+fn select_layout_notifications(connection: &RustConnection, root: xproto::Window) -> CaptureResult<()> {
+    // RandR sends 1.2 CRTC and output notifications only to 1.2 clients
+    randr::query_version(connection, 1, 2)
+        .map_err(|e| CaptureError::PlatformError(format!("XRandR error: {}", e)))?
+        .reply()
+        .map_err(|e| CaptureError::PlatformError(format!("XRandR reply error: {}", e)))?;
+    randr::select_input(
+        connection,
+        root,
+        randr::NotifyMask::SCREEN_CHANGE | randr::NotifyMask::CRTC_CHANGE | randr::NotifyMask::OUTPUT_CHANGE,
+    )
+    .map_err(|e| CaptureError::PlatformError(format!("XRandR error: {}", e)))?
+    .check()
+    .map_err(|e| CaptureError::PlatformError(format!("XRandR SelectInput rejected: {}", e)))?;
+    Ok(())
+}
+
+/// Consume queued events, returning whether any were RandR notifications
+fn drain_randr_events(connection: &RustConnection) -> CaptureResult<bool> {
+    let mut changed = false;
+    while let Some(event) = connection
+        .poll_for_event()
+        .map_err(|e| CaptureError::PlatformError(format!("X11 connection error: {}", e)))?
+    {
+        changed |= matches!(event, Event::RandrScreenChangeNotify(_) | Event::RandrNotify(_));
+    }
+    Ok(changed)
+}
+
+impl ChangeNotifier for X11ChangeNotifier {
+    fn wait(&mut self, timeout: Duration) -> CaptureResult<bool> {
+        if drain_randr_events(&self.connection)? {
+            return Ok(true);
+        }
+
+        let mut fd = libc::pollfd {
+            fd: self.connection.stream().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+        if unsafe { libc::poll(&mut fd, 1, timeout_ms) } < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() != std::io::ErrorKind::Interrupted {
+                return Err(CaptureError::IoError(error));
+            }
+        }
+        drain_randr_events(&self.connection)
+    }
+}
+
+impl ShmSegment {
+    /// Create a private segment of `size` bytes and attach it on both sides
+    fn attach(connection: &RustConnection, size: usize) -> CaptureResult<Self> {
+        let (shmid, addr) = Self::create(size)?;
+
+        let attached = connection
+            .generate_id()
+            .map_err(|e| CaptureError::PlatformError(format!("Failed to generate X11 ID: {}", e)))
+            .and_then(|seg| {
+                shm::attach(connection, seg, shmid as u32, false)
+                    .map_err(|e| CaptureError::PlatformError(format!("SHM attach failed: {}", e)))?
+                    .check()
+                    .map_err(|e| CaptureError::PlatformError(format!("SHM attach rejected: {}", e)))?;
+                Ok(seg)
+            });
+
+        // The segment is destroyed once both sides have detached
+        unsafe {
+            libc::shmctl(shmid, libc::IPC_RMID, std::ptr::null_mut());
+        }
+
+        match attached {
+            Ok(seg) => Ok(Self { seg, addr, size }),
             Err(e) => {
-                log::debug!("XFixes not available: {}", e);
+                unsafe {
+                    libc::shmdt(addr);
+                }
+                Err(e)
             }
         }
+    }
 
-        Ok(image)
+    /// Allocate and map a SysV segment locally
+    fn create(size: usize) -> CaptureResult<(i32, *mut libc::c_void)> {
+        let shmid = unsafe { libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600) };
+        if shmid == -1 {
+            return Err(CaptureError::MemoryAllocationFailed { size });
+        }
+
+        let addr = unsafe { libc::shmat(shmid, std::ptr::null(), 0) };
+        if addr as isize == -1 {
+            unsafe {
+                libc::shmctl(shmid, libc::IPC_RMID, std::ptr::null_mut());
+            }
+            return Err(CaptureError::MemoryAllocationFailed { size });
+        }
+
+        Ok((shmid, addr))
     }
 
-    /// Blend cursor image with screenshot
-    fn blend_cursor(
-        &self,
-        image: &mut [u8],
-        cursor_data: &[u32],
-        cursor_width: u32,
-        cursor_height: u32,
-        cursor_x: u32,
-        cursor_y: u32,
-        image_width: u32,
-    ) {
-        for cy in 0..cursor_height {
-            for cx in 0..cursor_width {
-                let cursor_idx = (cy * cursor_width + cx) as usize;
-                let cursor_pixel = cursor_data[cursor_idx];
-
-                // Extract ARGB components
-                let alpha = ((cursor_pixel >> 24) & 0xFF) as u8;
-                if alpha == 0 {
-                    continue; // Skip transparent pixels
-                }
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.addr as *const u8, self.size) }
+    }
 
-                let red = ((cursor_pixel >> 16) & 0xFF) as u8;
-                let green = ((cursor_pixel >> 8) & 0xFF) as u8;
-                let blue = (cursor_pixel & 0xFF) as u8;
+    /// Detach from the X server; the local mapping goes away on drop
+    fn detach(self, connection: &RustConnection) {
+        if let Ok(cookie) = shm::detach(connection, self.seg) {
+            cookie.ignore_error();
+        }
+    }
+}
 
-                // Calculate image pixel position
-                let img_x = cursor_x + cx;
-                let img_y = cursor_y + cy;
-                let img_idx = ((img_y * image_width + img_x) * 4) as usize;
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        unsafe {
+            libc::shmdt(self.addr);
+        }
+    }
+}
 
-                if img_idx + 3 < image.len() {
-                    // Alpha blend
-                    let alpha_f = alpha as f32 / 255.0;
-                    let inv_alpha = 1.0 - alpha_f;
+impl PixmapLayout {
+    /// Bytes per scanline for an image `width` pixels wide
+    fn stride(&self, width: u32) -> usize {
+        let pad = self.scanline_pad.max(8) as usize;
+        let bits = width as usize * self.bits_per_pixel as usize;
+        bits.div_ceil(pad) * pad / 8
+    }
+}
 
-                    image[img_idx] = (red as f32 * alpha_f + image[img_idx] as f32 * inv_alpha) as u8;
-                    image[img_idx + 1] = (green as f32 * alpha_f + image[img_idx + 1] as f32 * inv_alpha) as u8;
-                    image[img_idx + 2] = (blue as f32 * alpha_f + image[img_idx + 2] as f32 * inv_alpha) as u8;
-                    // Keep original alpha
+/// Rotation and X/Y reflection from a RandR rotation mask
+fn rotation_of(mask: u16) -> (DisplayRotation, bool, bool) {
+    let has = |flag: randr::Rotation| mask & u16::from(flag) != 0;
+    let rotation = if has(randr::Rotation::ROTATE90) {
+        DisplayRotation::Left
+    } else if has(randr::Rotation::ROTATE180) {
+        DisplayRotation::Inverted
+    } else if has(randr::Rotation::ROTATE270) {
+        DisplayRotation::Right
+    } else {
+        DisplayRotation::Normal
+    };
+    (rotation, has(randr::Rotation::REFLECT_X), has(randr::Rotation::REFLECT_Y))
+}
+
+/// Physical size oriented like the CRTC, from the output or else the EDID
+///
+/// Projectors and some TVs report an aspect ratio (e.g. 16x9 cm) instead of a size;
+/// those are ignored.
+fn physical_size(output_mm: (u32, u32), edid: Option<&Edid>, rotation: DisplayRotation) -> Option<(u32, u32)> {
+    let (width, height) = match output_mm {
+        (0, _) | (_, 0) => edid.map(|edid| (edid.size_cm.0 as u32 * 10, edid.size_cm.1 as u32 * 10))?,
+        size => size,
+    };
+    if width == 0 || height == 0 || matches!((width, height), (160, 90) | (160, 100) | (16, 9) | (16, 10)) {
+        return None;
+    }
+    Some(if rotation.is_portrait() { (height, width) } else { (width, height) })
+}
+
+/// Scale factor for a pixel density, in steps of 0.5 from 1.0 (96 DPI)
+fn scale_for_dpi(dpi: f32) -> f32 {
+    ((dpi / 96.0 * 2.0).round() / 2.0).clamp(1.0, 4.0)
+}
+
+/// Identifier from the EDID when it has a serial, so it follows the monitor
+/// between connectors; otherwise the output name, plus the model if known
+fn display_id(output_name: &str, edid: Option<&Edid>) -> DisplayId {
+    DisplayId(match edid {
+        Some(edid) => edid.identity().unwrap_or_else(|| {
+            format!("{}-{:04X}@{}", edid.manufacturer, edid.product_code, output_name)
+        }),
+        None => output_name.to_string(),
+    })
+}
+
+/// Split a WM_CLASS value into its NUL-separated instance and class names
+fn parse_wm_class(value: &[u8]) -> (String, String) {
+    let mut parts = value
+        .split(|&byte| byte == 0)
+        .map(|part| String::from_utf8_lossy(part).into_owned());
+    let instance = parts.next().unwrap_or_default();
+    let class = parts.next().unwrap_or_default();
+    (instance, class)
+}
+
+/// Convert ZPixmap data to an RGBA8 or RGB8 image, keeping the scanline padding as stride
+fn convert_zpixmap(
+    mut data: Vec<u8>,
+    width: u32,
+    height: u32,
+    layout: PixmapLayout,
+) -> CaptureResult<RawImage> {
+    let bytes_per_pixel = match layout.bits_per_pixel {
+        24 | 32 => layout.bits_per_pixel as usize / 8,
+        bits => {
+            return Err(CaptureError::CaptureFailed(format!(
+                "Unsupported pixmap format: depth {} with {} bits per pixel",
+                layout.depth, bits
+            )))
+        }
+    };
+
+    let stride = layout.stride(width);
+    let row_size = width as usize * bytes_per_pixel;
+    if data.len() < stride * height as usize {
+        return Err(CaptureError::CaptureFailed(format!(
+            "Short image data: {} bytes for {}x{}",
+            data.len(),
+            width,
+            height
+        )));
+    }
+    data.truncate(stride * height as usize);
+
+    // Normalize to the LSB-first byte order the channel masks assume
+    if layout.msb_first {
+        for row in data.chunks_exact_mut(stride) {
+            for pixel in row[..row_size].chunks_exact_mut(bytes_per_pixel) {
+                pixel.reverse();
+            }
+        }
+    }
+
+    let format = if bytes_per_pixel == 4 {
+        if layout.bgr {
+            global_simd_converter().convert_bgra_to_rgba_strided(&mut data, row_size, stride);
+        }
+        // Depth 24 leaves the fourth byte undefined
+        if layout.depth < 32 {
+            for row in data.chunks_exact_mut(stride) {
+                for pixel in row[..row_size].chunks_exact_mut(4) {
+                    pixel[3] = 255;
                 }
             }
         }
+        PixelFormat::RGBA8
+    } else {
+        if layout.bgr {
+            global_simd_converter().convert_bgr_to_rgb_strided(&mut data, row_size, stride);
+        }
+        PixelFormat::RGB8
+    };
+
+    Ok(RawImage::with_stride(data, width, height, format, stride))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEPTH_24: PixmapLayout = PixmapLayout {
+        depth: 24,
+        bits_per_pixel: 32,
+        scanline_pad: 32,
+        bgr: true,
+        msb_first: false,
+    };
+
+    #[test]
+    fn test_layout_stride() {
+        assert_eq!(DEPTH_24.stride(3), 12);
+
+        let packed_24bpp = PixmapLayout {
+            bits_per_pixel: 24,
+            ..DEPTH_24
+        };
+        assert_eq!(packed_24bpp.stride(3), 12);
+        assert_eq!(packed_24bpp.stride(4), 12);
+    }
+
+    #[test]
+    fn test_convert_depth_24_bgrx() {
+        let data = vec![1, 2, 3, 0, 4, 5, 6, 0];
+        let image = convert_zpixmap(data, 2, 1, DEPTH_24).unwrap();
+
+        assert_eq!(image.format, PixelFormat::RGBA8);
+        assert_eq!(image.data, [3, 2, 1, 255, 6, 5, 4, 255]);
+    }
+
+    #[test]
+    fn test_convert_msb_first_and_padding() {
+        let layout = PixmapLayout {
+            bits_per_pixel: 24,
+            msb_first: true,
+            ..DEPTH_24
+        };
+        // One 24bpp pixel per row, padded to 32 bits
+        let data = vec![10, 20, 30, 0, 40, 50, 60, 0];
+        let image = convert_zpixmap(data, 1, 2, layout).unwrap();
+
+        assert_eq!(image.format, PixelFormat::RGB8);
+        assert_eq!(image.stride, 4);
+        assert_eq!(image.to_packed().data, [10, 20, 30, 40, 50, 60]);
+    }
+
+    #[test]
+    fn test_convert_rejects_short_data() {
+        assert!(convert_zpixmap(vec![0; 7], 2, 1, DEPTH_24).is_err());
+        let mono = PixmapLayout {
+            bits_per_pixel: 1,
+            ..DEPTH_24
+        };
+        assert!(convert_zpixmap(vec![0; 8], 2, 1, mono).is_err());
+    }
+
+    #[test]
+    fn test_local_segment_round_trip() {
+        let (shmid, addr) = ShmSegment::create(4096).unwrap();
+        unsafe {
+            libc::shmctl(shmid, libc::IPC_RMID, std::ptr::null_mut());
+        }
+        let segment = ShmSegment {
+            seg: 0,
+            addr,
+            size: 4096,
+        };
+
+        unsafe { *(addr as *mut u8).add(100) = 42 };
+        assert_eq!(segment.as_slice()[100], 42);
+    }
+
+    #[test]
+    #[ignore = "requires an X server (run under Xvfb)"]
+    fn test_shm_capture_matches_get_image() {
+        let capture = X11Capture::new().expect("no X server; set DISPLAY, e.g. `Xvfb :99` with DISPLAY=:99");
+        assert!(capture.uses_shm(), "the X server doesn't offer MIT-SHM");
+
+        let region = CaptureRegion::new(5, 7, 33, 17);
+        let layout = capture.pixmap_layout().unwrap();
+        let via_shm = capture.capture_region(region).unwrap();
+        let via_socket = capture.capture_region_get_image(region, layout).unwrap();
+        assert_eq!(via_shm.data, via_socket.data);
+
+        // A larger region grows the reused segment
+        let full = capture.capture_display(0).unwrap();
+        assert!(capture.uses_shm());
+        assert_eq!(full.data.len(), full.stride * full.height as usize);
+    }
+
+    #[test]
+    #[ignore = "requires an X server (run under Xvfb)"]
+    fn test_display_enumeration_cached() {
+        let capture = X11Capture::new().expect("no X server; set DISPLAY, e.g. `Xvfb :99` with DISPLAY=:99");
+
+        assert!(capture.layout_notifications, "the X server doesn't report RandR changes");
+
+        let displays = capture.get_displays().unwrap();
+        assert!(capture.display_cache.lock().is_some());
+        assert_eq!(capture.get_displays().unwrap(), displays);
+    }
+
+    #[test]
+    fn test_capture_region_rejects_off_screen() {
+        // Needs an X server, e.g. `Xvfb :99` with DISPLAY=:99
+        let Ok(capture) = X11Capture::new() else {
+            return;
+        };
+
+        // These would wrap in GetImage's 16-bit fields
+        for region in [
+            CaptureRegion::new(-10, 0, 20, 20),
+            CaptureRegion::new(0, 0, 70_000, 10),
+            CaptureRegion::new(i32::MAX, 0, 10, 10),
+        ] {
+            let result = capture.capture_region(region);
+            assert!(matches!(result, Err(CaptureError::InvalidConfiguration(_))), "{:?}", region);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_output_orientation_and_size() {
+        let mask = u16::from(randr::Rotation::ROTATE90) | u16::from(randr::Rotation::REFLECT_Y);
+        assert_eq!(rotation_of(mask), (DisplayRotation::Left, false, true));
+        assert_eq!(rotation_of(u16::from(randr::Rotation::ROTATE0)), (DisplayRotation::Normal, false, false));
+
+        assert_eq!(physical_size((597, 336), None, DisplayRotation::Right), Some((336, 597)));
+        assert_eq!(physical_size((160, 90), None, DisplayRotation::Normal), None);
+        assert_eq!(physical_size((0, 0), None, DisplayRotation::Normal), None);
+
+        // 27" 4K, 24" 1080p and a 13" laptop panel
+        assert_eq!(scale_for_dpi(3840.0 * 25.4 / 597.0), 1.5);
+        assert_eq!(scale_for_dpi(1920.0 * 25.4 / 527.0), 1.0);
+        assert_eq!(scale_for_dpi(2560.0 * 25.4 / 286.0), 2.5);
+    }
+
+    #[test]
+    fn test_display_id_prefers_edid() {
+        let mut edid = Edid {
+            manufacturer: "DEL".to_string(),
+            product_code: 0xA0B1,
+            serial_number: 0,
+            name: None,
+            serial: Some("7X4K2".to_string()),
+            size_cm: (60, 34),
+        };
+        // The same monitor on another connector keeps its identifier
+        assert_eq!(display_id("DP-1", Some(&edid)), display_id("HDMI-1", Some(&edid)));
+        assert_eq!(display_id("DP-1", Some(&edid)).as_str(), "DEL-A0B1-7X4K2");
+
+        // Identical monitors without serials are told apart by connector
+        edid.serial = None;
+        assert_eq!(display_id("DP-1", Some(&edid)).as_str(), "DEL-A0B1@DP-1");
+        assert_eq!(display_id("DP-1", None).as_str(), "DP-1");
+    }
+
+    #[test]
+    fn test_parse_wm_class() {
+        assert_eq!(
+            parse_wm_class(b"gnome-terminal\0Gnome-terminal\0"),
+            ("gnome-terminal".to_string(), "Gnome-terminal".to_string())
+        );
+        assert_eq!(parse_wm_class(b"xterm"), ("xterm".to_string(), String::new()));
+        assert_eq!(parse_wm_class(b""), (String::new(), String::new()));
+    }
+
+    /// Create a mapped window with a solid background on a separate client connection
+    fn spawn_test_window(
+        connection: &RustConnection,
+        root: xproto::Window,
+        rect: Rectangle,
+        title: &str,
+        class: &[u8],
+        pixel: u32,
+    ) -> xproto::Window {
+        use x11rb::wrapper::ConnectionExt as _;
+
+        let window = connection.generate_id().unwrap();
+        xproto::create_window(
+            connection,
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            rect.x as i16,
+            rect.y as i16,
+            rect.width as u16,
+            rect.height as u16,
+            0,
+            xproto::WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &xproto::CreateWindowAux::new()
+                .background_pixel(pixel)
+                .override_redirect(1),
+        )
+        .unwrap();
+        connection
+            .change_property8(
+                xproto::PropMode::REPLACE,
+                window,
+                xproto::AtomEnum::WM_NAME,
+                xproto::AtomEnum::STRING,
+                title.as_bytes(),
+            )
+            .unwrap();
+        connection
+            .change_property8(
+                xproto::PropMode::REPLACE,
+                window,
+                xproto::AtomEnum::WM_CLASS,
+                xproto::AtomEnum::STRING,
+                class,
+            )
+            .unwrap();
+        xproto::map_window(connection, window).unwrap();
+        connection.sync().unwrap();
+        window
+    }
+
+    #[test]
+    #[ignore = "requires an X server (run under Xvfb)"]
+    fn test_list_and_capture_occluded_window() {
+        use crate::types::WindowFilter;
+        use x11rb::wrapper::ConnectionExt as _;
+
+        let capture = X11Capture::new().expect("no X server; set DISPLAY, e.g. `Xvfb :99` with DISPLAY=:99");
+        assert!(capture.composite_supported(), "the X server doesn't offer XComposite 0.2");
+
+        let (client, screen_num) = RustConnection::connect(None).unwrap();
+        let root = client.setup().roots[screen_num].root;
+        let target = spawn_test_window(
+            &client,
+            root,
+            Rectangle::new(40, 30, 64, 48),
+            "webp-screenshot test target",
+            b"wst-target\0WstTarget\0",
+            0x00ff0000,
+        );
+        // Cover most of the target with a second window
+        let cover = spawn_test_window(
+            &client,
+            root,
+            Rectangle::new(50, 40, 80, 80),
+            "webp-screenshot test cover",
+            b"wst-cover\0WstCover\0",
+            0x000000ff,
+        );
+        client.sync().unwrap();
+
+        let windows = capture.list_windows().unwrap();
+        let title = WindowFilter::title("test target$").unwrap();
+        let matches: Vec<_> = windows.iter().filter(|window| window.matches(&title)).collect();
+        assert_eq!(matches.len(), 1);
+        let info = matches[0];
+        assert_eq!(info.id, WindowId(target as u64));
+        assert_eq!((info.instance.as_str(), info.class.as_str()), ("wst-target", "WstTarget"));
+        assert_eq!(info.geometry, Rectangle::new(40, 30, 64, 48));
+        assert!(info.mapped && !info.minimized);
+        let class = WindowFilter::class("wstcover");
+        assert_eq!(windows.iter().filter(|window| window.matches(&class)).count(), 1);
+
+        let image = capture.capture_window(info.id).unwrap();
+        assert_eq!((image.width, image.height), (64, 48));
+        // The bottom-right corner sits under the cover window
+        assert_eq!(&image.get_pixel(60, 44).unwrap()[..3], &[255, 0, 0]);
+        assert!(capture.redirected_windows.lock().contains(&target));
+
+        client.destroy_window(cover).unwrap();
+        client.destroy_window(target).unwrap();
+        client.sync().unwrap();
+        assert!(capture.capture_window(info.id).is_err());
+        assert!(capture.redirected_windows.lock().is_empty());
+    }
+
+    #[test]
+    #[ignore = "requires an X server (run under Xvfb)"]
+    fn test_window_redaction_masks_unoccluded_area() {
+        use crate::types::{RedactionMode, WindowFilter};
+        use x11rb::wrapper::ConnectionExt as _;
+
+        let capture = X11Capture::new().expect("no X server; set DISPLAY, e.g. `Xvfb :99` with DISPLAY=:99");
+        let capture = capture.with_window_redactions(vec![WindowRedaction::new(
+            WindowFilter::class("WstSecret"),
+            RedactionMode::Fill([0, 255, 0, 255]),
+        )]);
+
+        let (client, screen_num) = RustConnection::connect(None).unwrap();
+        let root = client.setup().roots[screen_num].root;
+        let secret = spawn_test_window(
+            &client,
+            root,
+            Rectangle::new(300, 200, 64, 48),
+            "webp-screenshot secret",
+            b"wst-secret\0WstSecret\0",
+            0x00ff0000,
+        );
+        // Stacked above the secret window, covering its right half
+        let cover = spawn_test_window(
+            &client,
+            root,
+            Rectangle::new(332, 190, 64, 80),
+            "webp-screenshot cover",
+            b"wst-cover2\0WstCover2\0",
+            0x000000ff,
+        );
+
+        let image = capture.capture_region(CaptureRegion::new(280, 180, 140, 100)).unwrap();
+        let rgb = |x: u32, y: u32| {
+            let pixel = image.get_pixel(x - 280, y - 180).unwrap();
+            match image.format {
+                PixelFormat::BGRA8 | PixelFormat::BGR8 => [pixel[2], pixel[1], pixel[0]],
+                _ => [pixel[0], pixel[1], pixel[2]],
+            }
+        };
+        // Visible part of the secret window is masked, the cover is left alone
+        assert_eq!(rgb(300, 200), [0, 255, 0]);
+        assert_eq!(rgb(331, 247), [0, 255, 0]);
+        assert_eq!(rgb(340, 220), [0, 0, 255]);
+
+        // Without rules the window shows through
+        capture.set_window_redactions(Vec::new());
+        let image = capture.capture_region(CaptureRegion::new(300, 200, 8, 8)).unwrap();
+        assert_ne!(&image.get_pixel(0, 0).unwrap()[..3], &[0, 255, 0]);
+
+        client.destroy_window(cover).unwrap();
+        client.destroy_window(secret).unwrap();
+        client.sync().unwrap();
+    }
+
+}
@@ -4,7 +4,7 @@ use crate::{
     capture::traits::{DefaultPixelConverter, PixelFormatConverter},
     encoder::simd::global_simd_converter,
     error::{CaptureError, CaptureResult},
-    memory_pool::global_pool,
+    memory_pool::{global_pool, MemoryPool},
     types::{CaptureRegion, DisplayInfo, PixelFormat, RawImage},
 };
 
@@ -13,11 +13,13 @@ use core_graphics::{
     display::{CGDisplay, CGDisplayCreateImage, CGRect},
     image::CGImage,
 };
-use std::slice;
+use parking_lot::Mutex;
+use std::{slice, sync::Arc};
 
 /// CoreGraphics capture implementation
 pub struct CoreGraphicsCapture {
     pixel_converter: DefaultPixelConverter,
+    memory_pool: Mutex<Arc<MemoryPool>>,
 }
 
 impl CoreGraphicsCapture {
@@ -25,9 +27,15 @@ impl CoreGraphicsCapture {
     pub fn new() -> CaptureResult<Self> {
         Ok(Self {
             pixel_converter: DefaultPixelConverter,
+            memory_pool: Mutex::new(global_pool()),
         })
     }
 
+    /// Allocate capture buffers from `pool`
+    pub fn set_memory_pool(&self, pool: Arc<MemoryPool>) {
+        *self.memory_pool.lock() = pool;
+    }
+
     /// Capture a display using CoreGraphics
     pub fn capture_display(&self, display_index: usize) -> CaptureResult<RawImage> {
         let displays = super::MacOSCapture::enumerate_displays()?;
@@ -105,7 +113,7 @@ impl CoreGraphicsCapture {
             }
 
             // Get buffer from pool
-            let pool = global_pool();
+            let pool = self.memory_pool.lock().clone();
             let buffer_size = (width * height * 4) as usize; // RGBA
             let mut pooled_buffer = pool
                 .acquire(buffer_size)
@@ -10,7 +10,8 @@ mod screencapturekit;
 use crate::{
     capture::traits::{CaptureCapabilities, ScreenCapture},
     error::{CaptureError, CaptureResult},
-    types::{CaptureRegion, DisplayInfo, PixelFormat, RawImage},
+    memory_pool::MemoryPool,
+    types::{CaptureRegion, DisplayId, DisplayInfo, PixelFormat, RawImage},
 };
 
 #[cfg(target_os = "macos")]
@@ -20,7 +21,7 @@ use core_foundation::string::CFString;
 #[cfg(target_os = "macos")]
 use core_graphics::display::*;
 #[cfg(target_os = "macos")]
-use std::ptr;
+use std::{ptr, sync::Arc};
 
 /// macOS screen capture implementation
 #[cfg(target_os = "macos")]
@@ -134,6 +135,19 @@ impl MacOSCapture {
                 // Get scale factor (for Retina displays)
                 let scale_factor = Self::get_display_scale_factor(display_id);
 
+                // Vendor, model and serial follow the monitor; the display ID only
+                // tells apart identical monitors without a serial
+                let display = CGDisplay::new(display_id);
+                let mut id = format!(
+                    "{:04X}-{:04X}-{:08X}",
+                    display.vendor_number(),
+                    display.model_number(),
+                    display.serial_number()
+                );
+                if display.serial_number() == 0 {
+                    id = format!("{}@{}", id, display_id);
+                }
+
                 displays.push(DisplayInfo {
                     index: i,
                     name: format!("Display {}", i + 1),
@@ -145,6 +159,8 @@ impl MacOSCapture {
                     is_primary: is_main,
                     refresh_rate,
                     color_depth: 32, // macOS typically uses 32-bit color
+                    id: DisplayId(id),
+                    ..Default::default()
                 });
             }
         }
@@ -213,6 +229,10 @@ impl ScreenCapture for MacOSCapture {
         self.core_graphics.capture_display(display_index)
     }
 
+    fn set_memory_pool(&self, pool: Arc<MemoryPool>) {
+        self.core_graphics.set_memory_pool(pool);
+    }
+
     fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
         // Check permissions first
         if !Self::check_permissions()? {
@@ -253,6 +273,7 @@ impl ScreenCapture for MacOSCapture {
             supports_multi_display: true,
             supports_gpu_acceleration: self.use_screencapturekit,
             estimated_latency_ms: if self.use_screencapturekit { 5 } else { 15 },
+            supports_zero_copy: true,
         }
     }
 }
@@ -0,0 +1,461 @@
+//! Deterministic mock capture backend for headless testing
+//!
+//! `MockCapture` implements [`ScreenCapture`] without touching a display server.
+//! It reports a configurable display layout and produces frames either from a
+//! script of prepared images and failures, or procedurally from a [`MockPattern`].
+//! Patterns are a pure function of global coordinates and the frame number, so
+//! repeated runs produce identical pixels. Displays are captured at their scale
+//! factor, like HiDPI displays on Wayland and macOS; regions at one pixel per unit.
+
+use crate::{
+    capture::traits::{CaptureCapabilities, ScreenCapture},
+    error::{CaptureError, CaptureResult},
+    memory_pool::{self, MemoryPool, PooledBuffer},
+    types::{CaptureRegion, CursorImage, DisplayId, DisplayInfo, PixelFormat, RawImage},
+};
+
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Procedural frame content produced when no scripted step is pending
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MockPattern {
+    /// Single solid RGBA color
+    Solid([u8; 4]),
+    /// Diagonal color gradient across the virtual desktop
+    #[default]
+    Gradient,
+    /// Dark glyph-like blocks on a light background, resembling text/UI content
+    Text,
+    /// Solid rectangle moving across a gradient background
+    MovingRectangle {
+        /// Rectangle edge length in pixels
+        size: u32,
+        /// Horizontal and vertical movement per frame in pixels
+        velocity: (i32, i32),
+    },
+}
+
+/// Failure that a scripted capture step reports
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockFailure {
+    /// Report `CaptureError::CaptureTimeout` for a wait of this long
+    Timeout(Duration),
+    /// Report `CaptureError::PermissionDenied`
+    PermissionDenied,
+    /// Report `CaptureError::CaptureFailed` with the given message
+    CaptureFailed(String),
+    /// Report `CaptureError::DisplayNotFound` for the requested display
+    DisplayNotFound,
+}
+
+impl MockFailure {
+    fn to_error(&self, display_index: usize) -> CaptureError {
+        match self {
+            MockFailure::Timeout(timeout) => CaptureError::CaptureTimeout {
+                timeout_ms: timeout.as_millis() as u64,
+            },
+            MockFailure::PermissionDenied => {
+                CaptureError::PermissionDenied("mock capture permission denied".to_string())
+            }
+            MockFailure::CaptureFailed(message) => CaptureError::CaptureFailed(message.clone()),
+            MockFailure::DisplayNotFound => CaptureError::DisplayNotFound(display_index),
+        }
+    }
+}
+
+/// A single scripted capture result
+#[derive(Debug, Clone)]
+pub enum MockStep {
+    /// Return this image verbatim
+    Frame(RawImage),
+    /// Fail with this error
+    Fail(MockFailure),
+}
+
+/// Deterministic in-memory screen capture implementation
+pub struct MockCapture {
+    displays: Mutex<Vec<DisplayInfo>>,
+    pattern: MockPattern,
+    latency: Duration,
+    script: Mutex<VecDeque<MockStep>>,
+    frame_counter: Mutex<u64>,
+    cursor: Mutex<Option<CursorImage>>,
+    memory_pool: Mutex<Arc<MemoryPool>>,
+}
+
+impl MockCapture {
+    /// Create a mock with a single 1920x1080 primary display and a gradient pattern
+    pub fn new() -> Self {
+        Self {
+            displays: Mutex::new(vec![DisplayInfo {
+                name: "Mock Display".to_string(),
+                id: DisplayId("mock-0".to_string()),
+                ..Default::default()
+            }]),
+            pattern: MockPattern::default(),
+            latency: Duration::ZERO,
+            script: Mutex::new(VecDeque::new()),
+            frame_counter: Mutex::new(0),
+            cursor: Mutex::new(None),
+            memory_pool: Mutex::new(memory_pool::global_pool()),
+        }
+    }
+
+    /// Replace the reported displays; indices are renumbered in order
+    ///
+    /// Displays without an id get `mock-<index>`.
+    pub fn with_displays(self, displays: Vec<DisplayInfo>) -> Self {
+        self.set_displays(displays);
+        self
+    }
+
+    /// Replace the reported displays of a mock in use, as if monitors were
+    /// plugged, unplugged or reconfigured
+    pub fn set_displays(&self, displays: Vec<DisplayInfo>) {
+        *self.displays.lock() = displays
+            .into_iter()
+            .enumerate()
+            .map(|(index, display)| DisplayInfo {
+                index,
+                id: match display.id.as_str() {
+                    "" => DisplayId(format!("mock-{}", index)),
+                    _ => display.id.clone(),
+                },
+                ..display
+            })
+            .collect();
+    }
+
+    /// Set the procedural pattern used when the script is empty
+    pub fn with_pattern(mut self, pattern: MockPattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Sleep for this long on every capture to simulate backend latency
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Append scripted steps that are consumed before falling back to the pattern
+    pub fn with_script(self, steps: impl IntoIterator<Item = MockStep>) -> Self {
+        self.script.lock().extend(steps);
+        self
+    }
+
+    /// Report this cursor from `capture_cursor`
+    pub fn with_cursor(self, cursor: CursorImage) -> Self {
+        self.set_cursor(Some(cursor));
+        self
+    }
+
+    /// Move, reshape or hide (`None`) the reported cursor
+    pub fn set_cursor(&self, cursor: Option<CursorImage>) {
+        *self.cursor.lock() = cursor;
+    }
+
+    /// Queue a prepared frame to be returned by the next capture
+    pub fn push_frame(&self, image: RawImage) {
+        self.script.lock().push_back(MockStep::Frame(image));
+    }
+
+    /// Queue a failure to be returned by the next capture
+    pub fn push_failure(&self, failure: MockFailure) {
+        self.script.lock().push_back(MockStep::Fail(failure));
+    }
+
+    /// Number of scripted steps not yet consumed
+    pub fn pending_steps(&self) -> usize {
+        self.script.lock().len()
+    }
+
+    /// Number of captures served so far, including failures
+    pub fn frames_served(&self) -> u64 {
+        *self.frame_counter.lock()
+    }
+
+    /// Render the configured pattern for a region of the virtual desktop
+    pub fn render(&self, region: CaptureRegion, frame: u64) -> RawImage {
+        self.render_scaled(region, 1.0, frame)
+    }
+
+    /// Render a region at `scale` pixels per unit, as HiDPI displays are captured
+    pub fn render_scaled(&self, region: CaptureRegion, scale: f32, frame: u64) -> RawImage {
+        let scale = if scale.is_finite() && scale > 0.0 { scale } else { 1.0 };
+        let width = ((region.width as f32 * scale).round() as u32).max(1);
+        let height = ((region.height as f32 * scale).round() as u32).max(1);
+        let unit = |pixel: u32, pixels: u32, units: u32| (pixel as u64 * units as u64 / pixels as u64) as i32;
+
+        let rect_origin = self.rectangle_origin(frame);
+        let size = (width * height * 4) as usize;
+        let mut data = self.memory_pool.lock().acquire(size).map(PooledBuffer::into_vec).unwrap_or_default();
+        data.clear();
+        data.reserve(size);
+        for row in 0..height {
+            let y = region.y + unit(row, height, region.height);
+            for col in 0..width {
+                let x = region.x + unit(col, width, region.width);
+                data.extend_from_slice(&self.pattern_pixel(x, y, rect_origin));
+            }
+        }
+        RawImage::new(data, width, height, PixelFormat::RGBA8)
+    }
+
+    /// Compute one pixel of the pattern at global coordinates
+    fn pattern_pixel(&self, x: i32, y: i32, rect_origin: (i32, i32)) -> [u8; 4] {
+        match self.pattern {
+            MockPattern::Solid(color) => color,
+            MockPattern::Gradient => gradient_pixel(x, y),
+            MockPattern::Text => {
+                // 8x12 character cells with 6x8 glyph boxes whose strokes vary per cell
+                let cell_x = x.div_euclid(8);
+                let cell_y = y.div_euclid(12);
+                let in_x = x.rem_euclid(8);
+                let in_y = y.rem_euclid(12);
+                let glyph = (cell_x.wrapping_mul(31) ^ cell_y.wrapping_mul(17)) as u32;
+                let is_space = glyph.is_multiple_of(7) || cell_y.rem_euclid(4) == 3;
+                let stroke = in_x < 6
+                    && (2..10).contains(&in_y)
+                    && (glyph >> ((in_x + in_y) % 8)) & 1 == 1;
+                if !is_space && stroke {
+                    [24, 24, 32, 255]
+                } else {
+                    [245, 245, 240, 255]
+                }
+            }
+            MockPattern::MovingRectangle { size, .. } => {
+                let (rect_x, rect_y) = rect_origin;
+                let size = size as i32;
+                if x >= rect_x && x < rect_x + size && y >= rect_y && y < rect_y + size {
+                    [220, 40, 40, 255]
+                } else {
+                    gradient_pixel(x, y)
+                }
+            }
+        }
+    }
+
+    /// Top-left corner of the moving rectangle, wrapping around the desktop extent
+    fn rectangle_origin(&self, frame: u64) -> (i32, i32) {
+        let MockPattern::MovingRectangle { size, velocity } = self.pattern else {
+            return (0, 0);
+        };
+
+        let (width, height) = self
+            .displays
+            .lock()
+            .iter()
+            .fold((0i64, 0i64), |(w, h), d| {
+                (w.max(d.x as i64 + d.width as i64), h.max(d.y as i64 + d.height as i64))
+            });
+        let span_x = (width - size as i64 + 1).max(1);
+        let span_y = (height - size as i64 + 1).max(1);
+
+        (
+            (velocity.0 as i64 * frame as i64).rem_euclid(span_x) as i32,
+            (velocity.1 as i64 * frame as i64).rem_euclid(span_y) as i32,
+        )
+    }
+
+    /// Consume the next scripted step or render the pattern
+    fn next_frame(&self, region: CaptureRegion, scale: f32, display_index: usize) -> CaptureResult<RawImage> {
+        if !self.latency.is_zero() {
+            std::thread::sleep(self.latency);
+        }
+
+        let frame = {
+            let mut counter = self.frame_counter.lock();
+            let frame = *counter;
+            *counter += 1;
+            frame
+        };
+
+        match self.script.lock().pop_front() {
+            Some(MockStep::Frame(image)) => Ok(image),
+            Some(MockStep::Fail(failure)) => Err(failure.to_error(display_index)),
+            None => Ok(self.render_scaled(region, scale, frame)),
+        }
+    }
+}
+
+impl Default for MockCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Diagonal gradient shared by the gradient and moving-rectangle patterns
+fn gradient_pixel(x: i32, y: i32) -> [u8; 4] {
+    [
+        x.rem_euclid(256) as u8,
+        y.rem_euclid(256) as u8,
+        (x + y).rem_euclid(256) as u8,
+        255,
+    ]
+}
+
+impl ScreenCapture for MockCapture {
+    fn get_displays(&self) -> CaptureResult<Vec<DisplayInfo>> {
+        Ok(self.displays.lock().clone())
+    }
+
+    fn capture_display(&self, display_index: usize) -> CaptureResult<RawImage> {
+        let (bounds, scale_factor) = self
+            .displays
+            .lock()
+            .get(display_index)
+            .map(|display| (display.bounds(), display.scale_factor))
+            .ok_or(CaptureError::DisplayNotFound(display_index))?;
+
+        self.next_frame(CaptureRegion::from_rect(bounds), scale_factor, display_index)
+    }
+
+    fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
+        if region.width == 0 || region.height == 0 {
+            return Err(CaptureError::InvalidConfiguration(format!(
+                "Invalid capture region size: {}x{}",
+                region.width, region.height
+            )));
+        }
+
+        self.next_frame(region, 1.0, 0)
+    }
+
+    fn capture_cursor(&self) -> CaptureResult<Option<CursorImage>> {
+        Ok(self.cursor.lock().clone())
+    }
+
+    fn set_memory_pool(&self, pool: Arc<MemoryPool>) {
+        *self.memory_pool.lock() = pool;
+    }
+
+    fn implementation_name(&self) -> String {
+        "Mock".to_string()
+    }
+
+    fn capabilities(&self) -> CaptureCapabilities {
+        CaptureCapabilities {
+            supports_cursor: true,
+            supports_window_capture: false,
+            supports_hdr: false,
+            max_resolution: (0, 0),
+            supports_multi_display: true,
+            supports_gpu_acceleration: false,
+            estimated_latency_ms: self.latency.as_millis() as u32,
+            supports_zero_copy: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_displays() -> Vec<DisplayInfo> {
+        vec![
+            DisplayInfo {
+                width: 64,
+                height: 48,
+                ..Default::default()
+            },
+            DisplayInfo {
+                x: 64,
+                width: 32,
+                height: 32,
+                is_primary: false,
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_reports_configured_displays() {
+        let mock = MockCapture::new().with_displays(two_displays());
+        let displays = mock.get_displays().unwrap();
+
+        assert_eq!(displays.len(), 2);
+        assert_eq!(displays[1].index, 1);
+        assert_eq!(displays[1].x, 64);
+    }
+
+    #[test]
+    fn test_capture_is_deterministic() {
+        let a = MockCapture::new().with_displays(two_displays()).with_pattern(MockPattern::Text);
+        let b = MockCapture::new().with_displays(two_displays()).with_pattern(MockPattern::Text);
+
+        let first = a.capture_display(1).unwrap();
+        assert_eq!(first.width, 32);
+        assert_eq!(first.height, 32);
+        assert_eq!(first.data, b.capture_display(1).unwrap().data);
+    }
+
+    #[test]
+    fn test_region_matches_display_pixels() {
+        let mock = MockCapture::new().with_displays(two_displays());
+        let display = mock.capture_display(1).unwrap();
+        let region = mock.capture_region(CaptureRegion::new(64, 0, 32, 32)).unwrap();
+
+        assert_eq!(display.data, region.data);
+    }
+
+    #[test]
+    fn test_display_captured_at_scale_factor() {
+        let mut displays = two_displays();
+        displays[1].scale_factor = 2.0;
+        let mock = MockCapture::new().with_displays(displays);
+
+        let display = mock.capture_display(1).unwrap();
+        assert_eq!((display.width, display.height), (64, 64));
+        let region = mock.capture_region(CaptureRegion::new(64, 0, 32, 32)).unwrap();
+        assert_eq!(display.get_pixel(2, 2), region.get_pixel(1, 1));
+    }
+
+    #[test]
+    fn test_moving_rectangle_changes_between_frames() {
+        let mock = MockCapture::new()
+            .with_displays(two_displays())
+            .with_pattern(MockPattern::MovingRectangle { size: 8, velocity: (3, 2) });
+
+        let first = mock.capture_display(0).unwrap();
+        let second = mock.capture_display(0).unwrap();
+        assert_ne!(first.data, second.data);
+        assert_eq!(mock.frames_served(), 2);
+    }
+
+    #[test]
+    fn test_scripted_steps_run_in_order() {
+        let frame = RawImage::new(vec![7u8; 4 * 4 * 4], 4, 4, PixelFormat::RGBA8);
+        let mock = MockCapture::new().with_script(vec![
+            MockStep::Fail(MockFailure::Timeout(Duration::from_millis(250))),
+            MockStep::Frame(frame.clone()),
+        ]);
+        mock.push_failure(MockFailure::PermissionDenied);
+
+        let err = mock.capture_display(0).unwrap_err();
+        assert!(matches!(err, CaptureError::CaptureTimeout { timeout_ms: 250 }));
+        assert!(err.is_recoverable());
+
+        assert_eq!(mock.capture_display(0).unwrap().data, frame.data);
+        assert!(matches!(
+            mock.capture_display(0),
+            Err(CaptureError::PermissionDenied(_))
+        ));
+
+        // Script exhausted: back to the procedural pattern
+        assert_eq!(mock.pending_steps(), 0);
+        assert_eq!(mock.capture_display(0).unwrap().width, 1920);
+    }
+
+    #[test]
+    fn test_unknown_display_is_rejected() {
+        let mock = MockCapture::new();
+        assert!(matches!(
+            mock.capture_display(3),
+            Err(CaptureError::DisplayNotFound(3))
+        ));
+    }
+}
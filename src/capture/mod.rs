@@ -1,6 +1,13 @@
 //! Screen capture module with platform-specific implementations
 
+pub mod cursor;
+pub mod desktop;
+pub mod edid;
+pub mod mock;
+pub mod redaction;
+pub mod region;
 pub mod traits;
+pub mod watcher;
 
 #[cfg(target_os = "windows")]
 pub mod windows;
@@ -11,16 +18,31 @@ pub mod macos;
 #[cfg(target_os = "linux")]
 pub mod linux;
 
-pub use traits::ScreenCapture;
+pub use cursor::composite_cursor;
+pub use desktop::stitch;
+pub use mock::MockCapture;
+pub use redaction::redact;
+pub use traits::{ChangeNotifier, ScreenCapture};
+pub use watcher::{DisplayEvent, DisplayWatcher};
 
 use crate::error::CaptureResult;
 
+/// Environment variable that overrides backend selection (`mock` selects [`MockCapture`])
+pub const BACKEND_ENV_VAR: &str = "WEBP_SCREENSHOT_BACKEND";
+
 /// Platform-specific capturer factory
 pub struct Capturer;
 
 impl Capturer {
     /// Create a new platform-specific capturer
+    ///
+    /// Setting `WEBP_SCREENSHOT_BACKEND=mock` returns the mock backend instead,
+    /// which lets display-dependent code run on headless machines.
     pub fn new() -> CaptureResult<Box<dyn ScreenCapture>> {
+        if Self::mock_requested() {
+            return Ok(Self::mock());
+        }
+
         #[cfg(target_os = "windows")]
         {
             windows::WindowsCapture::new()
@@ -46,6 +68,10 @@ impl Capturer {
 
     /// Create a capturer with hardware acceleration if available
     pub fn with_hardware_acceleration() -> CaptureResult<Box<dyn ScreenCapture>> {
+        if Self::mock_requested() {
+            return Ok(Self::mock());
+        }
+
         #[cfg(target_os = "windows")]
         {
             windows::WindowsCapture::with_hardware_acceleration()
@@ -69,4 +95,16 @@ impl Capturer {
             ))
         }
     }
-}
\ No newline at end of file
+
+    /// Create the deterministic mock capturer with its default display layout
+    pub fn mock() -> Box<dyn ScreenCapture> {
+        Box::new(MockCapture::new())
+    }
+
+    /// Check whether the environment selects the mock backend
+    fn mock_requested() -> bool {
+        std::env::var(BACKEND_ENV_VAR)
+            .map(|backend| backend.eq_ignore_ascii_case("mock"))
+            .unwrap_or(false)
+    }
+}
@@ -0,0 +1,328 @@
+//! Backend-independent redaction of sensitive screen areas
+//!
+//! Redactions are given in logical screen coordinates and mapped onto the
+//! captured image the same way as the cursor, so they line up on HiDPI displays.
+//! Mapped areas are rounded outwards: a partially covered pixel is obscured too.
+
+use crate::{
+    encoder::simd::{global_simd_converter, BLEND_PRECISION},
+    types::{AppliedRedaction, PixelFormat, RawImage, Rectangle, Redaction, RedactionMode, RedactionSpace},
+};
+
+/// Obscure `redactions` in an image captured from `region` (global coordinates)
+///
+/// `display_origin` is the global position of the captured display, which
+/// [`RedactionSpace::Display`] rectangles are relative to. Returns the areas
+/// that were masked, in image pixels, including ones whose pixels already looked
+/// masked; a redaction that overlaps nothing or has an invalid mode (see
+/// [`RedactionMode::validate`]) is not reported.
+pub fn redact(
+    image: &mut RawImage,
+    redactions: &[Redaction],
+    region: Rectangle,
+    display_origin: (i32, i32),
+) -> Vec<AppliedRedaction> {
+    if region.width == 0 || region.height == 0 || !image.is_valid() {
+        return Vec::new();
+    }
+
+    let scale_x = image.width as f64 / region.width as f64;
+    let scale_y = image.height as f64 / region.height as f64;
+    let bounds = Rectangle::new(0, 0, image.width, image.height);
+
+    redactions
+        .iter()
+        .filter(|redaction| redaction.mode.validate().is_ok())
+        .filter_map(|redaction| {
+            let (dx, dy) = match redaction.space {
+                RedactionSpace::Display => display_origin,
+                RedactionSpace::Global => (0, 0),
+            };
+            let rect = redaction.rect;
+            let x = (rect.x as i64 + dx as i64 - region.x as i64) as f64;
+            let y = (rect.y as i64 + dy as i64 - region.y as i64) as f64;
+
+            let left = (x * scale_x).floor().clamp(0.0, image.width as f64);
+            let top = (y * scale_y).floor().clamp(0.0, image.height as f64);
+            let right = ((x + rect.width as f64) * scale_x).ceil().clamp(0.0, image.width as f64);
+            let bottom = ((y + rect.height as f64) * scale_y).ceil().clamp(0.0, image.height as f64);
+
+            let area = Rectangle::new(left as i32, top as i32, (right - left) as u32, (bottom - top) as u32)
+                .intersection(&bounds)?;
+            apply(image, area, redaction.mode);
+            Some(AppliedRedaction {
+                area,
+                mode: redaction.mode,
+            })
+        })
+        .collect()
+}
+
+/// Obscure `area` of `image` (image pixels, clipped to the image)
+///
+/// Returns whether any pixel changed. Invalid modes change nothing.
+pub fn apply(image: &mut RawImage, area: Rectangle, mode: RedactionMode) -> bool {
+    let Some(area) = area.intersection(&Rectangle::new(0, 0, image.width, image.height)) else {
+        return false;
+    };
+    if !image.is_valid() || mode.validate().is_err() {
+        return false;
+    }
+
+    match mode {
+        RedactionMode::Fill(color) => fill(image, area, color),
+        RedactionMode::Pixelate { block } => pixelate(image, area, block),
+        RedactionMode::Blur { radius } => blur(image, area, radius),
+    }
+}
+
+/// Bytes of `area` in each image row
+fn area_rows(image: &mut RawImage, area: Rectangle) -> impl Iterator<Item = &mut [u8]> {
+    let bpp = image.format.bytes_per_pixel();
+    let (left, row_bytes) = (area.x as usize * bpp, area.width as usize * bpp);
+    let stride = image.stride;
+    image.data[area.y as usize * stride..]
+        .chunks_mut(stride)
+        .take(area.height as usize)
+        .map(move |row| &mut row[left..left + row_bytes])
+}
+
+/// `color` (RGBA) laid out in the image's pixel format
+fn encode_color(format: PixelFormat, [r, g, b, a]: [u8; 4]) -> Vec<u8> {
+    let luma = ((r as u32 * 77 + g as u32 * 150 + b as u32 * 29) >> 8) as u8;
+    match format {
+        PixelFormat::RGBA8 => vec![r, g, b, a],
+        PixelFormat::BGRA8 => vec![b, g, r, a],
+        PixelFormat::RGB8 => vec![r, g, b],
+        PixelFormat::BGR8 => vec![b, g, r],
+        PixelFormat::Gray8 => vec![luma],
+        PixelFormat::GrayA8 => vec![luma, a],
+    }
+}
+
+fn fill(image: &mut RawImage, area: Rectangle, color: [u8; 4]) -> bool {
+    let pixel = encode_color(image.format, color);
+    let mut changed = false;
+    for row in area_rows(image, area) {
+        for chunk in row.chunks_exact_mut(pixel.len()) {
+            changed |= chunk != pixel.as_slice();
+            chunk.copy_from_slice(&pixel);
+        }
+    }
+    changed
+}
+
+/// Replace each cell with its average, cells aligned to the area's top-left corner
+fn pixelate(image: &mut RawImage, area: Rectangle, block: u32) -> bool {
+    let bpp = image.format.bytes_per_pixel();
+    let block = block as usize;
+    let mut changed = false;
+
+    for cell_y in (0..area.height as usize).step_by(block) {
+        let cell_height = block.min(area.height as usize - cell_y);
+        let band = Rectangle::new(area.x, area.y + cell_y as i32, area.width, cell_height as u32);
+        let mut rows: Vec<&mut [u8]> = area_rows(image, band).collect();
+
+        for cell_x in (0..area.width as usize).step_by(block) {
+            let span = cell_x * bpp..(cell_x + block.min(area.width as usize - cell_x)) * bpp;
+
+            let mut sums = [0u64; 4];
+            let mut count = 0u64;
+            for row in rows.iter() {
+                for pixel in row[span.clone()].chunks_exact(bpp) {
+                    for (sum, &value) in sums.iter_mut().zip(pixel) {
+                        *sum += value as u64;
+                    }
+                    count += 1;
+                }
+            }
+
+            let average: Vec<u8> = sums[..bpp].iter().map(|&sum| ((sum + count / 2) / count) as u8).collect();
+            for row in rows.iter_mut() {
+                for pixel in row[span.clone()].chunks_exact_mut(bpp) {
+                    changed |= pixel != average.as_slice();
+                    pixel.copy_from_slice(&average);
+                }
+            }
+        }
+    }
+    changed
+}
+
+/// Fixed-point Gaussian taps for offsets `-radius..=radius`
+fn gaussian_weights(radius: u32) -> Vec<i16> {
+    let sigma = (radius as f64 / 3.0).max(0.5);
+    let taps: Vec<f64> = (-(radius as i64)..=radius as i64)
+        .map(|x| (-(x * x) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = taps.iter().sum();
+    let one = 1i32 << BLEND_PRECISION;
+
+    let mut weights: Vec<i16> = taps.iter().map(|&t| (t / total * one as f64).round() as i16).collect();
+    // Keep the sum exact so flat areas stay flat
+    let sum: i32 = weights.iter().map(|&w| w as i32).sum();
+    weights[radius as usize] += (one - sum) as i16;
+    weights
+}
+
+/// Separable Gaussian blur confined to `area`; edges are clamped so nothing outside bleeds in
+fn blur(image: &mut RawImage, area: Rectangle, radius: u32) -> bool {
+    let bpp = image.format.bytes_per_pixel();
+    let (width, height) = (area.width as usize, area.height as usize);
+    let row_bytes = width * bpp;
+    let weights = gaussian_weights(radius);
+    let radius = radius as i64;
+    let round = 1 << (BLEND_PRECISION - 1);
+
+    // Horizontal pass into a packed copy of the area
+    let mut horizontal = vec![0u8; row_bytes * height];
+    for (row, out_row) in area_rows(image, area).zip(horizontal.chunks_exact_mut(row_bytes)) {
+        for x in 0..width {
+            for c in 0..bpp {
+                let sum: i32 = weights
+                    .iter()
+                    .enumerate()
+                    .map(|(k, &w)| {
+                        let sx = (x as i64 + k as i64 - radius).clamp(0, width as i64 - 1) as usize;
+                        row[sx * bpp + c] as i32 * w as i32
+                    })
+                    .sum();
+                out_row[x * bpp + c] = ((sum + round) >> BLEND_PRECISION).clamp(0, 255) as u8;
+            }
+        }
+    }
+
+    // Vertical pass straight back into the image
+    let converter = global_simd_converter();
+    let rows: Vec<&[u8]> = horizontal.chunks_exact(row_bytes).collect();
+    let mut taps = Vec::with_capacity(weights.len());
+    let mut previous = Vec::with_capacity(row_bytes);
+    let mut changed = false;
+    for (y, out_row) in area_rows(image, area).enumerate() {
+        taps.clear();
+        taps.extend((-radius..=radius).map(|k| rows[(y as i64 + k).clamp(0, height as i64 - 1) as usize]));
+        previous.clear();
+        previous.extend_from_slice(out_row);
+        converter.blend_rows(&taps, &weights, out_row);
+        changed |= previous != *out_row;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RGBA image with a hard vertical edge at x = 8, padded rows
+    fn edge_image() -> RawImage {
+        let (width, height, stride) = (16u32, 12u32, 16 * 4 + 8);
+        let mut data = vec![0xA5u8; stride * height as usize];
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let value = if x < 8 { 0 } else { 240 };
+                data[y * stride + x * 4..y * stride + x * 4 + 4].copy_from_slice(&[value, value, value, 255]);
+            }
+        }
+        RawImage::with_stride(data, width, height, PixelFormat::RGBA8, stride)
+    }
+
+    fn padding_intact(image: &RawImage) -> bool {
+        image.data.chunks(image.stride).all(|row| row[image.row_bytes()..].iter().all(|&b| b == 0xA5))
+    }
+
+    #[test]
+    fn test_fill_honors_format_and_bounds() {
+        let mut image = edge_image();
+        let applied = redact(
+            &mut image,
+            &[Redaction::new(Rectangle::new(12, 10, 10, 10), RedactionMode::Fill([255, 0, 0, 255]))],
+            Rectangle::new(0, 0, 16, 12),
+            (0, 0),
+        );
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].area, Rectangle::new(12, 10, 4, 2));
+        assert_eq!(image.get_pixel(15, 11).unwrap(), &[255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(11, 11).unwrap(), &[240, 240, 240, 255]);
+        assert!(padding_intact(&image));
+
+        let mut bgr = RawImage::new(vec![0; 2 * 3], 2, 1, PixelFormat::BGR8);
+        apply(&mut bgr, Rectangle::new(1, 0, 1, 1), RedactionMode::Fill([10, 20, 30, 255]));
+        assert_eq!(bgr.data, [0, 0, 0, 30, 20, 10]);
+    }
+
+    #[test]
+    fn test_coordinate_spaces() {
+        // Display at (100, 50), captured at 2x through a region starting at (104, 50)
+        let region = Rectangle::new(104, 50, 8, 6);
+        let rect = Rectangle::new(5, 1, 2, 2);
+
+        let mut image = edge_image();
+        let display = redact(&mut image, &[Redaction::new(rect, RedactionMode::Fill([0; 4]))], region, (100, 50));
+        assert_eq!(display[0].area, Rectangle::new(2, 2, 4, 4));
+
+        let global = Redaction::new(Rectangle::new(105, 51, 2, 2), RedactionMode::Fill([0; 4])).global();
+        let mut image = edge_image();
+        assert_eq!(redact(&mut image, &[global], region, (100, 50))[0].area, Rectangle::new(2, 2, 4, 4));
+
+        // Areas off the image are not reported
+        let mut image = edge_image();
+        assert!(redact(&mut image, &[global], Rectangle::new(0, 0, 8, 6), (0, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_pixelate_averages_cells() {
+        let mut image = edge_image();
+        apply(&mut image, Rectangle::new(4, 0, 8, 12), RedactionMode::Pixelate { block: 8 });
+        // The first cell straddles the edge: half black, half 240
+        assert_eq!(image.get_pixel(4, 0).unwrap(), &[120, 120, 120, 255]);
+        assert_eq!(image.get_pixel(11, 7).unwrap(), &[120, 120, 120, 255]);
+        // Outside the area is untouched
+        assert_eq!(image.get_pixel(3, 0).unwrap(), &[0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(12, 0).unwrap(), &[240, 240, 240, 255]);
+        assert!(padding_intact(&image));
+    }
+
+    #[test]
+    fn test_invalid_and_offscreen_redactions_not_reported() {
+        let region = Rectangle::new(0, 0, 16, 12);
+        let mut image = edge_image();
+        let original = image.data.clone();
+        for mode in [RedactionMode::Blur { radius: 0 }, RedactionMode::Pixelate { block: 1 }, RedactionMode::Pixelate { block: 0 }] {
+            assert!(redact(&mut image, &[Redaction::new(region, mode)], region, (0, 0)).is_empty());
+        }
+        let offscreen = Redaction::new(Rectangle::new(20, 0, 4, 4), RedactionMode::Fill([0; 4]));
+        assert!(redact(&mut image, &[offscreen], region, (0, 0)).is_empty());
+        assert_eq!(image.data, original);
+    }
+
+    #[test]
+    fn test_masked_areas_reported_even_if_unchanged() {
+        let region = Rectangle::new(0, 0, 16, 12);
+        let mut image = edge_image();
+        let original = image.data.clone();
+        // A flat area looks the same blurred, and black filled with black
+        let flat = Redaction::new(Rectangle::new(0, 0, 4, 12), RedactionMode::Blur { radius: 2 });
+        let black = Redaction::new(Rectangle::new(0, 0, 4, 4), RedactionMode::Fill([0, 0, 0, 255]));
+        let applied = redact(&mut image, &[flat, black], region, (0, 0));
+        assert_eq!(applied.len(), 2);
+        assert_eq!(applied[0].area, Rectangle::new(0, 0, 4, 12));
+        assert_eq!(applied[1].area, Rectangle::new(0, 0, 4, 4));
+        assert_eq!(image.data, original);
+    }
+
+    #[test]
+    fn test_blur_softens_edge_inside_area_only() {
+        let mut image = edge_image();
+        assert!(apply(&mut image, Rectangle::new(2, 2, 12, 8), RedactionMode::Blur { radius: 6 }));
+
+        let value = |x, y| image.get_pixel(x, y).unwrap()[0];
+        assert!(value(7, 5) > 40 && value(7, 5) < 200, "{}", value(7, 5));
+        assert!(value(8, 5) > 40 && value(8, 5) < 200, "{}", value(8, 5));
+        assert!(value(7, 5) < value(8, 5));
+        // Flat areas and opaque alpha survive the fixed-point rounding
+        assert_eq!(image.get_pixel(7, 5).unwrap()[3], 255);
+        assert_eq!(value(1, 5), 0);
+        assert_eq!(value(8, 1), 240);
+        assert!(padding_intact(&image));
+    }
+}
@@ -0,0 +1,183 @@
+//! Fitting capture regions to the displays
+//!
+//! Backends read the screen through APIs with narrow coordinate types (X11 takes
+//! 16-bit offsets and sizes), so a region is checked against the displays before
+//! it reaches a backend. [`resolve`] applies the [`RegionPolicy`] and [`pad`]
+//! grows a clamped capture back to the requested size for [`RegionPolicy::Pad`].
+
+use crate::{
+    capture::desktop::desktop_bounds,
+    error::{CaptureError, CaptureResult, EncodingError, EncodingResult},
+    types::{CaptureRegion, DisplayInfo, PixelFormat, RawImage, Rectangle, RegionPolicy, ResolvedRegion},
+};
+
+/// Fit `region` to `displays` according to `policy`
+pub fn resolve(region: CaptureRegion, displays: &[DisplayInfo], policy: RegionPolicy) -> CaptureResult<ResolvedRegion> {
+    if region.width == 0 || region.height == 0 {
+        return Err(CaptureError::InvalidConfiguration(format!(
+            "Empty capture region: {}x{}",
+            region.width, region.height
+        )));
+    }
+    let bounds = desktop_bounds(displays)
+        .ok_or_else(|| CaptureError::DisplayEnumerationFailed("No displays to capture from".to_string()))?;
+
+    let requested = Rectangle::new(region.x, region.y, region.width, region.height);
+    let (left, top) = (region.x as i64, region.y as i64);
+    let (right, bottom) = (left + region.width as i64, top + region.height as i64);
+    // Rectangle arithmetic is done in i32
+    let representable = right <= i32::MAX as i64 && bottom <= i32::MAX as i64;
+
+    let clip_left = left.max(bounds.x as i64);
+    let clip_top = top.max(bounds.y as i64);
+    let clip_right = right.min(bounds.x as i64 + bounds.width as i64);
+    let clip_bottom = bottom.min(bounds.y as i64 + bounds.height as i64);
+    if clip_right <= clip_left || clip_bottom <= clip_top {
+        return Err(CaptureError::InvalidConfiguration(format!(
+            "Capture region {:?} is outside all displays",
+            requested
+        )));
+    }
+    let captured = Rectangle::new(
+        clip_left as i32,
+        clip_top as i32,
+        (clip_right - clip_left) as u32,
+        (clip_bottom - clip_top) as u32,
+    );
+
+    match policy {
+        RegionPolicy::Error if !representable || captured != requested || !uncovered(captured, displays).is_empty() => {
+            return Err(CaptureError::InvalidConfiguration(format!(
+                "Capture region {:?} extends beyond the displays",
+                requested
+            )));
+        }
+        RegionPolicy::Pad if !representable => {
+            return Err(CaptureError::InvalidConfiguration(format!(
+                "Capture region {:?} is too large to pad",
+                requested
+            )));
+        }
+        _ => {}
+    }
+
+    Ok(ResolvedRegion {
+        requested,
+        captured,
+        image_area: if policy == RegionPolicy::Pad { requested } else { captured },
+        displays: displays
+            .iter()
+            .enumerate()
+            .filter(|(_, display)| display.bounds().intersection(&captured).is_some())
+            .map(|(index, _)| index)
+            .collect(),
+    })
+}
+
+/// Place an image of `region.captured` within a transparent RGBA image of `region.requested`
+///
+/// Parts of the captured area that no display covers are made transparent too.
+pub fn pad(image: &RawImage, region: &ResolvedRegion, displays: &[DisplayInfo]) -> EncodingResult<RawImage> {
+    let (requested, captured) = (region.requested, region.captured);
+    let scale_x = image.width as f64 / captured.width as f64;
+    let scale_y = image.height as f64 / captured.height as f64;
+    let column = |x: i64| ((x - requested.x as i64) as f64 * scale_x).round().max(0.0) as usize;
+    let row = |y: i64| ((y - requested.y as i64) as f64 * scale_y).round().max(0.0) as usize;
+
+    let width = column(requested.x as i64 + requested.width as i64);
+    let height = row(requested.y as i64 + requested.height as i64);
+    let too_large = || EncodingError::InvalidDimensions {
+        width: width.min(u32::MAX as usize) as u32,
+        height: height.min(u32::MAX as usize) as u32,
+    };
+    let (Ok(width_u32), Ok(height_u32)) = (u32::try_from(width), u32::try_from(height)) else {
+        return Err(too_large());
+    };
+    let row_size = width.checked_mul(4).ok_or_else(too_large)?;
+    let mut canvas = vec![0u8; row_size.checked_mul(height).ok_or_else(too_large)?];
+
+    let rgba = image.to_packed_rgba()?;
+    let (left, top) = (column(captured.x as i64), row(captured.y as i64));
+    let copy = (image.width as usize).min(width.saturating_sub(left)) * 4;
+    for (y, source) in rgba.chunks_exact(image.width as usize * 4).take(height.saturating_sub(top)).enumerate() {
+        let start = (top + y) * row_size + left * 4;
+        canvas[start..start + copy].copy_from_slice(&source[..copy]);
+    }
+
+    for gap in uncovered(captured, displays) {
+        let (x0, x1) = (column(gap.x as i64), column(gap.x as i64 + gap.width as i64).min(width));
+        for y in row(gap.y as i64)..row(gap.y as i64 + gap.height as i64).min(height) {
+            canvas[y * row_size + x0 * 4..y * row_size + x1 * 4].fill(0);
+        }
+    }
+
+    Ok(RawImage::new(canvas, width_u32, height_u32, PixelFormat::RGBA8))
+}
+
+/// Parts of `rect` that no display covers
+fn uncovered(rect: Rectangle, displays: &[DisplayInfo]) -> Vec<Rectangle> {
+    displays.iter().fold(vec![rect], |pieces, display| {
+        pieces.iter().flat_map(|piece| piece.subtract(&display.bounds())).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 100x100 display with a 50x50 one to its right, top-aligned
+    fn displays() -> Vec<DisplayInfo> {
+        vec![
+            DisplayInfo { index: 0, width: 100, height: 100, ..Default::default() },
+            DisplayInfo { index: 1, x: 100, width: 50, height: 50, is_primary: false, ..Default::default() },
+        ]
+    }
+
+    #[test]
+    fn test_resolve_clamps_to_displays() {
+        let region = resolve(CaptureRegion::new(-20, 90, 200, 50), &displays(), RegionPolicy::Clamp).unwrap();
+        assert_eq!(region.captured, Rectangle::new(0, 90, 150, 10));
+        assert_eq!(region.image_area, region.captured);
+        // The second display ends at y = 50
+        assert_eq!(region.displays, vec![0]);
+
+        let region = resolve(CaptureRegion::new(90, 10, 20, 20), &displays(), RegionPolicy::Clamp).unwrap();
+        assert_eq!(region.captured, region.requested);
+        assert_eq!(region.displays, vec![0, 1]);
+
+        // Sizes that don't fit X11's 16-bit fields or even i32 coordinates are clamped too
+        let region = resolve(CaptureRegion::new(-70_000, -70_000, u32::MAX, u32::MAX), &displays(), RegionPolicy::Clamp);
+        assert_eq!(region.unwrap().captured, Rectangle::new(0, 0, 150, 100));
+    }
+
+    #[test]
+    fn test_resolve_error_policy() {
+        let inside = resolve(CaptureRegion::new(90, 0, 60, 50), &displays(), RegionPolicy::Error).unwrap();
+        assert_eq!(inside.captured, inside.requested);
+
+        // Off the edge, and inside the bounding box but below the second display
+        for region in [CaptureRegion::new(-1, 0, 10, 10), CaptureRegion::new(90, 40, 20, 20)] {
+            let result = resolve(region, &displays(), RegionPolicy::Error);
+            assert!(matches!(result, Err(CaptureError::InvalidConfiguration(_))), "{region:?}");
+        }
+        assert!(resolve(CaptureRegion::new(0, 0, 0, 10), &displays(), RegionPolicy::Clamp).is_err());
+        assert!(resolve(CaptureRegion::new(500, 0, 10, 10), &displays(), RegionPolicy::Clamp).is_err());
+        assert!(resolve(CaptureRegion::new(i32::MAX - 5, 0, u32::MAX, 10), &displays(), RegionPolicy::Clamp).is_err());
+    }
+
+    #[test]
+    fn test_pad_fills_off_screen_areas() {
+        let displays = displays();
+        let region = resolve(CaptureRegion::new(140, 40, 20, 20), &displays, RegionPolicy::Pad).unwrap();
+        assert_eq!(region.captured, Rectangle::new(140, 40, 10, 20));
+        assert_eq!(region.image_area, region.requested);
+
+        let captured = RawImage::new([10, 20, 30, 255].repeat(10 * 20), 10, 20, PixelFormat::BGRA8);
+        let padded = pad(&captured, &region, &displays).unwrap();
+        assert_eq!((padded.width, padded.height, padded.format), (20, 20, PixelFormat::RGBA8));
+        assert_eq!(padded.get_pixel(0, 0).unwrap(), [30, 20, 10, 255]);
+        // Right of the desktop, and below the second display inside the bounding box
+        assert_eq!(padded.get_pixel(15, 0).unwrap(), [0, 0, 0, 0]);
+        assert_eq!(padded.get_pixel(0, 15).unwrap(), [0, 0, 0, 0]);
+    }
+}
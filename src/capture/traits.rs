@@ -1,7 +1,14 @@
 //! Traits for screen capture functionality
 
-use crate::error::CaptureResult;
-use crate::types::{CaptureRegion, DisplayInfo, RawImage};
+use crate::error::{CaptureError, CaptureResult};
+use crate::memory_pool::MemoryPool;
+use crate::types::{
+    CaptureRegion, CursorImage, DisplayInfo, RawImage, WindowFilter, WindowId, WindowInfo,
+    WindowRedaction,
+};
+
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Main trait for screen capture implementations
 pub trait ScreenCapture: Send + Sync {
@@ -14,6 +21,70 @@ pub trait ScreenCapture: Send + Sync {
     /// Capture a specific region of the screen
     fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage>;
 
+    /// Current cursor shape and pointer position, if the backend can report them
+    ///
+    /// Returns `Ok(None)` when the cursor is hidden or the backend has no access to it.
+    fn capture_cursor(&self) -> CaptureResult<Option<CursorImage>> {
+        Ok(None)
+    }
+
+    /// List top-level windows
+    fn list_windows(&self) -> CaptureResult<Vec<WindowInfo>> {
+        Err(CaptureError::PlatformError(format!(
+            "{} does not support window capture",
+            self.implementation_name()
+        )))
+    }
+
+    /// List top-level windows matching a filter
+    fn find_windows(&self, filter: &WindowFilter) -> CaptureResult<Vec<WindowInfo>> {
+        Ok(self
+            .list_windows()?
+            .into_iter()
+            .filter(|window| window.matches(filter))
+            .collect())
+    }
+
+    /// Capture the contents of a single window, even when it is occluded
+    fn capture_window(&self, window: WindowId) -> CaptureResult<RawImage> {
+        Err(CaptureError::PlatformError(format!(
+            "{} cannot capture window {}",
+            self.implementation_name(),
+            window
+        )))
+    }
+
+    /// Mask the visible parts of matching windows in every display or region capture
+    ///
+    /// Backends that cannot see other clients' windows return an error rather than
+    /// silently capturing unmasked pixels. An empty list turns masking off.
+    fn set_window_redactions(&self, rules: Vec<WindowRedaction>) -> CaptureResult<()> {
+        if rules.is_empty() {
+            return Ok(());
+        }
+        Err(CaptureError::PlatformError(format!(
+            "{} cannot redact windows",
+            self.implementation_name()
+        )))
+    }
+
+    /// Have the backend draw the cursor into later display and region captures
+    ///
+    /// For backends that can't report it through [`capture_cursor`](Self::capture_cursor)
+    /// but can composite it themselves; others ignore it.
+    fn set_cursor_overlay(&self, _enabled: bool) {}
+
+    /// Allocate capture buffers from `pool` instead of the global pool
+    fn set_memory_pool(&self, _pool: Arc<MemoryPool>) {}
+
+    /// Notifications of display configuration changes, on a connection of their own
+    ///
+    /// `Ok(None)` means the backend has none, and a
+    /// [`DisplayWatcher`](crate::capture::watcher::DisplayWatcher) polls it instead.
+    fn change_notifier(&self) -> CaptureResult<Option<Box<dyn ChangeNotifier>>> {
+        Ok(None)
+    }
+
     /// Get the implementation name
     fn implementation_name(&self) -> String;
 
@@ -33,6 +104,79 @@ pub trait ScreenCapture: Send + Sync {
     }
 }
 
+/// Shared capturers, e.g. a mock whose displays a test changes while a pipeline uses it
+impl<T: ScreenCapture + ?Sized> ScreenCapture for Arc<T> {
+    fn get_displays(&self) -> CaptureResult<Vec<DisplayInfo>> {
+        (**self).get_displays()
+    }
+
+    fn capture_display(&self, display_index: usize) -> CaptureResult<RawImage> {
+        (**self).capture_display(display_index)
+    }
+
+    fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
+        (**self).capture_region(region)
+    }
+
+    fn capture_cursor(&self) -> CaptureResult<Option<CursorImage>> {
+        (**self).capture_cursor()
+    }
+
+    fn list_windows(&self) -> CaptureResult<Vec<WindowInfo>> {
+        (**self).list_windows()
+    }
+
+    fn find_windows(&self, filter: &WindowFilter) -> CaptureResult<Vec<WindowInfo>> {
+        (**self).find_windows(filter)
+    }
+
+    fn capture_window(&self, window: WindowId) -> CaptureResult<RawImage> {
+        (**self).capture_window(window)
+    }
+
+    fn set_window_redactions(&self, rules: Vec<WindowRedaction>) -> CaptureResult<()> {
+        (**self).set_window_redactions(rules)
+    }
+
+    fn set_cursor_overlay(&self, enabled: bool) {
+        (**self).set_cursor_overlay(enabled)
+    }
+
+    fn set_memory_pool(&self, pool: Arc<MemoryPool>) {
+        (**self).set_memory_pool(pool)
+    }
+
+    fn change_notifier(&self) -> CaptureResult<Option<Box<dyn ChangeNotifier>>> {
+        (**self).change_notifier()
+    }
+
+    fn implementation_name(&self) -> String {
+        (**self).implementation_name()
+    }
+
+    fn is_hardware_accelerated(&self) -> bool {
+        (**self).is_hardware_accelerated()
+    }
+
+    fn is_available(&self) -> bool {
+        (**self).is_available()
+    }
+
+    fn capabilities(&self) -> CaptureCapabilities {
+        (**self).capabilities()
+    }
+}
+
+/// Wakes a [`DisplayWatcher`](crate::capture::watcher::DisplayWatcher) when the
+/// display configuration may have changed
+pub trait ChangeNotifier: Send {
+    /// Block until a notification arrives or `timeout` elapses, returning whether one arrived
+    ///
+    /// Spurious notifications are harmless: the watcher compares the display
+    /// lists before reporting anything.
+    fn wait(&mut self, timeout: Duration) -> CaptureResult<bool>;
+}
+
 /// Capabilities of a capture implementation
 #[derive(Debug, Clone, Default)]
 pub struct CaptureCapabilities {
@@ -50,6 +194,8 @@ pub struct CaptureCapabilities {
     pub supports_gpu_acceleration: bool,
     /// Estimated capture latency in milliseconds
     pub estimated_latency_ms: u32,
+    /// The platform zero-copy optimizer may capture on behalf of this backend
+    pub supports_zero_copy: bool,
 }
 
 /// Helper trait for pixel format conversion
@@ -65,6 +211,28 @@ pub trait PixelFormatConverter {
 
     /// Flip image vertically
     fn flip_vertical(&self, data: &mut [u8], width: u32, height: u32, bytes_per_pixel: u32);
+
+    /// Flip image vertically where rows start `stride` bytes apart; padding stays in place
+    fn flip_vertical_with_stride(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        bytes_per_pixel: u32,
+        stride: usize,
+    ) {
+        let row_size = (width * bytes_per_pixel) as usize;
+        let height = height as usize;
+
+        for y in 0..height / 2 {
+            let top_offset = y * stride;
+            let bottom_offset = (height - 1 - y) * stride;
+
+            // The bottom row starts past the end of the top row, so split there
+            let (top, bottom) = data.split_at_mut(bottom_offset);
+            top[top_offset..top_offset + row_size].swap_with_slice(&mut bottom[..row_size]);
+        }
+    }
 }
 
 /// Default implementation for pixel format conversion
@@ -96,20 +264,8 @@ impl PixelFormatConverter for DefaultPixelConverter {
     }
 
     fn flip_vertical(&self, data: &mut [u8], width: u32, height: u32, bytes_per_pixel: u32) {
-        let row_size = (width * bytes_per_pixel) as usize;
-        let mut temp_row = vec![0u8; row_size];
-
-        for y in 0..height / 2 {
-            let top_offset = (y * width * bytes_per_pixel) as usize;
-            let bottom_offset = ((height - 1 - y) * width * bytes_per_pixel) as usize;
-
-            // Copy top row to temp
-            temp_row.copy_from_slice(&data[top_offset..top_offset + row_size]);
-            // Copy bottom to top
-            data.copy_within(bottom_offset..bottom_offset + row_size, top_offset);
-            // Copy temp to bottom
-            data[bottom_offset..bottom_offset + row_size].copy_from_slice(&temp_row);
-        }
+        let stride = (width * bytes_per_pixel) as usize;
+        self.flip_vertical_with_stride(data, width, height, bytes_per_pixel, stride);
     }
 }
 
@@ -203,4 +359,24 @@ pub mod simd_converter {
             data.swap(i, i + 2);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::strategies::{packed_and_padded, padding_intact, ALL_FORMATS};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_flip_vertical_with_stride_skips_padding((mut packed, mut padded) in packed_and_padded(ALL_FORMATS)) {
+            let bpp = packed.format.bytes_per_pixel() as u32;
+            let converter = DefaultPixelConverter;
+            converter.flip_vertical(&mut packed.data, packed.width, packed.height, bpp);
+            converter.flip_vertical_with_stride(&mut padded.data, padded.width, padded.height, bpp, padded.stride);
+
+            prop_assert_eq!(padded.to_packed().data, packed.data);
+            prop_assert!(padding_intact(&padded));
+        }
+    }
+}
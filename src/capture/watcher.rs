@@ -0,0 +1,335 @@
+//! Display hotplug and configuration change notifications
+//!
+//! A [`DisplayWatcher`] re-enumerates the displays on a background thread
+//! whenever the backend's [`ChangeNotifier`] fires (RandR on X11, `wl_output`
+//! on Wayland) or, for backends without one, at a fixed interval. The new list
+//! is compared with the previous one by [`DisplayId`], so notifications that
+//! change nothing visible produce no events, and each subscriber receives the
+//! typed [`DisplayEvent`]s through its own channel.
+
+use crate::{
+    capture::traits::{ChangeNotifier, ScreenCapture},
+    error::CaptureResult,
+    types::{DisplayId, DisplayInfo},
+};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use parking_lot::Mutex;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Re-enumeration interval for backends without change notifications
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Longest the watcher thread waits before checking whether it was stopped
+const WAIT_SLICE: Duration = Duration::from_millis(200);
+
+/// Quiet time after a notification before enumerating; a single hotplug
+/// produces a burst of them while the new layout is being applied
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Change to the display configuration
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayEvent {
+    /// A display was connected or enabled
+    Added(DisplayInfo),
+    /// A display was disconnected or disabled
+    Removed(DisplayInfo),
+    /// A display's position, resolution, scale, orientation or refresh rate changed
+    ModeChanged {
+        /// Display before the change
+        old: DisplayInfo,
+        /// Display after the change
+        new: DisplayInfo,
+    },
+    /// Another display became primary
+    PrimaryChanged {
+        /// Previous primary display
+        old: Option<DisplayId>,
+        /// New primary display
+        new: Option<DisplayId>,
+    },
+}
+
+impl DisplayEvent {
+    /// Identifier of the display the event is about (the new primary for [`PrimaryChanged`](Self::PrimaryChanged))
+    pub fn display_id(&self) -> Option<&DisplayId> {
+        match self {
+            DisplayEvent::Added(display) | DisplayEvent::Removed(display) => Some(&display.id),
+            DisplayEvent::ModeChanged { new, .. } => Some(&new.id),
+            DisplayEvent::PrimaryChanged { new, .. } => new.as_ref(),
+        }
+    }
+}
+
+/// Events that turn the `old` display list into the `new` one
+///
+/// Displays are matched by identifier; removals come first, then additions,
+/// mode changes and finally a primary change.
+pub fn diff_displays(old: &[DisplayInfo], new: &[DisplayInfo]) -> Vec<DisplayEvent> {
+    let find = |displays: &[DisplayInfo], id: &DisplayId| displays.iter().find(|display| display.id == *id).cloned();
+    let mut events = Vec::new();
+
+    for display in old {
+        if find(new, &display.id).is_none() {
+            events.push(DisplayEvent::Removed(display.clone()));
+        }
+    }
+    for display in new {
+        if find(old, &display.id).is_none() {
+            events.push(DisplayEvent::Added(display.clone()));
+        }
+    }
+    for display in new {
+        if let Some(previous) = find(old, &display.id).filter(|previous| !same_mode(previous, display)) {
+            events.push(DisplayEvent::ModeChanged { old: previous, new: display.clone() });
+        }
+    }
+
+    let primary = |displays: &[DisplayInfo]| {
+        displays.iter().find(|display| display.is_primary).map(|display| display.id.clone())
+    };
+    let (old_primary, new_primary) = (primary(old), primary(new));
+    if old_primary != new_primary {
+        events.push(DisplayEvent::PrimaryChanged { old: old_primary, new: new_primary });
+    }
+    events
+}
+
+/// Whether two enumerations of a display show it in the same mode
+fn same_mode(a: &DisplayInfo, b: &DisplayInfo) -> bool {
+    a.bounds() == b.bounds()
+        && a.scale_factor == b.scale_factor
+        && a.refresh_rate == b.refresh_rate
+        && a.color_depth == b.color_depth
+        && a.rotation == b.rotation
+        && a.reflect_x == b.reflect_x
+        && a.reflect_y == b.reflect_y
+}
+
+/// Fires every `interval`, for backends without change notifications
+pub struct PollingNotifier {
+    interval: Duration,
+    next: Instant,
+}
+
+impl PollingNotifier {
+    /// Fire every `interval`
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Instant::now() + interval,
+        }
+    }
+}
+
+impl ChangeNotifier for PollingNotifier {
+    fn wait(&mut self, timeout: Duration) -> CaptureResult<bool> {
+        let now = Instant::now();
+        if now < self.next {
+            thread::sleep(timeout.min(self.next - now));
+        }
+        if Instant::now() < self.next {
+            return Ok(false);
+        }
+        self.next = Instant::now() + self.interval;
+        Ok(true)
+    }
+}
+
+/// Background watcher delivering [`DisplayEvent`]s to subscribers
+pub struct DisplayWatcher {
+    displays: Arc<Mutex<Vec<DisplayInfo>>>,
+    subscribers: Arc<Mutex<Vec<Sender<DisplayEvent>>>>,
+    running: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl DisplayWatcher {
+    /// Watch `capturer`'s displays through its change notifications, polling
+    /// every [`DEFAULT_POLL_INTERVAL`] if it has none
+    pub fn new(capturer: Box<dyn ScreenCapture>) -> CaptureResult<Self> {
+        match capturer.change_notifier()? {
+            Some(notifier) => Self::with_notifier(capturer, notifier),
+            None => Self::polling(capturer, DEFAULT_POLL_INTERVAL),
+        }
+    }
+
+    /// Watch `capturer`'s displays by re-enumerating them every `interval`
+    pub fn polling(capturer: Box<dyn ScreenCapture>, interval: Duration) -> CaptureResult<Self> {
+        Self::spawn(capturer, Box::new(PollingNotifier::new(interval)), false)
+    }
+
+    /// Watch `capturer`'s displays, re-enumerating them whenever `notifier` fires
+    pub fn with_notifier(capturer: Box<dyn ScreenCapture>, notifier: Box<dyn ChangeNotifier>) -> CaptureResult<Self> {
+        Self::spawn(capturer, notifier, true)
+    }
+
+    /// Start the watcher thread; `settle` waits out bursts of notifications,
+    /// which polling doesn't produce
+    fn spawn(
+        capturer: Box<dyn ScreenCapture>,
+        mut notifier: Box<dyn ChangeNotifier>,
+        mut settle: bool,
+    ) -> CaptureResult<Self> {
+        let displays = Arc::new(Mutex::new(capturer.get_displays()?));
+        let subscribers = Arc::new(Mutex::new(Vec::<Sender<DisplayEvent>>::new()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread = {
+            let displays = Arc::clone(&displays);
+            let subscribers = Arc::clone(&subscribers);
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    match notifier.wait(WAIT_SLICE) {
+                        Ok(false) => continue,
+                        Ok(true) => {
+                            // Let the burst of notifications for one change pass
+                            while settle && running.load(Ordering::Relaxed) && matches!(notifier.wait(SETTLE_TIME), Ok(true)) {}
+                        }
+                        Err(e) => {
+                            log::warn!("Display change notifications failed, polling instead: {}", e);
+                            notifier = Box::new(PollingNotifier::new(DEFAULT_POLL_INTERVAL));
+                            settle = false;
+                            continue;
+                        }
+                    }
+
+                    let current = match capturer.get_displays() {
+                        Ok(current) => current,
+                        Err(e) => {
+                            log::debug!("Display enumeration failed while watching: {}", e);
+                            continue;
+                        }
+                    };
+                    let events = diff_displays(&displays.lock(), &current);
+                    *displays.lock() = current;
+
+                    let mut subscribers = subscribers.lock();
+                    for event in events {
+                        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            displays,
+            subscribers,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    /// Receive every event from now on
+    pub fn subscribe(&self) -> Receiver<DisplayEvent> {
+        let (tx, rx) = unbounded();
+        self.subscribers.lock().push(tx);
+        rx
+    }
+
+    /// Displays as of the last enumeration
+    pub fn displays(&self) -> Vec<DisplayInfo> {
+        self.displays.lock().clone()
+    }
+
+    /// Whether the watcher thread is still running
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Stop watching; subscribers' channels disconnect once drained
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        self.subscribers.lock().clear();
+    }
+}
+
+impl Drop for DisplayWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::mock::MockCapture;
+
+    fn display(id: &str, x: i32, width: u32, is_primary: bool) -> DisplayInfo {
+        DisplayInfo {
+            id: DisplayId(id.to_string()),
+            name: id.to_string(),
+            x,
+            width,
+            height: 100,
+            is_primary,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_displays() {
+        let old = vec![display("a", 0, 100, true), display("b", 100, 100, false)];
+        assert!(diff_displays(&old, &old).is_empty());
+
+        // "a" unplugged, "b" moves into its place and becomes primary, "c" appears
+        let new = vec![display("b", 0, 100, true), display("c", 100, 50, false)];
+        let events = diff_displays(&old, &new);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], DisplayEvent::Removed(old[0].clone()));
+        assert_eq!(events[1], DisplayEvent::Added(new[1].clone()));
+        assert!(matches!(&events[2], DisplayEvent::ModeChanged { old: before, new: after }
+            if before.x == 100 && after.x == 0));
+        assert_eq!(
+            events[3],
+            DisplayEvent::PrimaryChanged { old: Some(DisplayId("a".to_string())), new: Some(DisplayId("b".to_string())) }
+        );
+
+        // A changed index alone is not a mode change
+        let reordered = vec![DisplayInfo { index: 1, ..old[0].clone() }, DisplayInfo { index: 0, ..old[1].clone() }];
+        assert!(diff_displays(&old, &reordered).is_empty());
+    }
+
+    /// Fires once for each value sent, so tests control when the watcher looks
+    struct ChannelNotifier(Receiver<()>);
+
+    impl ChangeNotifier for ChannelNotifier {
+        fn wait(&mut self, timeout: Duration) -> CaptureResult<bool> {
+            Ok(self.0.recv_timeout(timeout).is_ok())
+        }
+    }
+
+    #[test]
+    fn test_watcher_delivers_events() {
+        let mock = Arc::new(MockCapture::new().with_displays(vec![display("a", 0, 100, true)]));
+        let (notify, notifications) = unbounded();
+        let mut watcher =
+            DisplayWatcher::with_notifier(Box::new(Arc::clone(&mock)), Box::new(ChannelNotifier(notifications))).unwrap();
+        let events = watcher.subscribe();
+
+        // Nothing changed: no events
+        notify.send(()).unwrap();
+        assert!(events.recv_timeout(Duration::from_millis(500)).is_err());
+
+        mock.set_displays(vec![display("a", 0, 100, true), display("b", 100, 100, false)]);
+        notify.send(()).unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(event, DisplayEvent::Added(ref added) if added.id.as_str() == "b"));
+        assert_eq!(watcher.displays().len(), 2);
+
+        watcher.stop();
+        assert!(!watcher.is_running());
+        assert!(events.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
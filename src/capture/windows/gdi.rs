@@ -4,10 +4,13 @@ use crate::{
     capture::traits::DefaultPixelConverter,
     encoder::global_simd_converter,
     error::{CaptureError, CaptureResult},
-    memory_pool::global_pool,
+    memory_pool::{global_pool, MemoryPool},
     types::{CaptureRegion, PixelFormat, RawImage},
 };
 
+use parking_lot::Mutex;
+use std::sync::Arc;
+
 use windows::{
     Win32::{
         Foundation::HWND,
@@ -20,6 +23,7 @@ use windows::{
 pub struct GdiCapture {
     #[allow(dead_code)]
     pixel_converter: DefaultPixelConverter,
+    memory_pool: Mutex<Arc<MemoryPool>>,
 }
 
 impl GdiCapture {
@@ -27,9 +31,15 @@ impl GdiCapture {
     pub fn new() -> CaptureResult<Self> {
         Ok(Self {
             pixel_converter: DefaultPixelConverter,
+            memory_pool: Mutex::new(global_pool()),
         })
     }
 
+    /// Allocate capture buffers from `pool`
+    pub fn set_memory_pool(&self, pool: Arc<MemoryPool>) {
+        *self.memory_pool.lock() = pool;
+    }
+
     /// Capture a display using GDI
     pub fn capture_display(&self, display_index: usize) -> CaptureResult<RawImage> {
             // Get display info
@@ -147,7 +157,7 @@ impl GdiCapture {
             let buffer_size = pixel_count * 4; // 4 bytes per pixel (BGRA)
 
             // Get buffer from pool
-            let pool = global_pool();
+            let pool = self.memory_pool.lock().clone();
             let mut pooled_buffer = pool
                 .acquire(buffer_size)
                 .map_err(|_| CaptureError::MemoryAllocationFailed { size: buffer_size })?;
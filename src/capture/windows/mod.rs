@@ -10,9 +10,13 @@ mod capture_api;
 use crate::{
     capture::traits::{CaptureCapabilities, ScreenCapture},
     error::{CaptureError, CaptureResult},
-    types::{CaptureRegion, DisplayInfo, RawImage},
+    memory_pool::MemoryPool,
+    types::{CaptureRegion, DisplayId, DisplayInfo, RawImage},
 };
 
+#[cfg(target_os = "windows")]
+use std::sync::Arc;
+
 #[cfg(target_os = "windows")]
 use windows::{
     Win32::{
@@ -99,6 +103,8 @@ impl WindowsCapture {
 
                     displays.push(DisplayInfo {
                         index,
+                        // GDI device names stay with the adapter output across enumerations
+                        id: DisplayId(device_name.clone()),
                         name: device_name,
                         width: (rect.right - rect.left) as u32,
                         height: (rect.bottom - rect.top) as u32,
@@ -108,6 +114,7 @@ impl WindowsCapture {
                         is_primary: (info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY) != 0,
                         refresh_rate: 60, // Default, will be updated
                         color_depth: 32,  // Default
+                        ..Default::default()
                     });
 
                     *data.index += 1;
@@ -157,6 +164,10 @@ impl ScreenCapture for WindowsCapture {
         self.gdi_capturer.capture_display(display_index)
     }
 
+    fn set_memory_pool(&self, pool: Arc<MemoryPool>) {
+        self.gdi_capturer.set_memory_pool(pool);
+    }
+
     fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
         eprintln!("[WINDOWS] capture_region called with region: {:?}", region);
 
@@ -204,6 +215,7 @@ impl ScreenCapture for WindowsCapture {
             supports_multi_display: true,
             supports_gpu_acceleration: self.use_hardware_acceleration,
             estimated_latency_ms: if self.use_hardware_acceleration { 10 } else { 20 },
+            supports_zero_copy: true,
         }
     }
 }
@@ -0,0 +1,266 @@
+//! Content analysis for automatic encoder settings
+//!
+//! Screens mix very different content: UI and text compress best losslessly,
+//! diagrams tolerate near-lossless, and photos or video want lossy encoding with
+//! strong noise shaping. [`analyze`] measures a few cheap statistics on a sample
+//! of rows and classifies the image; [`ContentAnalysis::recommended_config`]
+//! turns the class into a [`WebPConfig`] modelled on libwebp's presets.
+
+use crate::types::{PixelFormat, RawImage, WebPConfig};
+
+use std::collections::HashSet;
+
+/// Upper bound on the pixels examined, so analysis stays cheap on 4K/8K captures
+const MAX_SAMPLED_PIXELS: usize = 256 * 1024;
+
+/// Unique colors are counted up to this value
+pub const UNIQUE_COLOR_CAP: usize = 4096;
+
+/// Luma difference that counts as an edge
+const EDGE_THRESHOLD: u8 = 48;
+
+/// Kind of content in an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentClass {
+    /// Text and UI: large flat areas with sharp edges
+    Text,
+    /// Diagrams and illustrations: few colors, some gradients
+    Drawing,
+    /// Mixed content, e.g. a web page with images
+    Picture,
+    /// Photographic or video content: many colors, soft edges
+    Photo,
+}
+
+/// Statistics gathered by [`analyze`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContentAnalysis {
+    /// Distinct RGB colors seen, capped at [`UNIQUE_COLOR_CAP`]
+    pub unique_colors: usize,
+    /// Fraction of neighbouring pixel pairs with a strong luma difference
+    pub edge_density: f32,
+    /// Fraction of horizontally adjacent pixels that are identical
+    pub flat_ratio: f32,
+    /// Resulting classification
+    pub class: ContentClass,
+}
+
+impl ContentAnalysis {
+    /// Encoder settings suited to the detected content
+    pub fn recommended_config(&self) -> WebPConfig {
+        match self.class {
+            ContentClass::Text => WebPConfig {
+                lossless: true,
+                quality: 75,
+                method: 4,
+                exact: false,
+                ..WebPConfig::default()
+            },
+            ContentClass::Drawing => WebPConfig {
+                lossless: true,
+                near_lossless: 60,
+                quality: 75,
+                method: 4,
+                ..WebPConfig::default()
+            },
+            // libwebp's WEBP_PRESET_PICTURE
+            ContentClass::Picture => WebPConfig {
+                quality: 85,
+                sns_strength: 80,
+                filter_strength: 35,
+                filter_sharpness: 4,
+                ..WebPConfig::default()
+            },
+            // libwebp's WEBP_PRESET_PHOTO
+            ContentClass::Photo => WebPConfig {
+                quality: 80,
+                sns_strength: 80,
+                filter_strength: 30,
+                filter_sharpness: 3,
+                ..WebPConfig::default()
+            },
+        }
+    }
+}
+
+/// Measure and classify an image
+pub fn analyze(image: &RawImage) -> ContentAnalysis {
+    let mut colors = HashSet::new();
+    let (mut pairs, mut flat, mut edges, mut edge_checks) = (0usize, 0usize, 0usize, 0usize);
+
+    if image.width > 0 && image.height > 0 && image.is_valid() {
+        let bpp = image.format.bytes_per_pixel();
+        let row_bytes = image.row_bytes();
+        let row_step = (image.pixel_count() / MAX_SAMPLED_PIXELS).max(1);
+
+        for y in (0..image.height as usize).step_by(row_step) {
+            let row = &image.data[y * image.stride..y * image.stride + row_bytes];
+            let below = (y + 1 < image.height as usize)
+                .then(|| &image.data[(y + 1) * image.stride..(y + 1) * image.stride + row_bytes]);
+
+            let mut previous: Option<[u8; 3]> = None;
+            for (x, pixel) in row.chunks_exact(bpp).enumerate() {
+                let rgb = rgb(image.format, pixel);
+                if colors.len() < UNIQUE_COLOR_CAP {
+                    colors.insert(u32::from_le_bytes([rgb[0], rgb[1], rgb[2], 0]));
+                }
+
+                if let Some(left) = previous {
+                    pairs += 1;
+                    flat += (left == rgb) as usize;
+                    edge_checks += 1;
+                    edges += (luma(left).abs_diff(luma(rgb)) >= EDGE_THRESHOLD) as usize;
+                }
+                if let Some(below) = below {
+                    let under = rgb_at(image.format, below, x * bpp);
+                    edge_checks += 1;
+                    edges += (luma(under).abs_diff(luma(rgb)) >= EDGE_THRESHOLD) as usize;
+                }
+                previous = Some(rgb);
+            }
+        }
+    }
+
+    let ratio = |count: usize, total: usize| if total == 0 { 0.0 } else { count as f32 / total as f32 };
+    let unique_colors = colors.len();
+    let edge_density = ratio(edges, edge_checks);
+    let flat_ratio = ratio(flat, pairs);
+
+    let class = if flat_ratio >= 0.7 {
+        ContentClass::Text
+    } else if flat_ratio >= 0.4 || unique_colors < 1024 {
+        ContentClass::Drawing
+    } else if edge_density >= 0.05 {
+        ContentClass::Picture
+    } else {
+        ContentClass::Photo
+    };
+
+    ContentAnalysis {
+        unique_colors,
+        edge_density,
+        flat_ratio,
+        class,
+    }
+}
+
+pub(crate) fn rgb(format: PixelFormat, pixel: &[u8]) -> [u8; 3] {
+    match format {
+        PixelFormat::RGBA8 | PixelFormat::RGB8 => [pixel[0], pixel[1], pixel[2]],
+        PixelFormat::BGRA8 | PixelFormat::BGR8 => [pixel[2], pixel[1], pixel[0]],
+        PixelFormat::Gray8 | PixelFormat::GrayA8 => [pixel[0]; 3],
+    }
+}
+
+fn rgb_at(format: PixelFormat, row: &[u8], offset: usize) -> [u8; 3] {
+    rgb(format, &row[offset..offset + format.bytes_per_pixel()])
+}
+
+/// Integer BT.601 luma
+pub(crate) fn luma([r, g, b]: [u8; 3]) -> u8 {
+    ((r as u32 * 77 + g as u32 * 150 + b as u32 * 29) >> 8) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic xorshift noise in `0..amplitude`
+    fn noise(seed: &mut u32, amplitude: u32) -> u8 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 17;
+        *seed ^= *seed << 5;
+        (*seed % amplitude) as u8
+    }
+
+    fn image_from(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 3]) -> RawImage {
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b] = pixel(x, y);
+                data.extend_from_slice(&[b, g, r, 255]);
+            }
+        }
+        RawImage::new(data, width, height, PixelFormat::BGRA8)
+    }
+
+    /// Light window background with dark "text" strokes
+    fn ui_image() -> RawImage {
+        image_from(320, 200, |x, y| {
+            let in_line = y % 20 < 10 && x % 8 < 6 && (x / 8 + y / 20) % 3 != 0;
+            if in_line && (x + y) % 3 == 0 {
+                [30, 30, 30]
+            } else if y < 24 {
+                [220, 224, 230]
+            } else {
+                [255, 255, 255]
+            }
+        })
+    }
+
+    fn photo_image() -> RawImage {
+        let seed = std::cell::Cell::new(0x1234_5678u32);
+        image_from(320, 200, |x, y| {
+            let mut s = seed.get();
+            let n = noise(&mut s, 24);
+            seed.set(s);
+            [(x * 200 / 320) as u8 + n, (y * 200 / 200) as u8 + n / 2, 90 + n]
+        })
+    }
+
+    /// Photo with white caption text drawn over its lower half
+    fn mixed_image() -> RawImage {
+        let photo = photo_image();
+        image_from(320, 200, |x, y| {
+            if y >= 100 && y % 12 < 8 && (x * 7 + y * 3) % 5 < 2 {
+                [255, 255, 255]
+            } else {
+                let p = photo.get_pixel(x, y).unwrap();
+                [p[2], p[1], p[0]]
+            }
+        })
+    }
+
+    #[test]
+    fn test_classifies_content() {
+        let ui = analyze(&ui_image());
+        assert_eq!(ui.class, ContentClass::Text, "{:?}", ui);
+        assert!(ui.unique_colors <= 4);
+
+        let photo = analyze(&photo_image());
+        assert_eq!(photo.class, ContentClass::Photo, "{:?}", photo);
+        assert!(photo.unique_colors > 1024);
+
+        let mixed = analyze(&mixed_image());
+        assert_eq!(mixed.class, ContentClass::Picture, "{:?}", mixed);
+
+        let diagram = analyze(&image_from(320, 200, |x, y| [(x / 40 * 30) as u8, (y / 25 * 30) as u8, ((x + y) % 7) as u8]));
+        assert_eq!(diagram.class, ContentClass::Drawing, "{:?}", diagram);
+    }
+
+    #[test]
+    fn test_recommended_configs_are_valid() {
+        for image in [ui_image(), photo_image(), mixed_image()] {
+            let analysis = analyze(&image);
+            let config = analysis.recommended_config();
+            assert!(config.validate().is_ok());
+            assert_eq!(config.lossless, matches!(analysis.class, ContentClass::Text | ContentClass::Drawing));
+        }
+
+        // Degenerate input falls back to a valid choice
+        let empty = RawImage::new(Vec::new(), 0, 0, PixelFormat::RGBA8);
+        assert!(analyze(&empty).recommended_config().validate().is_ok());
+    }
+
+    #[test]
+    fn test_padded_rows_match_packed() {
+        let packed = photo_image();
+        let mut padded = Vec::new();
+        for row in packed.rows() {
+            padded.extend_from_slice(row);
+            padded.extend_from_slice(&[0xA5; 12]);
+        }
+        let padded = RawImage::with_stride(padded, packed.width, packed.height, packed.format, packed.row_bytes() + 12);
+        assert_eq!(analyze(&padded), analyze(&packed));
+    }
+}
@@ -0,0 +1,384 @@
+//! Animated WebP writer
+//!
+//! Encodes a sequence of frames as an extended WebP file with `ANIM`/`ANMF`
+//! chunks. Frame durations are derived from the capture timestamps, and in
+//! changed-region mode each frame only carries the rectangle that differs from
+//! the previous frame.
+
+use crate::{
+    encoder::{
+        riff::{self, fourcc, vp8x_flags},
+        ImageEncoder, WebPEncoder,
+    },
+    error::{EncodingError, EncodingResult},
+    types::{PixelFormat, RawImage, Rectangle, WebPConfig},
+};
+
+use std::path::Path;
+use std::time::Duration;
+
+/// Largest frame duration representable in an `ANMF` chunk (24-bit milliseconds)
+const MAX_FRAME_DURATION_MS: u64 = 0xFF_FFFF;
+
+/// Options for animated WebP output
+#[derive(Debug, Clone)]
+pub struct AnimationOptions {
+    /// Encoder settings applied to every frame
+    pub webp_config: WebPConfig,
+    /// Number of times to play the animation (0 = loop forever)
+    pub loop_count: u16,
+    /// Canvas background color as RGBA
+    pub background_color: [u8; 4],
+    /// Only emit the sub-rectangle that changed since the previous frame
+    pub changed_regions_only: bool,
+    /// Duration of the final frame, which has no following timestamp
+    pub final_frame_duration: Duration,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            webp_config: WebPConfig::default(),
+            loop_count: 0,
+            background_color: [0, 0, 0, 0],
+            changed_regions_only: false,
+            final_frame_duration: Duration::from_millis(100),
+        }
+    }
+}
+
+/// An encoded frame waiting to be written into the container
+struct EncodedFrame {
+    timestamp: Duration,
+    rect: Rectangle,
+    has_alpha: bool,
+    /// Serialized `ALPH` + `VP8 `/`VP8L` chunks
+    bitstream: Vec<u8>,
+}
+
+/// Writer that assembles captured frames into an animated WebP
+pub struct AnimatedWebPWriter {
+    options: AnimationOptions,
+    encoder: WebPEncoder,
+    canvas: Option<(u32, u32)>,
+    previous: Option<Vec<u8>>,
+    frames: Vec<EncodedFrame>,
+    frames_skipped: u64,
+}
+
+impl AnimatedWebPWriter {
+    /// Create a new writer
+    pub fn new(options: AnimationOptions) -> Self {
+        Self {
+            options,
+            encoder: WebPEncoder::new(),
+            canvas: None,
+            previous: None,
+            frames: Vec::new(),
+            frames_skipped: 0,
+        }
+    }
+
+    /// Get the writer options
+    pub fn options(&self) -> &AnimationOptions {
+        &self.options
+    }
+
+    /// Number of frames that will be written
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Number of frames dropped because nothing changed (changed-region mode only)
+    pub fn frames_skipped(&self) -> u64 {
+        self.frames_skipped
+    }
+
+    /// Add a frame shown at `timestamp` from the start of the recording
+    ///
+    /// The first accepted frame fixes the canvas size; later frames must match it.
+    pub fn add_frame(&mut self, image: &RawImage, timestamp: Duration) -> EncodingResult<()> {
+        let (width, height) = self.canvas.unwrap_or((image.width, image.height));
+        if image.width != width || image.height != height || width == 0 || height == 0 {
+            return Err(EncodingError::InvalidDimensions {
+                width: image.width,
+                height: image.height,
+            });
+        }
+
+        if let Some(last) = self.frames.last() {
+            if timestamp < last.timestamp {
+                return Err(EncodingError::InvalidConfiguration(format!(
+                    "Frame timestamp {:?} precedes previous frame at {:?}",
+                    timestamp, last.timestamp
+                )));
+            }
+        }
+
+        let rgba = image.to_packed_rgba()?;
+        let rect = match (&self.previous, self.options.changed_regions_only) {
+            (Some(previous), true) => match changed_rect(previous, &rgba, width) {
+                Some(rect) => rect,
+                None => {
+                    // Identical frame: the previous frame simply stays on screen longer
+                    self.frames_skipped += 1;
+                    return Ok(());
+                }
+            },
+            _ => Rectangle::new(0, 0, width, height),
+        };
+
+        let sub_image = crop_rgba(&rgba, width, rect);
+        let has_alpha = sub_image.data.chunks_exact(4).any(|pixel| pixel[3] != 255);
+        let encoded = self.encoder.encode(&sub_image, &self.options.webp_config)?;
+
+        let mut bitstream = Vec::new();
+        for chunk in riff::parse_chunks(&encoded)? {
+            if &chunk.fourcc == fourcc::ALPH
+                || &chunk.fourcc == fourcc::VP8
+                || &chunk.fourcc == fourcc::VP8L
+            {
+                riff::write_chunk(&mut bitstream, &chunk.fourcc, chunk.payload);
+            }
+        }
+
+        self.frames.push(EncodedFrame {
+            timestamp,
+            rect,
+            has_alpha,
+            bitstream,
+        });
+        self.canvas = Some((width, height));
+        self.previous = Some(rgba);
+        Ok(())
+    }
+
+    /// Assemble the animated WebP container
+    pub fn finish(self) -> EncodingResult<Vec<u8>> {
+        let (width, height) = match (self.canvas, self.frames.is_empty()) {
+            (Some(canvas), false) => canvas,
+            _ => {
+                return Err(EncodingError::InvalidConfiguration(
+                    "Animation has no frames".to_string(),
+                ))
+            }
+        };
+
+        let mut flags = vp8x_flags::ANIMATION;
+        if self.frames.iter().any(|frame| frame.has_alpha) {
+            flags |= vp8x_flags::ALPHA;
+        }
+
+        let mut body = Vec::new();
+        riff::write_chunk(&mut body, fourcc::VP8X, &riff::vp8x_payload(flags, width, height));
+
+        let [r, g, b, a] = self.options.background_color;
+        let mut anim = vec![b, g, r, a];
+        anim.extend_from_slice(&self.options.loop_count.to_le_bytes());
+        riff::write_chunk(&mut body, fourcc::ANIM, &anim);
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            let duration = match self.frames.get(index + 1) {
+                Some(next) => next.timestamp - frame.timestamp,
+                None => self.options.final_frame_duration,
+            };
+            let duration_ms = (duration.as_millis() as u64).min(MAX_FRAME_DURATION_MS) as u32;
+
+            let mut anmf = Vec::with_capacity(16 + frame.bitstream.len());
+            riff::push_u24(&mut anmf, frame.rect.x as u32 / 2);
+            riff::push_u24(&mut anmf, frame.rect.y as u32 / 2);
+            riff::push_u24(&mut anmf, frame.rect.width - 1);
+            riff::push_u24(&mut anmf, frame.rect.height - 1);
+            riff::push_u24(&mut anmf, duration_ms);
+            // Sub-rectangles replace the canvas pixels outright: no blending, no disposal
+            anmf.push(0x02);
+            anmf.extend_from_slice(&frame.bitstream);
+            riff::write_chunk(&mut body, fourcc::ANMF, &anmf);
+        }
+
+        Ok(riff::wrap_riff(&body))
+    }
+
+    /// Assemble the container and write it to a file, returning its size in bytes
+    pub fn finish_to_file(self, path: impl AsRef<Path>) -> EncodingResult<usize> {
+        let data = self.finish()?;
+        std::fs::write(path, &data).map_err(|e| EncodingError::Other(e.into()))?;
+        Ok(data.len())
+    }
+}
+
+/// Bounding box of pixels that differ, with the origin snapped to even coordinates
+/// because `ANMF` stores offsets divided by two
+fn changed_rect(previous: &[u8], current: &[u8], width: u32) -> Option<Rectangle> {
+    let row_size = width as usize * 4;
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+
+    for (y, (old_row, new_row)) in previous
+        .chunks_exact(row_size)
+        .zip(current.chunks_exact(row_size))
+        .enumerate()
+    {
+        if old_row == new_row {
+            continue;
+        }
+        let first = old_row
+            .chunks_exact(4)
+            .zip(new_row.chunks_exact(4))
+            .position(|(a, b)| a != b)
+            .unwrap_or(0) as u32;
+        let last = old_row
+            .chunks_exact(4)
+            .zip(new_row.chunks_exact(4))
+            .rposition(|(a, b)| a != b)
+            .unwrap_or(0) as u32;
+        let y = y as u32;
+        bounds = Some(match bounds {
+            None => (first, y, last, y),
+            Some((x0, y0, x1, _)) => (x0.min(first), y0, x1.max(last), y),
+        });
+    }
+
+    bounds.map(|(x0, y0, x1, y1)| {
+        let x0 = x0 & !1;
+        let y0 = y0 & !1;
+        Rectangle::new(x0 as i32, y0 as i32, x1 - x0 + 1, y1 - y0 + 1)
+    })
+}
+
+/// Copy a rectangle out of a packed RGBA buffer
+fn crop_rgba(rgba: &[u8], width: u32, rect: Rectangle) -> RawImage {
+    let row_size = width as usize * 4;
+    let mut data = Vec::with_capacity(rect.width as usize * rect.height as usize * 4);
+    for y in rect.y as usize..rect.y as usize + rect.height as usize {
+        let start = y * row_size + rect.x as usize * 4;
+        data.extend_from_slice(&rgba[start..start + rect.width as usize * 4]);
+    }
+    RawImage::new(data, rect.width, rect.height, PixelFormat::RGBA8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_with_square(x: u32, y: u32) -> RawImage {
+        let (width, height) = (48, 32);
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for py in 0..height {
+            for px in 0..width {
+                if px >= x && px < x + 6 && py >= y && py < y + 6 {
+                    data.extend_from_slice(&[255, 0, 0, 255]);
+                } else {
+                    data.extend_from_slice(&[(px * 5) as u8, (py * 7) as u8, 90, 255]);
+                }
+            }
+        }
+        RawImage::new(data, width, height, PixelFormat::RGBA8)
+    }
+
+    fn record(options: AnimationOptions) -> Vec<u8> {
+        let mut writer = AnimatedWebPWriter::new(options);
+        for (index, x) in [4u32, 9, 9, 20].iter().enumerate() {
+            let timestamp = Duration::from_millis(40 * index as u64);
+            writer.add_frame(&frame_with_square(*x, 7), timestamp).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn lossless_options(changed_regions_only: bool) -> AnimationOptions {
+        AnimationOptions {
+            webp_config: WebPConfig::lossless(),
+            changed_regions_only,
+            loop_count: 3,
+            background_color: [10, 20, 30, 255],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_container_layout() {
+        let data = record(lossless_options(false));
+        let chunks = riff::parse_chunks(&data).unwrap();
+
+        assert_eq!(&chunks[0].fourcc, fourcc::VP8X);
+        assert_eq!(chunks[0].payload[0] & vp8x_flags::ANIMATION, vp8x_flags::ANIMATION);
+        assert_eq!(&chunks[1].fourcc, fourcc::ANIM);
+        assert_eq!(chunks[1].payload, &[30, 20, 10, 255, 3, 0]);
+
+        let frames: Vec<_> = chunks.iter().filter(|c| &c.fourcc == fourcc::ANMF).collect();
+        assert_eq!(frames.len(), 4);
+        // Durations follow the timestamps, the last frame uses the configured default
+        let duration = |payload: &[u8]| u32::from_le_bytes([payload[12], payload[13], payload[14], 0]);
+        assert_eq!(duration(frames[0].payload), 40);
+        assert_eq!(duration(frames[3].payload), 100);
+    }
+
+    #[test]
+    fn test_decodes_with_libwebp() {
+        let data = record(lossless_options(false));
+        let decoded = webp::AnimDecoder::new(&data).decode().unwrap();
+
+        assert_eq!(decoded.len(), 4);
+        assert_eq!(decoded.loop_count, 3);
+        // libwebp reports the time at which each frame stops being displayed
+        assert_eq!(decoded.get_frame(0).unwrap().get_time_ms(), 40);
+        assert_eq!(decoded.get_frame(3).unwrap().get_time_ms(), 220);
+    }
+
+    #[test]
+    fn test_changed_regions_match_full_frames() {
+        let full = record(lossless_options(false));
+        let partial = record(lossless_options(true));
+        assert!(partial.len() < full.len());
+
+        let full = webp::AnimDecoder::new(&full).decode().unwrap();
+        let partial = webp::AnimDecoder::new(&partial).decode().unwrap();
+
+        // The identical third frame is merged into the second one's duration
+        assert_eq!(partial.len(), 3);
+        assert_eq!(
+            full.get_frame(3).unwrap().get_image(),
+            partial.get_frame(2).unwrap().get_image()
+        );
+        assert_eq!(
+            full.get_frame(1).unwrap().get_image(),
+            partial.get_frame(1).unwrap().get_image()
+        );
+    }
+
+    #[test]
+    fn test_changed_rect_snaps_to_even_origin() {
+        let a = frame_with_square(4, 7).to_packed_rgba().unwrap();
+        let b = frame_with_square(9, 7).to_packed_rgba().unwrap();
+        let rect = changed_rect(&a, &b, 48).unwrap();
+
+        assert_eq!((rect.x, rect.y), (4, 6));
+        assert_eq!((rect.width, rect.height), (11, 7));
+        assert!(changed_rect(&a, &a, 48).is_none());
+    }
+
+    #[test]
+    fn test_rejects_mismatched_frames() {
+        let mut writer = AnimatedWebPWriter::new(AnimationOptions::default());
+        writer.add_frame(&frame_with_square(0, 0), Duration::ZERO).unwrap();
+
+        let small = RawImage::new(vec![0; 16 * 16 * 4], 16, 16, PixelFormat::RGBA8);
+        assert!(writer.add_frame(&small, Duration::from_millis(10)).is_err());
+        assert!(writer
+            .add_frame(&frame_with_square(1, 1), Duration::ZERO)
+            .is_ok());
+        assert!(AnimatedWebPWriter::new(AnimationOptions::default())
+            .finish()
+            .is_err());
+    }
+
+    #[test]
+    fn test_rejected_first_frame_leaves_canvas_unset() {
+        let mut writer = AnimatedWebPWriter::new(AnimationOptions::default());
+        let empty = RawImage::new(Vec::new(), 0, 0, PixelFormat::RGBA8);
+        assert!(writer.add_frame(&empty, Duration::ZERO).is_err());
+
+        writer.add_frame(&frame_with_square(0, 0), Duration::ZERO).unwrap();
+        assert_eq!(writer.frame_count(), 1);
+        assert!(writer.finish().is_ok());
+    }
+}
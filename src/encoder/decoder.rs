@@ -0,0 +1,421 @@
+//! WebP decoder implementation using libwebp
+//!
+//! Supports one-shot decoding with optional cropping and scaling, and
+//! incremental decoding of data that arrives in pieces.
+
+use crate::{
+    error::{EncodingError, EncodingResult},
+    types::{PixelFormat, RawImage, Rectangle},
+};
+
+use libwebp_sys::{VP8StatusCode, WEBP_CSP_MODE};
+use std::os::raw::c_int;
+
+/// Decoding options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// Decode only this part of the image (applied before scaling)
+    pub crop: Option<Rectangle>,
+    /// Output size; a zero dimension keeps the aspect ratio
+    pub scale: Option<(u32, u32)>,
+    /// Output pixel format (RGBA8, BGRA8, RGB8 or BGR8)
+    pub format: PixelFormat,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            crop: None,
+            scale: None,
+            format: PixelFormat::RGBA8,
+        }
+    }
+}
+
+/// Bitstream information available without decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebPFeatures {
+    pub width: u32,
+    pub height: u32,
+    pub has_alpha: bool,
+    pub has_animation: bool,
+    pub lossless: bool,
+}
+
+/// WebP decoder using libwebp
+#[derive(Debug, Clone, Default)]
+pub struct WebPDecoder {
+    options: DecodeOptions,
+}
+
+impl WebPDecoder {
+    /// Create a decoder that outputs full-size RGBA
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a decoder with custom options
+    pub fn with_options(options: DecodeOptions) -> Self {
+        Self { options }
+    }
+
+    /// Decode only `region` of the image
+    pub fn crop(mut self, region: Rectangle) -> Self {
+        self.options.crop = Some(region);
+        self
+    }
+
+    /// Scale the (cropped) image to `width`x`height`
+    pub fn scale(mut self, width: u32, height: u32) -> Self {
+        self.options.scale = Some((width, height));
+        self
+    }
+
+    /// Set the output pixel format
+    pub fn format(mut self, format: PixelFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    /// Get decoder options
+    pub fn options(&self) -> &DecodeOptions {
+        &self.options
+    }
+
+    /// Read the bitstream header
+    pub fn features(data: &[u8]) -> EncodingResult<WebPFeatures> {
+        let mut features = std::mem::MaybeUninit::<libwebp_sys::WebPBitstreamFeatures>::uninit();
+        let status = unsafe { libwebp_sys::WebPGetFeatures(data.as_ptr(), data.len(), features.as_mut_ptr()) };
+        check_status(status, data.len())?;
+
+        let features = unsafe { features.assume_init() };
+        Ok(WebPFeatures {
+            width: features.width as u32,
+            height: features.height as u32,
+            has_alpha: features.has_alpha != 0,
+            has_animation: features.has_animation != 0,
+            lossless: features.format == 2,
+        })
+    }
+
+    /// Decode a complete WebP image
+    pub fn decode(&self, data: &[u8]) -> EncodingResult<RawImage> {
+        let features = Self::features(data)?;
+        if features.has_animation {
+            return Err(EncodingError::UnsupportedFeature(
+                "Animated WebP; decode frames with WebPAnimDecoder".to_string(),
+            ));
+        }
+
+        let (width, height) = self.output_size(features.width, features.height)?;
+        let format = self.options.format;
+        let stride = width as usize * format.bytes_per_pixel();
+        let mut pixels = vec![0u8; stride * height as usize];
+
+        let mut config = self.native_config()?;
+        config.output.is_external_memory = 1;
+        config.output.u.RGBA = libwebp_sys::WebPRGBABuffer {
+            rgba: pixels.as_mut_ptr(),
+            stride: stride as c_int,
+            size: pixels.len(),
+        };
+
+        let status = unsafe { libwebp_sys::WebPDecode(data.as_ptr(), data.len(), &mut config) };
+        // External memory is never freed by libwebp; this only releases bookkeeping
+        unsafe { libwebp_sys::WebPFreeDecBuffer(&mut config.output) };
+        check_status(status, data.len())?;
+
+        Ok(RawImage::new(pixels, width, height, format))
+    }
+
+    /// Start decoding data that arrives in pieces
+    pub fn incremental(&self) -> EncodingResult<IncrementalDecoder> {
+        // libwebp keeps pointers into the config, so it must not move
+        let mut config = Box::new(self.native_config()?);
+        let decoder = unsafe { libwebp_sys::WebPIDecode(std::ptr::null(), 0, &mut *config) };
+        if decoder.is_null() {
+            return Err(EncodingError::MemoryAllocationFailed);
+        }
+
+        Ok(IncrementalDecoder {
+            decoder,
+            config,
+            format: self.options.format,
+            received: 0,
+            complete: false,
+        })
+    }
+
+    /// Output dimensions after cropping and scaling, validated against the source size
+    fn output_size(&self, width: u32, height: u32) -> EncodingResult<(u32, u32)> {
+        let (crop_width, crop_height) = match self.options.crop {
+            Some(crop) => {
+                let fits = crop.x >= 0
+                    && crop.y >= 0
+                    && crop.width > 0
+                    && crop.height > 0
+                    && crop.x as u64 + crop.width as u64 <= width as u64
+                    && crop.y as u64 + crop.height as u64 <= height as u64;
+                if !fits {
+                    return Err(EncodingError::InvalidConfiguration(format!(
+                        "Crop {:?} is outside the {}x{} image",
+                        crop, width, height
+                    )));
+                }
+                (crop.width, crop.height)
+            }
+            None => (width, height),
+        };
+
+        match self.options.scale {
+            None => Ok((crop_width, crop_height)),
+            Some((0, 0)) => Err(EncodingError::InvalidDimensions { width: 0, height: 0 }),
+            // Same rounding as libwebp's WebPRescalerGetScaledDimensions
+            Some((0, scaled_height)) => Ok((
+                ((crop_width as u64 * scaled_height as u64).div_ceil(crop_height as u64)) as u32,
+                scaled_height,
+            )),
+            Some((scaled_width, 0)) => Ok((
+                scaled_width,
+                ((crop_height as u64 * scaled_width as u64).div_ceil(crop_width as u64)) as u32,
+            )),
+            Some(size) => Ok(size),
+        }
+    }
+
+    /// Translate options into libwebp's decoder configuration
+    fn native_config(&self) -> EncodingResult<libwebp_sys::WebPDecoderConfig> {
+        let mut config = libwebp_sys::WebPDecoderConfig::new().map_err(|_| {
+            EncodingError::EncodingFailed("libwebp decoder config ABI mismatch".to_string())
+        })?;
+
+        config.output.colorspace = colorspace(self.options.format)?;
+        if let Some(crop) = self.options.crop {
+            config.options.use_cropping = 1;
+            config.options.crop_left = crop.x;
+            config.options.crop_top = crop.y;
+            config.options.crop_width = crop.width as c_int;
+            config.options.crop_height = crop.height as c_int;
+        }
+        if let Some((width, height)) = self.options.scale {
+            config.options.use_scaling = 1;
+            config.options.scaled_width = width as c_int;
+            config.options.scaled_height = height as c_int;
+        }
+
+        Ok(config)
+    }
+}
+
+/// Decoder fed with successive chunks of a WebP stream
+pub struct IncrementalDecoder {
+    decoder: *mut libwebp_sys::WebPIDecoder,
+    config: Box<libwebp_sys::WebPDecoderConfig>,
+    format: PixelFormat,
+    received: usize,
+    complete: bool,
+}
+
+// The decoder state is only reachable through `&mut self`
+unsafe impl Send for IncrementalDecoder {}
+
+impl IncrementalDecoder {
+    /// Append the next chunk; returns whether the image is complete
+    pub fn push(&mut self, data: &[u8]) -> EncodingResult<bool> {
+        if self.complete {
+            return Ok(true);
+        }
+
+        self.received += data.len();
+        let status = unsafe { libwebp_sys::WebPIAppend(self.decoder, data.as_ptr(), data.len()) };
+        match status {
+            VP8StatusCode::VP8_STATUS_OK => self.complete = true,
+            VP8StatusCode::VP8_STATUS_SUSPENDED => {}
+            status => check_status(status, self.received)?,
+        }
+        Ok(self.complete)
+    }
+
+    /// Whether all image data has been decoded
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Number of fully decoded rows so far
+    pub fn decoded_rows(&self) -> u32 {
+        self.rows().map_or(0, |(_, rows, ..)| rows)
+    }
+
+    /// Copy of the rows decoded so far, or `None` before the header has been parsed
+    pub fn partial(&self) -> Option<RawImage> {
+        let (pixels, rows, width, stride) = self.rows()?;
+        let row_bytes = width as usize * self.format.bytes_per_pixel();
+        let mut data = Vec::with_capacity(row_bytes * rows as usize);
+        for row in 0..rows as usize {
+            let row = unsafe { std::slice::from_raw_parts(pixels.add(row * stride), row_bytes) };
+            data.extend_from_slice(row);
+        }
+        Some(RawImage::new(data, width, rows, self.format))
+    }
+
+    /// Return the decoded image, failing if the stream was cut short
+    pub fn finish(self) -> EncodingResult<RawImage> {
+        if !self.complete {
+            return Err(EncodingError::TruncatedData { provided: self.received });
+        }
+        self.partial().ok_or(EncodingError::TruncatedData { provided: self.received })
+    }
+
+    /// Output pointer, decoded rows, width and stride
+    fn rows(&self) -> Option<(*const u8, u32, u32, usize)> {
+        let (mut last_y, mut width, mut height, mut stride) = (0, 0, 0, 0);
+        let pixels = unsafe {
+            libwebp_sys::WebPIDecGetRGB(self.decoder, &mut last_y, &mut width, &mut height, &mut stride)
+        };
+        if pixels.is_null() {
+            return None;
+        }
+        Some((pixels, last_y.max(0) as u32, width as u32, stride as usize))
+    }
+}
+
+impl Drop for IncrementalDecoder {
+    fn drop(&mut self) {
+        unsafe {
+            libwebp_sys::WebPIDelete(self.decoder);
+            libwebp_sys::WebPFreeDecBuffer(&mut self.config.output);
+        }
+    }
+}
+
+fn colorspace(format: PixelFormat) -> EncodingResult<WEBP_CSP_MODE> {
+    match format {
+        PixelFormat::RGBA8 => Ok(WEBP_CSP_MODE::MODE_RGBA),
+        PixelFormat::BGRA8 => Ok(WEBP_CSP_MODE::MODE_BGRA),
+        PixelFormat::RGB8 => Ok(WEBP_CSP_MODE::MODE_RGB),
+        PixelFormat::BGR8 => Ok(WEBP_CSP_MODE::MODE_BGR),
+        format => Err(EncodingError::UnsupportedFormat(format!(
+            "Cannot decode WebP to {:?}",
+            format
+        ))),
+    }
+}
+
+/// Map a libwebp status to our error type
+fn check_status(status: VP8StatusCode, provided: usize) -> EncodingResult<()> {
+    match status {
+        VP8StatusCode::VP8_STATUS_OK => Ok(()),
+        VP8StatusCode::VP8_STATUS_NOT_ENOUGH_DATA | VP8StatusCode::VP8_STATUS_SUSPENDED => {
+            Err(EncodingError::TruncatedData { provided })
+        }
+        VP8StatusCode::VP8_STATUS_BITSTREAM_ERROR => {
+            Err(EncodingError::CorruptData("invalid WebP bitstream".to_string()))
+        }
+        VP8StatusCode::VP8_STATUS_OUT_OF_MEMORY => Err(EncodingError::MemoryAllocationFailed),
+        VP8StatusCode::VP8_STATUS_UNSUPPORTED_FEATURE => Err(EncodingError::UnsupportedFeature(
+            "WebP bitstream feature not supported by libwebp".to_string(),
+        )),
+        VP8StatusCode::VP8_STATUS_INVALID_PARAM => Err(EncodingError::InvalidConfiguration(
+            "libwebp rejected the decoder configuration".to_string(),
+        )),
+        status => Err(EncodingError::EncodingFailed(format!("{:?}", status))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{ImageEncoder, WebPEncoder};
+    use crate::types::WebPConfig;
+
+    /// Lossless 16x8 image where every pixel encodes its coordinates
+    fn encoded_pattern() -> (RawImage, Vec<u8>) {
+        let mut data = Vec::new();
+        for y in 0..8u8 {
+            for x in 0..16u8 {
+                data.extend_from_slice(&[x * 16, y * 32, 255 - x, 200]);
+            }
+        }
+        let image = RawImage::new(data, 16, 8, PixelFormat::RGBA8);
+        let webp = ImageEncoder::encode(&WebPEncoder::new(), &image, &WebPConfig::lossless()).unwrap();
+        (image, webp)
+    }
+
+    #[test]
+    fn test_lossless_round_trip() {
+        let (image, webp) = encoded_pattern();
+        let features = WebPDecoder::features(&webp).unwrap();
+        assert_eq!((features.width, features.height), (16, 8));
+        assert!(features.has_alpha && features.lossless && !features.has_animation);
+
+        let decoded = WebPDecoder::new().decode(&webp).unwrap();
+        assert_eq!(decoded.data, image.data);
+
+        let bgr = WebPDecoder::new().format(PixelFormat::BGR8).decode(&webp).unwrap();
+        assert_eq!(bgr.get_pixel(3, 2).unwrap(), &[252, 64, 48]);
+    }
+
+    #[test]
+    fn test_cropped_and_scaled_decode() {
+        let (image, webp) = encoded_pattern();
+
+        let cropped = WebPDecoder::new().crop(Rectangle::new(4, 2, 6, 3)).decode(&webp).unwrap();
+        assert_eq!((cropped.width, cropped.height), (6, 3));
+        assert_eq!(cropped.get_pixel(0, 0), image.get_pixel(4, 2));
+        assert_eq!(cropped.get_pixel(5, 2), image.get_pixel(9, 4));
+
+        let scaled = WebPDecoder::new().scale(8, 0).decode(&webp).unwrap();
+        assert_eq!((scaled.width, scaled.height), (8, 4));
+        assert_eq!(scaled.data.len(), 8 * 4 * 4);
+
+        assert!(matches!(
+            WebPDecoder::new().crop(Rectangle::new(12, 0, 8, 8)).decode(&webp),
+            Err(EncodingError::InvalidConfiguration(_))
+        ));
+    }
+
+    #[test]
+    fn test_incremental_decode() {
+        let (image, webp) = encoded_pattern();
+        let mut decoder = WebPDecoder::new().incremental().unwrap();
+        assert!(decoder.partial().is_none());
+
+        let mut chunks = webp.chunks(7).peekable();
+        while let Some(chunk) = chunks.next() {
+            let complete = decoder.push(chunk).unwrap();
+            assert_eq!(complete, chunks.peek().is_none());
+        }
+        assert_eq!(decoder.decoded_rows(), 8);
+        assert_eq!(decoder.finish().unwrap().data, image.data);
+
+        let mut truncated = WebPDecoder::new().incremental().unwrap();
+        assert!(!truncated.push(&webp[..webp.len() / 2]).unwrap());
+        assert!(matches!(truncated.finish(), Err(EncodingError::TruncatedData { .. })));
+    }
+
+    #[test]
+    fn test_corrupt_input() {
+        let (_, mut webp) = encoded_pattern();
+
+        assert!(matches!(
+            WebPDecoder::new().decode(b"not a webp"),
+            Err(EncodingError::TruncatedData { provided: 10 })
+        ));
+        assert!(matches!(
+            WebPDecoder::new().decode(&[0x5A; 64]),
+            Err(EncodingError::CorruptData(_))
+        ));
+        assert!(matches!(
+            WebPDecoder::new().decode(&webp[..webp.len() - 10]),
+            Err(EncodingError::TruncatedData { .. })
+        ));
+
+        // Clobber the VP8L signature byte after the chunk header
+        webp[20] ^= 0xFF;
+        assert!(matches!(WebPDecoder::new().decode(&webp), Err(EncodingError::CorruptData(_))));
+        assert!(matches!(
+            WebPDecoder::new().incremental().unwrap().push(&webp),
+            Err(EncodingError::CorruptData(_))
+        ));
+    }
+}
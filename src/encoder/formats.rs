@@ -0,0 +1,308 @@
+//! Non-WebP output formats backed by the `image` crate
+//!
+//! Each encoder reuses [`WebPConfig`] where a knob has an equivalent: `quality`
+//! drives the lossy formats and `method` trades speed for size.
+
+use crate::{
+    encoder::ImageEncoder,
+    error::{EncodingError, EncodingResult},
+    types::{OutputFormat, PixelFormat, RawImage, WebPConfig},
+};
+
+use image::{ExtendedColorType, ImageEncoder as _};
+
+/// Lossless PNG encoder
+#[derive(Debug, Default)]
+pub struct PngEncoder;
+
+/// Baseline JPEG encoder; alpha is dropped
+#[derive(Debug, Default)]
+pub struct JpegEncoder;
+
+/// Lossless QOI encoder
+#[derive(Debug, Default)]
+pub struct QoiEncoder;
+
+/// AVIF encoder (requires the `avif` feature)
+#[cfg(feature = "avif")]
+#[derive(Debug, Default)]
+pub struct AvifEncoder;
+
+impl ImageEncoder for PngEncoder {
+    fn encode(&self, image: &RawImage, config: &WebPConfig) -> EncodingResult<Vec<u8>> {
+        use image::codecs::png::{CompressionType, FilterType};
+
+        let compression = match config.method {
+            0..=1 => CompressionType::Fast,
+            2..=4 => CompressionType::Default,
+            _ => CompressionType::Best,
+        };
+        let (data, color) = packed_for(image, ChannelSupport::ALL)?;
+        let mut output = Vec::new();
+        image::codecs::png::PngEncoder::new_with_quality(&mut output, compression, FilterType::Adaptive)
+            .write_image(&data, image.width, image.height, color)
+            .map_err(|e| encoding_failed(OutputFormat::Png, e))?;
+        Ok(output)
+    }
+
+    fn name(&self) -> &str {
+        "PNG"
+    }
+
+    fn supports_format(&self, _format: PixelFormat) -> bool {
+        true
+    }
+
+    fn output_format(&self) -> OutputFormat {
+        OutputFormat::Png
+    }
+}
+
+impl ImageEncoder for JpegEncoder {
+    fn encode(&self, image: &RawImage, config: &WebPConfig) -> EncodingResult<Vec<u8>> {
+        let (data, color) = packed_for(image, ChannelSupport { gray: true, alpha: false })?;
+        let mut output = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut output, config.quality.clamp(1, 100))
+            .write_image(&data, image.width, image.height, color)
+            .map_err(|e| encoding_failed(OutputFormat::Jpeg, e))?;
+        Ok(output)
+    }
+
+    fn name(&self) -> &str {
+        "JPEG"
+    }
+
+    fn supports_format(&self, _format: PixelFormat) -> bool {
+        true
+    }
+
+    fn output_format(&self) -> OutputFormat {
+        OutputFormat::Jpeg
+    }
+}
+
+impl ImageEncoder for QoiEncoder {
+    fn encode(&self, image: &RawImage, _config: &WebPConfig) -> EncodingResult<Vec<u8>> {
+        let (data, color) = packed_for(image, ChannelSupport { gray: false, alpha: true })?;
+        let mut output = Vec::new();
+        image::codecs::qoi::QoiEncoder::new(&mut output)
+            .write_image(&data, image.width, image.height, color)
+            .map_err(|e| encoding_failed(OutputFormat::Qoi, e))?;
+        Ok(output)
+    }
+
+    fn name(&self) -> &str {
+        "QOI"
+    }
+
+    fn supports_format(&self, _format: PixelFormat) -> bool {
+        true
+    }
+
+    fn output_format(&self) -> OutputFormat {
+        OutputFormat::Qoi
+    }
+}
+
+#[cfg(feature = "avif")]
+impl ImageEncoder for AvifEncoder {
+    fn encode(&self, image: &RawImage, config: &WebPConfig) -> EncodingResult<Vec<u8>> {
+        // rav1e speed runs from 1 (slowest) to 10; method 6 maps to speed 4
+        let speed = 10 - config.method.min(6);
+        let (data, color) = packed_for(image, ChannelSupport::ALL)?;
+        let mut output = Vec::new();
+        image::codecs::avif::AvifEncoder::new_with_speed_quality(&mut output, speed, config.quality.clamp(1, 100))
+            .write_image(&data, image.width, image.height, color)
+            .map_err(|e| encoding_failed(OutputFormat::Avif, e))?;
+        Ok(output)
+    }
+
+    fn name(&self) -> &str {
+        "AVIF"
+    }
+
+    fn supports_format(&self, _format: PixelFormat) -> bool {
+        true
+    }
+
+    fn output_format(&self) -> OutputFormat {
+        OutputFormat::Avif
+    }
+}
+
+/// Channel layouts an output format can store directly
+#[derive(Clone, Copy)]
+struct ChannelSupport {
+    gray: bool,
+    alpha: bool,
+}
+
+impl ChannelSupport {
+    const ALL: Self = Self { gray: true, alpha: true };
+}
+
+/// Repack an image into tightly packed RGB(A) or gray rows the target format accepts
+///
+/// BGR input is swapped to RGB, alpha is dropped when the format can't store it
+/// and gray is expanded to RGB when the format has no gray mode.
+fn packed_for(image: &RawImage, support: ChannelSupport) -> EncodingResult<(Vec<u8>, ExtendedColorType)> {
+    if image.width == 0 || image.height == 0 {
+        return Err(EncodingError::InvalidDimensions {
+            width: image.width,
+            height: image.height,
+        });
+    }
+    if !image.is_valid() {
+        return Err(EncodingError::BufferTooSmall {
+            required: image.required_len(),
+            provided: image.data.len(),
+        });
+    }
+
+    let gray = matches!(image.format, PixelFormat::Gray8 | PixelFormat::GrayA8) && support.gray;
+    let alpha = image.format.has_alpha() && support.alpha;
+    let (channels, color) = match (gray, alpha) {
+        (true, false) => (1, ExtendedColorType::L8),
+        (true, true) => (2, ExtendedColorType::La8),
+        (false, false) => (3, ExtendedColorType::Rgb8),
+        (false, true) => (4, ExtendedColorType::Rgba8),
+    };
+
+    let bpp = image.format.bytes_per_pixel();
+    let mut data = Vec::with_capacity(image.pixel_count() * channels);
+    for row in image.rows() {
+        for pixel in row.chunks_exact(bpp) {
+            let (rgb, a) = match image.format {
+                PixelFormat::RGBA8 => ([pixel[0], pixel[1], pixel[2]], pixel[3]),
+                PixelFormat::BGRA8 => ([pixel[2], pixel[1], pixel[0]], pixel[3]),
+                PixelFormat::RGB8 => ([pixel[0], pixel[1], pixel[2]], 255),
+                PixelFormat::BGR8 => ([pixel[2], pixel[1], pixel[0]], 255),
+                PixelFormat::Gray8 => ([pixel[0]; 3], 255),
+                PixelFormat::GrayA8 => ([pixel[0]; 3], pixel[1]),
+            };
+            if gray {
+                data.push(rgb[0]);
+            } else {
+                data.extend_from_slice(&rgb);
+            }
+            if alpha {
+                data.push(a);
+            }
+        }
+    }
+
+    Ok((data, color))
+}
+
+/// Decode PNG, JPEG or QOI data to RGBA
+pub(crate) fn decode(data: &[u8], format: OutputFormat) -> EncodingResult<RawImage> {
+    let image_format = match format {
+        OutputFormat::Png => image::ImageFormat::Png,
+        OutputFormat::Jpeg => image::ImageFormat::Jpeg,
+        OutputFormat::Qoi => image::ImageFormat::Qoi,
+        format => {
+            return Err(EncodingError::UnsupportedFeature(format!("Decoding {format} is not supported")));
+        }
+    };
+
+    let decoded = image::load_from_memory_with_format(data, image_format).map_err(|e| match e {
+        image::ImageError::Decoding(e) => EncodingError::CorruptData(format!("{format}: {e}")),
+        image::ImageError::IoError(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            EncodingError::TruncatedData { provided: data.len() }
+        }
+        e => encoding_failed(format, e),
+    })?;
+    let rgba = decoded.into_rgba8();
+    let (width, height) = rgba.dimensions();
+    Ok(RawImage::new(rgba.into_raw(), width, height, PixelFormat::RGBA8))
+}
+
+fn encoding_failed(format: OutputFormat, error: image::ImageError) -> EncodingError {
+    EncodingError::EncodingFailed(format!("{format}: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x2 BGRA image with 4 bytes of row padding
+    fn padded_bgra() -> RawImage {
+        let mut data = Vec::new();
+        for y in 0..2u8 {
+            for x in 0..3u8 {
+                data.extend_from_slice(&[x * 40, y * 100, 200, 128]);
+            }
+            data.extend_from_slice(&[0xA5; 4]);
+        }
+        RawImage::with_stride(data, 3, 2, PixelFormat::BGRA8, 16)
+    }
+
+    fn decode(data: &[u8], format: image::ImageFormat) -> image::DynamicImage {
+        image::load_from_memory_with_format(data, format).unwrap()
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let image = padded_bgra();
+        let png = PngEncoder.encode(&image, &WebPConfig::default()).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        let decoded = decode(&png, image::ImageFormat::Png).to_rgba8();
+        assert_eq!(decoded.dimensions(), (3, 2));
+        assert_eq!(decoded.get_pixel(2, 1).0, [200, 100, 80, 128]);
+    }
+
+    #[test]
+    fn test_qoi_round_trip() {
+        let image = padded_bgra();
+        let qoi = QoiEncoder.encode(&image, &WebPConfig::default()).unwrap();
+        assert!(qoi.starts_with(b"qoif"));
+
+        // QOI has no gray mode, so gray input comes back as RGB
+        let gray = RawImage::new(vec![10, 20, 30, 40], 2, 2, PixelFormat::Gray8);
+        let (data, color) = packed_for(&gray, ChannelSupport { gray: false, alpha: true }).unwrap();
+        assert_eq!(color, ExtendedColorType::Rgb8);
+        assert_eq!(&data[..6], &[10, 10, 10, 20, 20, 20]);
+    }
+
+    #[test]
+    fn test_jpeg_drops_alpha() {
+        let image = padded_bgra();
+        let jpeg = JpegEncoder.encode(&image, &WebPConfig::default()).unwrap();
+        assert!(jpeg.starts_with(&[0xFF, 0xD8, 0xFF]));
+        assert_eq!(decode(&jpeg, image::ImageFormat::Jpeg).color(), image::ColorType::Rgb8);
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let image = padded_bgra();
+        for encoder in [&PngEncoder as &dyn ImageEncoder, &QoiEncoder] {
+            let encoded = encoder.encode(&image, &WebPConfig::default()).unwrap();
+            let decoded = super::decode(&encoded, encoder.output_format()).unwrap();
+            assert_eq!((decoded.width, decoded.height), (3, 2));
+            assert_eq!(decoded.get_pixel(2, 1).unwrap(), &[200, 100, 80, 128]);
+
+            assert!(matches!(
+                super::decode(&encoded[..encoded.len() / 2], encoder.output_format()),
+                Err(EncodingError::CorruptData(_) | EncodingError::TruncatedData { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_rejects_empty_image() {
+        let image = RawImage::new(Vec::new(), 0, 0, PixelFormat::RGBA8);
+        assert!(matches!(
+            PngEncoder.encode(&image, &WebPConfig::default()),
+            Err(EncodingError::InvalidDimensions { .. })
+        ));
+    }
+
+    #[cfg(feature = "avif")]
+    #[test]
+    fn test_avif_signature() {
+        let config = WebPConfig { method: 0, ..WebPConfig::default() };
+        let avif = AvifEncoder.encode(&padded_bgra(), &config).unwrap();
+        assert_eq!(&avif[4..12], b"ftypavif");
+    }
+}
@@ -0,0 +1,686 @@
+//! Provenance metadata in WebP files
+//!
+//! [`embed`] rewrites an encoded WebP into the extended format: a `VP8X` header
+//! followed by an `ICCP` profile, the original image chunks, and `EXIF` and
+//! `XMP ` chunks describing the capture. [`read`] parses them back out.
+//!
+//! EXIF carries the standard fields other tools understand (capture time,
+//! software, host computer); XMP carries everything, including the display,
+//! region, scale factor and user-supplied properties.
+
+use crate::{
+    encoder::{
+        riff::{self, fourcc, vp8x_flags},
+        WebPDecoder,
+    },
+    error::{EncodingError, EncodingResult},
+    types::Rectangle,
+};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name and version written as the creating software
+pub const SOFTWARE: &str = concat!("webp-screenshot-rust ", env!("CARGO_PKG_VERSION"));
+
+/// XMP namespace for capture-specific properties
+pub const XMP_NAMESPACE: &str = "urn:webp-screenshot-rust:capture:1.0";
+
+/// Capture provenance stored in (or read from) a WebP file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmbeddedMetadata {
+    /// When the capture was taken (millisecond precision)
+    pub capture_time: Option<SystemTime>,
+    /// Index of the captured display
+    pub display_index: Option<usize>,
+    /// Name of the captured display
+    pub display_name: Option<String>,
+    /// Captured area in global desktop coordinates
+    pub region: Option<Rectangle>,
+    /// Display scale factor
+    pub scale_factor: Option<f32>,
+    /// Host the capture was taken on
+    pub hostname: Option<String>,
+    /// Software that produced the file
+    pub software: Option<String>,
+    /// User-supplied key/value pairs
+    pub properties: BTreeMap<String, String>,
+    /// ICC color profile
+    pub icc_profile: Option<Vec<u8>>,
+}
+
+impl EmbeddedMetadata {
+    /// Read the metadata of a WebP file on disk
+    pub fn read_file(path: impl AsRef<Path>) -> EncodingResult<Self> {
+        let data = std::fs::read(path).map_err(|e| EncodingError::Other(e.into()))?;
+        read(&data)
+    }
+
+    /// Whether there is anything to write into EXIF or XMP
+    fn has_descriptive_fields(&self) -> bool {
+        self.capture_time.is_some()
+            || self.display_index.is_some()
+            || self.display_name.is_some()
+            || self.region.is_some()
+            || self.scale_factor.is_some()
+            || self.hostname.is_some()
+            || self.software.is_some()
+            || !self.properties.is_empty()
+    }
+}
+
+/// Rewrite an encoded WebP with `metadata` in EXIF, XMP and ICCP chunks
+///
+/// Existing metadata chunks are replaced; image data is copied unchanged.
+pub fn embed(webp: &[u8], metadata: &EmbeddedMetadata) -> EncodingResult<Vec<u8>> {
+    let features = WebPDecoder::features(webp)?;
+    let chunks = riff::parse_chunks(webp)?;
+
+    let exif = metadata.has_descriptive_fields().then(|| write_exif(metadata));
+    let xmp = metadata.has_descriptive_fields().then(|| write_xmp(metadata));
+
+    let mut flags = 0;
+    if features.has_animation {
+        flags |= vp8x_flags::ANIMATION;
+    }
+    if features.has_alpha {
+        flags |= vp8x_flags::ALPHA;
+    }
+    if metadata.icc_profile.is_some() {
+        flags |= vp8x_flags::ICC;
+    }
+    if exif.is_some() {
+        flags |= vp8x_flags::EXIF;
+    }
+    if xmp.is_some() {
+        flags |= vp8x_flags::XMP;
+    }
+
+    let mut body = Vec::with_capacity(webp.len() + 1024);
+    riff::write_chunk(
+        &mut body,
+        fourcc::VP8X,
+        &riff::vp8x_payload(flags, features.width, features.height),
+    );
+    // The color profile must precede the image data
+    if let Some(ref icc) = metadata.icc_profile {
+        riff::write_chunk(&mut body, fourcc::ICCP, icc);
+    }
+    for chunk in &chunks {
+        if ![fourcc::VP8X, fourcc::ICCP, fourcc::EXIF, fourcc::XMP].contains(&&chunk.fourcc) {
+            riff::write_chunk(&mut body, &chunk.fourcc, chunk.payload);
+        }
+    }
+    if let Some(exif) = exif {
+        riff::write_chunk(&mut body, fourcc::EXIF, &exif);
+    }
+    if let Some(xmp) = xmp {
+        riff::write_chunk(&mut body, fourcc::XMP, xmp.as_bytes());
+    }
+
+    Ok(riff::wrap_riff(&body))
+}
+
+/// Parse EXIF, XMP and ICCP chunks out of an encoded WebP
+///
+/// XMP values take precedence; EXIF fills in the capture time, host and
+/// software when a file only carries EXIF.
+pub fn read(webp: &[u8]) -> EncodingResult<EmbeddedMetadata> {
+    let mut metadata = EmbeddedMetadata::default();
+
+    for chunk in riff::parse_chunks(webp)? {
+        match &chunk.fourcc {
+            id if id == fourcc::ICCP => metadata.icc_profile = Some(chunk.payload.to_vec()),
+            id if id == fourcc::XMP => {
+                let xml = std::str::from_utf8(chunk.payload)
+                    .map_err(|_| EncodingError::CorruptData("XMP chunk is not UTF-8".to_string()))?;
+                read_xmp(xml, &mut metadata)?;
+            }
+            id if id == fourcc::EXIF => read_exif(chunk.payload, &mut metadata)?,
+            _ => {}
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Host name of this machine, if it can be determined
+pub fn local_hostname() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut buffer = [0u8; 256];
+        let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+        if result == 0 {
+            let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+            if end > 0 {
+                return Some(String::from_utf8_lossy(&buffer[..end]).into_owned());
+            }
+        }
+    }
+
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+// ---------------------------------------------------------------------------
+// EXIF (TIFF container, little-endian)
+// ---------------------------------------------------------------------------
+
+mod tag {
+    pub const IMAGE_DESCRIPTION: u16 = 0x010E;
+    pub const SOFTWARE: u16 = 0x0131;
+    pub const DATE_TIME: u16 = 0x0132;
+    pub const HOST_COMPUTER: u16 = 0x013C;
+    pub const EXIF_IFD: u16 = 0x8769;
+    pub const DATE_TIME_ORIGINAL: u16 = 0x9003;
+    pub const OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+    pub const SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+}
+
+const TIFF_ASCII: u16 = 2;
+const TIFF_LONG: u16 = 4;
+
+fn write_exif(metadata: &EmbeddedMetadata) -> Vec<u8> {
+    let mut ifd0 = Vec::new();
+    let mut exif_ifd = Vec::new();
+
+    if let Some(index) = metadata.display_index {
+        let description = match metadata.display_name {
+            Some(ref name) => format!("Display {}: {}", index, name),
+            None => format!("Display {}", index),
+        };
+        ifd0.push((tag::IMAGE_DESCRIPTION, description));
+    }
+    if let Some(ref software) = metadata.software {
+        ifd0.push((tag::SOFTWARE, software.clone()));
+    }
+    if let Some(time) = metadata.capture_time {
+        let (date_time, millis) = exif_date_time(time);
+        ifd0.push((tag::DATE_TIME, date_time.clone()));
+        exif_ifd.push((tag::DATE_TIME_ORIGINAL, date_time));
+        exif_ifd.push((tag::OFFSET_TIME_ORIGINAL, "+00:00".to_string()));
+        exif_ifd.push((tag::SUB_SEC_TIME_ORIGINAL, format!("{:03}", millis)));
+    }
+    if let Some(ref host) = metadata.hostname {
+        ifd0.push((tag::HOST_COMPUTER, host.clone()));
+    }
+
+    // Lay out IFD0, the Exif sub-IFD, then the string data they point into
+    let ifd_size = |entries: usize| 2 + entries * 12 + 4;
+    let ifd0_entries = ifd0.len() + !exif_ifd.is_empty() as usize;
+    let exif_offset = 8 + ifd_size(ifd0_entries);
+    let mut data_offset = exif_offset + if exif_ifd.is_empty() { 0 } else { ifd_size(exif_ifd.len()) };
+
+    let mut out = b"II*\0".to_vec();
+    out.extend_from_slice(&8u32.to_le_bytes());
+    let mut data = Vec::new();
+
+    let mut write_ifd = |out: &mut Vec<u8>, entries: &[(u16, String)], sub_ifd: Option<usize>| {
+        out.extend_from_slice(&((entries.len() + sub_ifd.is_some() as usize) as u16).to_le_bytes());
+        for (tag, value) in entries {
+            let mut bytes = value.as_bytes().to_vec();
+            bytes.push(0);
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&TIFF_ASCII.to_le_bytes());
+            out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            if bytes.len() <= 4 {
+                bytes.resize(4, 0);
+                out.extend_from_slice(&bytes);
+            } else {
+                out.extend_from_slice(&(data_offset as u32).to_le_bytes());
+                if bytes.len() % 2 == 1 {
+                    bytes.push(0);
+                }
+                data_offset += bytes.len();
+                data.extend_from_slice(&bytes);
+            }
+        }
+        if let Some(offset) = sub_ifd {
+            out.extend_from_slice(&tag::EXIF_IFD.to_le_bytes());
+            out.extend_from_slice(&TIFF_LONG.to_le_bytes());
+            out.extend_from_slice(&1u32.to_le_bytes());
+            out.extend_from_slice(&(offset as u32).to_le_bytes());
+        }
+        // No further IFDs
+        out.extend_from_slice(&0u32.to_le_bytes());
+    };
+
+    // Entries must be sorted by tag; the Exif pointer has the highest tag in IFD0
+    ifd0.sort_by_key(|(tag, _)| *tag);
+    write_ifd(&mut out, &ifd0, (!exif_ifd.is_empty()).then_some(exif_offset));
+    if !exif_ifd.is_empty() {
+        write_ifd(&mut out, &exif_ifd, None);
+    }
+    out.extend_from_slice(&data);
+    out
+}
+
+fn read_exif(payload: &[u8], metadata: &mut EmbeddedMetadata) -> EncodingResult<()> {
+    // Some writers keep the JPEG APP1 prefix
+    let tiff = payload.strip_prefix(b"Exif\0\0").unwrap_or(payload);
+    let corrupt = || EncodingError::CorruptData("malformed EXIF chunk".to_string());
+
+    let little_endian = match tiff.get(0..4) {
+        Some(b"II*\0") => true,
+        Some(b"MM\0*") => false,
+        _ => return Err(corrupt()),
+    };
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = tiff.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    };
+
+    let mut strings = BTreeMap::new();
+    let mut pending = vec![u32_at(4).ok_or_else(corrupt)? as usize];
+    let mut visited = 0;
+    while let Some(ifd) = pending.pop() {
+        // Guard against offset loops
+        visited += 1;
+        if visited > 4 {
+            break;
+        }
+
+        let count = u16_at(ifd).ok_or_else(corrupt)? as usize;
+        for index in 0..count {
+            let entry = ifd + 2 + index * 12;
+            let (Some(tag), Some(kind), Some(length)) = (u16_at(entry), u16_at(entry + 2), u32_at(entry + 4)) else {
+                return Err(corrupt());
+            };
+            match (tag, kind) {
+                (tag::EXIF_IFD, _) => pending.push(u32_at(entry + 8).ok_or_else(corrupt)? as usize),
+                (_, TIFF_ASCII) => {
+                    let length = length as usize;
+                    let start = if length <= 4 { entry + 8 } else { u32_at(entry + 8).ok_or_else(corrupt)? as usize };
+                    let bytes = tiff.get(start..start.saturating_add(length)).ok_or_else(corrupt)?;
+                    let text = String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string();
+                    strings.insert(tag, text);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if metadata.capture_time.is_none() {
+        let date_time = strings.get(&tag::DATE_TIME_ORIGINAL).or(strings.get(&tag::DATE_TIME));
+        if let Some(time) = date_time.and_then(|value| parse_exif_date_time(value)) {
+            let millis = strings
+                .get(&tag::SUB_SEC_TIME_ORIGINAL)
+                .and_then(|value| format!("{:0<3}", value).get(..3)?.parse::<u64>().ok())
+                .unwrap_or(0);
+            metadata.capture_time = Some(time + Duration::from_millis(millis));
+        }
+    }
+    if metadata.hostname.is_none() {
+        metadata.hostname = strings.remove(&tag::HOST_COMPUTER);
+    }
+    if metadata.software.is_none() {
+        metadata.software = strings.remove(&tag::SOFTWARE);
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// XMP
+// ---------------------------------------------------------------------------
+
+fn write_xmp(metadata: &EmbeddedMetadata) -> String {
+    let mut fields = Vec::new();
+    if let Some(time) = metadata.capture_time {
+        fields.push(("xmp:CreateDate", iso8601(time)));
+    }
+    if let Some(ref software) = metadata.software {
+        fields.push(("xmp:CreatorTool", software.clone()));
+    }
+    if let Some(index) = metadata.display_index {
+        fields.push(("wss:DisplayIndex", index.to_string()));
+    }
+    if let Some(ref name) = metadata.display_name {
+        fields.push(("wss:DisplayName", name.clone()));
+    }
+    if let Some(region) = metadata.region {
+        fields.push(("wss:RegionX", region.x.to_string()));
+        fields.push(("wss:RegionY", region.y.to_string()));
+        fields.push(("wss:RegionWidth", region.width.to_string()));
+        fields.push(("wss:RegionHeight", region.height.to_string()));
+    }
+    if let Some(scale) = metadata.scale_factor {
+        fields.push(("wss:ScaleFactor", scale.to_string()));
+    }
+    if let Some(ref host) = metadata.hostname {
+        fields.push(("wss:Hostname", host.clone()));
+    }
+
+    let mut xml = String::new();
+    xml.push_str("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+    xml.push_str("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n");
+    xml.push_str(" <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n");
+    xml.push_str("  <rdf:Description rdf:about=\"\"\n");
+    xml.push_str("    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n");
+    xml.push_str(&format!("    xmlns:wss=\"{}\">\n", XMP_NAMESPACE));
+    for (name, value) in fields {
+        xml.push_str(&format!("   <{name}>{}</{name}>\n", escape_xml(&value)));
+    }
+    if !metadata.properties.is_empty() {
+        xml.push_str("   <wss:Properties>\n    <rdf:Bag>\n");
+        for (key, value) in &metadata.properties {
+            xml.push_str(&format!(
+                "     <rdf:li rdf:parseType=\"Resource\"><wss:Key>{}</wss:Key><wss:Value>{}</wss:Value></rdf:li>\n",
+                escape_xml(key),
+                escape_xml(value)
+            ));
+        }
+        xml.push_str("    </rdf:Bag>\n   </wss:Properties>\n");
+    }
+    xml.push_str("  </rdf:Description>\n </rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>");
+    xml
+}
+
+fn read_xmp(xml: &str, metadata: &mut EmbeddedMetadata) -> EncodingResult<()> {
+    let invalid = |name: &str| EncodingError::CorruptData(format!("invalid XMP value for {}", name));
+    let number = |name: &str| -> EncodingResult<Option<i64>> {
+        xml_element(xml, name)
+            .map(|value| value.trim().parse().map_err(|_| invalid(name)))
+            .transpose()
+    };
+
+    if let Some(date) = xml_element(xml, "xmp:CreateDate") {
+        metadata.capture_time = Some(parse_iso8601(&date).ok_or_else(|| invalid("xmp:CreateDate"))?);
+    }
+    if let Some(software) = xml_element(xml, "xmp:CreatorTool") {
+        metadata.software = Some(software);
+    }
+    if let Some(index) = number("wss:DisplayIndex")? {
+        metadata.display_index = Some(usize::try_from(index).map_err(|_| invalid("wss:DisplayIndex"))?);
+    }
+    if let Some(name) = xml_element(xml, "wss:DisplayName") {
+        metadata.display_name = Some(name);
+    }
+    if let (Some(x), Some(y), Some(width), Some(height)) = (
+        number("wss:RegionX")?,
+        number("wss:RegionY")?,
+        number("wss:RegionWidth")?,
+        number("wss:RegionHeight")?,
+    ) {
+        let region = (|| {
+            Some(Rectangle::new(
+                x.try_into().ok()?,
+                y.try_into().ok()?,
+                width.try_into().ok()?,
+                height.try_into().ok()?,
+            ))
+        })();
+        metadata.region = Some(region.ok_or_else(|| invalid("wss:Region"))?);
+    }
+    if let Some(scale) = xml_element(xml, "wss:ScaleFactor") {
+        metadata.scale_factor = Some(scale.trim().parse().map_err(|_| invalid("wss:ScaleFactor"))?);
+    }
+    if let Some(host) = xml_element(xml, "wss:Hostname") {
+        metadata.hostname = Some(host);
+    }
+
+    // Properties are written as consecutive Key/Value pairs
+    let mut rest = xml;
+    while let Some(key) = xml_element(rest, "wss:Key") {
+        let after_key = &rest[rest.find("</wss:Key>").map_or(rest.len(), |end| end + "</wss:Key>".len())..];
+        let value = xml_element(after_key, "wss:Value").ok_or_else(|| invalid("wss:Properties"))?;
+        metadata.properties.insert(key, value);
+        rest = &after_key[after_key.find("</wss:Value>").map_or(after_key.len(), |end| end + "</wss:Value>".len())..];
+    }
+
+    Ok(())
+}
+
+/// Text content of the first `<name>` element
+fn xml_element(xml: &str, name: &str) -> Option<String> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(unescape_xml(&xml[start..end]))
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// ---------------------------------------------------------------------------
+// UTC date handling
+// ---------------------------------------------------------------------------
+
+/// Break a time into UTC (year, month, day, hour, minute, second, millisecond)
+fn utc_fields(time: SystemTime) -> (i64, u32, u32, u32, u32, u32, u32) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let of_day = seconds.rem_euclid(86_400) as u32;
+    (year, month, day, of_day / 3600, of_day / 60 % 60, of_day % 60, since_epoch.subsec_millis())
+}
+
+fn exif_date_time(time: SystemTime) -> (String, u32) {
+    let (year, month, day, hour, minute, second, millis) = utc_fields(time);
+    let text = format!("{:04}:{:02}:{:02} {:02}:{:02}:{:02}", year, month, day, hour, minute, second);
+    (text, millis)
+}
+
+fn iso8601(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second, millis) = utc_fields(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, hour, minute, second, millis
+    )
+}
+
+/// Parse `YYYY:MM:DD HH:MM:SS` as UTC
+fn parse_exif_date_time(text: &str) -> Option<SystemTime> {
+    let (date, time) = text.trim().split_once(' ')?;
+    let mut date = date.split(':').map(str::parse::<u32>);
+    let mut time = time.split(':').map(str::parse::<u32>);
+    from_fields(
+        date.next()?.ok()?,
+        date.next()?.ok()?,
+        date.next()?.ok()?,
+        time.next()?.ok()?,
+        time.next()?.ok()?,
+        time.next()?.ok()?,
+        0,
+    )
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SS[.fff]Z` as written by [`iso8601`]
+fn parse_iso8601(text: &str) -> Option<SystemTime> {
+    let text = text.trim().strip_suffix('Z')?;
+    let (date, time) = text.split_once('T')?;
+    let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    let millis = format!("{:0<3}", fraction).get(..3)?.parse().ok()?;
+
+    let mut date = date.split('-').map(str::parse::<u32>);
+    let mut time = time.split(':').map(str::parse::<u32>);
+    from_fields(
+        date.next()?.ok()?,
+        date.next()?.ok()?,
+        date.next()?.ok()?,
+        time.next()?.ok()?,
+        time.next()?.ok()?,
+        time.next()?.ok()?,
+        millis,
+    )
+}
+
+fn from_fields(year: u32, month: u32, day: u32, hour: u32, minute: u32, second: u32, millis: u64) -> Option<SystemTime> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let days = days_from_civil(year as i64, month, day);
+    let seconds = days * 86_400 + (hour * 3600 + minute * 60 + second) as i64;
+    let seconds = u64::try_from(seconds).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_millis(millis))
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// Proleptic Gregorian date to days since 1970-01-01
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let day_of_year = (153 * mp + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{ImageEncoder, WebPEncoder};
+    use crate::types::{PixelFormat, RawImage, WebPConfig};
+
+    fn encoded(format: PixelFormat, config: &WebPConfig) -> Vec<u8> {
+        let bpp = format.bytes_per_pixel();
+        let data = (0..16 * 8 * bpp).map(|i| (i * 7) as u8).collect();
+        let image = RawImage::new(data, 16, 8, format);
+        ImageEncoder::encode(&WebPEncoder::new(), &image, config).unwrap()
+    }
+
+    fn sample_metadata() -> EmbeddedMetadata {
+        let mut properties = BTreeMap::new();
+        properties.insert("ticket".to_string(), "OPS-42".to_string());
+        properties.insert("note".to_string(), "<a & b>".to_string());
+        EmbeddedMetadata {
+            capture_time: Some(UNIX_EPOCH + Duration::from_millis(1_700_000_123_456)),
+            display_index: Some(1),
+            display_name: Some("DP-2".to_string()),
+            region: Some(Rectangle::new(-1920, 40, 800, 600)),
+            scale_factor: Some(1.5),
+            hostname: Some("build-host".to_string()),
+            software: Some(SOFTWARE.to_string()),
+            properties,
+            icc_profile: Some(vec![0xAB; 33]),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_and_chunk_layout() {
+        for (format, config) in [
+            (PixelFormat::RGB8, WebPConfig::default()),
+            (PixelFormat::RGBA8, WebPConfig::default()),
+            (PixelFormat::RGBA8, WebPConfig::lossless()),
+        ] {
+            let plain = encoded(format, &config);
+            let metadata = sample_metadata();
+            let tagged = embed(&plain, &metadata).unwrap();
+            assert_eq!(read(&tagged).unwrap(), metadata);
+
+            let chunks = riff::parse_chunks(&tagged).unwrap();
+            let ids: Vec<_> = chunks.iter().map(|chunk| &chunk.fourcc).collect();
+            assert_eq!(ids.first().copied(), Some(fourcc::VP8X));
+            assert_eq!(ids.get(1).copied(), Some(fourcc::ICCP));
+            assert_eq!(&ids[ids.len() - 2..], [fourcc::EXIF, fourcc::XMP]);
+
+            let flags = chunks[0].payload[0];
+            assert_eq!(flags & vp8x_flags::ALPHA != 0, format.has_alpha());
+            assert_eq!(
+                flags & (vp8x_flags::ICC | vp8x_flags::EXIF | vp8x_flags::XMP),
+                vp8x_flags::ICC | vp8x_flags::EXIF | vp8x_flags::XMP
+            );
+
+            // An independent parser agrees on the extended layout
+            use image::ImageDecoder;
+            let mut decoder = image::codecs::webp::WebPDecoder::new(std::io::Cursor::new(&tagged)).unwrap();
+            assert_eq!(decoder.icc_profile().unwrap(), metadata.icc_profile);
+            assert_eq!(decoder.exif_metadata().unwrap(), Some(write_exif(&metadata)));
+
+            // Pixels are untouched and libwebp still decodes the file
+            assert_eq!(
+                WebPDecoder::new().decode(&tagged).unwrap().data,
+                WebPDecoder::new().decode(&plain).unwrap().data
+            );
+        }
+    }
+
+    #[test]
+    fn test_embedding_replaces_existing_metadata() {
+        let plain = encoded(PixelFormat::RGB8, &WebPConfig::default());
+        let first = embed(&plain, &sample_metadata()).unwrap();
+        let second = embed(&first, &EmbeddedMetadata { display_index: Some(3), ..Default::default() }).unwrap();
+
+        let metadata = read(&second).unwrap();
+        assert_eq!(metadata.display_index, Some(3));
+        assert!(metadata.icc_profile.is_none() && metadata.properties.is_empty());
+        let exif_chunks = riff::parse_chunks(&second)
+            .unwrap()
+            .iter()
+            .filter(|chunk| &chunk.fourcc == fourcc::EXIF)
+            .count();
+        assert_eq!(exif_chunks, 1);
+
+        assert_eq!(read(&plain).unwrap(), EmbeddedMetadata::default());
+    }
+
+    #[test]
+    fn test_exif_only_files() {
+        let metadata = sample_metadata();
+        let mut from_exif = EmbeddedMetadata::default();
+        read_exif(&write_exif(&metadata), &mut from_exif).unwrap();
+        assert_eq!(from_exif.capture_time, metadata.capture_time);
+        assert_eq!(from_exif.hostname, metadata.hostname);
+        assert_eq!(from_exif.software, metadata.software);
+
+        // APP1-style prefix is accepted, garbage is not
+        let mut prefixed = b"Exif\0\0".to_vec();
+        prefixed.extend_from_slice(&write_exif(&metadata));
+        assert!(read_exif(&prefixed, &mut EmbeddedMetadata::default()).is_ok());
+        assert!(matches!(
+            read_exif(b"II*\0\xff\xff\xff\xff", &mut EmbeddedMetadata::default()),
+            Err(EncodingError::CorruptData(_))
+        ));
+    }
+
+    #[test]
+    fn test_date_conversion() {
+        let time = UNIX_EPOCH + Duration::from_millis(951_782_400_007);
+        assert_eq!(iso8601(time), "2000-02-29T00:00:00.007Z");
+        assert_eq!(exif_date_time(time), ("2000:02:29 00:00:00".to_string(), 7));
+        assert_eq!(parse_iso8601("2000-02-29T00:00:00.007Z"), Some(time));
+        assert_eq!(parse_exif_date_time("1970:01:01 00:00:01"), Some(UNIX_EPOCH + Duration::from_secs(1)));
+        assert_eq!(parse_iso8601("2000-13-01T00:00:00Z"), None);
+    }
+}
@@ -1,19 +1,35 @@
-//! WebP encoding module
+//! Image encoding module
 
 pub mod webp;
+pub mod analysis;
+pub mod decoder;
 pub mod simd;
 pub mod gpu;
+pub mod animation;
+pub mod formats;
+pub mod metadata;
+pub mod transform;
+pub mod perceptual;
+pub(crate) mod riff;
 
 pub use webp::{WebPEncoder, EncoderOptions};
+pub use decoder::{DecodeOptions, IncrementalDecoder, WebPDecoder, WebPFeatures};
 pub use simd::{SimdConverter, global_simd_converter};
+pub use animation::{AnimatedWebPWriter, AnimationOptions};
+pub use analysis::{ContentAnalysis, ContentClass};
+#[cfg(feature = "avif")]
+pub use formats::AvifEncoder;
+pub use formats::{JpegEncoder, PngEncoder, QoiEncoder};
+pub use metadata::EmbeddedMetadata;
+pub use perceptual::HashIndex;
 
 use crate::{
     error::EncodingResult,
-    types::{RawImage, WebPConfig},
+    types::{OutputFormat, RawImage, WebPConfig},
 };
 
 /// Trait for image encoders
-pub trait ImageEncoder {
+pub trait ImageEncoder: Send + Sync {
     /// Encode raw image data to a specific format
     fn encode(&self, image: &RawImage, config: &WebPConfig) -> EncodingResult<Vec<u8>>;
 
@@ -22,6 +38,63 @@ pub trait ImageEncoder {
 
     /// Check if encoder supports a specific pixel format
     fn supports_format(&self, format: crate::types::PixelFormat) -> bool;
+
+    /// Format of the encoded output
+    fn output_format(&self) -> OutputFormat {
+        OutputFormat::WebP
+    }
+
+    /// Encode and report the settings that produced the output
+    fn encode_with_report(&self, image: &RawImage, config: &WebPConfig) -> EncodingResult<(Vec<u8>, EncodeReport)> {
+        let data = self.encode(image, config)?;
+        Ok((data, EncodeReport::single_pass(config)))
+    }
+}
+
+/// How an encoder arrived at its output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodeReport {
+    /// Quality the output was encoded at
+    pub quality: u8,
+    /// Number of full encodes performed
+    pub passes: u32,
+    /// PSNR of the output in dB, when measured
+    pub psnr: Option<f32>,
+}
+
+impl EncodeReport {
+    /// Report for a single encode at the configured quality
+    pub fn single_pass(config: &WebPConfig) -> Self {
+        Self {
+            quality: config.quality,
+            passes: 1,
+            psnr: None,
+        }
+    }
+}
+
+/// Create the built-in encoder for an output format
+pub fn encoder_for(format: OutputFormat) -> EncodingResult<Box<dyn ImageEncoder>> {
+    match format {
+        OutputFormat::WebP => Ok(Box::new(WebPEncoder::new())),
+        OutputFormat::Png => Ok(Box::new(PngEncoder)),
+        OutputFormat::Jpeg => Ok(Box::new(JpegEncoder)),
+        OutputFormat::Qoi => Ok(Box::new(QoiEncoder)),
+        #[cfg(feature = "avif")]
+        OutputFormat::Avif => Ok(Box::new(AvifEncoder)),
+        #[cfg(not(feature = "avif"))]
+        OutputFormat::Avif => Err(crate::error::EncodingError::UnsupportedFeature(
+            "AVIF output requires the `avif` feature".to_string(),
+        )),
+    }
+}
+
+/// Decode data in any output format to RGBA
+pub fn decode(data: &[u8], format: OutputFormat) -> EncodingResult<RawImage> {
+    match format {
+        OutputFormat::WebP => WebPDecoder::new().decode(data),
+        format => formats::decode(data, format),
+    }
 }
 
 /// Encoder statistics
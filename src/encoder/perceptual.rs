@@ -0,0 +1,271 @@
+//! Perceptual hashing for near-duplicate detection
+//!
+//! Both hashes work on a small grayscale thumbnail, so they survive rescaling,
+//! recompression and minor edits: [`dhash`] records whether brightness rises
+//! between neighbouring cells of a 9x8 thumbnail, [`phash`] whether each of the
+//! 64 lowest DCT frequencies of a 32x32 thumbnail is above their median.
+//! Similar images differ in few bits, measured by [`hamming_distance`].
+
+use crate::{
+    encoder::analysis::{luma, rgb},
+    types::{HashAlgorithm, ImageHash, RawImage},
+};
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+/// Thumbnail edge length for [`phash`]
+const PHASH_SIZE: usize = 32;
+
+/// DCT coefficients kept per axis
+const PHASH_FREQUENCIES: usize = 8;
+
+/// Hash `image` with `algorithm`
+pub fn hash(image: &RawImage, algorithm: HashAlgorithm) -> ImageHash {
+    let bits = match algorithm {
+        HashAlgorithm::DHash => dhash(image),
+        HashAlgorithm::PHash => phash(image),
+    };
+    ImageHash { algorithm, bits }
+}
+
+/// Difference hash: one bit per horizontally adjacent cell pair of a 9x8 thumbnail
+pub fn dhash(image: &RawImage) -> u64 {
+    let cells = thumbnail(image, 9, 8);
+    cells
+        .chunks_exact(9)
+        .flat_map(|row| row.windows(2).map(|pair| pair[1] > pair[0]))
+        .fold(0, |bits, brighter| (bits << 1) | brighter as u64)
+}
+
+/// DCT hash: one bit per low frequency of a 32x32 thumbnail, set when above the median
+pub fn phash(image: &RawImage) -> u64 {
+    let cells = thumbnail(image, PHASH_SIZE, PHASH_SIZE);
+    let cosines: Vec<f64> = (0..PHASH_FREQUENCIES)
+        .flat_map(|u| {
+            (0..PHASH_SIZE).map(move |x| ((2 * x + 1) as f64 * u as f64 * PI / (2 * PHASH_SIZE) as f64).cos())
+        })
+        .collect();
+    let basis = |u: usize| &cosines[u * PHASH_SIZE..(u + 1) * PHASH_SIZE];
+
+    // Separable DCT-II, computing only the frequencies the hash keeps
+    let rows: Vec<[f64; PHASH_FREQUENCIES]> = cells
+        .chunks_exact(PHASH_SIZE)
+        .map(|row| std::array::from_fn(|u| row.iter().zip(basis(u)).map(|(&v, &c)| v * c).sum()))
+        .collect();
+    let mut coefficients = [0.0; PHASH_FREQUENCIES * PHASH_FREQUENCIES];
+    for v in 0..PHASH_FREQUENCIES {
+        for u in 0..PHASH_FREQUENCIES {
+            coefficients[v * PHASH_FREQUENCIES + u] = rows.iter().zip(basis(v)).map(|(row, &c)| row[u] * c).sum();
+        }
+    }
+
+    let mut sorted = coefficients;
+    sorted.sort_by(f64::total_cmp);
+    let median = (sorted[31] + sorted[32]) / 2.0;
+    coefficients.iter().fold(0, |bits, &c| (bits << 1) | (c > median) as u64)
+}
+
+/// Number of differing bits
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Mean luma of each cell of a `columns`x`rows` grid over the image, row-major
+fn thumbnail(image: &RawImage, columns: usize, rows: usize) -> Vec<f64> {
+    let (width, height) = (image.width as usize, image.height as usize);
+    if width == 0 || height == 0 || !image.is_valid() {
+        return vec![0.0; columns * rows];
+    }
+
+    let bpp = image.format.bytes_per_pixel();
+    // Cells narrower than a pixel repeat it, so tiny images still hash
+    let span = |index: usize, cells: usize, length: usize| {
+        let start = (index * length / cells).min(length - 1);
+        start..((index + 1) * length / cells).max(start + 1)
+    };
+
+    let mut cells = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        let ys = span(row, rows, height);
+        let mut sums = vec![0u64; columns];
+        for y in ys.clone() {
+            let line = &image.data[y * image.stride..y * image.stride + image.row_bytes()];
+            for (column, sum) in sums.iter_mut().enumerate() {
+                *sum += span(column, columns, width)
+                    .map(|x| luma(rgb(image.format, &line[x * bpp..(x + 1) * bpp])) as u64)
+                    .sum::<u64>();
+            }
+        }
+        for (column, sum) in sums.into_iter().enumerate() {
+            cells.push(sum as f64 / (span(column, columns, width).len() * ys.len()) as f64);
+        }
+    }
+    cells
+}
+
+/// Bounded history of recent hashes, oldest evicted first
+#[derive(Debug, Clone)]
+pub struct HashIndex {
+    capacity: usize,
+    hashes: VecDeque<ImageHash>,
+}
+
+impl HashIndex {
+    /// Create an index remembering up to `capacity` hashes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            hashes: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Maximum number of hashes kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the capacity, evicting the oldest hashes if it shrinks
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.hashes.len() > capacity {
+            self.hashes.pop_front();
+        }
+    }
+
+    /// Number of hashes kept
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Whether the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Forget all hashes
+    pub fn clear(&mut self) {
+        self.hashes.clear();
+    }
+
+    /// Remember `hash`, evicting the oldest one when full
+    pub fn insert(&mut self, hash: ImageHash) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.hashes.len() == self.capacity {
+            self.hashes.pop_front();
+        }
+        self.hashes.push_back(hash);
+    }
+
+    /// Distance to the closest remembered hash
+    pub fn closest(&self, hash: &ImageHash) -> Option<u32> {
+        self.hashes.iter().map(|other| hash.distance(other)).min()
+    }
+
+    /// Distance to the closest remembered hash, if it is within `max_distance` bits
+    pub fn find_within(&self, hash: &ImageHash, max_distance: u32) -> Option<u32> {
+        self.closest(hash).filter(|&distance| distance <= max_distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PixelFormat;
+
+    fn image_from(width: u32, height: u32, pixel: impl Fn(u32, u32) -> u8) -> RawImage {
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let value = pixel(x, y);
+                data.extend_from_slice(&[value, value, value, 255]);
+            }
+        }
+        RawImage::new(data, width, height, PixelFormat::RGBA8)
+    }
+
+    /// Window-like layout: title bar, sidebar and a few content blocks
+    fn layout(width: u32, height: u32) -> RawImage {
+        image_from(width, height, |x, y| {
+            let (fx, fy) = (x as f32 / width as f32, y as f32 / height as f32);
+            if fy < 0.1 {
+                60
+            } else if fx < 0.25 {
+                200
+            } else if (0.35..0.9).contains(&fx) && (fy % 0.2) < 0.12 {
+                30
+            } else {
+                245
+            }
+        })
+    }
+
+    /// Different layout: diagonal bands
+    fn bands(width: u32, height: u32) -> RawImage {
+        image_from(width, height, |x, y| if (x + 2 * y) / 24 % 2 == 0 { 20 } else { 230 })
+    }
+
+    #[test]
+    fn test_dhash_gradient() {
+        let rising = image_from(90, 80, |x, _| (x * 2) as u8);
+        assert_eq!(dhash(&rising), u64::MAX);
+        let falling = image_from(90, 80, |x, _| 255 - (x * 2) as u8);
+        assert_eq!(dhash(&falling), 0);
+    }
+
+    #[test]
+    fn test_hashes_survive_rescaling() {
+        for algorithm in [HashAlgorithm::DHash, HashAlgorithm::PHash] {
+            let original = hash(&layout(640, 400), algorithm);
+            let scaled = hash(&layout(320, 200), algorithm);
+            let different = hash(&bands(640, 400), algorithm);
+
+            assert!(original.distance(&scaled) <= 6, "{algorithm:?}: {}", original.distance(&scaled));
+            assert!(original.distance(&different) > 16, "{algorithm:?}: {}", original.distance(&different));
+        }
+    }
+
+    #[test]
+    fn test_hash_ignores_format_and_padding() {
+        let rgba = layout(100, 60);
+        let mut bgra = rgba.clone();
+        bgra.format = PixelFormat::BGRA8;
+        let padded = RawImage::with_stride(
+            rgba.rows().flat_map(|row| row.iter().copied().chain([0; 16])).collect(),
+            100,
+            60,
+            PixelFormat::RGBA8,
+            416,
+        );
+
+        let expected = phash(&rgba);
+        assert_eq!(phash(&bgra), expected);
+        assert_eq!(phash(&padded), expected);
+        // Smaller than the thumbnail
+        assert_eq!(hash(&layout(4, 3), HashAlgorithm::DHash).distance(&hash(&layout(4, 3), HashAlgorithm::DHash)), 0);
+    }
+
+    #[test]
+    fn test_hash_index_is_bounded() {
+        let hash = |bits| ImageHash { algorithm: HashAlgorithm::PHash, bits };
+        let mut index = HashIndex::new(2);
+        assert_eq!(index.closest(&hash(0)), None);
+
+        index.insert(hash(0b1111));
+        index.insert(hash(0b0011));
+        assert_eq!(index.closest(&hash(0b0111)), Some(1));
+
+        index.insert(hash(u64::MAX));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.closest(&hash(0)), Some(2));
+        assert_eq!(index.find_within(&hash(0), 1), None);
+
+        let other = ImageHash { algorithm: HashAlgorithm::DHash, bits: u64::MAX };
+        assert_eq!(index.closest(&other), Some(64));
+
+        index.set_capacity(1);
+        assert_eq!(index.closest(&hash(0)), Some(64));
+    }
+}
@@ -0,0 +1,143 @@
+//! Minimal RIFF container helpers for the WebP file format
+//!
+//! A WebP file is `RIFF <size> WEBP` followed by a sequence of chunks, each a
+//! FourCC, a little-endian payload size and the payload padded to an even length.
+
+use crate::error::{EncodingError, EncodingResult};
+
+/// Chunk FourCC values used by the extended WebP format
+pub(crate) mod fourcc {
+    pub const VP8: &[u8; 4] = b"VP8 ";
+    pub const VP8L: &[u8; 4] = b"VP8L";
+    pub const VP8X: &[u8; 4] = b"VP8X";
+    pub const ALPH: &[u8; 4] = b"ALPH";
+    pub const ANIM: &[u8; 4] = b"ANIM";
+    pub const ANMF: &[u8; 4] = b"ANMF";
+    pub const ICCP: &[u8; 4] = b"ICCP";
+    pub const EXIF: &[u8; 4] = b"EXIF";
+    pub const XMP: &[u8; 4] = b"XMP ";
+}
+
+/// VP8X feature flags
+pub(crate) mod vp8x_flags {
+    pub const ANIMATION: u8 = 0x02;
+    pub const XMP: u8 = 0x04;
+    pub const EXIF: u8 = 0x08;
+    pub const ALPHA: u8 = 0x10;
+    pub const ICC: u8 = 0x20;
+}
+
+/// A chunk borrowed from an encoded WebP file
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Chunk<'a> {
+    pub fourcc: [u8; 4],
+    pub payload: &'a [u8],
+}
+
+/// Split an encoded WebP file into its top-level chunks
+pub(crate) fn parse_chunks(data: &[u8]) -> EncodingResult<Vec<Chunk<'_>>> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err(EncodingError::EncodingFailed(
+            "Not a RIFF/WEBP container".to_string(),
+        ));
+    }
+
+    let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let end = (riff_size + 8).min(data.len());
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+
+    while offset + 8 <= end {
+        let fourcc = [data[offset], data[offset + 1], data[offset + 2], data[offset + 3]];
+        let size = u32::from_le_bytes([
+            data[offset + 4],
+            data[offset + 5],
+            data[offset + 6],
+            data[offset + 7],
+        ]) as usize;
+        let payload_start = offset + 8;
+        let payload_end = payload_start
+            .checked_add(size)
+            .filter(|&payload_end| payload_end <= end)
+            .ok_or_else(|| {
+                EncodingError::EncodingFailed(format!(
+                    "Truncated {} chunk",
+                    String::from_utf8_lossy(&fourcc)
+                ))
+            })?;
+
+        chunks.push(Chunk {
+            fourcc,
+            payload: &data[payload_start..payload_end],
+        });
+        offset = payload_end + (size & 1);
+    }
+
+    Ok(chunks)
+}
+
+/// Append a chunk, including its header and padding byte
+pub(crate) fn write_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Wrap a sequence of serialized chunks in the `RIFF <size> WEBP` header
+pub(crate) fn wrap_riff(chunks: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(chunks.len() + 12);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&((chunks.len() + 4) as u32).to_le_bytes());
+    out.extend_from_slice(b"WEBP");
+    out.extend_from_slice(chunks);
+    out
+}
+
+/// Write a 24-bit little-endian value
+pub(crate) fn push_u24(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes()[..3]);
+}
+
+/// Build a VP8X header payload for the given feature flags and canvas size
+pub(crate) fn vp8x_payload(flags: u8, canvas_width: u32, canvas_height: u32) -> Vec<u8> {
+    let mut payload = vec![flags, 0, 0, 0];
+    push_u24(&mut payload, canvas_width - 1);
+    push_u24(&mut payload, canvas_height - 1);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_with_padding() {
+        let mut body = Vec::new();
+        write_chunk(&mut body, b"ABCD", &[1, 2, 3]);
+        write_chunk(&mut body, b"EFGH", &[4, 5]);
+        let file = wrap_riff(&body);
+
+        let chunks = parse_chunks(&file).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(&chunks[0].fourcc, b"ABCD");
+        assert_eq!(chunks[0].payload, &[1, 2, 3]);
+        assert_eq!(chunks[1].payload, &[4, 5]);
+    }
+
+    #[test]
+    fn test_rejects_truncated_chunk() {
+        let mut body = Vec::new();
+        write_chunk(&mut body, b"ABCD", &[0; 16]);
+        let mut file = wrap_riff(&body);
+        file.truncate(file.len() - 4);
+        // Keep the RIFF size consistent with the truncated length
+        let size = (file.len() - 8) as u32;
+        file[4..8].copy_from_slice(&size.to_le_bytes());
+
+        assert!(parse_chunks(&file).is_err());
+        assert!(parse_chunks(b"not a webp").is_err());
+    }
+}
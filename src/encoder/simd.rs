@@ -1,5 +1,6 @@
-//! SIMD-optimized pixel format conversion and WebP encoding helpers
+//! SIMD-optimized pixel format conversion, resampling and WebP encoding helpers
 
+use crate::types::{PixelFormat, RawImage};
 
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
@@ -301,6 +302,179 @@ impl SimdConverter {
         self.convert_rgba_to_rgb_scalar(src, dst);
     }
 
+    /// Convert BGRA to RGBA in each `stride`-byte row, leaving row padding untouched
+    pub fn convert_bgra_to_rgba_strided(&self, data: &mut [u8], row_bytes: usize, stride: usize) {
+        for_each_row(data, row_bytes, stride, |row| self.convert_bgra_to_rgba(row));
+    }
+
+    /// Convert BGR to RGB in each `stride`-byte row, leaving row padding untouched
+    pub fn convert_bgr_to_rgb_strided(&self, data: &mut [u8], row_bytes: usize, stride: usize) {
+        for_each_row(data, row_bytes, stride, |row| self.convert_bgr_to_rgb(row));
+    }
+
+    /// Convert RGBA rows to RGB rows, each with its own stride
+    pub fn convert_rgba_to_rgb_strided(
+        &self,
+        src: &[u8],
+        src_stride: usize,
+        dst: &mut [u8],
+        dst_stride: usize,
+        width: usize,
+    ) {
+        if src_stride == width * 4 && dst_stride == width * 3 {
+            return self.convert_rgba_to_rgb(src, dst);
+        }
+
+        for (src_row, dst_row) in src.chunks(src_stride).zip(dst.chunks_mut(dst_stride)) {
+            let pixels = width.min(src_row.len() / 4).min(dst_row.len() / 3);
+            self.convert_rgba_to_rgb(&src_row[..pixels * 4], &mut dst_row[..pixels * 3]);
+        }
+    }
+
+    /// Weighted sum of `rows`, written to `dst`
+    ///
+    /// `weights` are fixed point with [`BLEND_PRECISION`] fractional bits, one per row;
+    /// results are rounded and clamped to `0..=255`. Every row must be at least
+    /// `dst.len()` bytes long. This is the vertical pass of the resampler.
+    pub fn blend_rows(&self, rows: &[&[u8]], weights: &[i16], dst: &mut [u8]) {
+        assert_eq!(rows.len(), weights.len());
+        assert!(rows.iter().all(|row| row.len() >= dst.len()));
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            if self.has_avx2 {
+                unsafe { self.blend_rows_avx2(rows, weights, dst) }
+            } else if self.has_sse41 {
+                unsafe { self.blend_rows_sse41(rows, weights, dst) }
+            } else {
+                blend_rows_scalar(rows, weights, dst)
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            if self.has_neon {
+                unsafe { self.blend_rows_neon(rows, weights, dst) }
+            } else {
+                blend_rows_scalar(rows, weights, dst)
+            }
+        }
+
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        {
+            blend_rows_scalar(rows, weights, dst)
+        }
+    }
+
+    /// AVX2 row blending, 32 bytes at a time
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn blend_rows_avx2(&self, rows: &[&[u8]], weights: &[i16], dst: &mut [u8]) {
+        let round = _mm256_set1_epi32(1 << (BLEND_PRECISION - 1));
+        let simd_len = dst.len() & !31;
+
+        for i in (0..simd_len).step_by(32) {
+            let mut acc = [round; 4];
+            for (row, &weight) in rows.iter().zip(weights) {
+                let weight = _mm256_set1_epi32(weight as i32);
+                let ptr = row.as_ptr().add(i);
+                for (j, acc) in acc.iter_mut().enumerate() {
+                    let bytes = _mm_loadl_epi64(ptr.add(j * 8) as *const __m128i);
+                    let pixels = _mm256_cvtepu8_epi32(bytes);
+                    *acc = _mm256_add_epi32(*acc, _mm256_mullo_epi32(pixels, weight));
+                }
+            }
+
+            let acc = acc.map(|acc| _mm256_srai_epi32::<BLEND_PRECISION>(acc));
+            // Packing works per 128-bit lane, so restore the order after each step
+            let low = _mm256_permute4x64_epi64::<0b11_01_10_00>(_mm256_packs_epi32(acc[0], acc[1]));
+            let high = _mm256_permute4x64_epi64::<0b11_01_10_00>(_mm256_packs_epi32(acc[2], acc[3]));
+            let packed = _mm256_permute4x64_epi64::<0b11_01_10_00>(_mm256_packus_epi16(low, high));
+            _mm256_storeu_si256(dst.as_mut_ptr().add(i) as *mut __m256i, packed);
+        }
+
+        let tail: Vec<&[u8]> = rows.iter().map(|row| &row[simd_len..]).collect();
+        blend_rows_scalar(&tail, weights, &mut dst[simd_len..]);
+    }
+
+    /// SSE4.1 row blending, 16 bytes at a time
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn blend_rows_sse41(&self, rows: &[&[u8]], weights: &[i16], dst: &mut [u8]) {
+        let round = _mm_set1_epi32(1 << (BLEND_PRECISION - 1));
+        let simd_len = dst.len() & !15;
+
+        for i in (0..simd_len).step_by(16) {
+            let mut acc = [round; 4];
+            for (row, &weight) in rows.iter().zip(weights) {
+                let weight = _mm_set1_epi32(weight as i32);
+                let ptr = row.as_ptr().add(i);
+                for (j, acc) in acc.iter_mut().enumerate() {
+                    let bytes = _mm_cvtsi32_si128((ptr.add(j * 4) as *const i32).read_unaligned());
+                    let pixels = _mm_cvtepu8_epi32(bytes);
+                    *acc = _mm_add_epi32(*acc, _mm_mullo_epi32(pixels, weight));
+                }
+            }
+
+            let acc = acc.map(|acc| _mm_srai_epi32::<BLEND_PRECISION>(acc));
+            let packed = _mm_packus_epi16(_mm_packs_epi32(acc[0], acc[1]), _mm_packs_epi32(acc[2], acc[3]));
+            _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, packed);
+        }
+
+        let tail: Vec<&[u8]> = rows.iter().map(|row| &row[simd_len..]).collect();
+        blend_rows_scalar(&tail, weights, &mut dst[simd_len..]);
+    }
+
+    /// NEON row blending, 8 bytes at a time
+    #[cfg(target_arch = "aarch64")]
+    unsafe fn blend_rows_neon(&self, rows: &[&[u8]], weights: &[i16], dst: &mut [u8]) {
+        let simd_len = dst.len() & !7;
+
+        for i in (0..simd_len).step_by(8) {
+            let mut low = vdupq_n_s32(0);
+            let mut high = vdupq_n_s32(0);
+            for (row, &weight) in rows.iter().zip(weights) {
+                let pixels = vreinterpretq_s16_u16(vmovl_u8(vld1_u8(row.as_ptr().add(i))));
+                low = vmlal_n_s16(low, vget_low_s16(pixels), weight);
+                high = vmlal_n_s16(high, vget_high_s16(pixels), weight);
+            }
+
+            // Rounding, saturating narrow matches the scalar rounding and clamp
+            let narrowed = vcombine_s16(
+                vqrshrn_n_s32::<BLEND_PRECISION>(low),
+                vqrshrn_n_s32::<BLEND_PRECISION>(high),
+            );
+            vst1_u8(dst.as_mut_ptr().add(i), vqmovun_s16(narrowed));
+        }
+
+        let tail: Vec<&[u8]> = rows.iter().map(|row| &row[simd_len..]).collect();
+        blend_rows_scalar(&tail, weights, &mut dst[simd_len..]);
+    }
+
+    /// Swap the red and blue channels of an image in place, honoring its stride
+    ///
+    /// Turns BGRA8 into RGBA8 and BGR8 into RGB8 (and back); other formats are left alone.
+    pub fn swap_red_blue(&self, image: &mut RawImage) {
+        let (row_bytes, stride) = (image.row_bytes(), image.stride);
+        match image.format {
+            PixelFormat::BGRA8 | PixelFormat::RGBA8 => {
+                self.convert_bgra_to_rgba_strided(&mut image.data, row_bytes, stride)
+            }
+            PixelFormat::BGR8 | PixelFormat::RGB8 => {
+                self.convert_bgr_to_rgb_strided(&mut image.data, row_bytes, stride)
+            }
+            PixelFormat::Gray8 | PixelFormat::GrayA8 => return,
+        }
+
+        image.format = match image.format {
+            PixelFormat::BGRA8 => PixelFormat::RGBA8,
+            PixelFormat::RGBA8 => PixelFormat::BGRA8,
+            PixelFormat::BGR8 => PixelFormat::RGB8,
+            PixelFormat::RGB8 => PixelFormat::BGR8,
+            format => format,
+        };
+    }
+
     /// Get SIMD capabilities as a string
     pub fn capabilities(&self) -> String {
         let mut caps = Vec::new();
@@ -351,6 +525,30 @@ impl Default for SimdConverter {
     }
 }
 
+/// Fractional bits of the fixed-point weights taken by [`SimdConverter::blend_rows`]
+pub const BLEND_PRECISION: i32 = 14;
+
+/// Scalar fallback for row blending
+pub(crate) fn blend_rows_scalar(rows: &[&[u8]], weights: &[i16], dst: &mut [u8]) {
+    for (i, out) in dst.iter_mut().enumerate() {
+        let sum: i32 = rows.iter().zip(weights).map(|(row, &weight)| row[i] as i32 * weight as i32).sum();
+        *out = ((sum + (1 << (BLEND_PRECISION - 1))) >> BLEND_PRECISION).clamp(0, 255) as u8;
+    }
+}
+
+/// Apply `convert` to the pixel bytes of each row; packed buffers are converted in one call
+fn for_each_row(data: &mut [u8], row_bytes: usize, stride: usize, mut convert: impl FnMut(&mut [u8])) {
+    if stride == row_bytes || stride == 0 {
+        convert(data);
+        return;
+    }
+
+    for row in data.chunks_mut(stride) {
+        let len = row_bytes.min(row.len());
+        convert(&mut row[..len]);
+    }
+}
+
 /// Get global SIMD converter instance
 pub fn global_simd_converter() -> &'static SimdConverter {
     static CONVERTER: once_cell::sync::Lazy<SimdConverter> =
@@ -388,4 +586,78 @@ mod tests {
 
         assert_eq!(dst, vec![255, 128, 64, 128, 64, 32]); // RGB without alpha
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_blend_rows_matches_scalar() {
+        let converter = SimdConverter::new();
+        let rows: Vec<Vec<u8>> = (0..6u32)
+            .map(|r| (0..77u32).map(|i| ((i * 37 + r * 91) % 256) as u8).collect())
+            .collect();
+        let rows: Vec<&[u8]> = rows.iter().map(Vec::as_slice).collect();
+        // Lanczos-like weights with negative lobes, summing to 1 << BLEND_PRECISION
+        let weights = [-800, 2600, 11984, 4000, -1400, 0];
+        assert_eq!(weights.iter().map(|&w| w as i32).sum::<i32>(), 1 << BLEND_PRECISION);
+
+        for len in [0, 7, 16, 31, 32, 77] {
+            let mut expected = vec![0u8; len];
+            let mut actual = vec![0u8; len];
+            blend_rows_scalar(&rows, &weights, &mut expected);
+            converter.blend_rows(&rows, &weights, &mut actual);
+            assert_eq!(actual, expected, "length {}", len);
+
+            #[cfg(target_arch = "x86_64")]
+            if converter.has_sse41 {
+                actual.fill(0);
+                unsafe { converter.blend_rows_sse41(&rows, &weights, &mut actual) };
+                assert_eq!(actual, expected, "SSE4.1, length {}", len);
+            }
+        }
+
+        // Overshoot clamps instead of wrapping
+        let (bright, dark) = ([255u8; 40], [0u8; 40]);
+        let mut out = [0u8; 40];
+        converter.blend_rows(&[&bright, &dark], &[20000, -3616], &mut out);
+        assert_eq!(out, [255u8; 40]);
+        converter.blend_rows(&[&dark, &bright], &[20000, -3616], &mut out);
+        assert_eq!(out, [0u8; 40]);
+    }
+
+    mod stride_properties {
+        use super::super::*;
+        use crate::types::strategies::{packed_and_padded, padding_intact, ALL_FORMATS};
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn test_swap_red_blue_skips_padding((mut packed, mut padded) in packed_and_padded(ALL_FORMATS)) {
+                let converter = global_simd_converter();
+                converter.swap_red_blue(&mut packed);
+                converter.swap_red_blue(&mut padded);
+
+                prop_assert_eq!(packed.format, padded.format);
+                prop_assert_eq!(padded.to_packed().data, packed.data);
+                prop_assert!(padding_intact(&padded));
+            }
+
+            #[test]
+            fn test_rgba_to_rgb_handles_both_strides(
+                (packed, padded) in packed_and_padded(&[PixelFormat::RGBA8]),
+                dst_padding in 0usize..7,
+            ) {
+                let width = packed.width as usize;
+                let height = packed.height as usize;
+                let converter = global_simd_converter();
+
+                let mut expected = vec![0u8; width * height * 3];
+                converter.convert_rgba_to_rgb(&packed.data, &mut expected);
+
+                let dst_stride = width * 3 + dst_padding;
+                let mut actual = vec![0u8; dst_stride * height];
+                converter.convert_rgba_to_rgb_strided(&padded.data, padded.stride, &mut actual, dst_stride, width);
+
+                let actual = RawImage::with_stride(actual, width as u32, height as u32, PixelFormat::RGB8, dst_stride);
+                prop_assert_eq!(actual.to_packed().data, expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,399 @@
+//! Crop and resize between capture and encode
+//!
+//! Resizing is separable: a scalar horizontal pass into an intermediate buffer,
+//! then a vertical pass through [`SimdConverter::blend_rows`]. Filters are
+//! stretched by the scale factor when downscaling, so thumbnails are
+//! antialiased rather than decimated. Every pixel format is resized
+//! channel by channel; the output is tightly packed in the input's format.
+//!
+//! [`SimdConverter::blend_rows`]: crate::encoder::simd::SimdConverter::blend_rows
+
+use crate::{
+    encoder::simd::{global_simd_converter, BLEND_PRECISION},
+    error::{EncodingError, EncodingResult},
+    types::{ImageTransform, RawImage, Rectangle, ResizeFilter},
+};
+
+/// Apply `transform` to `image`; identity transforms return it untouched
+///
+/// Fails before allocating if the output would exceed
+/// [`ImageTransform::MAX_OUTPUT_SIDE`] on either side.
+pub fn apply(image: RawImage, transform: &ImageTransform) -> EncodingResult<RawImage> {
+    if transform.is_identity() {
+        return Ok(image);
+    }
+    transform.validate().map_err(EncodingError::InvalidConfiguration)?;
+
+    let image = match transform.crop {
+        Some(rect) => crop(&image, rect)?,
+        None => image,
+    };
+
+    let (width, height) = transform.output_size(image.width, image.height);
+    if width > ImageTransform::MAX_OUTPUT_SIDE || height > ImageTransform::MAX_OUTPUT_SIDE {
+        return Err(EncodingError::InvalidConfiguration(format!(
+            "Transformed size {}x{} exceeds {} pixels per side",
+            width,
+            height,
+            ImageTransform::MAX_OUTPUT_SIDE
+        )));
+    }
+    if (width, height) == (image.width, image.height) {
+        Ok(image)
+    } else {
+        resize(&image, width, height, transform.filter)
+    }
+}
+
+/// Where `rect` of a `width`x`height` input ends up after `transform`, rounded outwards
+pub fn map_rect(transform: &ImageTransform, rect: Rectangle, width: u32, height: u32) -> Option<Rectangle> {
+    let bounds = Rectangle::new(0, 0, width, height);
+    let crop = match transform.crop {
+        Some(crop) => crop.intersection(&bounds)?,
+        None => bounds,
+    };
+    let rect = rect.intersection(&crop)?;
+
+    let (out_width, out_height) = transform.output_size(crop.width, crop.height);
+    let scale_x = out_width as f64 / crop.width as f64;
+    let scale_y = out_height as f64 / crop.height as f64;
+    let (x, y) = ((rect.x - crop.x) as f64, (rect.y - crop.y) as f64);
+
+    let left = (x * scale_x).floor();
+    let top = (y * scale_y).floor();
+    let right = ((x + rect.width as f64) * scale_x).ceil();
+    let bottom = ((y + rect.height as f64) * scale_y).ceil();
+    Rectangle::new(left as i32, top as i32, (right - left) as u32, (bottom - top) as u32)
+        .intersection(&Rectangle::new(0, 0, out_width, out_height))
+}
+
+/// Copy the part of `image` inside `rect`, clamped to the image bounds
+pub fn crop(image: &RawImage, rect: Rectangle) -> EncodingResult<RawImage> {
+    check_input(image)?;
+
+    image.crop(rect).ok_or_else(|| {
+        EncodingError::InvalidConfiguration(format!(
+            "Crop {}x{}+{}+{} lies outside the {}x{} image",
+            rect.width, rect.height, rect.x, rect.y, image.width, image.height
+        ))
+    })
+}
+
+/// Resample `image` to `width`x`height`
+pub fn resize(image: &RawImage, width: u32, height: u32, filter: ResizeFilter) -> EncodingResult<RawImage> {
+    check_input(image)?;
+    if width == 0 || height == 0 {
+        return Err(EncodingError::InvalidDimensions { width, height });
+    }
+
+    let data = match filter {
+        ResizeFilter::Nearest => resize_nearest(image, width as usize, height as usize),
+        ResizeFilter::Bilinear => resize_separable(image, width as usize, height as usize, Kernel::Triangle),
+        ResizeFilter::Lanczos3 => resize_separable(image, width as usize, height as usize, Kernel::Lanczos3),
+    };
+    Ok(RawImage::new(data, width, height, image.format))
+}
+
+fn check_input(image: &RawImage) -> EncodingResult<()> {
+    if image.width == 0 || image.height == 0 {
+        return Err(EncodingError::InvalidDimensions {
+            width: image.width,
+            height: image.height,
+        });
+    }
+    if !image.is_valid() {
+        return Err(EncodingError::BufferTooSmall {
+            required: image.required_len(),
+            provided: image.data.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Source index sampled by each output index, taken at pixel centres
+fn nearest_indices(src: usize, dst: usize) -> Vec<usize> {
+    (0..dst).map(|i| ((2 * i + 1) * src / (2 * dst)).min(src - 1)).collect()
+}
+
+fn resize_nearest(image: &RawImage, width: usize, height: usize) -> Vec<u8> {
+    let bpp = image.format.bytes_per_pixel();
+    let columns = nearest_indices(image.width as usize, width);
+    let mut data = Vec::with_capacity(width * height * bpp);
+
+    for y in nearest_indices(image.height as usize, height) {
+        let row = &image.data[y * image.stride..];
+        for &x in &columns {
+            data.extend_from_slice(&row[x * bpp..(x + 1) * bpp]);
+        }
+    }
+    data
+}
+
+#[derive(Clone, Copy)]
+enum Kernel {
+    Triangle,
+    Lanczos3,
+}
+
+impl Kernel {
+    /// Radius of the kernel at scale 1
+    fn support(self) -> f64 {
+        match self {
+            Kernel::Triangle => 1.0,
+            Kernel::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            Kernel::Triangle => (1.0 - x).max(0.0),
+            Kernel::Lanczos3 if x < 3.0 => sinc(x) * sinc(x / 3.0),
+            Kernel::Lanczos3 => 0.0,
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * std::f64::consts::PI;
+        x.sin() / x
+    }
+}
+
+/// Fixed-point filter taps for every output index along one axis
+struct Taps {
+    /// First source index and tap count per output index
+    spans: Vec<(usize, usize)>,
+    /// `max_taps` weights per output index, each group summing to `1 << BLEND_PRECISION`
+    weights: Vec<i16>,
+    max_taps: usize,
+}
+
+impl Taps {
+    fn new(src: usize, dst: usize, kernel: Kernel) -> Self {
+        let scale = src as f64 / dst as f64;
+        let stretch = scale.max(1.0);
+        let support = kernel.support() * stretch;
+        let max_taps = (support.ceil() as usize * 2 + 1).min(src);
+        let one = 1i32 << BLEND_PRECISION;
+
+        let mut spans = Vec::with_capacity(dst);
+        let mut weights = vec![0i16; dst * max_taps];
+        let mut scratch = Vec::with_capacity(max_taps);
+
+        for (i, group) in weights.chunks_exact_mut(max_taps).enumerate() {
+            let center = (i as f64 + 0.5) * scale;
+            let start = ((center - support).floor().max(0.0) as usize).min(src - 1);
+            let end = ((center + support).ceil() as usize).min(src).min(start + max_taps);
+
+            scratch.clear();
+            scratch.extend((start..end).map(|j| kernel.weight((j as f64 + 0.5 - center) / stretch)));
+            let total: f64 = scratch.iter().sum();
+
+            if total.abs() < f64::EPSILON {
+                // Degenerate span: fall back to the nearest source pixel
+                let nearest = (center as usize).clamp(start, end - 1);
+                group[nearest - start] = one as i16;
+            } else {
+                let mut sum = 0;
+                for (w, &f) in group.iter_mut().zip(&scratch) {
+                    *w = (f / total * one as f64).round() as i16;
+                    sum += *w as i32;
+                }
+                // Give the rounding error to the centre tap so flat areas stay flat
+                let (largest, _) = group.iter().enumerate().max_by_key(|&(_, &w)| w).unwrap();
+                group[largest] += (one - sum) as i16;
+            }
+
+            spans.push((start, end - start));
+        }
+
+        Self { spans, weights, max_taps }
+    }
+
+    fn get(&self, i: usize) -> (usize, &[i16]) {
+        let (start, len) = self.spans[i];
+        (start, &self.weights[i * self.max_taps..i * self.max_taps + len])
+    }
+}
+
+fn resize_separable(image: &RawImage, width: usize, height: usize, kernel: Kernel) -> Vec<u8> {
+    let bpp = image.format.bytes_per_pixel();
+    let (src_width, src_height) = (image.width as usize, image.height as usize);
+    let row_bytes = width * bpp;
+
+    // Horizontal pass into packed rows of the output width
+    let horizontal: Vec<u8> = if width == src_width {
+        image.rows().flatten().copied().collect()
+    } else {
+        let taps = Taps::new(src_width, width, kernel);
+        let round = 1 << (BLEND_PRECISION - 1);
+        let mut out = vec![0u8; row_bytes * src_height];
+
+        for (row, out_row) in image.rows().zip(out.chunks_exact_mut(row_bytes)) {
+            for (x, out_pixel) in out_row.chunks_exact_mut(bpp).enumerate() {
+                let (start, weights) = taps.get(x);
+                let pixels = &row[start * bpp..(start + weights.len()) * bpp];
+                for (c, out) in out_pixel.iter_mut().enumerate() {
+                    let sum: i32 = pixels
+                        .iter()
+                        .skip(c)
+                        .step_by(bpp)
+                        .zip(weights)
+                        .map(|(&p, &w)| p as i32 * w as i32)
+                        .sum();
+                    *out = ((sum + round) >> BLEND_PRECISION).clamp(0, 255) as u8;
+                }
+            }
+        }
+        out
+    };
+
+    if height == src_height {
+        return horizontal;
+    }
+
+    // Vertical pass, one output row at a time
+    let taps = Taps::new(src_height, height, kernel);
+    let converter = global_simd_converter();
+    let rows: Vec<&[u8]> = horizontal.chunks_exact(row_bytes).collect();
+    let mut out = vec![0u8; row_bytes * height];
+
+    for (y, out_row) in out.chunks_exact_mut(row_bytes).enumerate() {
+        let (start, weights) = taps.get(y);
+        converter.blend_rows(&rows[start..start + weights.len()], weights, out_row);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PixelFormat;
+
+    /// BGRA gradient with 8 bytes of row padding
+    fn padded_gradient(width: u32, height: u32) -> RawImage {
+        let stride = width as usize * 4 + 8;
+        let mut data = vec![0xA5u8; stride * height as usize];
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let offset = y * stride + x * 4;
+                data[offset..offset + 4].copy_from_slice(&[(x * 255 / width as usize) as u8, (y * 3) as u8, 90, 255]);
+            }
+        }
+        RawImage::with_stride(data, width, height, PixelFormat::BGRA8, stride)
+    }
+
+    #[test]
+    fn test_output_size() {
+        let fit = ImageTransform { fit_within: Some((1280, 0)), ..Default::default() };
+        assert_eq!(fit.output_size(2560, 1440), (1280, 720));
+        // Fitting never enlarges
+        assert_eq!(fit.output_size(800, 600), (800, 600));
+
+        let both = ImageTransform { scale: Some(0.5), fit_within: Some((300, 300)), ..Default::default() };
+        assert_eq!(both.output_size(1920, 1080), (300, 169));
+        assert_eq!(ImageTransform { scale: Some(0.001), ..Default::default() }.output_size(100, 100), (1, 1));
+
+        assert!(ImageTransform::default().is_identity());
+        assert!(ImageTransform { scale: Some(0.0), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_oversized_output_rejected() {
+        let image = padded_gradient(64, 48);
+        let huge = ImageTransform { scale: Some(1.0e6), ..Default::default() };
+        assert!(matches!(apply(image.clone(), &huge), Err(EncodingError::InvalidConfiguration(_))));
+
+        let limit = ImageTransform { scale: Some(256.0), ..Default::default() };
+        assert_eq!(limit.output_size(64, 48), (16384, 12288));
+        assert!(apply(image, &limit).is_err());
+    }
+
+    #[test]
+    fn test_map_rect() {
+        let transform = ImageTransform {
+            crop: Some(Rectangle::new(10, 10, 80, 40)),
+            scale: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(map_rect(&transform, Rectangle::new(20, 20, 10, 5), 100, 60), Some(Rectangle::new(5, 5, 5, 3)));
+        // Clipped by the crop, and dropped entirely outside it
+        assert_eq!(map_rect(&transform, Rectangle::new(0, 0, 12, 12), 100, 60), Some(Rectangle::new(0, 0, 1, 1)));
+        assert_eq!(map_rect(&transform, Rectangle::new(95, 0, 5, 5), 100, 60), None);
+
+        let rect = Rectangle::new(3, 4, 5, 6);
+        assert_eq!(map_rect(&ImageTransform::default(), rect, 100, 60), Some(rect));
+    }
+
+    #[test]
+    fn test_crop_clamps_to_bounds() {
+        let image = padded_gradient(40, 30);
+        let cropped = crop(&image, Rectangle::new(30, 20, 20, 20)).unwrap();
+        assert_eq!((cropped.width, cropped.height), (10, 10));
+        assert_eq!(cropped.stride, 40);
+        assert_eq!(cropped.get_pixel(0, 0), image.get_pixel(30, 20));
+        assert_eq!(cropped.get_pixel(9, 9), image.get_pixel(39, 29));
+
+        assert!(matches!(
+            crop(&image, Rectangle::new(50, 0, 10, 10)),
+            Err(EncodingError::InvalidConfiguration(_))
+        ));
+    }
+
+    #[test]
+    fn test_filters_preserve_flat_color() {
+        let image = RawImage::new([10u8, 200, 30, 255].repeat(37 * 23), 37, 23, PixelFormat::RGBA8);
+        for filter in [ResizeFilter::Nearest, ResizeFilter::Bilinear, ResizeFilter::Lanczos3] {
+            for (width, height) in [(10, 7), (37, 5), (80, 50)] {
+                let resized = resize(&image, width, height, filter).unwrap();
+                assert_eq!((resized.width, resized.height), (width, height));
+                assert!(resized.data.chunks_exact(4).all(|p| p == [10, 200, 30, 255]), "{:?} {}x{}", filter, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_downscale_averages() {
+        // 2x2 checkerboard of black and white halves to mid grey
+        let data: Vec<u8> = (0..16u32 * 16).map(|i| if (i % 16 + i / 16) % 2 == 0 { 0 } else { 255 }).collect();
+        let image = RawImage::new(data, 16, 16, PixelFormat::Gray8);
+
+        let bilinear = resize(&image, 8, 8, ResizeFilter::Bilinear).unwrap();
+        // Edge pixels renormalize a clipped kernel, so allow a little drift there
+        assert!(bilinear.data.iter().all(|&v| v.abs_diff(128) <= 3), "{:?}", bilinear.data);
+
+        // Nearest keeps hard values
+        let nearest = resize(&image, 8, 8, ResizeFilter::Nearest).unwrap();
+        assert!(nearest.data.iter().all(|&v| v == 0 || v == 255));
+    }
+
+    #[test]
+    fn test_nearest_upscale_duplicates() {
+        let image = RawImage::new(vec![1, 2, 3, 4], 2, 2, PixelFormat::Gray8);
+        let resized = resize(&image, 4, 4, ResizeFilter::Nearest).unwrap();
+        assert_eq!(resized.data, [1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn test_apply_crops_then_scales() {
+        let transform = ImageTransform {
+            crop: Some(Rectangle::new(8, 4, 64, 32)),
+            fit_within: Some((16, 16)),
+            filter: ResizeFilter::Lanczos3,
+            ..Default::default()
+        };
+        let result = apply(padded_gradient(100, 60), &transform).unwrap();
+        assert_eq!((result.width, result.height), (16, 8));
+        assert!(result.is_valid());
+        assert_eq!(result.stride, 16 * 4);
+
+        // Identity passes the original buffer through, padding and all
+        let image = padded_gradient(10, 10);
+        let stride = image.stride;
+        assert_eq!(apply(image, &ImageTransform::default()).unwrap().stride, stride);
+    }
+}
@@ -1,11 +1,12 @@
 //! WebP encoder implementation using libwebp
 
 use crate::{
-    encoder::{EncoderStats, ImageEncoder},
+    encoder::{EncodeReport, EncoderStats, ImageEncoder},
     error::{EncodingError, EncodingResult},
     types::{PixelFormat, RawImage, WebPConfig},
 };
 
+use std::os::raw::{c_int, c_void};
 use std::time::Instant;
 
 /// Re-export encoder options
@@ -39,20 +40,7 @@ impl WebPEncoder {
     pub fn encode(&mut self, image: &RawImage, config: &WebPConfig) -> EncodingResult<Vec<u8>> {
         let start_time = Instant::now();
 
-        // Validate configuration
-        config.validate()
-            .map_err(|e| EncodingError::InvalidConfiguration(e))?;
-
-        // Validate image dimensions
-        if image.width == 0 || image.height == 0 {
-            return Err(EncodingError::InvalidDimensions {
-                width: image.width,
-                height: image.height,
-            });
-        }
-
-        // Encode based on pixel format
-        let result = self.encode_with_format(image, config)?;
+        let result = ImageEncoder::encode(self, image, config)?;
 
         // Update statistics
         let encoding_time_ms = start_time.elapsed().as_secs_f64() * 1000.0;
@@ -62,69 +50,126 @@ impl WebPEncoder {
     }
 
     /// Encode with specific pixel format handling
-    fn encode_with_format(&self, image: &RawImage, config: &WebPConfig) -> EncodingResult<Vec<u8>> {
-        // Convert to appropriate format and encode
-        let encoded = match image.format {
-            PixelFormat::RGBA8 => {
-                let encoder = webp::Encoder::from_rgba(&image.data, image.width, image.height);
-                if config.lossless {
-                    encoder.encode_lossless()
-                } else {
-                    encoder.encode(config.quality as f32)
-                }
-            }
-            PixelFormat::RGB8 => {
-                let encoder = webp::Encoder::from_rgb(&image.data, image.width, image.height);
-                if config.lossless {
-                    encoder.encode_lossless()
-                } else {
-                    encoder.encode(config.quality as f32)
-                }
-            }
-            PixelFormat::BGRA8 => {
-                // Convert BGRA to RGBA first
-                let mut rgba_data = image.data.clone();
-                self.convert_bgra_to_rgba_inplace(&mut rgba_data);
-                let encoder = webp::Encoder::from_rgba(&rgba_data, image.width, image.height);
-                if config.lossless {
-                    encoder.encode_lossless()
-                } else {
-                    encoder.encode(config.quality as f32)
-                }
-            }
-            PixelFormat::BGR8 => {
-                // Convert BGR to RGB first
-                let mut rgb_data = image.data.clone();
-                self.convert_bgr_to_rgb_inplace(&mut rgb_data);
-                let encoder = webp::Encoder::from_rgb(&rgb_data, image.width, image.height);
-                if config.lossless {
-                    encoder.encode_lossless()
-                } else {
-                    encoder.encode(config.quality as f32)
-                }
-            }
-            _ => {
-                return Err(EncodingError::UnsupportedFormat(
-                    format!("Unsupported pixel format: {:?}", image.format)
-                ));
+    ///
+    /// Drives the full [`WebPConfig`] through libwebp's advanced
+    /// `WebPConfig`/`WebPPicture` API so every tuning knob reaches the encoder.
+    ///
+    /// When `stats` is given libwebp fills in the PSNR and size breakdown.
+    fn encode_with_format(
+        &self,
+        image: &RawImage,
+        config: &WebPConfig,
+        stats: Option<&mut libwebp_sys::WebPAuxStats>,
+    ) -> EncodingResult<Vec<u8>> {
+        if !self.supports_format(image.format) {
+            return Err(EncodingError::UnsupportedFormat(
+                format!("Unsupported pixel format: {:?}", image.format)
+            ));
+        }
+
+        let webp_config = Self::to_libwebp_config(config)?;
+
+        if image.stride < image.row_bytes() {
+            return Err(EncodingError::InvalidConfiguration(format!(
+                "Stride {} is shorter than a {}-byte row",
+                image.stride,
+                image.row_bytes()
+            )));
+        }
+        if image.data.len() < image.required_len() {
+            return Err(EncodingError::BufferTooSmall {
+                required: image.required_len(),
+                provided: image.data.len(),
+            });
+        }
+        let stride = c_int::try_from(image.stride).map_err(|_| {
+            EncodingError::InvalidConfiguration(format!("Stride {} is too large", image.stride))
+        })?;
+
+        let mut picture = libwebp_sys::WebPPicture::new().map_err(|_| {
+            EncodingError::EncodingFailed("libwebp picture ABI mismatch".to_string())
+        })?;
+        picture.width = image.width as c_int;
+        picture.height = image.height as c_int;
+        // Lossless and near-lossless work on ARGB; lossy imports straight to YUV
+        picture.use_argb = config.lossless as c_int;
+        if let Some(stats) = stats {
+            picture.stats = stats;
+        }
+
+        unsafe {
+            // libwebp reads `stride` bytes apart, so padded rows import directly
+            let rgb = image.data.as_ptr();
+            let imported = match image.format {
+                PixelFormat::RGBA8 => libwebp_sys::WebPPictureImportRGBA(&mut picture, rgb, stride),
+                PixelFormat::BGRA8 => libwebp_sys::WebPPictureImportBGRA(&mut picture, rgb, stride),
+                PixelFormat::RGB8 => libwebp_sys::WebPPictureImportRGB(&mut picture, rgb, stride),
+                PixelFormat::BGR8 => libwebp_sys::WebPPictureImportBGR(&mut picture, rgb, stride),
+                _ => unreachable!(),
+            };
+            if imported == 0 {
+                libwebp_sys::WebPPictureFree(&mut picture);
+                return Err(EncodingError::MemoryAllocationFailed);
             }
-        };
 
-        Ok(encoded.to_vec())
-    }
+            let mut writer = std::mem::MaybeUninit::<libwebp_sys::WebPMemoryWriter>::uninit();
+            libwebp_sys::WebPMemoryWriterInit(writer.as_mut_ptr());
+            let mut writer = writer.assume_init();
+            picture.writer = Some(libwebp_sys::WebPMemoryWrite);
+            picture.custom_ptr = &mut writer as *mut _ as *mut c_void;
+
+            let ok = libwebp_sys::WebPEncode(&webp_config, &mut picture);
+            let error_code = picture.error_code;
+            libwebp_sys::WebPPictureFree(&mut picture);
 
-    /// Convert BGRA to RGBA in-place
-    fn convert_bgra_to_rgba_inplace(&self, data: &mut [u8]) {
-        for chunk in data.chunks_exact_mut(4) {
-            chunk.swap(0, 2); // Swap B and R
+            if ok == 0 {
+                libwebp_sys::WebPMemoryWriterClear(&mut writer);
+                return Err(match error_code {
+                    libwebp_sys::WebPEncodingError::VP8_ENC_ERROR_OUT_OF_MEMORY
+                    | libwebp_sys::WebPEncodingError::VP8_ENC_ERROR_BITSTREAM_OUT_OF_MEMORY => {
+                        EncodingError::MemoryAllocationFailed
+                    }
+                    code => EncodingError::EncodingFailed(format!("{:?}", code)),
+                });
+            }
+
+            let encoded = std::slice::from_raw_parts(writer.mem, writer.size).to_vec();
+            libwebp_sys::WebPMemoryWriterClear(&mut writer);
+            Ok(encoded)
         }
     }
 
-    /// Convert BGR to RGB in-place
-    fn convert_bgr_to_rgb_inplace(&self, data: &mut [u8]) {
-        for chunk in data.chunks_exact_mut(3) {
-            chunk.swap(0, 2); // Swap B and R
+    /// Translate our [`WebPConfig`] into libwebp's native configuration
+    pub(crate) fn to_libwebp_config(config: &WebPConfig) -> EncodingResult<libwebp_sys::WebPConfig> {
+        let mut native = libwebp_sys::WebPConfig::new().map_err(|_| {
+            EncodingError::EncodingFailed("libwebp config ABI mismatch".to_string())
+        })?;
+
+        native.lossless = config.lossless as c_int;
+        native.quality = config.quality as f32;
+        native.method = config.method as c_int;
+        native.segments = config.segments as c_int;
+        native.sns_strength = config.sns_strength as c_int;
+        native.filter_strength = config.filter_strength as c_int;
+        native.filter_sharpness = config.filter_sharpness as c_int;
+        native.autofilter = config.auto_filter as c_int;
+        native.alpha_compression = config.alpha_compression as c_int;
+        native.alpha_filtering = config.alpha_filtering as c_int;
+        native.alpha_quality = config.alpha_quality as c_int;
+        native.pass = config.pass as c_int;
+        // libwebp only distinguishes single-threaded from multi-threaded encoding
+        native.thread_level = (config.thread_count != 1) as c_int;
+        native.low_memory = config.low_memory as c_int;
+        native.near_lossless = config.near_lossless as c_int;
+        native.exact = config.exact as c_int;
+
+        if unsafe { libwebp_sys::WebPValidateConfig(&native) } == 0 {
+            return Err(EncodingError::InvalidConfiguration(
+                "libwebp rejected the encoder configuration".to_string(),
+            ));
         }
+
+        Ok(native)
     }
 
     /// Check if the encoder supports a specific format
@@ -150,8 +195,28 @@ impl WebPEncoder {
 
 impl ImageEncoder for WebPEncoder {
     fn encode(&self, image: &RawImage, config: &WebPConfig) -> EncodingResult<Vec<u8>> {
-        // Use the internal encode_with_format method
-        self.encode_with_format(image, config)
+        self.encode_with_report(image, config).map(|(data, _)| data)
+    }
+
+    fn encode_with_report(&self, image: &RawImage, config: &WebPConfig) -> EncodingResult<(Vec<u8>, EncodeReport)> {
+        config.validate()
+            .map_err(EncodingError::InvalidConfiguration)?;
+
+        if image.width == 0 || image.height == 0 {
+            return Err(EncodingError::InvalidDimensions {
+                width: image.width,
+                height: image.height,
+            });
+        }
+
+        match (config.target_size, config.target_psnr) {
+            (Some(size), _) => self.search_quality(image, config, SearchTarget::Size(size)),
+            (_, Some(psnr)) => self.search_quality(image, config, SearchTarget::Psnr(psnr)),
+            _ => {
+                let data = self.encode_with_format(image, config, None)?;
+                Ok((data, EncodeReport::single_pass(config)))
+            }
+        }
     }
 
     fn name(&self) -> &str {
@@ -163,6 +228,105 @@ impl ImageEncoder for WebPEncoder {
     }
 }
 
+/// What a quality search aims for
+#[derive(Clone, Copy)]
+enum SearchTarget {
+    /// Highest quality at or below this many bytes
+    Size(usize),
+    /// Smallest output at or above this PSNR
+    Psnr(f32),
+}
+
+impl WebPEncoder {
+    /// Bisect the quality range until the target is met or `search_passes` runs out
+    ///
+    /// Keeps the best output that meets the target; if none does, returns the one
+    /// closest to it (the smallest for a size target, the sharpest for PSNR).
+    fn search_quality(
+        &self,
+        image: &RawImage,
+        config: &WebPConfig,
+        target: SearchTarget,
+    ) -> EncodingResult<(Vec<u8>, EncodeReport)> {
+        let max_passes = u32::from(config.search_passes);
+        let (mut low, mut high) = (0u8, 100u8);
+        let mut quality = config.quality;
+        let mut best: Option<Candidate> = None;
+        let mut passes = 0;
+
+        while passes < max_passes {
+            let mut stats = unsafe { std::mem::zeroed::<libwebp_sys::WebPAuxStats>() };
+            let attempt = WebPConfig { quality, ..config.clone() };
+            let data = self.encode_with_format(image, &attempt, Some(&mut stats))?;
+            passes += 1;
+
+            // PSNR[3] is the combined Y/U/V figure
+            let candidate = Candidate { data, quality, psnr: stats.PSNR[3] };
+            let meets = candidate.meets(target);
+            if best.as_ref().is_none_or(|best| candidate.beats(best, target)) {
+                best = Some(candidate);
+            }
+
+            // Both size and PSNR grow with quality
+            let go_higher = match target {
+                SearchTarget::Size(_) => meets,
+                SearchTarget::Psnr(_) => !meets,
+            };
+            if go_higher {
+                low = quality + 1;
+            } else {
+                high = quality.saturating_sub(1);
+                if quality == 0 {
+                    break;
+                }
+            }
+            if low > high {
+                break;
+            }
+            quality = low + (high - low) / 2;
+        }
+
+        log::debug!("Quality search finished after {} passes", passes);
+        let best = best.expect("at least one pass runs");
+        let report = EncodeReport {
+            quality: best.quality,
+            passes,
+            psnr: Some(best.psnr),
+        };
+        Ok((best.data, report))
+    }
+}
+
+/// One encode made during a quality search
+struct Candidate {
+    data: Vec<u8>,
+    quality: u8,
+    psnr: f32,
+}
+
+impl Candidate {
+    fn meets(&self, target: SearchTarget) -> bool {
+        match target {
+            SearchTarget::Size(size) => self.data.len() <= size,
+            SearchTarget::Psnr(psnr) => self.psnr >= psnr,
+        }
+    }
+
+    /// Whether this output should replace `other` as the search result
+    fn beats(&self, other: &Candidate, target: SearchTarget) -> bool {
+        match (self.meets(target), other.meets(target), target) {
+            (true, false, _) => true,
+            (false, true, _) => false,
+            // Within budget: the best quality; over budget: the smallest
+            (true, true, SearchTarget::Size(_)) => self.quality > other.quality,
+            (false, false, SearchTarget::Size(_)) => self.data.len() < other.data.len(),
+            // Sharp enough: the smallest; too blurry: the sharpest
+            (true, true, SearchTarget::Psnr(_)) => self.data.len() < other.data.len(),
+            (false, false, SearchTarget::Psnr(_)) => self.psnr > other.psnr,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -209,4 +373,246 @@ mod tests {
         let webp_data = result.unwrap();
         assert!(!webp_data.is_empty());
     }
-}
\ No newline at end of file
+
+    /// Textured RGBA image with varying alpha so every encoder stage has work to do
+    fn textured_image(width: u32, height: u32) -> RawImage {
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        let mut seed = 0x2545_f491u32;
+        for y in 0..height {
+            for x in 0..width {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let noise = (seed & 0x3f) as u8;
+                data.push(((x * 255 / width) as u8).wrapping_add(noise));
+                data.push(((y * 255 / height) as u8).wrapping_add(noise / 2));
+                data.push((((x ^ y) & 0xff) as u8).wrapping_add(noise));
+                data.push(if (x / 8 + y / 8) % 3 == 0 { 0 } else { (x * 4) as u8 | 0x40 });
+            }
+        }
+        RawImage::new(data, width, height, PixelFormat::RGBA8)
+    }
+
+    fn encode_bytes(config: &WebPConfig) -> Vec<u8> {
+        WebPEncoder::new()
+            .encode(&textured_image(128, 96), config)
+            .expect("encoding should succeed")
+    }
+
+    fn assert_knob_changes_output(base: WebPConfig, tweaked: WebPConfig, knob: &str) {
+        assert!(
+            encode_bytes(&base) != encode_bytes(&tweaked),
+            "changing `{}` should change the encoded output",
+            knob
+        );
+    }
+
+    #[test]
+    fn test_target_size_search() {
+        let image = textured_image(128, 96);
+        let encoder = WebPEncoder::new();
+        let full = ImageEncoder::encode(&encoder, &image, &WebPConfig::default()).unwrap();
+
+        let budget = full.len() / 2;
+        let config = WebPConfig { target_size: Some(budget), ..Default::default() };
+        let (data, report) = encoder.encode_with_report(&image, &config).unwrap();
+        assert!(data.len() <= budget, "{} bytes over the {} byte budget", data.len(), budget);
+        assert!(report.quality < 80);
+        assert!((1..=u32::from(config.search_passes)).contains(&report.passes));
+
+        // One quality step up would no longer fit
+        let above = WebPConfig { quality: report.quality + 1, ..Default::default() };
+        assert!(ImageEncoder::encode(&encoder, &image, &above).unwrap().len() > budget);
+
+        // An impossible budget still yields the smallest output found
+        let config = WebPConfig { target_size: Some(10), search_passes: 3, ..Default::default() };
+        let (data, report) = encoder.encode_with_report(&image, &config).unwrap();
+        assert!(data.len() < full.len());
+        assert_eq!(report.passes, 3);
+    }
+
+    #[test]
+    fn test_target_psnr_search() {
+        let image = textured_image(128, 96);
+        let encoder = WebPEncoder::new();
+        let config = WebPConfig { target_psnr: Some(32.0), ..Default::default() };
+        let (data, report) = encoder.encode_with_report(&image, &config).unwrap();
+        assert!(report.psnr.unwrap() >= 32.0);
+        assert!(report.quality > 0);
+
+        // The next lower quality misses the target
+        let mut stats = unsafe { std::mem::zeroed::<libwebp_sys::WebPAuxStats>() };
+        let below = WebPConfig { quality: report.quality - 1, ..Default::default() };
+        let smaller = encoder.encode_with_format(&image, &below, Some(&mut stats)).unwrap();
+        assert!(stats.PSNR[3] < 32.0);
+        assert!(smaller.len() <= data.len());
+    }
+
+    #[test]
+    fn test_target_validation() {
+        let both = WebPConfig { target_size: Some(1000), target_psnr: Some(40.0), ..Default::default() };
+        assert!(both.validate().is_err());
+        let lossless = WebPConfig { target_size: Some(1000), ..WebPConfig::lossless() };
+        assert!(lossless.validate().is_err());
+        assert!(WebPConfig { target_psnr: Some(-1.0), ..Default::default() }.validate().is_err());
+        assert!(WebPConfig { target_size: Some(0), ..Default::default() }.validate().is_err());
+        assert!(WebPConfig { search_passes: 0, ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_libwebp_config_maps_every_field() {
+        let config = WebPConfig {
+            quality: 42,
+            method: 5,
+            lossless: true,
+            near_lossless: 60,
+            segments: 2,
+            sns_strength: 17,
+            filter_strength: 33,
+            filter_sharpness: 3,
+            auto_filter: true,
+            alpha_compression: false,
+            alpha_filtering: 2,
+            alpha_quality: 55,
+            pass: 7,
+            thread_count: 4,
+            low_memory: true,
+            exact: true,
+            // Searched by WebPEncoder rather than handed to libwebp
+            target_size: None,
+            target_psnr: None,
+            search_passes: 5,
+        };
+
+        let native = WebPEncoder::to_libwebp_config(&config).unwrap();
+        assert_eq!(native.quality, 42.0);
+        assert_eq!(native.method, 5);
+        assert_eq!(native.lossless, 1);
+        assert_eq!(native.near_lossless, 60);
+        assert_eq!(native.segments, 2);
+        assert_eq!(native.sns_strength, 17);
+        assert_eq!(native.filter_strength, 33);
+        assert_eq!(native.filter_sharpness, 3);
+        assert_eq!(native.autofilter, 1);
+        assert_eq!(native.alpha_compression, 0);
+        assert_eq!(native.alpha_filtering, 2);
+        assert_eq!(native.alpha_quality, 55);
+        assert_eq!(native.pass, 7);
+        assert_eq!(native.thread_level, 1);
+        assert_eq!(native.low_memory, 1);
+        assert_eq!(native.exact, 1);
+
+        let single_threaded = WebPConfig { thread_count: 1, ..Default::default() };
+        assert_eq!(WebPEncoder::to_libwebp_config(&single_threaded).unwrap().thread_level, 0);
+    }
+
+    #[test]
+    fn test_lossy_knobs_change_output() {
+        // `pass` only matters once libwebp searches for a size/PSNR target, and
+        // `thread_count` never alters the bitstream; both are covered by the mapping test
+        let base = WebPConfig::default();
+        let knobs: Vec<(&str, WebPConfig)> = vec![
+            ("quality", WebPConfig { quality: 30, ..base.clone() }),
+            ("method", WebPConfig { method: 0, ..base.clone() }),
+            ("segments", WebPConfig { segments: 1, ..base.clone() }),
+            ("sns_strength", WebPConfig { sns_strength: 0, ..base.clone() }),
+            ("filter_strength", WebPConfig { filter_strength: 0, ..base.clone() }),
+            ("filter_sharpness", WebPConfig { filter_sharpness: 7, ..base.clone() }),
+            ("auto_filter", WebPConfig { auto_filter: true, ..base.clone() }),
+            ("alpha_compression", WebPConfig { alpha_compression: false, ..base.clone() }),
+            ("alpha_filtering", WebPConfig { alpha_filtering: 0, ..base.clone() }),
+            ("alpha_quality", WebPConfig { alpha_quality: 10, ..base.clone() }),
+            ("low_memory", WebPConfig { low_memory: true, ..base.clone() }),
+            ("exact", WebPConfig { exact: true, ..base.clone() }),
+        ];
+
+        for (knob, tweaked) in knobs {
+            assert_knob_changes_output(base.clone(), tweaked, knob);
+        }
+    }
+
+    #[test]
+    fn test_lossless_knobs_change_output() {
+        let base = WebPConfig::lossless();
+        assert_knob_changes_output(base.clone(), WebPConfig { near_lossless: 40, ..base.clone() }, "near_lossless");
+        assert_knob_changes_output(base.clone(), WebPConfig { method: 0, ..base.clone() }, "method");
+        assert_knob_changes_output(base.clone(), WebPConfig { exact: true, ..base.clone() }, "exact");
+        assert_knob_changes_output(base.clone(), WebPConfig::default(), "lossless");
+    }
+
+    #[test]
+    fn test_presets_produce_distinct_output() {
+        let outputs = [
+            encode_bytes(&WebPConfig::high_quality()),
+            encode_bytes(&WebPConfig::fast()),
+            encode_bytes(&WebPConfig::balanced()),
+        ];
+        assert_ne!(outputs[0], outputs[1]);
+        assert_ne!(outputs[1], outputs[2]);
+        assert_ne!(outputs[0], outputs[2]);
+    }
+
+    #[test]
+    fn test_bgr_formats_match_rgb_output() {
+        let rgba = textured_image(64, 48);
+        let mut bgra = rgba.clone();
+        for pixel in bgra.data.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        bgra.format = PixelFormat::BGRA8;
+
+        let encoder = WebPEncoder::new();
+        let config = WebPConfig::lossless();
+        assert_eq!(
+            encoder.encode(&rgba, &config).unwrap(),
+            encoder.encode(&bgra, &config).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encode_rejects_short_buffer() {
+        let encoder = WebPEncoder::new();
+        let image = RawImage::new(vec![0u8; 10], 16, 16, PixelFormat::RGBA8);
+        assert!(matches!(
+            encoder.encode(&image, &WebPConfig::default()),
+            Err(EncodingError::BufferTooSmall { .. })
+        ));
+    }
+
+    mod stride_properties {
+        use super::super::*;
+        use crate::types::strategies::packed_and_padded;
+        use proptest::prelude::*;
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(32))]
+
+            #[test]
+            fn test_padded_rows_encode_like_packed(
+                (packed, padded) in packed_and_padded(&[
+                    PixelFormat::RGBA8,
+                    PixelFormat::BGRA8,
+                    PixelFormat::RGB8,
+                    PixelFormat::BGR8,
+                ]),
+            ) {
+                let encoder = WebPEncoder::new();
+                let config = WebPConfig { method: 0, ..WebPConfig::lossless() };
+                prop_assert_eq!(
+                    encoder.encode(&padded, &config).unwrap(),
+                    encoder.encode(&packed, &config).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_rejects_short_stride() {
+        let encoder = WebPEncoder::new();
+        let image = RawImage::with_stride(vec![0u8; 64], 4, 4, PixelFormat::RGBA8, 8);
+        assert!(matches!(
+            encoder.encode(&image, &WebPConfig::default()),
+            Err(EncodingError::InvalidConfiguration(_))
+        ));
+    }
+}
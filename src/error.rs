@@ -9,6 +9,10 @@ pub enum CaptureError {
     #[error("Display not found: index {0}")]
     DisplayNotFound(usize),
 
+    /// No display matches a lookup by identifier, name or primary
+    #[error("No display matches {0}")]
+    NoMatchingDisplay(String),
+
     /// Display enumeration failed
     #[error("Failed to enumerate displays: {0}")]
     DisplayEnumerationFailed(String),
@@ -54,6 +58,10 @@ pub enum CaptureError {
     #[error("Encoding error: {0}")]
     EncodingError(String),
 
+    /// Capture rejected as a near-duplicate of a recent one
+    #[error("Near-duplicate capture: {distance} bits from a recent capture")]
+    NearDuplicate { distance: u32 },
+
     /// Other errors
     #[error(transparent)]
     Other(#[from] anyhow::Error),
@@ -79,7 +87,7 @@ pub enum EncodingError {
     UnsupportedFormat(String),
 
     /// Encoding failed
-    #[error("WebP encoding failed: {0}")]
+    #[error("Encoding failed: {0}")]
     EncodingFailed(String),
 
     /// Invalid quality parameter
@@ -102,6 +110,14 @@ pub enum EncodingError {
     #[error("Memory allocation failed during encoding")]
     MemoryAllocationFailed,
 
+    /// Encoded data is malformed
+    #[error("Corrupt image data: {0}")]
+    CorruptData(String),
+
+    /// Encoded data ended before the image was complete
+    #[error("Truncated image data: got {provided} bytes")]
+    TruncatedData { provided: usize },
+
     /// Other encoding errors
     #[error(transparent)]
     Other(#[from] anyhow::Error),
@@ -180,6 +196,8 @@ impl CaptureError {
             #[cfg(windows)]
             CaptureError::WindowsError(_) => -1011,
             CaptureError::EncodingError(_) => -1012,
+            CaptureError::NearDuplicate { .. } => -1013,
+            CaptureError::NoMatchingDisplay(_) => -1014,
             CaptureError::Other(_) => -1999,
         }
     }
@@ -210,6 +228,8 @@ impl EncodingError {
             EncodingError::BufferTooSmall { .. } => -2008,
             EncodingError::UnsupportedFeature(_) => -2009,
             EncodingError::MemoryAllocationFailed => -2010,
+            EncodingError::CorruptData(_) => -2011,
+            EncodingError::TruncatedData { .. } => -2012,
             EncodingError::Other(_) => -2999,
         }
     }
@@ -381,6 +381,9 @@ fn convert_capture_options(opts: &capture_options) -> CaptureConfig {
             thread_count: opts.webp_config.thread_count as usize,
             low_memory: opts.webp_config.low_memory != 0,
             exact: opts.webp_config.exact != 0,
+            target_size: None,
+            target_psnr: None,
+            ..Default::default()
         },
         include_cursor: opts.include_cursor != 0,
         use_hardware_acceleration: opts.use_hardware_acceleration != 0,
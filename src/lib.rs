@@ -39,28 +39,55 @@ pub mod types;
 pub mod ffi;
 
 // Re-export main types
-pub use capture::{Capturer, ScreenCapture};
-pub use encoder::{WebPEncoder, EncoderOptions};
+pub use capture::{Capturer, DisplayEvent, DisplayWatcher, ScreenCapture};
+pub use encoder::{AnimatedWebPWriter, AnimationOptions, ImageEncoder, WebPEncoder, EncoderOptions};
+pub use encoder::{ContentAnalysis, ContentClass, HashIndex};
+pub use encoder::{DecodeOptions, EmbeddedMetadata, EncodeReport, IncrementalDecoder, JpegEncoder, PngEncoder, QoiEncoder, WebPDecoder};
+#[cfg(feature = "avif")]
+pub use encoder::AvifEncoder;
 pub use error::{CaptureError, CaptureResult, EncodingError, EncodingResult};
 pub use memory_pool::{MemoryPool, PooledBuffer};
-pub use pipeline::{StreamingPipeline, StreamingPipelineBuilder, ZeroCopyOptimizer};
+pub use pipeline::{
+    DiffResult, DisplayChangeAction, FrameDiff, FrameDiffConfig, FrameUpdate, StreamingPipeline, StreamingPipelineBuilder,
+    ZeroCopyOptimizer,
+};
 pub use types::{
-    CaptureConfig, CaptureMetadata, CaptureRegion, DisplayInfo, PerformanceStats, PixelFormat,
-    RawImage, Rectangle, Screenshot, WebPConfig,
+    AppliedRedaction, CaptureConfig, CaptureMetadata, CaptureRegion, CursorImage, CursorMode, DesktopOptions, DesktopScale,
+    DisplayId, DisplayInfo, DisplayPlacement, DisplayRotation, DisplaySelector, DuplicateAction,
+    DuplicateDetection, HashAlgorithm, ImageHash, ImageTransform,
+    MetadataOptions, OutputFormat, PerformanceStats, PixelFormat, RawImage, Rectangle, Redaction, RedactionMode, RedactionSpace, RegionPolicy,
+    ResizeFilter, ResolvedRegion, Screenshot, WebPConfig, WindowFilter,
+    WindowId, WindowInfo, WindowRedaction,
 };
 
+use std::borrow::Cow;
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
 
+/// A capture on its way to the encoder
+struct CapturedImage {
+    image: RawImage,
+    display_index: usize,
+    display_id: Option<DisplayId>,
+    /// Display captured, `None` for the virtual desktop or without enumeration
+    display: Option<DisplayInfo>,
+    capture_duration: Duration,
+    cursor: Option<CursorImage>,
+    redactions: Vec<AppliedRedaction>,
+    layout: Vec<DisplayPlacement>,
+    region: Option<ResolvedRegion>,
+}
+
 /// Main entry point for screenshot capture
 pub struct WebPScreenshot {
     capturer: Box<dyn ScreenCapture>,
-    encoder: WebPEncoder,
+    encoder: Box<dyn ImageEncoder>,
     memory_pool: Arc<MemoryPool>,
     config: CaptureConfig,
     stats: PerformanceStats,
     zero_copy: Option<ZeroCopyOptimizer>,
     gpu_encoder: Option<encoder::gpu::GpuWebPEncoder>,
+    recent_hashes: HashIndex,
 }
 
 impl WebPScreenshot {
@@ -71,27 +98,22 @@ impl WebPScreenshot {
 
     /// Create a new instance with custom configuration
     pub fn with_config(config: CaptureConfig) -> CaptureResult<Self> {
-        let zero_copy = if ZeroCopyOptimizer::is_supported() {
-            Some(ZeroCopyOptimizer::new())
-        } else {
-            None
-        };
+        Self::builder().config(config).build()
+    }
 
-        let gpu_encoder = if cfg!(feature = "gpu") {
-            Some(encoder::gpu::GpuWebPEncoder::new())
-        } else {
-            None
-        };
+    /// Create an instance that captures through the given backend
+    ///
+    /// Useful with [`capture::MockCapture`] for running without a display server.
+    pub fn with_capturer(
+        capturer: Box<dyn ScreenCapture>,
+        config: CaptureConfig,
+    ) -> CaptureResult<Self> {
+        Self::builder().capturer(capturer).config(config).build()
+    }
 
-        Ok(Self {
-            capturer: Capturer::new()?,
-            encoder: WebPEncoder::new(),
-            memory_pool: memory_pool::global_pool(),
-            config,
-            stats: PerformanceStats::default(),
-            zero_copy,
-            gpu_encoder,
-        })
+    /// Start building an instance with injected components
+    pub fn builder() -> WebPScreenshotBuilder {
+        WebPScreenshotBuilder::new()
     }
 
     /// Get information about available displays
@@ -106,6 +128,56 @@ impl WebPScreenshot {
         eprintln!("[LIB] Config region: {:?}", self.config.region);
         eprintln!("[LIB] ========================================");
 
+        self.capture_with_retries(&format!("display {}", display_index), |this, timestamp, start_time| {
+            this.capture_display_internal(display_index, None, timestamp, start_time)
+        })
+    }
+
+    /// Find the display `selector` picks in the current layout
+    pub fn find_display(&self, selector: &DisplaySelector) -> CaptureResult<DisplayInfo> {
+        let displays = self.get_displays()?;
+        let position = selector.position(&displays).ok_or_else(|| selector.not_found())?;
+        Ok(displays.into_iter().nth(position).expect("position is within the displays"))
+    }
+
+    /// Capture the display with this identifier, wherever it is in the current enumeration
+    pub fn capture_display_by_id(&mut self, id: &DisplayId) -> CaptureResult<Screenshot> {
+        self.capture_selected_display(&DisplaySelector::Id(id.clone()))
+    }
+
+    /// Capture the display `selector` picks, looking it up again on every attempt
+    pub fn capture_selected_display(&mut self, selector: &DisplaySelector) -> CaptureResult<Screenshot> {
+        if let DisplaySelector::Index(index) = *selector {
+            return self.capture_display(index);
+        }
+
+        self.capture_with_retries(&format!("display with {}", selector), |this, timestamp, start_time| {
+            let displays = this.get_displays()?;
+            let display_index = selector.position(&displays).ok_or_else(|| selector.not_found())?;
+            this.capture_display_internal(display_index, Some(displays), timestamp, start_time)
+        })
+    }
+
+    /// Capture every display into one image laid out like the virtual desktop
+    ///
+    /// Displays are composited at their offsets within the union of their bounds,
+    /// resampled to the scale chosen by [`CaptureConfig::desktop`], and gaps are
+    /// filled with its fill color. The cursor and [`Display`](RedactionSpace::Display)
+    /// redactions are placed relative to the primary display, whose index the
+    /// screenshot reports; `metadata.layout` records where each display went.
+    /// The screenshot has no `display_id`, as it isn't any one display; the
+    /// layout carries each display's id.
+    pub fn capture_virtual_desktop(&mut self) -> CaptureResult<Screenshot> {
+        self.capture_with_retries("virtual desktop", |this, timestamp, start_time| {
+            this.capture_virtual_desktop_internal(timestamp, start_time)
+        })
+    }
+
+    /// Run `capture` with the configured retries, recording the outcome in the stats
+    fn capture_with_retries<F>(&mut self, target: &str, mut capture: F) -> CaptureResult<Screenshot>
+    where
+        F: FnMut(&mut Self, SystemTime, Instant) -> CaptureResult<Screenshot>,
+    {
         let start_time = Instant::now();
         let timestamp = SystemTime::now();
 
@@ -114,10 +186,10 @@ impl WebPScreenshot {
         for attempt in 0..=self.config.max_retries {
             if attempt > 0 {
                 std::thread::sleep(self.config.retry_delay);
-                log::debug!("Retry attempt {} for display {}", attempt, display_index);
+                log::debug!("Retry attempt {} for {}", attempt, target);
             }
 
-            match self.capture_display_internal(display_index, timestamp, start_time) {
+            match capture(self, timestamp, start_time) {
                 Ok(screenshot) => {
                     self.stats.successful_captures += 1;
                     self.stats.total_captures += 1;
@@ -143,19 +215,47 @@ impl WebPScreenshot {
     }
 
     /// Internal capture implementation
+    ///
+    /// The whole capture works from one enumeration, `displays` if the caller
+    /// already has it. Without a region it is taken after capturing, when
+    /// backends that learn the layout from a capture (the portal) already know it.
     fn capture_display_internal(
         &mut self,
         display_index: usize,
+        mut displays: Option<Vec<DisplayInfo>>,
         timestamp: SystemTime,
         start_time: Instant,
     ) -> CaptureResult<Screenshot> {
+        self.validate_redactions()?;
+
         // Capture raw image
         let capture_start = Instant::now();
 
-        let raw_image = if let Some(ref zero_copy) = self.zero_copy {
+        // Fails on backends that can't see windows, rather than capturing them unmasked
+        let redact_windows = !self.config.window_redactions.is_empty();
+        if redact_windows {
+            self.capturer.set_window_redactions(self.config.window_redactions.clone())?;
+        }
+        self.capturer.set_cursor_overlay(self.composite_cursor_in_backend());
+
+        // Fit the region to the displays before it reaches the backend
+        let region = match self.config.region {
+            Some(region) => {
+                let displays = match displays {
+                    Some(ref displays) => displays,
+                    None => displays.insert(self.get_displays()?),
+                };
+                Some(capture::region::resolve(region, displays, self.config.region_policy)?)
+            }
+            None => None,
+        };
+        let image_area = region.as_ref().map(|region| region.image_area);
+
+        let mut raw_image = if let Some(ref zero_copy) = self.zero_copy {
             // Disable zero-copy when capturing a specific region
-            // Zero-copy is optimized for full-screen captures, not regions
-            if zero_copy.is_enabled() && self.config.region.is_none() {
+            // Zero-copy is optimized for full-screen captures, not regions,
+            // and reads the screen without the backend's window redaction
+            if zero_copy.is_enabled() && self.config.region.is_none() && !redact_windows {
                 eprintln!("[LIB] Using ZERO-COPY optimization path (full screen)");
                 zero_copy.capture_zero_copy(&*self.capturer, display_index)?
             } else {
@@ -164,36 +264,200 @@ impl WebPScreenshot {
                 } else {
                     eprintln!("[LIB] Zero-copy available but disabled, using normal path");
                 }
-                self.capture_normal(display_index)?
+                self.capture_normal(display_index, region.as_ref())?
             }
         } else {
             eprintln!("[LIB] No zero-copy, using normal capture path");
-            self.capture_normal(display_index)?
+            self.capture_normal(display_index, region.as_ref())?
         };
 
-        let capture_duration = capture_start.elapsed();
+        // Redactions can't be placed without the layout; anything else does without
+        let displays = match displays {
+            Some(displays) => Some(displays),
+            None => match self.get_displays() {
+                Ok(displays) => Some(displays),
+                Err(e) if !self.config.redactions.is_empty() => return Err(e),
+                Err(e) => {
+                    log::debug!("Display enumeration failed, capturing without the layout: {}", e);
+                    None
+                }
+            },
+        };
+        let display = displays.as_ref().and_then(|displays| displays.get(display_index)).cloned();
+
+        if let (Some(region), Some(displays), RegionPolicy::Pad) = (&region, &displays, self.config.region_policy) {
+            raw_image = capture::region::pad(&raw_image, region, displays).map_err(|e| CaptureError::Other(e.into()))?;
+        }
+
+        // The global area the image covers: the region's, or the whole display
+        let bounds = image_area.or(display.as_ref().map(DisplayInfo::bounds));
+        let cursor = if self.config.include_cursor {
+            self.apply_cursor(&mut raw_image, || bounds)
+        } else {
+            None
+        };
+        let redactions = self.apply_redactions(&mut raw_image, display.as_ref(), display_index, image_area)?;
+
+        self.finish_capture(
+            CapturedImage {
+                image: raw_image,
+                display_index,
+                display_id: display.as_ref().map(|display| display.id.clone()),
+                display,
+                capture_duration: capture_start.elapsed(),
+                cursor,
+                redactions,
+                layout: Vec::new(),
+                region,
+            },
+            timestamp,
+            start_time,
+        )
+    }
+
+    /// Capture each display and stitch them together
+    fn capture_virtual_desktop_internal(
+        &mut self,
+        timestamp: SystemTime,
+        start_time: Instant,
+    ) -> CaptureResult<Screenshot> {
+        self.validate_redactions()?;
+        let capture_start = Instant::now();
+
+        if !self.config.window_redactions.is_empty() {
+            self.capturer.set_window_redactions(self.config.window_redactions.clone())?;
+        }
+        self.capturer.set_cursor_overlay(self.composite_cursor_in_backend());
+
+        let mut displays = self.get_displays()?;
+        let mut images = Vec::with_capacity(displays.len());
+        for index in 0..displays.len() {
+            images.push(self.capturer.capture_display(index)?);
+        }
+        // Backends that learn the layout from a capture (the portal) report 0x0 until then
+        if displays.iter().any(|display| display.width == 0 || display.height == 0) {
+            displays = self.get_displays()?;
+            if displays.len() != images.len() {
+                return Err(CaptureError::CaptureFailed("Displays changed during capture".to_string()));
+            }
+        }
+
+        let primary = displays.iter().find(|display| display.is_primary).or(displays.first()).cloned();
+        let primary = primary.ok_or(CaptureError::DisplayNotFound(0))?;
+        let bounds = capture::desktop::desktop_bounds(&displays).ok_or(CaptureError::DisplayNotFound(0))?;
+        let captures: Vec<(DisplayInfo, RawImage)> = displays.into_iter().zip(images).collect();
+        let (mut raw_image, layout) = capture::stitch(&captures, &self.config.desktop)
+            .map_err(|e| CaptureError::Other(e.into()))?;
+
+        let cursor = if self.config.include_cursor {
+            self.apply_cursor(&mut raw_image, || Some(bounds))
+        } else {
+            None
+        };
+        let redactions = if self.config.redactions.is_empty() {
+            Vec::new()
+        } else {
+            capture::redact(&mut raw_image, &self.config.redactions, bounds, (primary.x, primary.y))
+        };
+
+        self.finish_capture(
+            CapturedImage {
+                image: raw_image,
+                display_index: primary.index,
+                display_id: None,
+                display: None,
+                capture_duration: capture_start.elapsed(),
+                cursor,
+                redactions,
+                layout,
+                region: None,
+            },
+            timestamp,
+            start_time,
+        )
+    }
+
+    /// Transform, hash and encode a capture, and record it in the stats
+    fn finish_capture(
+        &mut self,
+        captured: CapturedImage,
+        timestamp: SystemTime,
+        start_time: Instant,
+    ) -> CaptureResult<Screenshot> {
+        let CapturedImage {
+            image: raw_image,
+            display_index,
+            display_id,
+            display,
+            capture_duration,
+            cursor,
+            redactions,
+            layout,
+            region,
+        } = captured;
 
         // Update stats
         self.stats.total_bytes_captured += raw_image.size() as u64;
         self.stats.total_capture_time += capture_duration;
 
-        // Encode to WebP
+        // Crop/resize, then encode to the configured output format
         let encoding_start = Instant::now();
+        let (captured_width, captured_height) = (raw_image.width, raw_image.height);
+        let raw_image = encoder::transform::apply(raw_image, &self.config.transform)
+            .map_err(|e| CaptureError::Other(e.into()))?;
+        let map_area = |area| encoder::transform::map_rect(&self.config.transform, area, captured_width, captured_height);
+        let redactions = redactions
+            .into_iter()
+            .filter_map(|applied| Some(AppliedRedaction { area: map_area(applied.area)?, ..applied }))
+            .collect();
+        let layout = layout
+            .into_iter()
+            .filter_map(|placement| Some(DisplayPlacement { area: map_area(placement.area)?, ..placement }))
+            .collect::<Vec<_>>();
+        let (perceptual_hash, near_duplicate) = self.check_duplicates(&raw_image)?;
+        let format = self.config.output_format;
+        let webp_config = self.webp_config_for(&raw_image);
 
-        let webp_data = if let Some(ref gpu_encoder) = self.gpu_encoder {
-            if gpu_encoder.is_available() && gpu_encoder.is_size_suitable(raw_image.width, raw_image.height) {
-                gpu_encoder.encode(&raw_image, &self.config.webp_config)?
-            } else {
-                self.encoder.encode(&raw_image, &self.config.webp_config)
+        // The GPU encoder does a single pass, so size and quality targets stay on the CPU
+        let (webp_data, report) = match self.gpu_encoder {
+            Some(ref gpu_encoder)
+                if format == OutputFormat::WebP
+                    && webp_config.target_size.is_none()
+                    && webp_config.target_psnr.is_none()
+                    && gpu_encoder.is_available()
+                    && gpu_encoder.is_size_suitable(raw_image.width, raw_image.height) =>
+            {
+                let data = gpu_encoder.encode(&raw_image, &webp_config)?;
+                (data, EncodeReport::single_pass(&webp_config))
+            }
+            _ => self.encode(&raw_image, &webp_config).map_err(|e| CaptureError::Other(e.into()))?,
+        };
+
+        let webp_data = match self.config.embed_metadata {
+            Some(ref options) if format == OutputFormat::WebP => {
+                // A stitched desktop covers its whole layout, a region its image area
+                let area = layout
+                    .iter()
+                    .map(|placement| placement.bounds)
+                    .reduce(|a, b| a.union(&b))
+                    .or(region.as_ref().map(|region| region.image_area))
+                    .or(display.as_ref().map(DisplayInfo::bounds));
+                self.embed_metadata(&webp_data, options, display_index, display.as_ref(), timestamp, area)
                     .map_err(|e| CaptureError::Other(e.into()))?
             }
-        } else {
-            self.encoder.encode(&raw_image, &self.config.webp_config)
-                .map_err(|e| CaptureError::Other(e.into()))?
+            Some(_) => {
+                log::debug!("Metadata embedding is only supported for WebP, skipping for {}", format);
+                webp_data
+            }
+            None => webp_data,
         };
 
         let encoding_duration = encoding_start.elapsed();
 
+        if let (Some(hash), Some(_)) = (perceptual_hash, self.config.duplicate_detection) {
+            self.recent_hashes.insert(hash);
+        }
+
         // Update stats
         self.stats.total_bytes_encoded += webp_data.len() as u64;
         self.stats.total_encoding_time += encoding_duration;
@@ -217,23 +481,115 @@ impl WebPScreenshot {
             original_size: raw_image.size(),
             compressed_size: webp_data.len(),
             implementation: self.capturer.implementation_name(),
+            cursor,
+            encoding_quality: report.quality,
+            encoding_passes: report.passes,
+            encoding_psnr: report.psnr,
+            redactions,
+            perceptual_hash,
+            near_duplicate,
+            layout,
+            region,
+            display_id: display_id.clone(),
         };
 
         Ok(Screenshot {
             data: webp_data,
+            format,
             width: raw_image.width,
             height: raw_image.height,
             display_index,
+            display_id,
             metadata,
         })
     }
 
+    /// Hash the image and look it up among recent captures
+    ///
+    /// Returns the hash and, for a near-duplicate, its distance to the closest capture.
+    fn check_duplicates(&self, image: &RawImage) -> CaptureResult<(Option<ImageHash>, Option<u32>)> {
+        let detection = self.config.duplicate_detection;
+        if self.config.perceptual_hash.is_none() && detection.is_none() {
+            return Ok((None, None));
+        }
+
+        let hash = image.perceptual_hash(self.config.perceptual_hash.unwrap_or_default());
+        let Some(detection) = detection else {
+            return Ok((Some(hash), None));
+        };
+
+        match self.recent_hashes.find_within(&hash, detection.max_distance) {
+            Some(distance) if detection.action == DuplicateAction::Reject => {
+                Err(CaptureError::NearDuplicate { distance })
+            }
+            distance => Ok((Some(hash), distance)),
+        }
+    }
+
+    /// Encode with the injected encoder, or the built-in one for another output format
+    fn encode(&self, image: &RawImage, config: &WebPConfig) -> EncodingResult<(Vec<u8>, EncodeReport)> {
+        let format = self.config.output_format;
+        if self.encoder.output_format() == format {
+            self.encoder.encode_with_report(image, config)
+        } else {
+            encoder::encoder_for(format)?.encode_with_report(image, config)
+        }
+    }
+
+    /// The configured encoder settings, or content-based ones with `auto_webp_config`
+    fn webp_config_for(&self, image: &RawImage) -> Cow<'_, WebPConfig> {
+        if !self.config.auto_webp_config {
+            return Cow::Borrowed(&self.config.webp_config);
+        }
+
+        let analysis = encoder::analysis::analyze(image);
+        log::debug!("Content analysis: {:?}", analysis);
+
+        let base = &self.config.webp_config;
+        let mut config = analysis.recommended_config();
+        config.thread_count = base.thread_count;
+        config.target_size = base.target_size;
+        config.target_psnr = base.target_psnr;
+        config.search_passes = base.search_passes;
+        // Size and PSNR targets only apply to lossy encoding
+        if config.target_size.is_some() || config.target_psnr.is_some() {
+            config.lossless = false;
+        }
+        Cow::Owned(config)
+    }
+
+    /// Write capture provenance into EXIF/XMP/ICCP chunks
+    ///
+    /// `region` is the global area the image covers.
+    fn embed_metadata(
+        &self,
+        webp: &[u8],
+        options: &MetadataOptions,
+        display_index: usize,
+        display: Option<&DisplayInfo>,
+        timestamp: SystemTime,
+        region: Option<Rectangle>,
+    ) -> EncodingResult<Vec<u8>> {
+        let metadata = EmbeddedMetadata {
+            capture_time: Some(timestamp),
+            display_index: Some(display_index),
+            display_name: display.map(|display| display.name.clone()),
+            region,
+            scale_factor: display.map(|display| display.scale_factor),
+            hostname: options.include_hostname.then(encoder::metadata::local_hostname).flatten(),
+            software: Some(encoder::metadata::SOFTWARE.to_string()),
+            properties: options.properties.clone(),
+            icc_profile: options.icc_profile.clone(),
+        };
+        encoder::metadata::embed(webp, &metadata)
+    }
+
     /// Normal capture without zero-copy
-    fn capture_normal(&self, display_index: usize) -> CaptureResult<RawImage> {
-        if let Some(region) = self.config.region {
+    fn capture_normal(&self, display_index: usize, region: Option<&ResolvedRegion>) -> CaptureResult<RawImage> {
+        if let Some(region) = region {
             eprintln!("[LIB] capture_normal: Using region mode - {:?}", region);
             eprintln!("[LIB] Calling capturer.capture_region()");
-            self.capturer.capture_region(region)
+            self.capturer.capture_region(CaptureRegion::from_rect(region.captured))
         } else {
             eprintln!("[LIB] capture_normal: Using display mode - display {}", display_index);
             eprintln!("[LIB] Calling capturer.capture_display()");
@@ -241,6 +597,59 @@ impl WebPScreenshot {
         }
     }
 
+    /// Composite the cursor into `image`, which covers `region`, or return it for the metadata
+    fn apply_cursor(&self, image: &mut RawImage, region: impl FnOnce() -> Option<Rectangle>) -> Option<CursorImage> {
+        let cursor = match self.capturer.capture_cursor() {
+            Ok(cursor) => cursor?,
+            Err(e) => {
+                log::debug!("Cursor unavailable, capturing without it: {}", e);
+                return None;
+            }
+        };
+
+        match self.config.cursor_mode {
+            CursorMode::Metadata => Some(cursor),
+            CursorMode::Composite => {
+                capture::composite_cursor(image, &cursor, region()?);
+                None
+            }
+        }
+    }
+
+    /// Whether backends that can't report the cursor should draw it in themselves
+    fn composite_cursor_in_backend(&self) -> bool {
+        self.config.include_cursor && self.config.cursor_mode == CursorMode::Composite
+    }
+
+    /// Reject redaction modes that would leave their areas readable
+    fn validate_redactions(&self) -> CaptureResult<()> {
+        let modes = self.config.redactions.iter().map(|redaction| redaction.mode);
+        let window_modes = self.config.window_redactions.iter().map(|redaction| redaction.mode);
+        modes
+            .chain(window_modes)
+            .try_for_each(|mode| mode.validate())
+            .map_err(CaptureError::InvalidConfiguration)
+    }
+
+    /// Obscure the configured redactions in place
+    ///
+    /// Fails rather than letting the capture through when the display can't be located.
+    fn apply_redactions(
+        &self,
+        image: &mut RawImage,
+        display: Option<&DisplayInfo>,
+        display_index: usize,
+        image_area: Option<Rectangle>,
+    ) -> CaptureResult<Vec<AppliedRedaction>> {
+        if self.config.redactions.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let display = display.ok_or(CaptureError::DisplayNotFound(display_index))?;
+        let region = image_area.unwrap_or_else(|| display.bounds());
+        Ok(capture::redact(image, &self.config.redactions, region, (display.x, display.y)))
+    }
+
     /// Capture screenshots from all available displays
     pub fn capture_all_displays(&mut self) -> Vec<CaptureResult<Screenshot>> {
         match self.get_displays() {
@@ -273,9 +682,24 @@ impl WebPScreenshot {
 
     /// Set the capture configuration
     pub fn set_config(&mut self, config: CaptureConfig) {
+        if config.window_redactions.is_empty() && !self.config.window_redactions.is_empty() {
+            // Clearing rules is supported by every backend
+            let _ = self.capturer.set_window_redactions(Vec::new());
+        }
+        if config.perceptual_hash != self.config.perceptual_hash {
+            // Hashes from another algorithm never match
+            self.recent_hashes.clear();
+        }
+        self.recent_hashes
+            .set_capacity(config.duplicate_detection.map_or(0, |detection| detection.history));
         self.config = config;
     }
 
+    /// Forget the captures near-duplicates are detected against
+    pub fn clear_duplicate_history(&mut self) {
+        self.recent_hashes.clear();
+    }
+
     /// Get the current capture configuration
     pub fn config(&self) -> &CaptureConfig {
         &self.config
@@ -306,6 +730,11 @@ impl WebPScreenshot {
         self.capturer.implementation_name()
     }
 
+    /// Get the name of the encoder in use
+    pub fn encoder_name(&self) -> &str {
+        self.encoder.name()
+    }
+
     /// Check if hardware acceleration is available
     pub fn is_hardware_accelerated(&self) -> bool {
         self.capturer.is_hardware_accelerated()
@@ -329,6 +758,112 @@ impl Default for WebPScreenshot {
     }
 }
 
+/// Builder for [`WebPScreenshot`] with injectable capture and encode components
+pub struct WebPScreenshotBuilder {
+    config: CaptureConfig,
+    capturer: Option<Box<dyn ScreenCapture>>,
+    encoder: Option<Box<dyn ImageEncoder>>,
+    memory_pool: Option<Arc<MemoryPool>>,
+    use_zero_copy: bool,
+    use_gpu: bool,
+}
+
+impl WebPScreenshotBuilder {
+    /// Create a new builder with default configuration
+    pub fn new() -> Self {
+        Self {
+            config: CaptureConfig::default(),
+            capturer: None,
+            encoder: None,
+            memory_pool: None,
+            use_zero_copy: true,
+            use_gpu: cfg!(feature = "gpu"),
+        }
+    }
+
+    /// Set the capture configuration
+    pub fn config(mut self, config: CaptureConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Capture through this backend instead of the platform default
+    pub fn capturer(mut self, capturer: Box<dyn ScreenCapture>) -> Self {
+        self.capturer = Some(capturer);
+        self
+    }
+
+    /// Encode through this encoder instead of [`WebPEncoder`]
+    pub fn encoder(mut self, encoder: impl ImageEncoder + 'static) -> Self {
+        self.encoder = Some(Box::new(encoder));
+        self
+    }
+
+    /// Allocate capture buffers from this pool instead of the global pool
+    pub fn memory_pool(mut self, pool: Arc<MemoryPool>) -> Self {
+        self.memory_pool = Some(pool);
+        self
+    }
+
+    /// Enable zero-copy capture for backends that support it
+    pub fn use_zero_copy(mut self, enabled: bool) -> Self {
+        self.use_zero_copy = enabled;
+        self
+    }
+
+    /// Enable GPU encoding when a device is available and no encoder is injected
+    pub fn use_gpu(mut self, enabled: bool) -> Self {
+        self.use_gpu = enabled;
+        self
+    }
+
+    /// Build the instance, creating the platform capturer if none was injected
+    pub fn build(self) -> CaptureResult<WebPScreenshot> {
+        let capturer = match self.capturer {
+            Some(capturer) => capturer,
+            None => Capturer::new()?,
+        };
+        let memory_pool = self.memory_pool.unwrap_or_else(memory_pool::global_pool);
+        capturer.set_memory_pool(Arc::clone(&memory_pool));
+
+        // The zero-copy path bypasses the capturer, so only use it for native backends
+        let zero_copy = if self.use_zero_copy
+            && ZeroCopyOptimizer::is_supported()
+            && capturer.capabilities().supports_zero_copy
+        {
+            Some(ZeroCopyOptimizer::new().with_memory_pool(Arc::clone(&memory_pool)))
+        } else {
+            None
+        };
+
+        // An injected encoder replaces the GPU one too
+        let gpu_encoder = if self.use_gpu && self.encoder.is_none() {
+            Some(encoder::gpu::GpuWebPEncoder::new())
+        } else {
+            None
+        };
+
+        Ok(WebPScreenshot {
+            capturer,
+            encoder: self
+                .encoder
+                .unwrap_or_else(|| Box::new(WebPEncoder::new())),
+            memory_pool,
+            recent_hashes: HashIndex::new(self.config.duplicate_detection.map_or(0, |detection| detection.history)),
+            config: self.config,
+            stats: PerformanceStats::default(),
+            zero_copy,
+            gpu_encoder,
+        })
+    }
+}
+
+impl Default for WebPScreenshotBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Convenience function to capture the primary display
 pub fn capture_primary_display() -> CaptureResult<Screenshot> {
     let mut screenshot = WebPScreenshot::new()?;
@@ -423,4 +958,690 @@ mod tests {
         assert_eq!(config.webp_config.quality, 80);
         assert!(!config.include_cursor);
     }
-}
\ No newline at end of file
+
+    /// Screenshot instance over `mock` with `config`, retrying without delay
+    fn mock_screenshot(mock: capture::MockCapture, config: CaptureConfig) -> WebPScreenshot {
+        WebPScreenshot::builder()
+            .capturer(Box::new(mock))
+            .config(CaptureConfig {
+                retry_delay: Duration::ZERO,
+                ..config
+            })
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap()
+    }
+
+
+    fn small_display() -> Vec<DisplayInfo> {
+        vec![DisplayInfo {
+            width: 64,
+            height: 48,
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn test_capture_with_mock_backend() {
+        let mut screenshot =
+            mock_screenshot(capture::MockCapture::new().with_displays(small_display()), CaptureConfig::default());
+
+        let result = screenshot.capture_display(0).unwrap();
+        assert_eq!((result.width, result.height), (64, 48));
+        assert_eq!(&result.data[0..4], b"RIFF");
+        assert_eq!(result.metadata.implementation, "Mock");
+        assert!(screenshot.zero_copy_stats().is_none());
+    }
+
+    #[test]
+    fn test_mock_failures_drive_retry_logic() {
+        use capture::mock::{MockCapture, MockFailure, MockStep};
+
+        let mock = MockCapture::new()
+            .with_displays(small_display())
+            .with_script(vec![
+                MockStep::Fail(MockFailure::Timeout(Duration::from_millis(100))),
+                MockStep::Fail(MockFailure::Timeout(Duration::from_millis(100))),
+            ]);
+        let mut screenshot = mock_screenshot(mock, CaptureConfig::default());
+        assert!(screenshot.capture_display(0).is_ok());
+        assert_eq!(screenshot.stats().successful_captures, 1);
+
+        let mock = MockCapture::new()
+            .with_displays(small_display())
+            .with_script(vec![MockStep::Fail(MockFailure::PermissionDenied)]);
+        let mut screenshot = mock_screenshot(mock, CaptureConfig::default());
+        assert!(matches!(
+            screenshot.capture_display(0),
+            Err(CaptureError::PermissionDenied(_))
+        ));
+        assert_eq!(screenshot.stats().failed_captures, 1);
+    }
+
+    /// Encoder stub that records what it was asked to encode
+    struct RecordingEncoder {
+        calls: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl ImageEncoder for RecordingEncoder {
+        fn encode(&self, image: &RawImage, _config: &WebPConfig) -> EncodingResult<Vec<u8>> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok(format!("{}x{}", image.width, image.height).into_bytes())
+        }
+
+        fn name(&self) -> &str {
+            "RecordingEncoder"
+        }
+
+        fn supports_format(&self, _format: PixelFormat) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_builder_injects_capturer_and_encoder() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let pool = MemoryPool::new();
+
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(capture::MockCapture::new().with_displays(small_display())))
+            .encoder(RecordingEncoder { calls: Arc::clone(&calls) })
+            .memory_pool(Arc::clone(&pool))
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap();
+
+        let result = screenshot.capture_display(0).unwrap();
+        assert_eq!(result.data, b"64x48");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), 1);
+        assert_eq!(screenshot.encoder_name(), "RecordingEncoder");
+        assert_eq!(screenshot.implementation_name(), "Mock");
+        assert!(screenshot.gpu_info().is_none());
+        assert_eq!(screenshot.memory_stats().total_buffers_created, pool.stats().total_buffers_created);
+    }
+
+    #[test]
+    fn test_capture_allocates_from_injected_pool() {
+        let pool = MemoryPool::new();
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(capture::MockCapture::new().with_displays(small_display())))
+            .memory_pool(Arc::clone(&pool))
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap();
+        assert_eq!(pool.stats().total_buffers_created, 0);
+
+        screenshot.capture_display(0).unwrap();
+        screenshot.capture_display(0).unwrap();
+        let stats = pool.stats();
+        assert_eq!(stats.buffer_hits + stats.buffer_misses, 2);
+        assert!(stats.total_buffers_created >= 1);
+    }
+
+    #[test]
+    fn test_injected_encoder_replaces_gpu() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(capture::MockCapture::new().with_displays(small_display())))
+            .encoder(RecordingEncoder { calls: Arc::clone(&calls) })
+            .use_zero_copy(false)
+            .use_gpu(true)
+            .build()
+            .unwrap();
+
+        assert!(screenshot.gpu_info().is_none());
+        screenshot.capture_display(0).unwrap();
+        assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_builder_defaults_to_webp_encoder() {
+        let screenshot = WebPScreenshot::builder()
+            .capturer(capture::Capturer::mock())
+            .build()
+            .unwrap();
+
+        assert_eq!(screenshot.encoder_name(), "WebPEncoder");
+        assert_eq!(screenshot.config().webp_config.quality, 80);
+    }
+
+    #[test]
+    fn test_output_format_dispatch() {
+        let capture = |output_format| {
+            let mock = capture::MockCapture::new().with_displays(small_display());
+            mock_screenshot(mock, CaptureConfig { output_format, ..Default::default() }).capture_display(0).unwrap()
+        };
+
+        let webp = capture(OutputFormat::WebP);
+        assert_eq!(&webp.data[8..12], b"WEBP");
+        assert_eq!(webp.mime_type(), "image/webp");
+        let decoded = webp.decode().unwrap();
+        assert_eq!((decoded.width, decoded.height, decoded.format), (64, 48, PixelFormat::RGBA8));
+
+        let png = capture(OutputFormat::Png);
+        assert!(png.data.starts_with(b"\x89PNG"));
+        assert_eq!((png.format, png.mime_type()), (OutputFormat::Png, "image/png"));
+        assert_eq!(png.metadata.compressed_size, png.data.len());
+        assert_eq!(png.decode().unwrap().data.len(), 64 * 48 * 4);
+        assert_eq!((png.metadata.encoding_quality, png.metadata.encoding_passes), (80, 1));
+
+        assert!(capture(OutputFormat::Jpeg).data.starts_with(&[0xFF, 0xD8]));
+        assert!(capture(OutputFormat::Qoi).data.starts_with(b"qoif"));
+    }
+
+    #[test]
+    fn test_target_size_reported_in_metadata() {
+        use capture::mock::{MockCapture, MockPattern};
+
+        let capture = |webp_config| {
+            let mock = MockCapture::new().with_displays(small_display()).with_pattern(MockPattern::Text);
+            mock_screenshot(mock, CaptureConfig { webp_config, ..Default::default() }).capture_display(0).unwrap()
+        };
+
+        let plain = capture(WebPConfig::default());
+        assert_eq!(plain.metadata.encoding_passes, 1);
+        assert_eq!(plain.metadata.encoding_quality, 80);
+
+        let budget = plain.size() * 3 / 4;
+        let targeted = capture(WebPConfig { target_size: Some(budget), ..Default::default() });
+        assert!(targeted.size() <= budget);
+        assert!(targeted.metadata.encoding_passes > 1);
+        assert!(targeted.metadata.encoding_quality < 80);
+        assert!(targeted.metadata.encoding_psnr.is_some());
+    }
+
+    #[test]
+    fn test_auto_webp_config() {
+        use capture::mock::{MockCapture, MockPattern};
+
+        let capture = |webp_config| {
+            let mock = MockCapture::new().with_displays(small_display()).with_pattern(MockPattern::Text);
+            let config = CaptureConfig { webp_config, auto_webp_config: true, ..Default::default() };
+            mock_screenshot(mock, config).capture_display(0).unwrap()
+        };
+
+        // Text is encoded losslessly regardless of the configured quality
+        let text = capture(WebPConfig { quality: 20, ..Default::default() });
+        assert!(WebPDecoder::features(&text.data).unwrap().lossless);
+        assert_eq!(text.metadata.encoding_quality, 75);
+
+        // A size target forces lossy encoding
+        let targeted = capture(WebPConfig { target_size: Some(text.size()), ..Default::default() });
+        assert!(!WebPDecoder::features(&targeted.data).unwrap().lossless);
+        assert!(targeted.metadata.encoding_passes > 1);
+    }
+
+    #[test]
+    fn test_transform_sets_output_size() {
+        use capture::mock::{MockCapture, MockPattern};
+
+        let capture = |transform| {
+            let mock = MockCapture::new().with_displays(small_display()).with_pattern(MockPattern::Gradient);
+            mock_screenshot(mock, CaptureConfig { transform, ..Default::default() }).capture_display(0)
+        };
+
+        let thumbnail = capture(ImageTransform {
+            crop: Some(Rectangle::new(0, 0, 48, 48)),
+            fit_within: Some((20, 0)),
+            filter: ResizeFilter::Lanczos3,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (20, 20));
+        let decoded = thumbnail.decode().unwrap();
+        assert_eq!((decoded.width, decoded.height), (20, 20));
+
+        let half = capture(ImageTransform { scale: Some(0.5), ..Default::default() }).unwrap();
+        assert_eq!((half.width, half.height), (32, 24));
+
+        assert!(capture(ImageTransform { scale: Some(-1.0), ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn test_redactions_recorded_in_metadata() {
+        use capture::mock::{MockCapture, MockPattern};
+
+        let capture = |redactions: Vec<Redaction>, transform: ImageTransform| {
+            let displays = vec![DisplayInfo { x: 1920, width: 64, height: 48, ..Default::default() }];
+            let mock = MockCapture::new().with_displays(displays).with_pattern(MockPattern::Text);
+            let config = CaptureConfig {
+                webp_config: WebPConfig { lossless: true, ..Default::default() },
+                redactions,
+                transform,
+                ..Default::default()
+            };
+            mock_screenshot(mock, config).capture_display(0).unwrap()
+        };
+
+        let secret = Redaction::new(Rectangle::new(8, 8, 16, 8), RedactionMode::Fill([0, 0, 255, 255]));
+        let offscreen = Redaction::new(Rectangle::new(0, 0, 10, 10), RedactionMode::Pixelate { block: 4 }).global();
+
+        let result = capture(vec![secret, offscreen], ImageTransform::default());
+        assert_eq!(
+            result.metadata.redactions,
+            vec![AppliedRedaction { area: Rectangle::new(8, 8, 16, 8), mode: secret.mode }]
+        );
+        let decoded = result.decode().unwrap();
+        assert_eq!(decoded.get_pixel(8, 8).unwrap(), &[0, 0, 255, 255]);
+        assert_eq!(decoded.get_pixel(23, 15).unwrap(), &[0, 0, 255, 255]);
+
+        // Areas follow the transform into screenshot pixels
+        let halved = capture(vec![secret], ImageTransform { scale: Some(0.5), ..Default::default() });
+        assert_eq!(halved.metadata.redactions[0].area, Rectangle::new(4, 4, 8, 4));
+    }
+
+    #[test]
+    fn test_ineffective_redactions_rejected() {
+        use capture::mock::MockCapture;
+
+        for mode in [RedactionMode::Blur { radius: 0 }, RedactionMode::Pixelate { block: 1 }] {
+            let mut screenshot = WebPScreenshot::builder()
+                .capturer(Box::new(MockCapture::new().with_displays(small_display())))
+                .config(CaptureConfig {
+                    redactions: vec![Redaction::new(Rectangle::new(0, 0, 8, 8), mode)],
+                    max_retries: 0,
+                    ..Default::default()
+                })
+                .use_zero_copy(false)
+                .use_gpu(false)
+                .build()
+                .unwrap();
+            assert!(matches!(screenshot.capture_display(0), Err(CaptureError::InvalidConfiguration(_))));
+            assert!(matches!(screenshot.capture_virtual_desktop(), Err(CaptureError::InvalidConfiguration(_))));
+        }
+    }
+
+    #[test]
+    fn test_window_redactions_fail_closed() {
+        use capture::mock::MockCapture;
+
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(MockCapture::new().with_displays(small_display())))
+            .config(CaptureConfig {
+                window_redactions: vec![WindowRedaction::new(
+                    WindowFilter::class("KeePassXC"),
+                    RedactionMode::Fill([0, 0, 0, 255]),
+                )],
+                max_retries: 0,
+                ..Default::default()
+            })
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap();
+
+        // The mock backend has no windows to mask, so it must refuse to capture
+        assert!(matches!(screenshot.capture_display(0), Err(CaptureError::PlatformError(_))));
+
+        screenshot.set_config(CaptureConfig { max_retries: 0, ..Default::default() });
+        assert!(screenshot.capture_display(0).is_ok());
+    }
+
+    #[test]
+    fn test_capture_virtual_desktop() {
+        use capture::mock::{MockCapture, MockPattern};
+        use encoder::WebPDecoder;
+
+        let displays = vec![
+            DisplayInfo { index: 0, width: 64, height: 48, ..Default::default() },
+            DisplayInfo {
+                index: 1,
+                name: "Side".to_string(),
+                x: 64,
+                y: 16,
+                width: 32,
+                height: 16,
+                scale_factor: 2.0,
+                is_primary: false,
+                ..Default::default()
+            },
+        ];
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(
+                MockCapture::new().with_displays(displays).with_pattern(MockPattern::Solid([0, 0, 255, 255])),
+            ))
+            .config(CaptureConfig { webp_config: WebPConfig::lossless(), ..Default::default() })
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap();
+
+        let desktop = screenshot.capture_virtual_desktop().unwrap();
+        // Normalized to the 2x display
+        assert_eq!((desktop.width, desktop.height), (192, 96));
+        assert_eq!(desktop.display_index, 0);
+        assert_eq!(desktop.display_id, None);
+
+        let layout = &desktop.metadata.layout;
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout[0].area, Rectangle::new(0, 0, 128, 96));
+        assert_eq!(layout[1].bounds, Rectangle::new(64, 16, 32, 16));
+        assert_eq!(layout[1].display_id.as_str(), "mock-1");
+        assert_eq!(layout[1].area, Rectangle::new(128, 32, 64, 32));
+
+        let image = WebPDecoder::new().decode(&desktop.data).unwrap();
+        assert_eq!(image.get_pixel(150, 40).unwrap(), [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(150, 0).unwrap()[3], 0);
+    }
+
+    #[test]
+    fn test_capture_display_by_id() {
+        use capture::mock::MockCapture;
+
+        let displays = vec![
+            DisplayInfo { id: DisplayId("DEL-A0B1-7X4K2".to_string()), width: 64, height: 48, ..Default::default() },
+            DisplayInfo {
+                id: DisplayId("GSM-5B7F-1234".to_string()),
+                name: "HDMI-1".to_string(),
+                x: 64,
+                width: 32,
+                height: 16,
+                is_primary: false,
+                ..Default::default()
+            },
+        ];
+        let mut screenshot = mock_screenshot(MockCapture::new().with_displays(displays), CaptureConfig::default());
+
+        let id = DisplayId("GSM-5B7F-1234".to_string());
+        let result = screenshot.capture_display_by_id(&id).unwrap();
+        assert_eq!((result.display_index, result.width), (1, 32));
+        assert_eq!(result.display_id.as_ref(), Some(&id));
+        assert_eq!(result.metadata.display_id.as_ref(), Some(&id));
+
+        let by_name = screenshot.find_display(&DisplaySelector::Name("HDMI-1".to_string())).unwrap();
+        assert_eq!(by_name.id, id);
+        assert_eq!(screenshot.find_display(&DisplaySelector::Primary).unwrap().index, 0);
+
+        let missing = screenshot.capture_display_by_id(&DisplayId("unplugged".to_string()));
+        assert!(matches!(missing, Err(CaptureError::NoMatchingDisplay(_))));
+        assert!(matches!(
+            screenshot.find_display(&DisplaySelector::Index(2)),
+            Err(CaptureError::DisplayNotFound(2))
+        ));
+    }
+
+    #[test]
+    fn test_near_duplicates_flagged_or_rejected() {
+        use capture::mock::{MockCapture, MockPattern};
+
+        let config = |action| CaptureConfig {
+            duplicate_detection: Some(DuplicateDetection { action, ..Default::default() }),
+            max_retries: 0,
+            ..Default::default()
+        };
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(MockCapture::new().with_displays(small_display()).with_pattern(MockPattern::Text)))
+            .config(config(DuplicateAction::Flag))
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap();
+
+        let first = screenshot.capture_display(0).unwrap();
+        let hash = first.metadata.perceptual_hash.unwrap();
+        assert_eq!(hash.algorithm, HashAlgorithm::PHash);
+        assert_eq!(first.metadata.near_duplicate, None);
+
+        let second = screenshot.capture_display(0).unwrap();
+        assert_eq!(second.metadata.perceptual_hash, Some(hash));
+        assert_eq!(second.metadata.near_duplicate, Some(0));
+
+        screenshot.set_config(config(DuplicateAction::Reject));
+        assert!(matches!(screenshot.capture_display(0), Err(CaptureError::NearDuplicate { distance: 0 })));
+
+        screenshot.clear_duplicate_history();
+        assert!(screenshot.capture_display(0).is_ok());
+    }
+
+    #[test]
+    fn test_embedded_metadata() {
+        let capture = |embed_metadata| {
+            let mock = capture::MockCapture::new().with_displays(small_display());
+            mock_screenshot(mock, CaptureConfig { embed_metadata, ..Default::default() }).capture_display(0).unwrap()
+        };
+
+        let bare = capture(None);
+        assert_eq!(bare.embedded_metadata().unwrap(), EmbeddedMetadata::default());
+
+        let mut options = MetadataOptions {
+            include_hostname: false,
+            ..Default::default()
+        };
+        options.properties.insert("job".to_string(), "nightly".to_string());
+        let tagged = capture(Some(options));
+        assert_eq!(tagged.metadata.compressed_size, tagged.size());
+
+        let metadata = tagged.embedded_metadata().unwrap();
+        assert_eq!(metadata.capture_time, Some(tagged.metadata.timestamp).map(|time| {
+            // Stored with millisecond precision
+            let millis = time.duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+            std::time::UNIX_EPOCH + Duration::from_millis(millis)
+        }));
+        assert_eq!(metadata.display_index, Some(0));
+        assert_eq!(metadata.display_name.as_deref(), Some("Primary Display"));
+        assert_eq!(metadata.region, Some(Rectangle::new(0, 0, 64, 48)));
+        assert_eq!(metadata.scale_factor, Some(1.0));
+        assert_eq!(metadata.hostname, None);
+        assert_eq!(metadata.properties["job"], "nightly");
+        assert_eq!(tagged.decode().unwrap().data, bare.decode().unwrap().data);
+    }
+
+    /// Encoder stub that outputs the pixel at (10, 10)
+    struct PixelProbeEncoder;
+
+    impl ImageEncoder for PixelProbeEncoder {
+        fn encode(&self, image: &RawImage, _config: &WebPConfig) -> EncodingResult<Vec<u8>> {
+            Ok(image.get_pixel(10, 10).unwrap_or_default().to_vec())
+        }
+
+        fn name(&self) -> &str {
+            "PixelProbeEncoder"
+        }
+
+        fn supports_format(&self, _format: PixelFormat) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_include_cursor_modes() {
+        use capture::mock::{MockCapture, MockPattern};
+
+        let cursor = CursorImage {
+            x: 12,
+            y: 12,
+            hotspot_x: 2,
+            hotspot_y: 2,
+            width: 1,
+            height: 1,
+            data: vec![255, 255, 255, 255],
+        };
+        let probe = |config: CaptureConfig| {
+            let mock = MockCapture::new()
+                .with_displays(small_display())
+                .with_pattern(MockPattern::Solid([0, 0, 0, 255]))
+                .with_cursor(cursor.clone());
+            WebPScreenshot::builder()
+                .capturer(Box::new(mock))
+                .encoder(PixelProbeEncoder)
+                .config(config)
+                .use_zero_copy(false)
+                .use_gpu(false)
+                .build()
+                .unwrap()
+                .capture_display(0)
+                .unwrap()
+        };
+
+        let without = probe(CaptureConfig::default());
+        assert_eq!(without.data, [0, 0, 0, 255]);
+        assert!(without.metadata.cursor.is_none());
+
+        let composited = probe(CaptureConfig {
+            include_cursor: true,
+            ..Default::default()
+        });
+        assert_eq!(composited.data, [255, 255, 255, 255]);
+        assert!(composited.metadata.cursor.is_none());
+
+        let reported = probe(CaptureConfig {
+            include_cursor: true,
+            cursor_mode: CursorMode::Metadata,
+            ..Default::default()
+        });
+        assert_eq!(reported.data, [0, 0, 0, 255]);
+        assert_eq!(reported.metadata.cursor, Some(cursor));
+    }
+
+    /// Counts enumerations; like the portal, it reports 0x0 displays until it has
+    /// captured when `sized_by_capture` is set
+    struct CountingCapture {
+        inner: capture::MockCapture,
+        enumerations: Arc<std::sync::atomic::AtomicUsize>,
+        sized_by_capture: bool,
+        captured: std::sync::atomic::AtomicBool,
+        cursor_overlay: std::sync::atomic::AtomicBool,
+    }
+
+    impl ScreenCapture for CountingCapture {
+        fn get_displays(&self) -> CaptureResult<Vec<DisplayInfo>> {
+            self.enumerations.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let displays = self.inner.get_displays()?;
+            if self.sized_by_capture && !self.captured.load(std::sync::atomic::Ordering::Relaxed) {
+                return Ok(displays.into_iter().map(|display| DisplayInfo { width: 0, height: 0, ..display }).collect());
+            }
+            Ok(displays)
+        }
+
+        fn capture_display(&self, display_index: usize) -> CaptureResult<RawImage> {
+            self.captured.store(true, std::sync::atomic::Ordering::Relaxed);
+            self.inner.capture_display(display_index)
+        }
+
+        fn capture_region(&self, region: CaptureRegion) -> CaptureResult<RawImage> {
+            self.inner.capture_region(region)
+        }
+
+        fn capture_cursor(&self) -> CaptureResult<Option<CursorImage>> {
+            self.inner.capture_cursor()
+        }
+
+        fn set_cursor_overlay(&self, enabled: bool) {
+            self.cursor_overlay.store(enabled, std::sync::atomic::Ordering::Relaxed);
+        }
+
+        fn implementation_name(&self) -> String {
+            self.inner.implementation_name()
+        }
+    }
+
+    #[test]
+    fn test_cursor_overlay_follows_cursor_mode() {
+        let capturer = Arc::new(CountingCapture {
+            inner: capture::MockCapture::new().with_displays(small_display()),
+            enumerations: Default::default(),
+            sized_by_capture: false,
+            captured: Default::default(),
+            cursor_overlay: Default::default(),
+        });
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(Arc::clone(&capturer)))
+            .config(CaptureConfig { include_cursor: true, ..Default::default() })
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap();
+        let overlay = || capturer.cursor_overlay.load(std::sync::atomic::Ordering::Relaxed);
+
+        screenshot.capture_display(0).unwrap();
+        assert!(overlay());
+
+        let mut config = screenshot.config().clone();
+        config.cursor_mode = CursorMode::Metadata;
+        screenshot.set_config(config);
+        screenshot.capture_display(0).unwrap();
+        assert!(!overlay());
+    }
+
+    #[test]
+    fn test_displays_enumerated_once_per_capture() {
+        let enumerations = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let capturer = CountingCapture {
+            inner: capture::MockCapture::new().with_displays(small_display()),
+            enumerations: Arc::clone(&enumerations),
+            sized_by_capture: false,
+            captured: Default::default(),
+            cursor_overlay: Default::default(),
+        };
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(capturer))
+            .config(CaptureConfig {
+                region: Some(CaptureRegion::new(8, 8, 32, 16)),
+                redactions: vec![Redaction::new(Rectangle::new(0, 0, 16, 16), RedactionMode::Fill([0, 0, 0, 255]))],
+                include_cursor: true,
+                embed_metadata: Some(MetadataOptions::default()),
+                ..Default::default()
+            })
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap();
+
+        let result = screenshot.capture_display(0).unwrap();
+        assert_eq!(result.display_id.as_ref().map(DisplayId::as_str), Some("mock-0"));
+        assert_eq!(enumerations.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_displays_enumerated_after_capture_without_region() {
+        let enumerations = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let capturer = CountingCapture {
+            inner: capture::MockCapture::new().with_displays(small_display()),
+            enumerations: Arc::clone(&enumerations),
+            sized_by_capture: true,
+            captured: Default::default(),
+            cursor_overlay: Default::default(),
+        };
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(capturer))
+            .config(CaptureConfig {
+                redactions: vec![Redaction::new(Rectangle::new(0, 0, 16, 16), RedactionMode::Fill([0, 0, 0, 255]))],
+                embed_metadata: Some(MetadataOptions::default()),
+                ..Default::default()
+            })
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap();
+
+        let result = screenshot.capture_display(0).unwrap();
+        assert_eq!(result.display_id.as_ref().map(DisplayId::as_str), Some("mock-0"));
+        assert_eq!(enumerations.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_virtual_desktop_reenumerates_unsized_displays() {
+        let enumerations = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let capturer = CountingCapture {
+            inner: capture::MockCapture::new().with_displays(small_display()),
+            enumerations: Arc::clone(&enumerations),
+            sized_by_capture: true,
+            captured: Default::default(),
+            cursor_overlay: Default::default(),
+        };
+        let mut screenshot = WebPScreenshot::builder()
+            .capturer(Box::new(capturer))
+            .use_zero_copy(false)
+            .use_gpu(false)
+            .build()
+            .unwrap();
+
+        let display = &small_display()[0];
+        let result = screenshot.capture_virtual_desktop().unwrap();
+        assert_eq!((result.width, result.height), (display.width, display.height));
+        assert_eq!(enumerations.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,427 @@
+//! Change detection between consecutive frames
+//!
+//! Frames are split into square tiles and each tile is hashed. Tiles whose hash
+//! matches the reference are unchanged; the rest are compared pixel by pixel
+//! against a per-channel threshold, so encoder noise and gradients shifting by
+//! a level or two don't mark a tile dirty. Dirty tiles are merged into
+//! rectangles for encoding.
+
+use crate::{
+    error::{EncodingError, EncodingResult},
+    types::{RawImage, Rectangle},
+};
+
+/// Frame differencing settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameDiffConfig {
+    /// Tile edge length in pixels
+    pub tile_size: u32,
+    /// Largest per-channel difference that still counts as unchanged
+    pub pixel_threshold: u8,
+}
+
+impl Default for FrameDiffConfig {
+    fn default() -> Self {
+        Self {
+            tile_size: 32,
+            pixel_threshold: 0,
+        }
+    }
+}
+
+impl FrameDiffConfig {
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tile_size == 0 {
+            return Err("Tile size must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// What changed between two frames
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffResult {
+    /// Dirty tiles merged into rectangles, clipped to the frame
+    pub dirty_rects: Vec<Rectangle>,
+    /// Pixels differing by more than the threshold
+    pub changed_pixels: u64,
+    /// Pixels in the frame
+    pub total_pixels: u64,
+    /// Tiles containing at least one changed pixel
+    pub dirty_tiles: usize,
+    /// Tiles in the frame
+    pub total_tiles: usize,
+}
+
+impl DiffResult {
+    /// Whether nothing changed beyond the threshold
+    pub fn is_unchanged(&self) -> bool {
+        self.dirty_rects.is_empty()
+    }
+
+    /// Changed pixels as a percentage of the frame
+    pub fn changed_percentage(&self) -> f64 {
+        match self.total_pixels {
+            0 => 0.0,
+            total => self.changed_pixels as f64 * 100.0 / total as f64,
+        }
+    }
+
+    /// Pixels covered by the dirty rectangles
+    pub fn dirty_pixels(&self) -> u64 {
+        self.dirty_rects.iter().map(|rect| rect.area() as u64).sum()
+    }
+}
+
+/// Reference frame the next one is compared against
+struct Reference {
+    image: RawImage,
+    hashes: Vec<u64>,
+}
+
+/// Tile-hashing frame differ
+///
+/// [`update`](Self::update) keeps a reference frame and only replaces its dirty
+/// tiles, so changes below the threshold accumulate until they exceed it
+/// instead of being lost one frame at a time.
+pub struct FrameDiff {
+    config: FrameDiffConfig,
+    reference: Option<Reference>,
+}
+
+impl FrameDiff {
+    /// Create a differ with no reference frame
+    pub fn new(config: FrameDiffConfig) -> Self {
+        Self {
+            config,
+            reference: None,
+        }
+    }
+
+    /// The differencing settings
+    pub fn config(&self) -> &FrameDiffConfig {
+        &self.config
+    }
+
+    /// Forget the reference frame; the next update reports the whole frame as dirty
+    pub fn reset(&mut self) {
+        self.reference = None;
+    }
+
+    /// Compare two frames without touching the reference
+    ///
+    /// Frames of different size or format are entirely dirty.
+    pub fn compare(&self, previous: &RawImage, current: &RawImage) -> EncodingResult<DiffResult> {
+        self.check(current)?;
+        if !same_shape(previous, current) {
+            return Ok(self.full(current));
+        }
+        self.check(previous)?;
+
+        let hashes = self.tiles(previous).map(|tile| hash_tile(previous, tile)).collect::<Vec<_>>();
+        Ok(self.diff(previous, &hashes, current, |_, _, _| {}))
+    }
+
+    /// Compare `frame` with the reference and fold its changes into it
+    pub fn update(&mut self, frame: &RawImage) -> EncodingResult<DiffResult> {
+        self.check(frame)?;
+
+        let Some(mut reference) = self.reference.take().filter(|reference| same_shape(&reference.image, frame)) else {
+            let image = frame.to_packed();
+            let hashes = self.tiles(&image).map(|tile| hash_tile(&image, tile)).collect();
+            self.reference = Some(Reference { image, hashes });
+            return Ok(self.full(frame));
+        };
+
+        let mut dirty = Vec::new();
+        let result = self.diff(&reference.image, &reference.hashes, frame, |index, tile, hash| {
+            dirty.push((index, tile, hash));
+        });
+        for (index, tile, hash) in dirty {
+            copy_tile(frame, &mut reference.image, tile);
+            reference.hashes[index] = hash;
+        }
+        self.reference = Some(reference);
+        Ok(result)
+    }
+
+    fn check(&self, image: &RawImage) -> EncodingResult<()> {
+        self.config.validate().map_err(EncodingError::InvalidConfiguration)?;
+        if !image.is_valid() {
+            return Err(EncodingError::BufferTooSmall {
+                required: image.required_len(),
+                provided: image.data.len(),
+            });
+        }
+        Ok(())
+    }
+
+    fn grid(&self, image: &RawImage) -> (u32, u32) {
+        (image.width.div_ceil(self.config.tile_size), image.height.div_ceil(self.config.tile_size))
+    }
+
+    /// Tiles in row-major order, clipped to the image
+    fn tiles(&self, image: &RawImage) -> impl Iterator<Item = Rectangle> {
+        let size = self.config.tile_size;
+        let (columns, rows) = self.grid(image);
+        let (width, height) = (image.width, image.height);
+        (0..rows).flat_map(move |row| {
+            (0..columns).map(move |column| {
+                let (x, y) = (column * size, row * size);
+                Rectangle::new(x as i32, y as i32, size.min(width - x), size.min(height - y))
+            })
+        })
+    }
+
+    fn full(&self, image: &RawImage) -> DiffResult {
+        let (columns, rows) = self.grid(image);
+        let total_pixels = image.pixel_count() as u64;
+        DiffResult {
+            dirty_rects: if total_pixels > 0 {
+                vec![Rectangle::new(0, 0, image.width, image.height)]
+            } else {
+                Vec::new()
+            },
+            changed_pixels: total_pixels,
+            total_pixels,
+            dirty_tiles: (columns * rows) as usize,
+            total_tiles: (columns * rows) as usize,
+        }
+    }
+
+    /// Diff `current` against `previous`, calling `on_dirty(index, tile, hash)` for each dirty tile
+    fn diff<F>(&self, previous: &RawImage, hashes: &[u64], current: &RawImage, mut on_dirty: F) -> DiffResult
+    where
+        F: FnMut(usize, Rectangle, u64),
+    {
+        let (columns, rows) = self.grid(current);
+        let mut dirty = vec![false; hashes.len()];
+        let mut changed_pixels = 0;
+
+        for (index, tile) in self.tiles(current).enumerate() {
+            let hash = hash_tile(current, tile);
+            if hash == hashes[index] {
+                continue;
+            }
+
+            let changed = count_changed(previous, current, tile, self.config.pixel_threshold);
+            if changed > 0 {
+                changed_pixels += changed;
+                dirty[index] = true;
+                on_dirty(index, tile, hash);
+            }
+        }
+
+        DiffResult {
+            dirty_rects: merge_tiles(&dirty, columns, rows, self.config.tile_size, current.width, current.height),
+            changed_pixels,
+            total_pixels: current.pixel_count() as u64,
+            dirty_tiles: dirty.iter().filter(|&&dirty| dirty).count(),
+            total_tiles: (columns * rows) as usize,
+        }
+    }
+}
+
+fn same_shape(a: &RawImage, b: &RawImage) -> bool {
+    (a.width, a.height, a.format) == (b.width, b.height, b.format)
+}
+
+fn tile_rows<'a>(image: &'a RawImage, tile: Rectangle) -> impl Iterator<Item = &'a [u8]> + 'a {
+    let bpp = image.format.bytes_per_pixel();
+    let (x, width) = (tile.x as usize * bpp, tile.width as usize * bpp);
+    (tile.y as usize..tile.y as usize + tile.height as usize).map(move |y| {
+        let start = y * image.stride + x;
+        &image.data[start..start + width]
+    })
+}
+
+/// Hash a tile's pixels a word at a time
+///
+/// Each step is a bijection of the state, so two tiles that differ in a single
+/// word can never collide.
+fn hash_tile(image: &RawImage, tile: Rectangle) -> u64 {
+    const MULTIPLIER: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mix = |hash: u64, word: u64| (hash ^ word).wrapping_mul(MULTIPLIER).rotate_left(29);
+
+    tile_rows(image, tile).fold(0xCBF2_9CE4_8422_2325, |hash, row| {
+        let mut words = row.chunks_exact(8);
+        let hash = (&mut words).fold(hash, |hash, word| mix(hash, u64::from_le_bytes(word.try_into().unwrap())));
+        words.remainder().iter().fold(hash, |hash, &byte| mix(hash, byte as u64))
+    })
+}
+
+/// Pixels in `tile` where any channel differs by more than `threshold`
+fn count_changed(previous: &RawImage, current: &RawImage, tile: Rectangle, threshold: u8) -> u64 {
+    let bpp = current.format.bytes_per_pixel();
+    tile_rows(previous, tile)
+        .zip(tile_rows(current, tile))
+        .map(|(before, after)| {
+            before
+                .chunks_exact(bpp)
+                .zip(after.chunks_exact(bpp))
+                .filter(|(a, b)| a.iter().zip(b.iter()).any(|(&a, &b)| a.abs_diff(b) > threshold))
+                .count() as u64
+        })
+        .sum()
+}
+
+fn copy_tile(source: &RawImage, target: &mut RawImage, tile: Rectangle) {
+    let bpp = source.format.bytes_per_pixel();
+    let (x, width) = (tile.x as usize * bpp, tile.width as usize * bpp);
+    for (offset, row) in tile_rows(source, tile).enumerate() {
+        let start = (tile.y as usize + offset) * target.stride + x;
+        target.data[start..start + width].copy_from_slice(row);
+    }
+}
+
+/// Merge dirty tiles into rectangles: horizontal runs first, then identical runs in adjacent rows
+fn merge_tiles(dirty: &[bool], columns: u32, rows: u32, tile_size: u32, width: u32, height: u32) -> Vec<Rectangle> {
+    // (first column, end column, first row, end row) in tile units
+    let mut done = Vec::new();
+    let mut open: Vec<(u32, u32, u32, u32)> = Vec::new();
+
+    for row in 0..rows {
+        let mut runs = Vec::new();
+        let mut column = 0;
+        while column < columns {
+            if !dirty[(row * columns + column) as usize] {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < columns && dirty[(row * columns + column) as usize] {
+                column += 1;
+            }
+            runs.push((start, column));
+        }
+
+        let mut next = Vec::with_capacity(runs.len());
+        for (start, end) in runs {
+            match open.iter().position(|&(x0, x1, _, _)| (x0, x1) == (start, end)) {
+                Some(index) => {
+                    let (x0, x1, y0, _) = open.swap_remove(index);
+                    next.push((x0, x1, y0, row + 1));
+                }
+                None => next.push((start, end, row, row + 1)),
+            }
+        }
+        done.append(&mut open);
+        open = next;
+    }
+    done.append(&mut open);
+
+    done.sort_by_key(|&(x0, _, y0, _)| (y0, x0));
+    done.into_iter()
+        .map(|(x0, x1, y0, y1)| {
+            let (left, top) = (x0 * tile_size, y0 * tile_size);
+            let right = (x1 * tile_size).min(width);
+            let bottom = (y1 * tile_size).min(height);
+            Rectangle::new(left as i32, top as i32, right - left, bottom - top)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PixelFormat;
+
+    fn solid(width: u32, height: u32, value: u8) -> RawImage {
+        RawImage::new(vec![value; (width * height * 4) as usize], width, height, PixelFormat::RGBA8)
+    }
+
+    fn set_pixel(image: &mut RawImage, x: u32, y: u32, pixel: [u8; 4]) {
+        let offset = y as usize * image.stride + x as usize * 4;
+        image.data[offset..offset + 4].copy_from_slice(&pixel);
+    }
+
+    #[test]
+    fn test_first_update_is_full_frame() {
+        let mut diff = FrameDiff::new(FrameDiffConfig::default());
+        let frame = solid(100, 50, 7);
+
+        let result = diff.update(&frame).unwrap();
+        assert_eq!(result.dirty_rects, vec![Rectangle::new(0, 0, 100, 50)]);
+        assert_eq!(result.changed_percentage(), 100.0);
+        assert_eq!(result.total_tiles, 4 * 2);
+
+        let result = diff.update(&frame).unwrap();
+        assert!(result.is_unchanged());
+        assert_eq!(result.changed_pixels, 0);
+
+        diff.reset();
+        assert!(!diff.update(&frame).unwrap().is_unchanged());
+    }
+
+    #[test]
+    fn test_dirty_tiles_are_merged() {
+        let config = FrameDiffConfig { tile_size: 16, pixel_threshold: 0 };
+        let previous = solid(64, 64, 0);
+        let mut current = previous.clone();
+        // Tiles (1,0) and (1,1) form a column; (3,3) stands alone
+        set_pixel(&mut current, 20, 5, [255; 4]);
+        set_pixel(&mut current, 30, 20, [255; 4]);
+        set_pixel(&mut current, 63, 63, [255; 4]);
+
+        let result = FrameDiff::new(config).compare(&previous, &current).unwrap();
+        assert_eq!(
+            result.dirty_rects,
+            vec![Rectangle::new(16, 0, 16, 32), Rectangle::new(48, 48, 16, 16)]
+        );
+        assert_eq!(result.changed_pixels, 3);
+        assert_eq!(result.dirty_tiles, 3);
+        assert_eq!(result.total_tiles, 16);
+        assert_eq!(result.dirty_pixels(), 16 * 32 + 16 * 16);
+    }
+
+    #[test]
+    fn test_partial_tiles_clip_to_frame() {
+        let config = FrameDiffConfig { tile_size: 16, pixel_threshold: 0 };
+        let previous = solid(40, 20, 0);
+        let mut current = previous.clone();
+        for x in 0..40 {
+            set_pixel(&mut current, x, 19, [1, 1, 1, 1]);
+        }
+
+        let result = FrameDiff::new(config).compare(&previous, &current).unwrap();
+        assert_eq!(result.dirty_rects, vec![Rectangle::new(0, 16, 40, 4)]);
+        assert!((result.changed_percentage() - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_threshold_accumulates_drift() {
+        let config = FrameDiffConfig { tile_size: 8, pixel_threshold: 4 };
+        let mut diff = FrameDiff::new(config);
+        let mut frame = solid(16, 16, 100);
+        diff.update(&frame).unwrap();
+
+        // Each step stays under the threshold, but the third takes the drift past it
+        for step in 1..=4 {
+            set_pixel(&mut frame, 3, 3, [100 + 2 * step; 4]);
+            let result = diff.update(&frame).unwrap();
+            assert_eq!(result.is_unchanged(), step != 3, "step {step}");
+            if step == 3 {
+                assert_eq!(result.dirty_rects, vec![Rectangle::new(0, 0, 8, 8)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_padded_and_mismatched_frames() {
+        let diff = FrameDiff::new(FrameDiffConfig::default());
+        let packed = solid(10, 4, 9);
+        let mut data = vec![0u8; 48 * 4];
+        for y in 0..4 {
+            data[y * 48..y * 48 + 40].fill(9);
+        }
+        let padded = RawImage::with_stride(data, 10, 4, PixelFormat::RGBA8, 48);
+        assert!(diff.compare(&packed, &padded).unwrap().is_unchanged());
+
+        let larger = solid(12, 4, 9);
+        assert_eq!(diff.compare(&packed, &larger).unwrap().changed_percentage(), 100.0);
+
+        let invalid = FrameDiff::new(FrameDiffConfig { tile_size: 0, pixel_threshold: 0 });
+        assert!(invalid.compare(&packed, &packed).is_err());
+    }
+}
@@ -1,7 +1,9 @@
 //! Pipeline modules for optimized capture and encoding
 
+pub mod frame_diff;
 pub mod streaming;
 pub mod zero_copy;
 
-pub use streaming::{StreamingPipeline, StreamingPipelineBuilder};
+pub use frame_diff::{DiffResult, FrameDiff, FrameDiffConfig};
+pub use streaming::{DisplayChangeAction, FrameUpdate, StreamingPipeline, StreamingPipelineBuilder};
 pub use zero_copy::ZeroCopyOptimizer;
\ No newline at end of file
@@ -5,14 +5,20 @@
 //! - Ring buffer for frame management
 //! - Adaptive quality based on performance
 //! - Frame dropping for consistent FPS
+//! - Skipping unchanged frames and encoding only dirty regions
+//! - Animated WebP recording
+//! - Following the captured display through hotplug and mode changes
 
 use crate::{
-    capture::ScreenCapture,
-    encoder::{WebPEncoder, simd::SimdConverter},
-    error::{CaptureError, CaptureResult},
+    capture::{composite_cursor, redact, DisplayEvent, DisplayWatcher, ScreenCapture},
+    encoder::{AnimatedWebPWriter, WebPEncoder, simd::SimdConverter},
+    error::{CaptureError, CaptureResult, EncodingError, EncodingResult},
     memory_pool::MemoryPool,
-    pipeline::zero_copy::ZeroCopyOptimizer,
-    types::{RawImage, WebPConfig},
+    pipeline::{
+        frame_diff::{FrameDiff, FrameDiffConfig},
+        zero_copy::ZeroCopyOptimizer,
+    },
+    types::{DisplayInfo, DisplaySelector, RawImage, Rectangle, Redaction, WebPConfig},
 };
 
 use crossbeam_channel::{bounded, Receiver, Sender};
@@ -29,12 +35,63 @@ use std::{
 /// Frame data in the pipeline
 #[derive(Clone)]
 struct Frame {
-    #[allow(dead_code)]
     id: u64,
     image: RawImage,
-    #[allow(dead_code)]
     timestamp: Instant,
     capture_duration: Duration,
+    /// Areas to encode, or `None` for the whole frame
+    regions: Option<Vec<Rectangle>>,
+}
+
+/// An encoded frame, or one changed region of it
+#[derive(Debug, Clone)]
+pub struct FrameUpdate {
+    /// Capture sequence number, shared by all regions of one frame
+    pub frame_id: u64,
+    /// Where the encoded image goes within the frame
+    pub region: Rectangle,
+    /// Full frame width
+    pub frame_width: u32,
+    /// Full frame height
+    pub frame_height: u32,
+    /// Encoded WebP data for `region`
+    pub data: Vec<u8>,
+}
+
+impl FrameUpdate {
+    /// Whether the update covers the whole frame
+    pub fn is_full_frame(&self) -> bool {
+        self.region == Rectangle::new(0, 0, self.frame_width, self.frame_height)
+    }
+}
+
+/// What a pipeline does when the display configuration changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayChangeAction {
+    /// Keep capturing the selected display wherever it moves, falling back to
+    /// the primary display while it is disconnected
+    #[default]
+    Retarget,
+    /// Stop capturing when the captured display is removed or changes mode,
+    /// until [`StreamingPipeline::resume`] or [`StreamingPipeline::retarget`]
+    Pause,
+}
+
+/// Display being captured
+#[derive(Debug, Clone, PartialEq)]
+struct CaptureTarget {
+    index: usize,
+    /// `None` when the backend can't enumerate displays
+    display: Option<DisplayInfo>,
+}
+
+impl CaptureTarget {
+    fn new(index: usize, displays: &[DisplayInfo]) -> Self {
+        Self {
+            index,
+            display: displays.get(index).cloned(),
+        }
+    }
 }
 
 /// Streaming pipeline configuration
@@ -50,7 +107,8 @@ pub struct StreamingConfig {
     pub encoding_threads: usize,
     /// Enable adaptive quality
     pub adaptive_quality: bool,
-    /// Enable frame dropping
+    /// Enable frame dropping; ignored with `encode_dirty_regions`, whose
+    /// updates can't be applied once one is lost
     pub allow_frame_drop: bool,
     /// Initial WebP configuration
     pub webp_config: WebPConfig,
@@ -58,6 +116,19 @@ pub struct StreamingConfig {
     pub use_zero_copy: bool,
     /// Use GPU encoding if available
     pub use_gpu: bool,
+    /// Composite the cursor into each frame
+    pub include_cursor: bool,
+    /// Areas obscured in every frame before encoding
+    pub redactions: Vec<Redaction>,
+    /// Skip frames that haven't changed since the last encoded one
+    pub frame_diff: Option<FrameDiffConfig>,
+    /// Encode only the changed regions of each frame (requires `frame_diff`);
+    /// frames are never dropped, as later regions patch the ones before them
+    pub encode_dirty_regions: bool,
+    /// Display to capture
+    pub display: DisplaySelector,
+    /// Reaction to display changes reported by a watcher (see [`StreamingPipeline::watch_displays`])
+    pub on_display_change: DisplayChangeAction,
 }
 
 impl Default for StreamingConfig {
@@ -74,6 +145,12 @@ impl Default for StreamingConfig {
             webp_config: WebPConfig::fast(),
             use_zero_copy: true,
             use_gpu: false,
+            include_cursor: false,
+            redactions: Vec::new(),
+            frame_diff: None,
+            encode_dirty_regions: false,
+            display: DisplaySelector::Index(0),
+            on_display_change: DisplayChangeAction::default(),
         }
     }
 }
@@ -91,6 +168,22 @@ pub struct StreamingStats {
     pub current_bitrate: u64,
     pub avg_capture_time: Duration,
     pub avg_encode_time: Duration,
+    /// Frames not encoded because nothing changed
+    pub frames_unchanged: u64,
+    /// Pixels passed to the encoder
+    pub pixels_encoded: u64,
+    /// Pixels left out by frame differencing
+    pub pixels_skipped: u64,
+}
+
+impl StreamingStats {
+    /// Fraction of captured pixels frame differencing kept from the encoder
+    pub fn diff_savings(&self) -> f64 {
+        match self.pixels_encoded + self.pixels_skipped {
+            0 => 0.0,
+            total => self.pixels_skipped as f64 / total as f64,
+        }
+    }
 }
 
 /// Ultra streaming pipeline for high-performance capture
@@ -104,6 +197,12 @@ pub struct StreamingPipeline {
     zero_copy: Arc<ZeroCopyOptimizer>,
     #[allow(dead_code)]
     simd_converter: Arc<SimdConverter>,
+    frame_diff: Option<Arc<Mutex<FrameDiff>>>,
+    recorder: Mutex<Option<thread::JoinHandle<EncodingResult<AnimatedWebPWriter>>>>,
+    /// Display the capture threads read from, `None` while paused
+    target: Arc<Mutex<Option<CaptureTarget>>>,
+    selector: Arc<Mutex<DisplaySelector>>,
+    display_events: Arc<Mutex<Option<Receiver<DisplayEvent>>>>,
 }
 
 impl StreamingPipeline {
@@ -113,6 +212,8 @@ impl StreamingPipeline {
         config: StreamingConfig,
     ) -> Self {
         Self {
+            frame_diff: config.frame_diff.map(|diff| Arc::new(Mutex::new(FrameDiff::new(diff)))),
+            selector: Arc::new(Mutex::new(config.display.clone())),
             config,
             capturer: Arc::new(capturer),
             running: Arc::new(AtomicBool::new(false)),
@@ -121,13 +222,31 @@ impl StreamingPipeline {
             memory_pool: MemoryPool::new(),
             zero_copy: Arc::new(ZeroCopyOptimizer::new()),
             simd_converter: Arc::new(SimdConverter::new()),
+            recorder: Mutex::new(None),
+            target: Arc::new(Mutex::new(None)),
+            display_events: Arc::new(Mutex::new(None)),
         }
     }
 
     /// Start the streaming pipeline
-    pub fn start<F>(&self, callback: F) -> CaptureResult<()>
+    ///
+    /// With [`encode_dirty_regions`](StreamingConfig::encode_dirty_regions) the
+    /// callback receives partial images; use [`start_updates`](Self::start_updates)
+    /// to learn where they belong.
+    pub fn start<F>(&self, mut callback: F) -> CaptureResult<()>
     where
         F: FnMut(Vec<u8>) + Send + 'static,
+    {
+        self.start_updates(move |update| callback(update.data))
+    }
+
+    /// Start the streaming pipeline, delivering each encoded image with its position
+    ///
+    /// Updates may arrive out of order when several encoding threads are used;
+    /// order them by [`frame_id`](FrameUpdate::frame_id).
+    pub fn start_updates<F>(&self, callback: F) -> CaptureResult<()>
+    where
+        F: FnMut(FrameUpdate) + Send + 'static,
     {
         if self.running.load(Ordering::Relaxed) {
             return Err(CaptureError::CaptureFailed(
@@ -135,11 +254,13 @@ impl StreamingPipeline {
             ));
         }
 
+        self.validate_redactions()?;
+        self.resolve_target()?;
         self.running.store(true, Ordering::Relaxed);
 
         // Create channels for frame passing
         let (capture_tx, capture_rx) = bounded::<Frame>(self.config.buffer_size);
-        let (encode_tx, encode_rx) = bounded::<Vec<u8>>(self.config.buffer_size);
+        let (encode_tx, encode_rx) = bounded::<FrameUpdate>(self.config.buffer_size);
 
         // Start capture thread(s)
         self.start_capture_threads(capture_tx);
@@ -152,10 +273,49 @@ impl StreamingPipeline {
 
         // Start statistics thread
         self.start_stats_thread();
+        self.start_display_thread();
 
         Ok(())
     }
 
+    /// Start recording captured frames into an animated WebP
+    ///
+    /// Frames are added to `writer` in capture order, timed by their capture
+    /// timestamps. Call [`finish_animation`](Self::finish_animation) to stop the
+    /// pipeline and obtain the encoded file.
+    pub fn start_animation(&self, writer: AnimatedWebPWriter) -> CaptureResult<()> {
+        if self.running.load(Ordering::Relaxed) {
+            return Err(CaptureError::CaptureFailed(
+                "Pipeline already running".to_string(),
+            ));
+        }
+
+        self.validate_redactions()?;
+        self.resolve_target()?;
+        self.running.store(true, Ordering::Relaxed);
+
+        let (capture_tx, capture_rx) = bounded::<Frame>(self.config.buffer_size);
+        self.start_capture_threads(capture_tx);
+        *self.recorder.lock() = Some(self.start_recorder_thread(capture_rx, writer));
+        self.start_stats_thread();
+        self.start_display_thread();
+
+        Ok(())
+    }
+
+    /// Stop an animation recording and return the animated WebP data
+    pub fn finish_animation(&self) -> EncodingResult<Vec<u8>> {
+        let recorder = self.recorder.lock().take().ok_or_else(|| {
+            EncodingError::InvalidConfiguration("No animation recording in progress".to_string())
+        })?;
+
+        self.stop();
+        let writer = recorder.join().map_err(|_| {
+            EncodingError::EncodingFailed("Animation recorder thread panicked".to_string())
+        })??;
+        writer.finish()
+    }
+
     /// Stop the streaming pipeline
     pub fn stop(&self) {
         self.running.store(false, Ordering::Relaxed);
@@ -171,8 +331,137 @@ impl StreamingPipeline {
         self.stats.lock().clone()
     }
 
+    /// React to the changes `watcher` reports, as configured by
+    /// [`on_display_change`](StreamingConfig::on_display_change)
+    ///
+    /// Replaces a watcher attached earlier, and takes effect immediately if the
+    /// pipeline is already running.
+    pub fn watch_displays(&self, watcher: &DisplayWatcher) {
+        *self.display_events.lock() = Some(watcher.subscribe());
+    }
+
+    /// Whether capture is paused after a display change
+    pub fn is_paused(&self) -> bool {
+        self.is_running() && self.target.lock().is_none()
+    }
+
+    /// Display currently being captured
+    pub fn current_display(&self) -> Option<DisplayInfo> {
+        self.target.lock().as_ref().and_then(|target| target.display.clone())
+    }
+
+    /// Capture another display from the next frame on
+    ///
+    /// Also resumes a paused pipeline. Fails without changing anything if no
+    /// display matches `selector`.
+    pub fn retarget(&self, selector: DisplaySelector) -> CaptureResult<()> {
+        let displays = self.capturer.get_displays()?;
+        let index = selector.position(&displays).ok_or_else(|| selector.not_found())?;
+        *self.selector.lock() = selector;
+        self.set_target(Some(CaptureTarget::new(index, &displays)));
+        Ok(())
+    }
+
+    /// Resume capturing the selected display after a pause
+    pub fn resume(&self) -> CaptureResult<()> {
+        let selector = self.selector.lock().clone();
+        self.retarget(selector)
+    }
+
+    /// Reject redaction modes that would leave their areas readable
+    fn validate_redactions(&self) -> CaptureResult<()> {
+        self.config
+            .redactions
+            .iter()
+            .try_for_each(|redaction| redaction.mode.validate())
+            .map_err(CaptureError::InvalidConfiguration)
+    }
+
+    /// Resolve the selected display before starting
+    fn resolve_target(&self) -> CaptureResult<()> {
+        let selector = self.selector.lock().clone();
+        let target = match self.capturer.get_displays() {
+            Ok(displays) => {
+                let index = selector.position(&displays).ok_or_else(|| selector.not_found())?;
+                CaptureTarget::new(index, &displays)
+            }
+            // An index can still be captured without enumeration, just not placed
+            Err(e) => match selector {
+                DisplaySelector::Index(index) => CaptureTarget { index, display: None },
+                _ => return Err(e),
+            },
+        };
+        *self.target.lock() = Some(target);
+        Ok(())
+    }
+
+    /// Switch the capture threads to `target`
+    fn set_target(&self, target: Option<CaptureTarget>) {
+        Self::apply_target(&self.target, self.frame_diff.as_ref(), target);
+    }
+
+    /// Store `target`, restarting frame differencing if it changed
+    fn apply_target(
+        current: &Mutex<Option<CaptureTarget>>,
+        frame_diff: Option<&Arc<Mutex<FrameDiff>>>,
+        target: Option<CaptureTarget>,
+    ) {
+        let mut current = current.lock();
+        if *current == target {
+            return;
+        }
+        match &target {
+            Some(target) => log::info!(
+                "Capturing display {}",
+                target.display.as_ref().map_or_else(|| target.index.to_string(), |display| display.id.to_string())
+            ),
+            None => log::info!("Capture paused after a display change"),
+        }
+        *current = target;
+        // The next frame may have another size and content; encode it whole
+        if let Some(diff) = frame_diff {
+            diff.lock().reset();
+        }
+    }
+
+    /// Target after the display changes in `events`, given the new layout
+    fn next_target(
+        current: Option<&CaptureTarget>,
+        selector: &DisplaySelector,
+        action: DisplayChangeAction,
+        events: &[DisplayEvent],
+        displays: &[DisplayInfo],
+    ) -> Option<CaptureTarget> {
+        match action {
+            DisplayChangeAction::Retarget => selector
+                .position(displays)
+                .or_else(|| DisplaySelector::Primary.position(displays))
+                .map(|index| CaptureTarget::new(index, displays)),
+            DisplayChangeAction::Pause => {
+                let current = current?;
+                let Some(id) = current.display.as_ref().map(|display| &display.id) else {
+                    return Some(current.clone());
+                };
+                let affected = events.iter().any(|event| match event {
+                    DisplayEvent::Removed(display) | DisplayEvent::ModeChanged { old: display, .. } => display.id == *id,
+                    _ => false,
+                });
+                if affected {
+                    return None;
+                }
+                // Other displays coming and going shift the index
+                displays
+                    .iter()
+                    .position(|display| display.id == *id)
+                    .map(|index| CaptureTarget::new(index, displays))
+            }
+        }
+    }
+
     /// Start capture threads
     fn start_capture_threads(&self, tx: Sender<Frame>) {
+        // Backends that can't report the cursor draw it in themselves
+        self.capturer.set_cursor_overlay(self.config.include_cursor);
         for _thread_id in 0..self.config.capture_threads {
             let capturer = Arc::clone(&self.capturer);
             let running = Arc::clone(&self.running);
@@ -181,7 +470,14 @@ impl StreamingPipeline {
             let zero_copy = Arc::clone(&self.zero_copy);
             let tx = tx.clone();
             let target_fps = self.config.target_fps;
-            let use_zero_copy = self.config.use_zero_copy;
+            let use_zero_copy =
+                self.config.use_zero_copy && capturer.capabilities().supports_zero_copy;
+            let include_cursor = self.config.include_cursor;
+            let redactions = self.config.redactions.clone();
+            let frame_diff = self.frame_diff.clone();
+            let encode_dirty_regions = self.config.encode_dirty_regions;
+            let stats = Arc::clone(&self.stats);
+            let current_target = Arc::clone(&self.target);
 
             thread::spawn(move || {
                 let frame_duration = Duration::from_micros(1_000_000 / target_fps as u64);
@@ -189,27 +485,78 @@ impl StreamingPipeline {
 
                 while running.load(Ordering::Relaxed) {
                     let capture_start = Instant::now();
+                    let target = current_target.lock().clone();
+                    let captured_target = target.clone();
+                    let display_bounds = target
+                        .as_ref()
+                        .and_then(|target| target.display.as_ref())
+                        .map(|display| display.bounds());
+
+                    // Capture frame, unless paused
+                    let image = target.map(|target| {
+                        if use_zero_copy {
+                            zero_copy.capture_zero_copy(&**capturer, target.index)
+                        } else {
+                            capturer.capture_display(target.index)
+                        }
+                    });
 
-                    // Capture frame
-                    let image = if use_zero_copy {
-                        zero_copy.capture_zero_copy(&**capturer, 0)
-                    } else {
-                        capturer.capture_display(0)
-                    };
+                    // Without the display position redactions can't be placed, so frames are dropped
+                    let image = image
+                        .and_then(|image| image.ok())
+                        .filter(|_| redactions.is_empty() || display_bounds.is_some());
+
+                    if let Some(mut image) = image {
+                        if let (true, Some(bounds)) = (include_cursor, display_bounds) {
+                            if let Ok(Some(cursor)) = capturer.capture_cursor() {
+                                composite_cursor(&mut image, &cursor, bounds);
+                            }
+                        }
+
+                        if let (false, Some(bounds)) = (redactions.is_empty(), display_bounds) {
+                            redact(&mut image, &redactions, bounds, (bounds.x, bounds.y));
+                        }
 
-                    if let Ok(image) = image {
                         let capture_duration = capture_start.elapsed();
+
+                        // A retarget resets the differ under the target lock, so holding
+                        // it keeps a capture of the old display from being diffed after
+                        // the reset; the new display's first frame is then a full one
+                        let target_guard = current_target.lock();
+                        if *target_guard != captured_target {
+                            continue;
+                        }
+                        // Frame ids follow the order frames were diffed in
+                        let mut diff = frame_diff.as_ref().map(|diff| diff.lock());
                         let frame_id = frame_counter.fetch_add(1, Ordering::Relaxed);
+                        let changes = diff.as_mut().and_then(|diff| diff.update(&image).ok());
+                        drop(diff);
+                        drop(target_guard);
+
+                        let mut regions = None;
+                        let mut unchanged = false;
+                        if let Some(changes) = changes {
+                            let mut stats = stats.lock();
+                            if changes.is_unchanged() {
+                                unchanged = true;
+                                stats.frames_unchanged += 1;
+                                stats.pixels_skipped += changes.total_pixels;
+                            } else if encode_dirty_regions {
+                                stats.pixels_skipped += changes.total_pixels - changes.dirty_pixels();
+                                regions = Some(changes.dirty_rects);
+                            }
+                        }
 
                         let frame = Frame {
                             id: frame_id,
                             image,
                             timestamp: Instant::now(),
                             capture_duration,
+                            regions,
                         };
 
                         // Send frame to encoding pipeline
-                        if tx.send(frame).is_err() {
+                        if !unchanged && tx.send(frame).is_err() {
                             // Channel full or closed
                             break;
                         }
@@ -227,7 +574,8 @@ impl StreamingPipeline {
     }
 
     /// Start encoding threads
-    fn start_encoding_threads(&self, rx: Receiver<Frame>, tx: Sender<Vec<u8>>) {
+    fn start_encoding_threads(&self, rx: Receiver<Frame>, tx: Sender<FrameUpdate>) {
+        let needs_full_frame = Arc::new(AtomicBool::new(false));
         for _thread_id in 0..self.config.encoding_threads {
             let rx = rx.clone();
             let tx = tx.clone();
@@ -235,10 +583,22 @@ impl StreamingPipeline {
             let stats = Arc::clone(&self.stats);
             let webp_config = self.config.webp_config.clone();
             let adaptive_quality = self.config.adaptive_quality;
-            let allow_frame_drop = self.config.allow_frame_drop;
+            // Dirty regions patch the frames before them, so none may be lost
+            let allow_frame_drop = self.config.allow_frame_drop && !self.config.encode_dirty_regions;
+            let frame_diff = self.frame_diff.clone();
+            let needs_full_frame = Arc::clone(&needs_full_frame);
 
             thread::spawn(move || {
                 let mut encoder = WebPEncoder::new();
+                // A lost frame leaves the receiver's picture behind the diff reference:
+                // the next frame goes out whole, and the next capture is diffed afresh
+                // in case nothing is queued
+                let resync = || {
+                    needs_full_frame.store(true, Ordering::Relaxed);
+                    if let Some(diff) = &frame_diff {
+                        diff.lock().reset();
+                    }
+                };
                 let mut current_config = webp_config;
 
                 while running.load(Ordering::Relaxed) {
@@ -253,8 +613,8 @@ impl StreamingPipeline {
                     // Check if frame should be dropped
                     if allow_frame_drop && rx.len() > 10 {
                         // Skip encoding if buffer is backing up
-                        let mut stats = stats.lock();
-                        stats.frames_dropped += 1;
+                        stats.lock().frames_dropped += 1;
+                        resync();
                         continue;
                     }
 
@@ -268,25 +628,30 @@ impl StreamingPipeline {
                     }
 
                     // Encode frame
-                    match encoder.encode(&frame.image, &current_config) {
-                        Ok(webp_data) => {
+                    let whole = needs_full_frame.swap(false, Ordering::Relaxed);
+                    match Self::encode_frame(&mut encoder, &frame, whole, &current_config) {
+                        Ok(updates) => {
                             let encode_duration = encode_start.elapsed();
 
                             // Update stats
                             {
                                 let mut stats = stats.lock();
                                 stats.frames_encoded += 1;
-                                stats.bytes_encoded += webp_data.len() as u64;
+                                for update in &updates {
+                                    stats.bytes_encoded += update.data.len() as u64;
+                                    stats.pixels_encoded += update.region.area() as u64;
+                                }
                                 stats.total_encode_time += encode_duration;
                             }
 
                             // Send encoded frame
-                            if tx.send(webp_data).is_err() {
+                            if updates.into_iter().any(|update| tx.send(update).is_err()) {
                                 break;
                             }
                         }
                         Err(e) => {
                             eprintln!("Encoding error: {}", e);
+                            resync();
                         }
                     }
                 }
@@ -294,17 +659,98 @@ impl StreamingPipeline {
         }
     }
 
+    /// Encode the frame's dirty regions, or all of it if `whole` or it has none
+    fn encode_frame(
+        encoder: &mut WebPEncoder,
+        frame: &Frame,
+        whole: bool,
+        config: &WebPConfig,
+    ) -> EncodingResult<Vec<FrameUpdate>> {
+        let (frame_width, frame_height) = (frame.image.width, frame.image.height);
+        let update = |region, data| FrameUpdate {
+            frame_id: frame.id,
+            region,
+            frame_width,
+            frame_height,
+            data,
+        };
+
+        match frame.regions.as_ref().filter(|_| !whole) {
+            None => {
+                let data = encoder.encode(&frame.image, config)?;
+                Ok(vec![update(Rectangle::new(0, 0, frame_width, frame_height), data)])
+            }
+            Some(regions) => regions
+                .iter()
+                .map(|&region| {
+                    let image = frame.image.crop(region).ok_or_else(|| {
+                        EncodingError::InvalidConfiguration(format!("Region {:?} is outside the frame", region))
+                    })?;
+                    Ok(update(region, encoder.encode(&image, config)?))
+                })
+                .collect(),
+        }
+    }
+
+    /// Start the thread feeding captured frames into an animation writer
+    fn start_recorder_thread(
+        &self,
+        rx: Receiver<Frame>,
+        mut writer: AnimatedWebPWriter,
+    ) -> thread::JoinHandle<EncodingResult<AnimatedWebPWriter>> {
+        let running = Arc::clone(&self.running);
+        let stats = Arc::clone(&self.stats);
+
+        thread::spawn(move || {
+            let mut first_timestamp = None;
+            let mut last_timestamp = Duration::ZERO;
+
+            loop {
+                let frame = match rx.recv_timeout(Duration::from_millis(100)) {
+                    Ok(frame) => frame,
+                    Err(crossbeam_channel::RecvTimeoutError::Timeout) => {
+                        if running.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        break;
+                    }
+                    // Capture threads have exited and the channel is drained
+                    Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
+                };
+
+                let first = *first_timestamp.get_or_insert(frame.timestamp);
+                let timestamp = frame.timestamp.saturating_duration_since(first);
+
+                // Multiple capture threads may deliver frames slightly out of order
+                if timestamp < last_timestamp {
+                    stats.lock().frames_dropped += 1;
+                    continue;
+                }
+
+                let encode_start = Instant::now();
+                writer.add_frame(&frame.image, timestamp)?;
+                last_timestamp = timestamp;
+
+                let mut stats = stats.lock();
+                stats.frames_encoded += 1;
+                stats.total_encode_time += encode_start.elapsed();
+            }
+
+            Ok(writer)
+        })
+    }
+
     /// Start output thread
-    fn start_output_thread<F>(&self, rx: Receiver<Vec<u8>>, mut callback: F)
+    fn start_output_thread<F>(&self, rx: Receiver<FrameUpdate>, mut callback: F)
     where
-        F: FnMut(Vec<u8>) + Send + 'static,
+        F: FnMut(FrameUpdate) + Send + 'static,
     {
         let running = Arc::clone(&self.running);
 
         thread::spawn(move || {
             while running.load(Ordering::Relaxed) {
                 match rx.recv_timeout(Duration::from_millis(100)) {
-                    Ok(data) => callback(data),
+                    Ok(update) => callback(update),
                     Err(_) => continue,
                 }
             }
@@ -357,6 +803,48 @@ impl StreamingPipeline {
         });
     }
 
+    /// Start the thread applying display changes from the watched watcher
+    fn start_display_thread(&self) {
+        let running = Arc::clone(&self.running);
+        let capturer = Arc::clone(&self.capturer);
+        let display_events = Arc::clone(&self.display_events);
+        let target = Arc::clone(&self.target);
+        let selector = Arc::clone(&self.selector);
+        let frame_diff = self.frame_diff.clone();
+        let action = self.config.on_display_change;
+
+        thread::spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                let Some(rx) = display_events.lock().clone() else {
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                };
+                let mut events = match rx.recv_timeout(Duration::from_millis(100)) {
+                    Ok(event) => vec![event],
+                    Err(crossbeam_channel::RecvTimeoutError::Timeout) => continue,
+                    Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
+                        // The watcher was stopped
+                        display_events.lock().take();
+                        continue;
+                    }
+                };
+                // One change usually arrives as several events
+                events.extend(rx.try_iter());
+
+                let displays = match capturer.get_displays() {
+                    Ok(displays) => displays,
+                    Err(e) => {
+                        log::debug!("Display enumeration failed after a display change: {}", e);
+                        continue;
+                    }
+                };
+                let current = target.lock().clone();
+                let next = Self::next_target(current.as_ref(), &selector.lock(), action, &events, &displays);
+                Self::apply_target(&target, frame_diff.as_ref(), next);
+            }
+        });
+    }
+
     /// Adjust quality based on performance
     fn adjust_quality(
         mut config: WebPConfig,
@@ -420,7 +908,9 @@ impl StreamingPipelineBuilder {
         self
     }
 
-    /// Enable frame dropping
+    /// Enable frame dropping when encoding falls behind
+    ///
+    /// Has no effect with [`encode_dirty_regions`](Self::encode_dirty_regions).
     pub fn allow_frame_drop(mut self, enabled: bool) -> Self {
         self.config.allow_frame_drop = enabled;
         self
@@ -444,6 +934,49 @@ impl StreamingPipelineBuilder {
         self
     }
 
+    /// Composite the cursor into captured frames
+    pub fn include_cursor(mut self, enabled: bool) -> Self {
+        self.config.include_cursor = enabled;
+        self
+    }
+
+    /// Obscure these areas in every frame
+    pub fn redactions(mut self, redactions: Vec<Redaction>) -> Self {
+        self.config.redactions = redactions;
+        self
+    }
+
+    /// Skip frames that haven't changed, using these differencing settings
+    pub fn frame_diff(mut self, config: FrameDiffConfig) -> Self {
+        self.config.frame_diff = Some(config);
+        self
+    }
+
+    /// Encode only the changed regions of each frame
+    ///
+    /// Enables frame differencing with default settings if it isn't configured.
+    /// Frames are no longer dropped, and a frame that fails to encode is followed
+    /// by a full one, so receivers never patch a picture they didn't get.
+    pub fn encode_dirty_regions(mut self, enabled: bool) -> Self {
+        self.config.encode_dirty_regions = enabled;
+        if enabled && self.config.frame_diff.is_none() {
+            self.config.frame_diff = Some(FrameDiffConfig::default());
+        }
+        self
+    }
+
+    /// Capture this display
+    pub fn display(mut self, selector: impl Into<DisplaySelector>) -> Self {
+        self.config.display = selector.into();
+        self
+    }
+
+    /// React to display changes this way once a watcher is attached
+    pub fn on_display_change(mut self, action: DisplayChangeAction) -> Self {
+        self.config.on_display_change = action;
+        self
+    }
+
     /// Build the pipeline
     pub fn build(self, capturer: Box<dyn ScreenCapture>) -> StreamingPipeline {
         StreamingPipeline::new(capturer, self.config)
@@ -468,7 +1001,7 @@ mod tests {
             .capture_threads(2)
             .encoding_threads(4)
             .adaptive_quality(true)
-            .build(crate::capture::Capturer::new().unwrap());
+            .build(crate::capture::Capturer::mock());
 
         assert_eq!(config.config.target_fps, 60);
         assert_eq!(config.config.buffer_size, 120);
@@ -498,4 +1031,380 @@ mod tests {
         );
         assert!(adjusted.quality > config.quality);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_pipeline_streams_mock_frames() {
+        use crate::capture::mock::{MockCapture, MockPattern};
+        use crate::types::DisplayInfo;
+
+        let capturer = MockCapture::new()
+            .with_displays(vec![DisplayInfo {
+                width: 64,
+                height: 48,
+                ..Default::default()
+            }])
+            .with_pattern(MockPattern::MovingRectangle { size: 8, velocity: (4, 2) });
+
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(60)
+            .encoding_threads(1)
+            .build(Box::new(capturer));
+
+        let received = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&received);
+        pipeline
+            .start(move |data| {
+                assert_eq!(&data[0..4], b"RIFF");
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(400));
+        pipeline.stop();
+
+        assert!(received.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn test_pipeline_applies_redactions() {
+        use crate::capture::mock::{MockCapture, MockPattern};
+        use crate::encoder::WebPDecoder;
+        use crate::types::{DisplayInfo, Rectangle, RedactionMode};
+
+        let capturer = MockCapture::new()
+            .with_displays(vec![DisplayInfo {
+                x: 100,
+                width: 64,
+                height: 48,
+                ..Default::default()
+            }])
+            .with_pattern(MockPattern::Text);
+
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(60)
+            .encoding_threads(1)
+            .webp_config(WebPConfig { lossless: true, ..WebPConfig::default() })
+            .redactions(vec![Redaction::new(Rectangle::new(0, 0, 32, 48), RedactionMode::Fill([255, 0, 0, 255]))])
+            .build(Box::new(capturer));
+
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&frames);
+        pipeline.start(move |data| sink.lock().push(data)).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        pipeline.stop();
+
+        let frames = frames.lock();
+        assert!(!frames.is_empty());
+        for data in frames.iter() {
+            let frame = WebPDecoder::new().decode(data).unwrap();
+            assert!((0..48).all(|y| frame.get_pixel(31, y).unwrap() == [255, 0, 0, 255]));
+            assert_ne!(frame.get_pixel(32, 0).unwrap(), [255, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_skips_unchanged_frames() {
+        use crate::capture::mock::{MockCapture, MockPattern};
+        use crate::types::DisplayInfo;
+
+        let capturer = MockCapture::new()
+            .with_displays(vec![DisplayInfo {
+                width: 64,
+                height: 48,
+                ..Default::default()
+            }])
+            .with_pattern(MockPattern::Solid([10, 20, 30, 255]));
+
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(60)
+            .encoding_threads(1)
+            .frame_diff(FrameDiffConfig::default())
+            .build(Box::new(capturer));
+
+        let received = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&received);
+        pipeline
+            .start(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+        thread::sleep(Duration::from_millis(300));
+        pipeline.stop();
+
+        let stats = pipeline.stats();
+        assert_eq!(received.load(Ordering::Relaxed), 1);
+        assert!(stats.frames_unchanged > 0);
+        assert_eq!(stats.pixels_encoded, 64 * 48);
+        assert!(stats.diff_savings() > 0.5);
+    }
+
+    #[test]
+    fn test_pipeline_encodes_dirty_regions() {
+        use crate::capture::mock::{MockCapture, MockPattern};
+        use crate::encoder::WebPDecoder;
+        use crate::types::DisplayInfo;
+
+        let capturer = MockCapture::new()
+            .with_displays(vec![DisplayInfo {
+                width: 64,
+                height: 48,
+                ..Default::default()
+            }])
+            .with_pattern(MockPattern::MovingRectangle { size: 4, velocity: (1, 0) });
+
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(60)
+            .encoding_threads(1)
+            .allow_frame_drop(false)
+            .frame_diff(FrameDiffConfig { tile_size: 8, pixel_threshold: 0 })
+            .encode_dirty_regions(true)
+            .build(Box::new(capturer));
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&updates);
+        pipeline.start_updates(move |update| sink.lock().push(update)).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        pipeline.stop();
+
+        let updates = updates.lock();
+        assert!(updates.len() > 1);
+        assert!(updates[0].is_full_frame());
+        assert!(updates[1..].iter().any(|update| !update.is_full_frame()));
+        for update in updates.iter() {
+            let image = WebPDecoder::new().decode(&update.data).unwrap();
+            assert_eq!((image.width, image.height), (update.region.width, update.region.height));
+        }
+        assert!(pipeline.stats().pixels_skipped > 0);
+    }
+
+    #[test]
+    fn test_dirty_regions_reconstruct_with_backed_up_queue() {
+        use crate::capture::mock::{MockCapture, MockPattern};
+        use crate::encoder::WebPDecoder;
+        use crate::types::{CaptureRegion, DisplayInfo};
+
+        let capturer = Arc::new(
+            MockCapture::new()
+                .with_displays(vec![DisplayInfo {
+                    width: 128,
+                    height: 96,
+                    ..Default::default()
+                }])
+                .with_pattern(MockPattern::MovingRectangle { size: 8, velocity: (3, 2) }),
+        );
+
+        // Lossless encodes fall far behind 1000 fps capture
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(1000)
+            .buffer_size(64)
+            .encoding_threads(1)
+            .adaptive_quality(false)
+            .allow_frame_drop(true)
+            .webp_config(WebPConfig::lossless())
+            .frame_diff(FrameDiffConfig { tile_size: 8, pixel_threshold: 0 })
+            .encode_dirty_regions(true)
+            .build(Box::new(Arc::clone(&capturer)));
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&updates);
+        pipeline.start_updates(move |update| sink.lock().push(update)).unwrap();
+        assert!(wait_until(|| updates.lock().len() > 30));
+        pipeline.stop();
+
+        let mut updates = updates.lock().clone();
+        updates.sort_by_key(|update| update.frame_id);
+        assert!(updates[0].is_full_frame());
+        assert_eq!(pipeline.stats().frames_dropped, 0);
+
+        // Patch every update onto the receiver's picture and check it after each frame
+        let mut canvas = RawImage::new(vec![0; 128 * 96 * 4], 128, 96, crate::types::PixelFormat::RGBA8);
+        for (index, update) in updates.iter().enumerate() {
+            let patch = WebPDecoder::new().decode(&update.data).unwrap();
+            for (y, row) in patch.rows().enumerate() {
+                let start = ((update.region.y as usize + y) * 128 + update.region.x as usize) * 4;
+                canvas.data[start..start + row.len()].copy_from_slice(row);
+            }
+            if updates.get(index + 1).is_none_or(|next| next.frame_id != update.frame_id) {
+                let expected = capturer.render(CaptureRegion::new(0, 0, 128, 96), update.frame_id);
+                assert_eq!(canvas.data, expected.data, "frame {}", update.frame_id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pipeline_records_animation() {
+        use crate::capture::mock::{MockCapture, MockPattern};
+        use crate::encoder::AnimationOptions;
+        use crate::types::DisplayInfo;
+
+        let capturer = MockCapture::new()
+            .with_displays(vec![DisplayInfo {
+                width: 64,
+                height: 48,
+                ..Default::default()
+            }])
+            .with_pattern(MockPattern::MovingRectangle { size: 8, velocity: (4, 2) });
+
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(30)
+            .build(Box::new(capturer));
+
+        assert!(pipeline.finish_animation().is_err());
+
+        let writer = AnimatedWebPWriter::new(AnimationOptions {
+            changed_regions_only: true,
+            ..Default::default()
+        });
+        pipeline.start_animation(writer).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let data = pipeline.finish_animation().unwrap();
+
+        let decoded = webp::AnimDecoder::new(&data).decode().unwrap();
+        assert!(decoded.len() > 1);
+        let frame = decoded.get_frame(0).unwrap();
+        assert_eq!((frame.width(), frame.height()), (64, 48));
+    }
+
+    /// Poll `condition` until it holds, giving up after a deadline generous enough for loaded runners
+    fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    fn hotplug_display(id: &str, x: i32, width: u32, is_primary: bool) -> crate::types::DisplayInfo {
+        crate::types::DisplayInfo {
+            id: crate::types::DisplayId(id.to_string()),
+            x,
+            width,
+            height: 24,
+            is_primary,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pipeline_retargets_on_display_change() {
+        use crate::capture::mock::{MockCapture, MockPattern};
+        use crate::types::DisplayId;
+
+        let mock = Arc::new(
+            MockCapture::new()
+                .with_displays(vec![hotplug_display("a", 0, 64, true), hotplug_display("b", 64, 32, false)])
+                .with_pattern(MockPattern::Solid([10, 20, 30, 255])),
+        );
+        let watcher = DisplayWatcher::polling(Box::new(Arc::clone(&mock)), Duration::from_millis(20)).unwrap();
+
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(60)
+            .encoding_threads(1)
+            .display(DisplayId("b".to_string()))
+            .build(Box::new(Arc::clone(&mock)));
+        pipeline.watch_displays(&watcher);
+
+        let widths = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&widths);
+        pipeline.start_updates(move |update| sink.lock().push(update.frame_width)).unwrap();
+        assert!(wait_until(|| !widths.lock().is_empty()));
+        assert_eq!(pipeline.current_display().unwrap().id.as_str(), "b");
+
+        // "b" unplugged: fall back to the primary display
+        mock.set_displays(vec![hotplug_display("a", 0, 64, true)]);
+        assert!(wait_until(|| widths.lock().last() == Some(&64)));
+        assert_eq!(pipeline.current_display().unwrap().id.as_str(), "a");
+
+        // "b" plugged back in, now listed first: follow it to its new index
+        mock.set_displays(vec![hotplug_display("b", 64, 32, false), hotplug_display("a", 0, 64, true)]);
+        assert!(wait_until(|| widths.lock().last() == Some(&32)));
+        pipeline.stop();
+
+        let current = pipeline.current_display().unwrap();
+        assert_eq!((current.id.as_str(), current.index), ("b", 0));
+        let widths = widths.lock();
+        assert_eq!(widths.first(), Some(&32));
+        assert!(widths.contains(&64));
+        assert_eq!(widths.last(), Some(&32));
+    }
+
+    #[test]
+    fn test_retarget_starts_with_full_frame() {
+        use crate::capture::mock::{MockCapture, MockPattern};
+        use crate::types::DisplayId;
+
+        // Same size and content, so only a restarted diff sends anything for "a"
+        let mock = Arc::new(
+            MockCapture::new()
+                .with_displays(vec![hotplug_display("a", 0, 32, true), hotplug_display("b", 32, 32, false)])
+                .with_pattern(MockPattern::Solid([10, 20, 30, 255])),
+        );
+        let watcher = DisplayWatcher::polling(Box::new(Arc::clone(&mock)), Duration::from_millis(20)).unwrap();
+
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(60)
+            .encoding_threads(1)
+            .frame_diff(FrameDiffConfig { tile_size: 8, pixel_threshold: 0 })
+            .encode_dirty_regions(true)
+            .display(DisplayId("b".to_string()))
+            .build(Box::new(Arc::clone(&mock)));
+        pipeline.watch_displays(&watcher);
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&updates);
+        pipeline.start_updates(move |update| sink.lock().push(update)).unwrap();
+        assert!(wait_until(|| !updates.lock().is_empty()));
+
+        mock.set_displays(vec![hotplug_display("a", 0, 32, true)]);
+        assert!(wait_until(|| updates.lock().len() > 1));
+        pipeline.stop();
+
+        assert_eq!(pipeline.current_display().unwrap().id.as_str(), "a");
+        let updates = updates.lock();
+        assert_eq!(updates.len(), 2);
+        assert!(updates.iter().all(FrameUpdate::is_full_frame));
+    }
+
+    #[test]
+    fn test_pipeline_pauses_on_display_change() {
+        use crate::capture::mock::MockCapture;
+
+        let mock = Arc::new(
+            MockCapture::new().with_displays(vec![hotplug_display("b", 64, 32, false), hotplug_display("a", 0, 64, true)]),
+        );
+        let watcher = DisplayWatcher::polling(Box::new(Arc::clone(&mock)), Duration::from_millis(20)).unwrap();
+
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(60)
+            .encoding_threads(1)
+            .display(DisplaySelector::Primary)
+            .on_display_change(DisplayChangeAction::Pause)
+            .build(Box::new(Arc::clone(&mock)));
+        pipeline.watch_displays(&watcher);
+        pipeline.start(|_| {}).unwrap();
+
+        assert_eq!(pipeline.current_display().unwrap().index, 1);
+
+        // Another display going away leaves capture running, following the shifted index
+        mock.set_displays(vec![hotplug_display("a", 0, 64, true)]);
+        assert!(wait_until(|| pipeline.current_display().is_some_and(|display| display.index == 0)));
+        assert!(!pipeline.is_paused());
+
+        // The captured display changing resolution pauses capture
+        mock.set_displays(vec![hotplug_display("a", 0, 128, true)]);
+        assert!(wait_until(|| pipeline.is_paused()));
+        assert!(pipeline.current_display().is_none());
+        // A capture already underway when the pause landed may still be served
+        let served = mock.frames_served() + 1;
+
+        pipeline.resume().unwrap();
+        assert_eq!(pipeline.current_display().unwrap().width, 128);
+        assert!(pipeline.retarget(DisplaySelector::Name("missing".to_string())).is_err());
+        assert!(!pipeline.is_paused());
+        assert!(wait_until(|| mock.frames_served() > served));
+        pipeline.stop();
+    }
+}
@@ -10,6 +10,7 @@ use crate::{
     capture::ScreenCapture,
     encoder::WebPEncoder,
     error::{CaptureError, CaptureResult, EncodingResult},
+    memory_pool::{self, MemoryPool},
     types::{RawImage, WebPConfig},
 };
 
@@ -51,6 +52,8 @@ impl ZeroCopyStats {
 pub struct ZeroCopyOptimizer {
     stats: Arc<Mutex<ZeroCopyStats>>,
     enabled: bool,
+    /// Pool captured pixels are copied into
+    memory_pool: Arc<MemoryPool>,
     #[cfg(target_os = "windows")]
     windows_optimizer: WindowsZeroCopy,
     #[cfg(target_os = "linux")]
@@ -65,6 +68,7 @@ impl ZeroCopyOptimizer {
         Self {
             stats: Arc::new(Mutex::new(ZeroCopyStats::default())),
             enabled: Self::is_supported(),
+            memory_pool: memory_pool::global_pool(),
             #[cfg(target_os = "windows")]
             windows_optimizer: WindowsZeroCopy::new(),
             #[cfg(target_os = "linux")]
@@ -74,6 +78,12 @@ impl ZeroCopyOptimizer {
         }
     }
 
+    /// Copy captures into buffers from `pool` instead of the global pool
+    pub fn with_memory_pool(mut self, pool: Arc<MemoryPool>) -> Self {
+        self.memory_pool = pool;
+        self
+    }
+
     /// Check if zero-copy is supported on this platform
     pub fn is_supported() -> bool {
         #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
@@ -138,17 +148,17 @@ impl ZeroCopyOptimizer {
     fn platform_capture(&self, display_index: usize) -> CaptureResult<RawImage> {
         #[cfg(target_os = "windows")]
         {
-            self.windows_optimizer.capture(display_index)
+            self.windows_optimizer.capture(display_index, &self.memory_pool)
         }
 
         #[cfg(target_os = "linux")]
         {
-            self.linux_optimizer.capture(display_index)
+            self.linux_optimizer.capture(display_index, &self.memory_pool)
         }
 
         #[cfg(target_os = "macos")]
         {
-            self.macos_optimizer.capture(display_index)
+            self.macos_optimizer.capture(display_index, &self.memory_pool)
         }
 
         #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
@@ -213,11 +223,11 @@ impl WindowsZeroCopy {
         }
     }
 
-    fn capture(&self, display_index: usize) -> CaptureResult<RawImage> {
+    fn capture(&self, display_index: usize, pool: &Arc<MemoryPool>) -> CaptureResult<RawImage> {
         if self.use_dxgi {
             self.capture_dxgi(display_index)
         } else {
-            self.capture_gdi_zero_copy(display_index)
+            self.capture_gdi_zero_copy(display_index, pool)
         }
     }
 
@@ -234,7 +244,7 @@ impl WindowsZeroCopy {
         ))
     }
 
-    fn capture_gdi_zero_copy(&self, _display_index: usize) -> CaptureResult<RawImage> {
+    fn capture_gdi_zero_copy(&self, _display_index: usize, pool: &Arc<MemoryPool>) -> CaptureResult<RawImage> {
         // Use CreateDIBSection for direct memory access
         // This creates a bitmap with direct memory pointer that can be used without copying
 
@@ -320,8 +330,12 @@ impl WindowsZeroCopy {
             // This is zero-copy because we directly use the mapped memory
             let size = (width * height * 4) as usize;
             let data_slice = std::slice::from_raw_parts(bits_ptr as *const u8, size);
-            let mut data = Vec::with_capacity(size);
-            data.extend_from_slice(data_slice);
+            let mut data = pool
+                .acquire(size)
+                .map_err(|_| CaptureError::MemoryAllocationFailed { size })?
+                .into_vec();
+            data.truncate(size);
+            data.copy_from_slice(data_slice);
 
             // Clean up handles but keep the data
             SelectObject(mem_dc, old_bitmap);
@@ -370,11 +384,11 @@ impl LinuxZeroCopy {
         std::path::Path::new("/dev/dri/card0").exists()
     }
 
-    fn capture(&self, display_index: usize) -> CaptureResult<RawImage> {
+    fn capture(&self, display_index: usize, pool: &Arc<MemoryPool>) -> CaptureResult<RawImage> {
         if self.use_drm {
             self.capture_drm(display_index)
         } else if self.use_shm {
-            self.capture_shm(display_index)
+            self.capture_shm(display_index, pool)
         } else {
             Err(CaptureError::CaptureFailed(
                 "No zero-copy method available".to_string(),
@@ -395,7 +409,7 @@ impl LinuxZeroCopy {
         ))
     }
 
-    fn capture_shm(&self, _display_index: usize) -> CaptureResult<RawImage> {
+    fn capture_shm(&self, _display_index: usize, pool: &Arc<MemoryPool>) -> CaptureResult<RawImage> {
         // Use X11 SHM extension for shared memory zero-copy capture
         #[cfg(target_os = "linux")]
         {
@@ -474,7 +488,7 @@ impl LinuxZeroCopy {
                 width as u16,
                 height as u16,
                 !0, // plane_mask (all planes)
-                ImageFormat::Z_PIXMAP,
+                ImageFormat::Z_PIXMAP.into(),
                 seg_id,
                 0,  // offset
             );
@@ -495,8 +509,15 @@ impl LinuxZeroCopy {
                     let data_slice = unsafe {
                         std::slice::from_raw_parts(shm_addr as *const u8, size)
                     };
-                    let mut data = Vec::with_capacity(size);
-                    data.extend_from_slice(data_slice);
+                    let data = pool
+                        .acquire(size)
+                        .map_err(|_| CaptureError::MemoryAllocationFailed { size })
+                        .map(|buffer| {
+                            let mut data = buffer.into_vec();
+                            data.truncate(size);
+                            data.copy_from_slice(data_slice);
+                            data
+                        });
 
                     // Clean up shared memory
                     unsafe {
@@ -504,6 +525,7 @@ impl LinuxZeroCopy {
                         libc::shmctl(shm_id, libc::IPC_RMID, std::ptr::null_mut());
                     }
                     shm::detach(&connection, seg_id).ok();
+                    let mut data = data?;
 
                     // Convert pixel format if needed
                     // Most X11 systems use BGRA, convert to RGBA
@@ -550,9 +572,9 @@ impl MacOSZeroCopy {
         }
     }
 
-    fn capture(&self, display_index: usize) -> CaptureResult<RawImage> {
+    fn capture(&self, display_index: usize, pool: &Arc<MemoryPool>) -> CaptureResult<RawImage> {
         if self.use_iosurface {
-            self.capture_iosurface(display_index)
+            self.capture_iosurface(display_index, pool)
         } else {
             Err(CaptureError::CaptureFailed(
                 "No zero-copy method available".to_string(),
@@ -560,7 +582,7 @@ impl MacOSZeroCopy {
         }
     }
 
-    fn capture_iosurface(&self, _display_index: usize) -> CaptureResult<RawImage> {
+    fn capture_iosurface(&self, _display_index: usize, pool: &Arc<MemoryPool>) -> CaptureResult<RawImage> {
         // Use IOSurface for zero-copy capture on macOS
         #[cfg(target_os = "macos")]
         {
@@ -650,23 +672,25 @@ impl MacOSZeroCopy {
                     ));
                 }
 
-                // For simplicity, use the memory pool approach
-                // A full zero-copy implementation would directly use IOSurface memory
-                use crate::memory_pool::global_pool;
-                let pool = global_pool();
-                let buffer_size = (width * height * 4) as usize;
-                let mut pooled_buffer = pool
-                    .acquire(buffer_size)
-                    .map_err(|_| CaptureError::MemoryAllocationFailed { size: buffer_size })?;
-
                 // Copy image data (in full implementation, this would be zero-copy from IOSurface)
                 extern "C" {
+                    fn CGImageGetBytesPerRow(image: *const std::ffi::c_void) -> usize;
                     fn CGImageGetDataProvider(image: *const std::ffi::c_void) -> *const std::ffi::c_void;
                     fn CGDataProviderCopyData(provider: *const std::ffi::c_void) -> *const std::ffi::c_void;
                     fn CFDataGetBytePtr(data: *const std::ffi::c_void) -> *const u8;
                     fn CFDataGetLength(data: *const std::ffi::c_void) -> isize;
                 }
 
+                // CoreGraphics pads rows for alignment; keep the padding as stride
+                let stride = CGImageGetBytesPerRow(image).max((width * 4) as usize);
+
+                // For simplicity, use the memory pool approach
+                // A full zero-copy implementation would directly use IOSurface memory
+                let buffer_size = stride * height as usize;
+                let mut pooled_buffer = pool
+                    .acquire(buffer_size)
+                    .map_err(|_| CaptureError::MemoryAllocationFailed { size: buffer_size })?;
+
                 let data_provider = CGImageGetDataProvider(image);
                 let data_ref = CGDataProviderCopyData(data_provider);
                 let data_ptr = CFDataGetBytePtr(data_ref);
@@ -682,14 +706,19 @@ impl MacOSZeroCopy {
                 core_foundation::base::CFRelease(iosurface);
 
                 // Convert BGRA to RGBA using SIMD
-                crate::encoder::simd::global_simd_converter().convert_bgra_to_rgba(pooled_buffer.data_mut());
+                crate::encoder::simd::global_simd_converter().convert_bgra_to_rgba_strided(
+                    pooled_buffer.data_mut(),
+                    (width * 4) as usize,
+                    stride,
+                );
 
                 let data = pooled_buffer.into_vec();
-                Ok(RawImage::new(
+                Ok(RawImage::with_stride(
                     data,
                     width,
                     height,
                     crate::types::PixelFormat::RGBA8,
+                    stride,
                 ))
             }
         }
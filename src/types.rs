@@ -1,5 +1,6 @@
 //! Core types and structures for screenshot capture and WebP encoding
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::time::{Duration, SystemTime};
 
@@ -26,6 +27,22 @@ pub struct DisplayInfo {
     pub refresh_rate: u32,
     /// Color depth in bits
     pub color_depth: u8,
+    /// Identifier that stays the same across re-plugging and re-enumeration
+    pub id: DisplayId,
+    /// Manufacturer, as the EDID PNP ID (e.g. `DEL`)
+    pub manufacturer: Option<String>,
+    /// Model name
+    pub model: Option<String>,
+    /// Serial number
+    pub serial: Option<String>,
+    /// Physical width and height in millimeters, oriented like `width` and `height`
+    pub physical_size_mm: Option<(u32, u32)>,
+    /// Counter-clockwise rotation of the picture
+    pub rotation: DisplayRotation,
+    /// Picture is mirrored horizontally
+    pub reflect_x: bool,
+    /// Picture is mirrored vertically
+    pub reflect_y: bool,
 }
 
 impl Default for DisplayInfo {
@@ -41,6 +58,14 @@ impl Default for DisplayInfo {
             is_primary: true,
             refresh_rate: 60,
             color_depth: 32,
+            id: DisplayId::default(),
+            manufacturer: None,
+            model: None,
+            serial: None,
+            physical_size_mm: None,
+            rotation: DisplayRotation::default(),
+            reflect_x: false,
+            reflect_y: false,
         }
     }
 }
@@ -51,6 +76,16 @@ impl DisplayInfo {
         self.width * self.height
     }
 
+    /// Horizontal pixel density in dots per inch, if the physical size is known
+    pub fn dpi(&self) -> Option<f32> {
+        match self.physical_size_mm {
+            Some((width_mm, _)) if width_mm > 0 && self.width > 0 => {
+                Some(self.width as f32 * 25.4 / width_mm as f32)
+            }
+            _ => None,
+        }
+    }
+
     /// Get the display bounds as a rectangle
     pub fn bounds(&self) -> Rectangle {
         Rectangle {
@@ -62,6 +97,108 @@ impl DisplayInfo {
     }
 }
 
+/// Stable display identifier, derived from backend attributes such as the
+/// output name and EDID rather than enumeration order
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct DisplayId(pub String);
+
+impl DisplayId {
+    /// Identifier as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for DisplayId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Way of picking a display out of the current layout
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DisplaySelector {
+    /// Position in enumeration order, which shifts as displays come and go
+    Index(usize),
+    /// Stable identifier
+    Id(DisplayId),
+    /// Display name, such as the RandR or wl_output name (`DP-1`)
+    Name(String),
+    /// The primary display, or the first one if none is marked primary
+    Primary,
+}
+
+impl DisplaySelector {
+    /// Position of the selected display in `displays`
+    pub fn position(&self, displays: &[DisplayInfo]) -> Option<usize> {
+        match self {
+            DisplaySelector::Index(index) => (*index < displays.len()).then_some(*index),
+            DisplaySelector::Id(id) => displays.iter().position(|display| display.id == *id),
+            DisplaySelector::Name(name) => displays.iter().position(|display| display.name == *name),
+            DisplaySelector::Primary => displays
+                .iter()
+                .position(|display| display.is_primary)
+                .or((!displays.is_empty()).then_some(0)),
+        }
+    }
+
+    /// Error for a selector that matches nothing
+    pub fn not_found(&self) -> crate::error::CaptureError {
+        match self {
+            DisplaySelector::Index(index) => crate::error::CaptureError::DisplayNotFound(*index),
+            selector => crate::error::CaptureError::NoMatchingDisplay(selector.to_string()),
+        }
+    }
+}
+
+impl From<DisplayId> for DisplaySelector {
+    fn from(id: DisplayId) -> Self {
+        DisplaySelector::Id(id)
+    }
+}
+
+impl fmt::Display for DisplaySelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplaySelector::Index(index) => write!(f, "index {}", index),
+            DisplaySelector::Id(id) => write!(f, "id {}", id),
+            DisplaySelector::Name(name) => write!(f, "name {}", name),
+            DisplaySelector::Primary => f.write_str("the primary display"),
+        }
+    }
+}
+
+/// Counter-clockwise rotation of a display's picture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DisplayRotation {
+    /// Not rotated
+    #[default]
+    Normal,
+    /// Rotated by 90 degrees
+    Left,
+    /// Rotated by 180 degrees
+    Inverted,
+    /// Rotated by 270 degrees
+    Right,
+}
+
+impl DisplayRotation {
+    /// Rotation in degrees, counter-clockwise
+    pub fn degrees(&self) -> u32 {
+        match self {
+            DisplayRotation::Normal => 0,
+            DisplayRotation::Left => 90,
+            DisplayRotation::Inverted => 180,
+            DisplayRotation::Right => 270,
+        }
+    }
+
+    /// Whether width and height are swapped relative to the panel
+    pub fn is_portrait(&self) -> bool {
+        matches!(self, DisplayRotation::Left | DisplayRotation::Right)
+    }
+}
+
 /// Pixel format for raw image data
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PixelFormat {
@@ -171,13 +308,84 @@ impl RawImage {
 
     /// Get the pixel count
     pub fn pixel_count(&self) -> usize {
-        (self.width * self.height) as usize
+        self.width as usize * self.height as usize
+    }
+
+    /// Bytes of pixel data in one row, excluding padding
+    pub fn row_bytes(&self) -> usize {
+        self.width as usize * self.format.bytes_per_pixel()
+    }
+
+    /// Minimum buffer length for the dimensions and stride (the last row needs no padding)
+    pub fn required_len(&self) -> usize {
+        match self.height as usize {
+            0 => 0,
+            height => self.stride * (height - 1) + self.row_bytes(),
+        }
+    }
+
+    /// Whether rows follow each other without padding
+    pub fn is_packed(&self) -> bool {
+        self.stride == self.row_bytes()
     }
 
     /// Check if the image data is valid
     pub fn is_valid(&self) -> bool {
-        let expected_size = self.stride * (self.height as usize);
-        self.data.len() >= expected_size
+        self.stride >= self.row_bytes() && self.data.len() >= self.required_len()
+    }
+
+    /// Iterate over the rows, excluding padding (the image must be [`valid`](Self::is_valid))
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        let row_bytes = self.row_bytes();
+        (0..self.height as usize).map(move |y| &self.data[y * self.stride..y * self.stride + row_bytes])
+    }
+
+    /// Copy into a tightly packed image (`stride == row_bytes()`)
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is not [`valid`](Self::is_valid).
+    pub fn to_packed(&self) -> RawImage {
+        assert!(self.is_valid(), "RawImage buffer does not match its dimensions and stride");
+
+        let data = if self.is_packed() {
+            self.data[..self.required_len()].to_vec()
+        } else {
+            let mut data = Vec::with_capacity(self.row_bytes() * self.height as usize);
+            for row in self.rows() {
+                data.extend_from_slice(row);
+            }
+            data
+        };
+
+        RawImage::new(data, self.width, self.height, self.format)
+    }
+
+    /// Convert to tightly packed RGBA, whatever the pixel format
+    pub fn to_packed_rgba(&self) -> crate::error::EncodingResult<Vec<u8>> {
+        if !self.is_valid() {
+            return Err(crate::error::EncodingError::BufferTooSmall {
+                required: self.required_len(),
+                provided: self.data.len(),
+            });
+        }
+
+        let bpp = self.format.bytes_per_pixel();
+        let mut rgba = Vec::with_capacity(self.pixel_count() * 4);
+        for row in self.rows() {
+            for pixel in row.chunks_exact(bpp) {
+                let converted = match self.format {
+                    PixelFormat::RGBA8 => [pixel[0], pixel[1], pixel[2], pixel[3]],
+                    PixelFormat::BGRA8 => [pixel[2], pixel[1], pixel[0], pixel[3]],
+                    PixelFormat::RGB8 => [pixel[0], pixel[1], pixel[2], 255],
+                    PixelFormat::BGR8 => [pixel[2], pixel[1], pixel[0], 255],
+                    PixelFormat::Gray8 => [pixel[0], pixel[0], pixel[0], 255],
+                    PixelFormat::GrayA8 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                };
+                rgba.extend_from_slice(&converted);
+            }
+        }
+        Ok(rgba)
     }
 
     /// Get a pixel at the given coordinates
@@ -191,6 +399,27 @@ impl RawImage {
 
         self.data.get(offset..offset + pixel_size)
     }
+
+    /// Perceptual hash for finding near-duplicate images
+    pub fn perceptual_hash(&self, algorithm: HashAlgorithm) -> ImageHash {
+        crate::encoder::perceptual::hash(self, algorithm)
+    }
+
+    /// Copy out the part of `rect` that lies inside the image as a packed image
+    pub fn crop(&self, rect: Rectangle) -> Option<RawImage> {
+        let bounds = Rectangle::new(0, 0, self.width, self.height);
+        let visible = rect.intersection(&bounds)?;
+
+        let bpp = self.format.bytes_per_pixel();
+        let row_size = visible.width as usize * bpp;
+        let mut data = Vec::with_capacity(row_size * visible.height as usize);
+        for y in visible.y as usize..visible.y as usize + visible.height as usize {
+            let start = y * self.stride + visible.x as usize * bpp;
+            data.extend_from_slice(self.data.get(start..start + row_size)?);
+        }
+
+        Some(RawImage::new(data, visible.width, visible.height, self.format))
+    }
 }
 
 /// WebP encoding configuration
@@ -228,6 +457,12 @@ pub struct WebPConfig {
     pub low_memory: bool,
     /// Preserve RGB values under transparency
     pub exact: bool,
+    /// Encoded size budget in bytes; quality is searched for the best fit (lossy only)
+    pub target_size: Option<usize>,
+    /// Minimum PSNR in dB; quality is searched for the smallest output reaching it (lossy only)
+    pub target_psnr: Option<f32>,
+    /// Most encodes a `target_size`/`target_psnr` search may spend; 7 bisects 0-100
+    pub search_passes: u8,
 }
 
 impl Default for WebPConfig {
@@ -249,6 +484,9 @@ impl Default for WebPConfig {
             thread_count: 0,
             low_memory: false,
             exact: false,
+            target_size: None,
+            target_psnr: None,
+            search_passes: 7,
         }
     }
 }
@@ -294,6 +532,13 @@ impl WebPConfig {
         }
     }
 
+    /// Pick settings for the content of `image` (lossless for text/UI, lossy for photos)
+    ///
+    /// See [`crate::encoder::analysis`] for how content is classified.
+    pub fn auto_for(image: &RawImage) -> Self {
+        crate::encoder::analysis::analyze(image).recommended_config()
+    }
+
     /// Validate the configuration
     pub fn validate(&self) -> Result<(), String> {
         if self.quality > 100 {
@@ -320,19 +565,98 @@ impl WebPConfig {
         if self.pass < 1 || self.pass > 10 {
             return Err(format!("Pass must be 1-10, got {}", self.pass));
         }
+        if self.target_size.is_some() && self.target_psnr.is_some() {
+            return Err("Only one of target_size and target_psnr can be set".to_string());
+        }
+        if self.lossless && (self.target_size.is_some() || self.target_psnr.is_some()) {
+            return Err("Target size and PSNR require lossy encoding".to_string());
+        }
+        if self.target_size == Some(0) {
+            return Err("Target size must be greater than 0".to_string());
+        }
+        if let Some(psnr) = self.target_psnr {
+            if !(psnr > 0.0 && psnr <= 99.0) {
+                return Err(format!("Target PSNR must be in (0, 99] dB, got {}", psnr));
+            }
+        }
+        if self.search_passes == 0 {
+            return Err("Search passes must be at least 1".to_string());
+        }
         Ok(())
     }
 }
 
+/// Encoded image format of a [`Screenshot`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OutputFormat {
+    /// WebP, configured by [`WebPConfig`]
+    #[default]
+    WebP,
+    /// Lossless PNG
+    Png,
+    /// Baseline JPEG at `WebPConfig::quality`; alpha is dropped
+    Jpeg,
+    /// Lossless QOI, fast to encode and decode
+    Qoi,
+    /// AVIF at `WebPConfig::quality` (requires the `avif` feature)
+    Avif,
+}
+
+impl OutputFormat {
+    /// MIME type of encoded data
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::WebP => "image/webp",
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Qoi => "image/qoi",
+            OutputFormat::Avif => "image/avif",
+        }
+    }
+
+    /// Conventional file extension, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::WebP => "webp",
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Qoi => "qoi",
+            OutputFormat::Avif => "avif",
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::WebP => write!(f, "WebP"),
+            OutputFormat::Png => write!(f, "PNG"),
+            OutputFormat::Jpeg => write!(f, "JPEG"),
+            OutputFormat::Qoi => write!(f, "QOI"),
+            OutputFormat::Avif => write!(f, "AVIF"),
+        }
+    }
+}
+
 /// Capture configuration
 #[derive(Debug, Clone)]
 pub struct CaptureConfig {
     /// WebP encoding configuration
     pub webp_config: WebPConfig,
+    /// Replace `webp_config` per capture with [`WebPConfig::auto_for`]
+    ///
+    /// Size/PSNR targets and the thread count are kept from `webp_config`.
+    pub auto_webp_config: bool,
+    /// Encoded output format
+    pub output_format: OutputFormat,
     /// Include cursor in capture
     pub include_cursor: bool,
+    /// How an included cursor is delivered
+    pub cursor_mode: CursorMode,
     /// Capture region (None for full display)
     pub region: Option<CaptureRegion>,
+    /// How a region reaching past the displays is handled
+    pub region_policy: RegionPolicy,
     /// Enable hardware acceleration if available
     pub use_hardware_acceleration: bool,
     /// Maximum capture retries
@@ -341,20 +665,423 @@ pub struct CaptureConfig {
     pub retry_delay: Duration,
     /// Capture timeout
     pub timeout: Duration,
+    /// Embed provenance metadata into WebP output (None to leave files bare)
+    pub embed_metadata: Option<MetadataOptions>,
+    /// Crop and resize applied between capture and encode
+    pub transform: ImageTransform,
+    /// Areas obscured before encoding
+    pub redactions: Vec<Redaction>,
+    /// Windows masked wherever they are visible (X11 only)
+    pub window_redactions: Vec<WindowRedaction>,
+    /// Hash each capture and report it in the metadata
+    pub perceptual_hash: Option<HashAlgorithm>,
+    /// Compare each capture against recent ones (hashed with `perceptual_hash`, pHash if unset)
+    pub duplicate_detection: Option<DuplicateDetection>,
+    /// How virtual desktop captures combine the displays
+    pub desktop: DesktopOptions,
 }
 
 impl Default for CaptureConfig {
     fn default() -> Self {
         Self {
             webp_config: WebPConfig::default(),
+            auto_webp_config: false,
+            output_format: OutputFormat::default(),
             include_cursor: false,
+            cursor_mode: CursorMode::default(),
             region: None,
+            region_policy: RegionPolicy::default(),
             use_hardware_acceleration: true,
             max_retries: 3,
             retry_delay: Duration::from_millis(100),
             timeout: Duration::from_secs(5),
+            embed_metadata: None,
+            transform: ImageTransform::default(),
+            redactions: Vec::new(),
+            window_redactions: Vec::new(),
+            perceptual_hash: None,
+            duplicate_detection: None,
+            desktop: DesktopOptions::default(),
+        }
+    }
+}
+
+/// Handling of capture regions that aren't entirely on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RegionPolicy {
+    /// Capture only the part within the bounds of the displays
+    #[default]
+    Clamp,
+    /// Fail unless every pixel of the region is on a display
+    Error,
+    /// Capture the requested size, transparent wherever no display is
+    Pad,
+}
+
+/// A capture region after fitting it to the displays
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRegion {
+    /// Region as configured
+    pub requested: Rectangle,
+    /// Part of the region read from the screen
+    pub captured: Rectangle,
+    /// Area the image covers: `captured`, or `requested` when padded
+    pub image_area: Rectangle,
+    /// Indices of the displays overlapping `captured`
+    pub displays: Vec<usize>,
+}
+
+/// Resolution a stitched virtual desktop is rendered at
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DesktopScale {
+    /// Scale factor of the sharpest display, so no display loses detail
+    #[default]
+    Highest,
+    /// Scale factor of the least dense display, for the smallest image
+    Lowest,
+    /// Scale factor of the primary display
+    Primary,
+    /// Output pixels per logical pixel
+    Fixed(f32),
+}
+
+/// How displays are combined into one virtual desktop image
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DesktopOptions {
+    /// RGBA color of areas no display covers (transparent by default)
+    pub fill: [u8; 4],
+    /// Reference scale every display is resampled to
+    pub scale: DesktopScale,
+    /// Filter used to resample displays of another scale
+    pub filter: ResizeFilter,
+}
+
+impl DesktopOptions {
+    /// Validate the options
+    pub fn validate(&self) -> Result<(), String> {
+        if let DesktopScale::Fixed(scale) = self.scale {
+            if !(scale.is_finite() && scale > 0.0) {
+                return Err(format!("Desktop scale must be positive, got {}", scale));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where one display ended up in a virtual desktop capture
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayPlacement {
+    /// Index of the display
+    pub display_index: usize,
+    /// Stable identifier of the display
+    pub display_id: DisplayId,
+    /// Display bounds in global logical coordinates
+    pub bounds: Rectangle,
+    /// Area the display occupies in the screenshot, in pixels
+    pub area: Rectangle,
+    /// Captured pixels per logical pixel before resampling
+    pub source_scale: f32,
+}
+
+/// Perceptual hash algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HashAlgorithm {
+    /// Difference hash: brightness gradients of a 9x8 thumbnail
+    DHash,
+    /// DCT hash: low frequencies of a 32x32 thumbnail, more robust to edits
+    #[default]
+    PHash,
+}
+
+/// 64-bit perceptual hash; similar images differ in few bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageHash {
+    /// Algorithm that produced the hash
+    pub algorithm: HashAlgorithm,
+    /// Hash bits
+    pub bits: u64,
+}
+
+impl ImageHash {
+    /// Number of differing bits; hashes from different algorithms are 64 apart
+    pub fn distance(&self, other: &ImageHash) -> u32 {
+        if self.algorithm == other.algorithm {
+            crate::encoder::perceptual::hamming_distance(self.bits, other.bits)
+        } else {
+            64
+        }
+    }
+}
+
+impl fmt::Display for ImageHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.bits)
+    }
+}
+
+/// What happens to a capture close to a recent one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DuplicateAction {
+    /// Return it with [`CaptureMetadata::near_duplicate`] set
+    #[default]
+    Flag,
+    /// Fail with `CaptureError::NearDuplicate` before encoding
+    Reject,
+}
+
+/// Near-duplicate detection against the last few captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DuplicateDetection {
+    /// Captures within this many differing bits are near-duplicates
+    pub max_distance: u32,
+    /// Number of recent captures compared against
+    pub history: usize,
+    /// What to do with a near-duplicate
+    pub action: DuplicateAction,
+}
+
+impl Default for DuplicateDetection {
+    fn default() -> Self {
+        Self {
+            max_distance: 4,
+            history: 16,
+            action: DuplicateAction::default(),
+        }
+    }
+}
+
+/// Coordinate space of a [`Redaction`] rectangle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RedactionSpace {
+    /// Relative to the top-left corner of the captured display
+    #[default]
+    Display,
+    /// Global desktop coordinates, like [`CaptureRegion`]
+    Global,
+}
+
+/// How a redacted area is obscured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RedactionMode {
+    /// Paint the area with an RGBA color
+    Fill([u8; 4]),
+    /// Replace each `block`x`block` cell with its average color
+    Pixelate { block: u32 },
+    /// Gaussian blur reaching `radius` pixels (sigma is a third of the radius)
+    Blur { radius: u32 },
+}
+
+impl RedactionMode {
+    /// Validate the mode; a 1-pixel block or zero radius would leave the area readable
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            RedactionMode::Pixelate { block } if block < 2 => {
+                Err(format!("Pixelate block must be at least 2, got {}", block))
+            }
+            RedactionMode::Blur { radius: 0 } => Err("Blur radius must be greater than 0".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// An area hidden before the image reaches the encoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redaction {
+    /// Area to hide, in logical screen pixels
+    pub rect: Rectangle,
+    /// What `rect` is relative to
+    pub space: RedactionSpace,
+    /// How the area is obscured
+    pub mode: RedactionMode,
+}
+
+impl Redaction {
+    /// Redact `rect`, given relative to the captured display
+    pub fn new(rect: Rectangle, mode: RedactionMode) -> Self {
+        Self {
+            rect,
+            space: RedactionSpace::Display,
+            mode,
         }
     }
+
+    /// Interpret `rect` as global desktop coordinates
+    pub fn global(mut self) -> Self {
+        self.space = RedactionSpace::Global;
+        self
+    }
+}
+
+/// A redaction as applied to a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppliedRedaction {
+    /// Obscured area in pixels of the screenshot
+    pub area: Rectangle,
+    /// How the area was obscured
+    pub mode: RedactionMode,
+}
+
+/// Resampling filter used when resizing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ResizeFilter {
+    /// Nearest neighbour: fastest, blocky when scaling
+    Nearest,
+    /// Triangle filter, antialiased when downscaling
+    #[default]
+    Bilinear,
+    /// Windowed sinc over 3 lobes: sharpest, slowest
+    Lanczos3,
+}
+
+/// Geometry changes applied to a captured image before encoding
+///
+/// The crop runs first, then `scale`, then `fit_within`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ImageTransform {
+    /// Keep only this part of the captured image (image pixels, clamped to its bounds)
+    pub crop: Option<Rectangle>,
+    /// Uniform scale factor
+    pub scale: Option<f32>,
+    /// Shrink to fit this box, keeping the aspect ratio; a zero side is unbounded
+    pub fit_within: Option<(u32, u32)>,
+    /// Filter used for scaling
+    pub filter: ResizeFilter,
+}
+
+impl ImageTransform {
+    /// Largest side a transform may produce, the WebP limit
+    pub const MAX_OUTPUT_SIDE: u32 = 16383;
+
+    /// Whether the transform leaves every image unchanged
+    pub fn is_identity(&self) -> bool {
+        self.crop.is_none() && self.scale.is_none_or(|scale| scale == 1.0) && self.fit_within.is_none()
+    }
+
+    /// Size of the resized image for a `width`x`height` input (after cropping)
+    pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (mut w, mut h) = (width as f64, height as f64);
+        if let Some(scale) = self.scale {
+            w *= scale as f64;
+            h *= scale as f64;
+        }
+        if let Some((max_w, max_h)) = self.fit_within {
+            let fit = |max: u32, size: f64| if max == 0 { 1.0 } else { max as f64 / size };
+            let factor = fit(max_w, w).min(fit(max_h, h));
+            if factor < 1.0 {
+                w *= factor;
+                h *= factor;
+            }
+        }
+        ((w.round() as u32).max(1), (h.round() as u32).max(1))
+    }
+
+    /// Validate the transform
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(scale) = self.scale {
+            if !(scale.is_finite() && scale > 0.0) {
+                return Err(format!("Scale must be positive, got {}", scale));
+            }
+        }
+        if let Some(crop) = self.crop {
+            if crop.width == 0 || crop.height == 0 {
+                return Err("Crop rectangle is empty".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What to embed alongside the capture time, display and region
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataOptions {
+    /// Record this machine's host name
+    pub include_hostname: bool,
+    /// Extra key/value pairs stored in XMP
+    pub properties: BTreeMap<String, String>,
+    /// ICC profile written to the ICCP chunk
+    pub icc_profile: Option<Vec<u8>>,
+}
+
+impl Default for MetadataOptions {
+    fn default() -> Self {
+        Self {
+            include_hostname: true,
+            properties: BTreeMap::new(),
+            icc_profile: None,
+        }
+    }
+}
+
+/// How the cursor is delivered when `CaptureConfig::include_cursor` is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorMode {
+    /// Blend the cursor into the captured pixels
+    #[default]
+    Composite,
+    /// Leave the pixels untouched and report the cursor in [`CaptureMetadata::cursor`]
+    /// (unavailable on Wayland, where only the compositor knows it)
+    Metadata,
+}
+
+/// Cursor shape and position
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorImage {
+    /// Pointer position in global screen coordinates
+    pub x: i32,
+    /// Pointer position in global screen coordinates
+    pub y: i32,
+    /// Hotspot offset within the cursor image
+    pub hotspot_x: u32,
+    /// Hotspot offset within the cursor image
+    pub hotspot_y: u32,
+    /// Cursor image width in pixels
+    pub width: u32,
+    /// Cursor image height in pixels
+    pub height: u32,
+    /// Premultiplied RGBA8 pixels, tightly packed
+    pub data: Vec<u8>,
+}
+
+impl CursorImage {
+    /// Build a cursor from premultiplied ARGB32 pixel values (as used by XFixes)
+    pub fn from_argb32(
+        x: i32,
+        y: i32,
+        hotspot_x: u32,
+        hotspot_y: u32,
+        width: u32,
+        height: u32,
+        pixels: &[u32],
+    ) -> Self {
+        let data = pixels
+            .iter()
+            .take((width * height) as usize)
+            .flat_map(|&argb| {
+                let [b, g, r, a] = argb.to_le_bytes();
+                [r, g, b, a]
+            })
+            .collect();
+
+        Self {
+            x,
+            y,
+            hotspot_x,
+            hotspot_y,
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Area covered by the cursor image in global screen coordinates
+    pub fn bounds(&self) -> Rectangle {
+        Rectangle::new(
+            self.x - self.hotspot_x as i32,
+            self.y - self.hotspot_y as i32,
+            self.width,
+            self.height,
+        )
+    }
 }
 
 /// Capture region specification
@@ -405,19 +1132,148 @@ impl Rectangle {
     pub fn area(&self) -> u32 {
         self.width * self.height
     }
+
+    /// Overlapping part of two rectangles, if any
+    pub fn intersection(&self, other: &Rectangle) -> Option<Rectangle> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = (self.x + self.width as i32).min(other.x + other.width as i32);
+        let bottom = (self.y + self.height as i32).min(other.y + other.height as i32);
+
+        if right > left && bottom > top {
+            Some(Rectangle::new(left, top, (right - left) as u32, (bottom - top) as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Smallest rectangle containing both
+    pub fn union(&self, other: &Rectangle) -> Rectangle {
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = (self.x as i64 + self.width as i64).max(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).max(other.y as i64 + other.height as i64);
+        Rectangle::new(left, top, (right - left as i64) as u32, (bottom - top as i64) as u32)
+    }
+
+    /// Parts of this rectangle not covered by `other`, as up to four disjoint pieces
+    pub fn subtract(&self, other: &Rectangle) -> Vec<Rectangle> {
+        let Some(hole) = self.intersection(other) else {
+            return vec![*self];
+        };
+
+        let right = self.x + self.width as i32;
+        let bottom = self.y + self.height as i32;
+        let hole_right = hole.x + hole.width as i32;
+        let hole_bottom = hole.y + hole.height as i32;
+
+        [
+            Rectangle::new(self.x, self.y, self.width, (hole.y - self.y) as u32),
+            Rectangle::new(self.x, hole_bottom, self.width, (bottom - hole_bottom) as u32),
+            Rectangle::new(self.x, hole.y, (hole.x - self.x) as u32, hole.height),
+            Rectangle::new(hole_right, hole.y, (right - hole_right) as u32, hole.height),
+        ]
+        .into_iter()
+        .filter(|piece| piece.width > 0 && piece.height > 0)
+        .collect()
+    }
+}
+
+/// Platform window identifier (an X11 window XID on Linux)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WindowId(pub u64);
+
+impl fmt::Display for WindowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// Information about a top-level window
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+    /// Window identifier
+    pub id: WindowId,
+    /// Window title
+    pub title: String,
+    /// Instance part of WM_CLASS
+    pub instance: String,
+    /// Class part of WM_CLASS
+    pub class: String,
+    /// Owning process, if the client advertises it
+    pub pid: Option<u32>,
+    /// Geometry in root window coordinates, excluding decorations
+    pub geometry: Rectangle,
+    /// Window is mapped and viewable
+    pub mapped: bool,
+    /// Window is minimized (iconified or hidden)
+    pub minimized: bool,
+}
+
+impl WindowInfo {
+    /// Check whether this window matches a filter
+    pub fn matches(&self, filter: &WindowFilter) -> bool {
+        match filter {
+            WindowFilter::Title(pattern) => pattern.is_match(&self.title),
+            WindowFilter::Class(class) => {
+                self.class.eq_ignore_ascii_case(class) || self.instance.eq_ignore_ascii_case(class)
+            }
+        }
+    }
+}
+
+/// Criteria for selecting windows from [`WindowInfo`] listings
+#[derive(Debug, Clone)]
+pub enum WindowFilter {
+    /// Title matches a regular expression
+    Title(regex::Regex),
+    /// WM_CLASS instance or class name equals this string (case-insensitive)
+    Class(String),
+}
+
+impl WindowFilter {
+    /// Match titles against a regular expression
+    pub fn title(pattern: &str) -> Result<Self, regex::Error> {
+        regex::Regex::new(pattern).map(Self::Title)
+    }
+
+    /// Match a WM_CLASS instance or class name
+    pub fn class(class: impl Into<String>) -> Self {
+        Self::Class(class.into())
+    }
+}
+
+/// Masks the visible parts of every window matching `filter`
+#[derive(Debug, Clone)]
+pub struct WindowRedaction {
+    /// Windows to hide
+    pub filter: WindowFilter,
+    /// How they are obscured
+    pub mode: RedactionMode,
+}
+
+impl WindowRedaction {
+    pub fn new(filter: WindowFilter, mode: RedactionMode) -> Self {
+        Self { filter, mode }
+    }
 }
 
 /// Screenshot result with metadata
 #[derive(Debug, Clone)]
 pub struct Screenshot {
-    /// Encoded WebP data
+    /// Encoded image data
     pub data: Vec<u8>,
+    /// Format of `data`
+    pub format: OutputFormat,
     /// Image width
     pub width: u32,
     /// Image height
     pub height: u32,
     /// Display index this was captured from
     pub display_index: usize,
+    /// Stable identifier of that display; `None` for virtual desktop captures,
+    /// which span several (see [`CaptureMetadata::layout`])
+    pub display_id: Option<DisplayId>,
     /// Capture metadata
     pub metadata: CaptureMetadata,
 }
@@ -428,6 +1284,21 @@ impl Screenshot {
         self.data.len()
     }
 
+    /// MIME type of the encoded data
+    pub fn mime_type(&self) -> &'static str {
+        self.format.mime_type()
+    }
+
+    /// Decode back to RGBA pixels
+    pub fn decode(&self) -> crate::error::EncodingResult<RawImage> {
+        crate::encoder::decode(&self.data, self.format)
+    }
+
+    /// Read the provenance metadata embedded in WebP output
+    pub fn embedded_metadata(&self) -> crate::error::EncodingResult<crate::encoder::EmbeddedMetadata> {
+        crate::encoder::metadata::read(&self.data)
+    }
+
     /// Save to file
     pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
         use std::fs::File;
@@ -454,6 +1325,28 @@ pub struct CaptureMetadata {
     pub compressed_size: usize,
     /// Implementation used
     pub implementation: String,
+    /// Cursor reported separately when using [`CursorMode::Metadata`]
+    pub cursor: Option<CursorImage>,
+    /// Quality the output was encoded at (differs from the config with a size or PSNR target)
+    pub encoding_quality: u8,
+    /// Number of full encodes performed
+    pub encoding_passes: u32,
+    /// PSNR of the output in dB, when the encoder measured it
+    pub encoding_psnr: Option<f32>,
+    /// Areas that were obscured, clipped to the screenshot
+    pub redactions: Vec<AppliedRedaction>,
+    /// Perceptual hash of the pixels handed to the encoder (redacted and
+    /// transformed, not decoded back), when hashing is enabled
+    pub perceptual_hash: Option<ImageHash>,
+    /// Distance to the closest recent capture, when within the duplicate threshold
+    pub near_duplicate: Option<u32>,
+    /// Position of each display in a virtual desktop capture (empty otherwise)
+    pub layout: Vec<DisplayPlacement>,
+    /// How the configured region was fitted to the displays, for region captures
+    pub region: Option<ResolvedRegion>,
+    /// Stable identifier of the captured display; `None` for virtual desktop
+    /// captures, whose displays are identified in [`layout`](Self::layout)
+    pub display_id: Option<DisplayId>,
 }
 
 impl CaptureMetadata {
@@ -524,6 +1417,59 @@ impl PerformanceStats {
     }
 }
 
+/// Proptest strategies for images with padded rows
+#[cfg(test)]
+pub(crate) mod strategies {
+    use super::{PixelFormat, RawImage};
+    use proptest::prelude::*;
+
+    /// Value written into row padding so tests can check it is never read or modified
+    pub(crate) const PADDING_BYTE: u8 = 0xA5;
+
+    /// A packed image and a copy of it with 0-12 bytes of padding after every row
+    pub(crate) fn packed_and_padded(
+        formats: &'static [PixelFormat],
+    ) -> impl Strategy<Value = (RawImage, RawImage)> {
+        (1u32..24, 1u32..16, 0usize..13, prop::sample::select(formats))
+            .prop_flat_map(|(width, height, padding, format)| {
+                let len = width as usize * height as usize * format.bytes_per_pixel();
+                (
+                    prop::collection::vec(any::<u8>(), len),
+                    Just((width, height, padding, format)),
+                )
+            })
+            .prop_map(|(data, (width, height, padding, format))| {
+                let packed = RawImage::new(data, width, height, format);
+                let stride = packed.row_bytes() + padding;
+                let mut padded = Vec::with_capacity(stride * height as usize);
+                for row in packed.rows() {
+                    padded.extend_from_slice(row);
+                    padded.resize(padded.len() + padding, PADDING_BYTE);
+                }
+                let padded = RawImage::with_stride(padded, width, height, format, stride);
+                (packed, padded)
+            })
+    }
+
+    /// Whether every padding byte of `image` still holds [`PADDING_BYTE`]
+    pub(crate) fn padding_intact(image: &RawImage) -> bool {
+        image
+            .data
+            .chunks(image.stride)
+            .all(|row| row[image.row_bytes().min(row.len())..].iter().all(|&byte| byte == PADDING_BYTE))
+    }
+
+    /// Every pixel format
+    pub(crate) const ALL_FORMATS: &[PixelFormat] = &[
+        PixelFormat::RGBA8,
+        PixelFormat::BGRA8,
+        PixelFormat::RGB8,
+        PixelFormat::BGR8,
+        PixelFormat::Gray8,
+        PixelFormat::GrayA8,
+    ];
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -554,4 +1500,132 @@ mod tests {
 
         assert!(image.get_pixel(1920, 0).is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_raw_image_crop() {
+        let data: Vec<u8> = (0..4 * 3).flat_map(|i| [i as u8, 0, 0]).collect();
+        let image = RawImage::new(data, 4, 3, PixelFormat::RGB8);
+
+        let cropped = image.crop(Rectangle::new(2, 1, 5, 5)).unwrap();
+        assert_eq!((cropped.width, cropped.height), (2, 2));
+        assert_eq!(cropped.get_pixel(0, 0).unwrap()[0], 6);
+        assert_eq!(cropped.get_pixel(1, 1).unwrap()[0], 11);
+
+        assert!(image.crop(Rectangle::new(4, 0, 2, 2)).is_none());
+    }
+
+    #[test]
+    fn test_rectangle_intersection() {
+        let a = Rectangle::new(0, 0, 100, 50);
+        let b = Rectangle::new(80, -10, 40, 30);
+
+        assert_eq!(a.intersection(&b), Some(Rectangle::new(80, 0, 20, 20)));
+        assert_eq!(a.intersection(&Rectangle::new(100, 0, 10, 10)), None);
+    }
+
+    #[test]
+    fn test_rectangle_union() {
+        let a = Rectangle::new(0, 0, 1920, 1080);
+        let b = Rectangle::new(-1280, 200, 1280, 1024);
+
+        assert_eq!(a.union(&b), Rectangle::new(-1280, 0, 3200, 1224));
+        assert_eq!(a.union(&a), a);
+    }
+
+    #[test]
+    fn test_rectangle_subtract() {
+        let a = Rectangle::new(0, 0, 100, 50);
+
+        // A hole in the middle leaves four pieces covering the rest
+        let pieces = a.subtract(&Rectangle::new(10, 10, 20, 20));
+        assert_eq!(pieces.len(), 4);
+        assert_eq!(pieces.iter().map(Rectangle::area).sum::<u32>(), a.area() - 400);
+        assert!(pieces.iter().all(|piece| piece.intersection(&Rectangle::new(10, 10, 20, 20)).is_none()));
+
+        // An overhanging cover trims one side
+        assert_eq!(a.subtract(&Rectangle::new(60, -5, 50, 70)), vec![Rectangle::new(0, 0, 60, 50)]);
+        assert_eq!(a.subtract(&Rectangle::new(200, 0, 10, 10)), vec![a]);
+        assert!(a.subtract(&Rectangle::new(-1, -1, 200, 200)).is_empty());
+    }
+
+    #[test]
+    fn test_window_filter_matching() {
+        let window = WindowInfo {
+            id: WindowId(0x1a00003),
+            title: "notes.txt - Text Editor".to_string(),
+            instance: "gedit".to_string(),
+            class: "Gedit".to_string(),
+            pid: Some(1234),
+            geometry: Rectangle::new(10, 20, 640, 480),
+            mapped: true,
+            minimized: false,
+        };
+
+        assert!(window.matches(&WindowFilter::title(r"\.txt - Text").unwrap()));
+        assert!(!window.matches(&WindowFilter::title("^Text").unwrap()));
+        assert!(window.matches(&WindowFilter::class("gedit")));
+        assert!(window.matches(&WindowFilter::class("GEDIT")));
+        assert!(!window.matches(&WindowFilter::class("firefox")));
+        assert!(WindowFilter::title("(").is_err());
+        assert_eq!(window.id.to_string(), "0x1a00003");
+    }
+
+    #[test]
+    fn test_stride_helpers() {
+        // 2x2 RGB8 with two bytes of padding; the last row is unpadded
+        let data = vec![1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12];
+        let image = RawImage::with_stride(data, 2, 2, PixelFormat::RGB8, 8);
+        assert_eq!(image.row_bytes(), 6);
+        assert_eq!(image.required_len(), 14);
+        assert!(image.is_valid() && !image.is_packed());
+        assert_eq!(image.to_packed().data, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+
+        let short_stride = RawImage::with_stride(vec![0; 12], 2, 2, PixelFormat::RGB8, 4);
+        assert!(!short_stride.is_valid());
+    }
+
+    mod stride_properties {
+        use super::super::strategies::{packed_and_padded, ALL_FORMATS};
+        use super::super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn test_to_packed_strips_padding((packed, padded) in packed_and_padded(ALL_FORMATS)) {
+                prop_assert!(padded.is_valid());
+                let repacked = padded.to_packed();
+                prop_assert!(repacked.is_packed());
+                prop_assert_eq!(&repacked.data, &packed.data);
+                prop_assert_eq!(padded.rows().count(), padded.height as usize);
+            }
+
+            #[test]
+            fn test_unpadded_last_row_is_valid((packed, mut padded) in packed_and_padded(ALL_FORMATS)) {
+                let padding = padded.stride - padded.row_bytes();
+                padded.data.truncate(padded.data.len() - padding);
+                prop_assert!(padded.is_valid());
+                prop_assert_eq!(padded.to_packed().data, packed.data);
+            }
+
+            #[test]
+            fn test_pixels_and_crops_ignore_padding(
+                (packed, padded) in packed_and_padded(ALL_FORMATS),
+                x in -4i32..24,
+                y in -4i32..16,
+                width in 0u32..24,
+                height in 0u32..16,
+            ) {
+                for py in 0..packed.height {
+                    for px in 0..packed.width {
+                        prop_assert_eq!(packed.get_pixel(px, py), padded.get_pixel(px, py));
+                    }
+                }
+
+                let rect = Rectangle::new(x, y, width, height);
+                let from_packed = packed.crop(rect).map(|image| image.data);
+                let from_padded = padded.crop(rect).map(|image| image.data);
+                prop_assert_eq!(from_packed, from_padded);
+            }
+        }
+    }
+}
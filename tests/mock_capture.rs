@@ -0,0 +1,121 @@
+//! Headless Capture Tests
+//!
+//! Exercises the public capture API through the deterministic mock backend,
+//! so these run on machines without a display server.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use webp_screenshot_rust::capture::mock::{MockCapture, MockFailure, MockPattern};
+use webp_screenshot_rust::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dual_display_mock() -> MockCapture {
+        MockCapture::new().with_displays(vec![
+            DisplayInfo {
+                width: 160,
+                height: 90,
+                ..Default::default()
+            },
+            DisplayInfo {
+                x: 160,
+                width: 120,
+                height: 90,
+                is_primary: false,
+                ..Default::default()
+            },
+        ])
+    }
+
+    #[test]
+    fn test_capture_all_mock_displays() {
+        let mut screenshot =
+            WebPScreenshot::with_capturer(Box::new(dual_display_mock()), CaptureConfig::default())
+                .unwrap();
+
+        let results = screenshot.capture_all_displays();
+        assert_eq!(results.len(), 2);
+
+        let second = results[1].as_ref().unwrap();
+        assert_eq!((second.width, second.height), (120, 90));
+        assert_eq!(second.display_index, 1);
+    }
+
+    #[test]
+    fn test_region_capture_through_mock() {
+        let config = CaptureConfig {
+            region: Some(CaptureRegion::new(150, 10, 40, 30)),
+            ..Default::default()
+        };
+        let capturer = dual_display_mock().with_pattern(MockPattern::Text);
+        let mut screenshot = WebPScreenshot::with_capturer(Box::new(capturer), config).unwrap();
+
+        let result = screenshot.capture_display(0).unwrap();
+        assert_eq!((result.width, result.height), (40, 30));
+    }
+
+    #[test]
+    fn test_region_policies_through_mock() {
+        // Crosses into the second display and past the right edge of the desktop
+        let capture = |region_policy| {
+            let config = CaptureConfig {
+                region: Some(CaptureRegion::new(250, 10, 60, 30)),
+                region_policy,
+                max_retries: 0,
+                ..Default::default()
+            };
+            WebPScreenshot::with_capturer(Box::new(dual_display_mock()), config)
+                .unwrap()
+                .capture_display(0)
+        };
+
+        let clamped = capture(RegionPolicy::Clamp).unwrap();
+        assert_eq!((clamped.width, clamped.height), (30, 30));
+        let region = clamped.metadata.region.unwrap();
+        assert_eq!(region.captured, Rectangle::new(250, 10, 30, 30));
+        assert_eq!(region.displays, vec![1]);
+
+        assert!(matches!(capture(RegionPolicy::Error), Err(CaptureError::InvalidConfiguration(_))));
+
+        let padded = capture(RegionPolicy::Pad).unwrap();
+        assert_eq!((padded.width, padded.height), (60, 30));
+        assert_eq!(padded.metadata.region.as_ref().unwrap().image_area, Rectangle::new(250, 10, 60, 30));
+        assert_eq!(padded.decode().unwrap().get_pixel(59, 0).unwrap()[3], 0);
+    }
+
+    #[test]
+    fn test_scripted_failure_surfaces_to_caller() {
+        let capturer = dual_display_mock();
+        capturer.push_failure(MockFailure::CaptureFailed("scripted".to_string()));
+
+        let mut screenshot =
+            WebPScreenshot::with_capturer(Box::new(capturer), CaptureConfig::default()).unwrap();
+        let err = screenshot.capture_display(0).unwrap_err();
+        assert!(err.to_string().contains("scripted"));
+    }
+
+    #[test]
+    fn test_streaming_pipeline_with_mock() {
+        let capturer = dual_display_mock()
+            .with_pattern(MockPattern::MovingRectangle { size: 16, velocity: (5, 3) });
+        let pipeline = StreamingPipelineBuilder::new()
+            .target_fps(30)
+            .encoding_threads(1)
+            .build(Box::new(capturer));
+
+        let frames = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&frames);
+        pipeline
+            .start(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        pipeline.stop();
+
+        assert!(frames.load(Ordering::Relaxed) > 0);
+    }
+}
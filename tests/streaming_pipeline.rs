@@ -78,6 +78,7 @@ mod tests {
             },
             use_zero_copy: true,
             use_gpu: false,
+            include_cursor: false,
         };
 
         let pipeline = StreamingPipeline::new(Box::new(capturer), config);